@@ -0,0 +1,93 @@
+//! Times the layout engine on the hot paths of a key press
+//!
+//! Run with `cargo bench`, the numbers are the mean time of one
+//! round of key events.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use xppen_ack05::prelude::KeyCode;
+use xppen_ack05::prelude::*;
+
+const ROUNDS: u32 = 100_000;
+
+fn layout() -> LayerSwitcher {
+    let builder = LayoutBuilder::new()
+        .layer(
+            LayerBuilder::new()
+                .name("base")
+                .key(KeyCoords(0, 0, 0), G().k(KeyCode::KEY_A).p())
+                .key(
+                    KeyCoords(0, 0, 1),
+                    G().m(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_Z).p(),
+                )
+                .key(
+                    KeyCoords(0, 1, 0),
+                    KeymapEvent::Klong(G().k(KeyCode::KEY_X), G().k(KeyCode::KEY_Y)),
+                )
+                .key(KeyCoords(0, 1, 1), KeymapEvent::Khl(G().k(KeyCode::KEY_ESC), 2)),
+        )
+        .layer(LayerBuilder::new().name("left").inherits("base"))
+        .layer(
+            LayerBuilder::new()
+                .name("tools")
+                .inherits("left")
+                .key(KeyCoords(0, 0, 0), G().k(KeyCode::KEY_B).p()),
+        );
+    let mut layout = LayerSwitcher::new(&builder.build().unwrap());
+    layout.start();
+    layout
+}
+
+/// Run `round` `ROUNDS` times and print the mean time of one round
+fn bench<F>(name: &str, mut round: F)
+where
+    F: FnMut(&mut LayerSwitcher, Instant),
+{
+    let mut layout = layout();
+    let mut t = Instant::now();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        round(&mut layout, t);
+        layout.render(|k, v| {
+            black_box((k, v));
+        });
+        t += Duration::from_millis(10);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<24} {:>8.0} ns/round",
+        name,
+        elapsed.as_nanos() as f64 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    bench("press release", |layout, t| {
+        layout.process_keyevent(KeyStateChange::Pressed(KeyCoords(0, 0, 0)), t);
+        layout.process_keyevent(KeyStateChange::Released(KeyCoords(0, 0, 0)), t);
+    });
+
+    bench("chord of two keys", |layout, t| {
+        layout.process_keyevent(KeyStateChange::Pressed(KeyCoords(0, 0, 0)), t);
+        layout.process_keyevent(KeyStateChange::Pressed(KeyCoords(0, 0, 1)), t);
+        layout.process_keyevent(KeyStateChange::Released(KeyCoords(0, 0, 1)), t);
+        layout.process_keyevent(KeyStateChange::Released(KeyCoords(0, 0, 0)), t);
+    });
+
+    bench("long press", |layout, t| {
+        let long = t + Duration::from_secs(1);
+        layout.process_keyevent(KeyStateChange::Pressed(KeyCoords(0, 1, 0)), t);
+        layout.process_keyevent(KeyStateChange::LongPress(KeyCoords(0, 1, 0), long - t), long);
+        layout.process_keyevent(KeyStateChange::Released(KeyCoords(0, 1, 0)), long);
+    });
+
+    bench("hold layer", |layout, t| {
+        let long = t + Duration::from_secs(1);
+        layout.process_keyevent(KeyStateChange::Pressed(KeyCoords(0, 1, 1)), t);
+        layout.process_keyevent(KeyStateChange::LongPress(KeyCoords(0, 1, 1), long - t), long);
+        layout.process_keyevent(KeyStateChange::Pressed(KeyCoords(0, 0, 0)), long);
+        layout.process_keyevent(KeyStateChange::Released(KeyCoords(0, 0, 0)), long);
+        layout.process_keyevent(KeyStateChange::Released(KeyCoords(0, 1, 1)), long);
+    });
+}
@@ -0,0 +1,201 @@
+use std::future;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::unix::AsyncFd;
+use tokio::runtime::{self, Runtime};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::button_device::reader::{stopped, Report, Request, REPORT_QUEUE, REQUEST_POLL_MS};
+use crate::button_device::{ButtonDevice, ReadResult};
+
+/// A descriptor owned by someone else, only watched for being readable
+struct Watched(RawFd);
+
+impl AsRawFd for Watched {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+/// Waits for all the event sources of the main loop on a tokio runtime
+///
+/// The device is read by a blocking task the same way the `DeviceReader`
+/// reads it in its thread. The other sources are futures waking the main
+/// loop up instead of being noticed with the next idle poll: the next timer
+/// of the layout as a sleep, the watched layout file as its readable inotify
+/// descriptor and the control requests through the `waker`. The main loop
+/// then polls them all the same way as without the runtime.
+pub struct AsyncFrontend<D: ButtonDevice> {
+    reports: mpsc::Receiver<Report<D>>,
+    requests: mpsc::UnboundedSender<Request<D>>,
+    /// None while the device is released
+    task: Option<JoinHandle<()>>,
+    wake: Arc<Notify>,
+    watched: Option<AsyncFd<Watched>>,
+    /// Dropped last, it waits for the blocking task which ends only
+    /// when the requests are closed
+    runtime: Runtime,
+}
+
+impl<D> AsyncFrontend<D>
+where
+    D: ButtonDevice + Send + 'static,
+    ReadResult<D::Button>: Send,
+    D::Error: Send + From<io::Error> + 'static,
+{
+    pub fn start(device: D) -> Self {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Cannot start the tokio runtime");
+        let (reports, requests, task) = Self::spawn(&runtime, device);
+
+        Self {
+            reports,
+            requests,
+            task: Some(task),
+            wake: Arc::new(Notify::new()),
+            watched: None,
+            runtime,
+        }
+    }
+
+    /// Read another device, eg. the reconnected one. The old task ends.
+    pub fn restart(&mut self, device: D) {
+        let (reports, requests, task) = Self::spawn(&self.runtime, device);
+        (self.reports, self.requests, self.task) = (reports, requests, Some(task));
+    }
+
+    /// Restore the device and close it the way `DeviceReader::release` does
+    pub fn release(&mut self) -> Result<(), D::Error> {
+        let restored = self
+            .with(D::restore)
+            .unwrap_or_else(|| Err(stopped().into()));
+
+        // The task ends with the closed channels and drops the device
+        (_, self.reports) = mpsc::channel(1);
+        (self.requests, _) = mpsc::unbounded_channel();
+        if let Some(task) = self.task.take() {
+            let _ = self.runtime.block_on(task);
+        }
+        restored
+    }
+
+    /// Was the device released and not restarted yet?
+    pub fn is_released(&self) -> bool {
+        self.task.is_none()
+    }
+
+    fn spawn(
+        runtime: &Runtime,
+        mut device: D,
+    ) -> (
+        mpsc::Receiver<Report<D>>,
+        mpsc::UnboundedSender<Request<D>>,
+        JoinHandle<()>,
+    ) {
+        let (report_tx, reports) = mpsc::channel(REPORT_QUEUE);
+        let (requests, mut request_rx) = mpsc::unbounded_channel::<Request<D>>();
+
+        let task = runtime.spawn_blocking(move || loop {
+            loop {
+                match request_rx.try_recv() {
+                    Ok(request) => request(&mut device),
+                    Err(TryRecvError::Empty) => break,
+                    // The frontend was dropped
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            let result = device.read_timeout(REQUEST_POLL_MS);
+            let t = Instant::now();
+            if let Ok(ReadResult::Timeout) = result {
+                continue;
+            }
+            let failed = result.is_err();
+            if report_tx.blocking_send(result.map(|r| (r, t))).is_err() || failed {
+                // Nobody is interested anymore or the device is gone
+                return;
+            }
+        });
+
+        (reports, requests, task)
+    }
+
+    /// Wake the main loop up when the `fd` turns readable, eg. the descriptor
+    /// of the layout file watcher. None stops watching.
+    pub fn watch<F: AsRawFd>(&mut self, fd: Option<&F>) {
+        let _runtime = self.runtime.enter();
+        self.watched = fd.and_then(|fd| {
+            AsyncFd::new(Watched(fd.as_raw_fd()))
+                .map_err(|e| warn!("Cannot watch the descriptor: {}", e))
+                .ok()
+        });
+    }
+
+    /// A callback waking the main loop up, eg. for a queued control request.
+    /// A wake up before the wait is not lost.
+    pub fn waker(&self) -> impl FnMut() + Send + 'static {
+        let wake = self.wake.clone();
+        move || wake.notify_one()
+    }
+
+    /// Wait at most `timeout` for the next report. Any other wake up
+    /// is reported as a timeout at the current time.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Report<D> {
+        let Self {
+            reports,
+            task,
+            wake,
+            watched,
+            runtime,
+            ..
+        } = self;
+        runtime.block_on(async {
+            tokio::select! {
+                // A released device has no reports
+                report = reports.recv(), if task.is_some() => match report {
+                    Some(report) => report,
+                    // Only a panic ends the task without reporting the error
+                    None => Err(stopped().into()),
+                },
+                _ = tokio::time::sleep(timeout) => Ok((ReadResult::Timeout, Instant::now())),
+                _ = wake.notified() => Ok((ReadResult::Timeout, Instant::now())),
+                _ = readable(watched.as_ref()) => Ok((ReadResult::Timeout, Instant::now())),
+            }
+        })
+    }
+
+    /// Run `f` with the device in the reader task and wait for its result.
+    /// None when the reader has already stopped.
+    pub fn with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut D) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = std_mpsc::channel();
+        let request = Box::new(move |device: &mut D| {
+            let _ = tx.send(f(device));
+        });
+        self.requests.send(request).ok()?;
+        rx.recv().ok()
+    }
+}
+
+/// Wait until the watched descriptor is readable, forever when there is none.
+/// The owner of the descriptor reads it afterwards.
+async fn readable(fd: Option<&AsyncFd<Watched>>) {
+    match fd.map(|fd| fd.readable()) {
+        Some(readable) => match readable.await {
+            Ok(mut guard) => guard.clear_ready(),
+            Err(_) => future::pending().await,
+        },
+        None => future::pending().await,
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(feature = "audio")]
+use tracing::warn;
+
+use crate::layout::types::LayerId;
+
+/// Volume of all the cues, they are meant to be noticed, not to be loud
+#[cfg(feature = "audio")]
+const VOLUME: f32 = 0.2;
+
+/// Things worth an audible indication. When drawing in full-screen there
+/// is no place to glance at, so a short tone is the cheapest mode indicator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cue {
+    /// A new layer became active
+    LayerEnter,
+    /// A layer was deactivated
+    LayerExit,
+    /// A lock (Caps Lock, Num Lock, ..) was toggled
+    LockToggle,
+    /// Something went wrong
+    Error,
+}
+
+impl Cue {
+    /// The tone sequence of the cue as (frequency in Hz, duration in ms)
+    pub fn tones(&self) -> &'static [(f32, u64)] {
+        match self {
+            Cue::LayerEnter => &[(660.0, 40), (880.0, 40)],
+            Cue::LayerExit => &[(880.0, 40), (660.0, 40)],
+            Cue::LockToggle => &[(1320.0, 30)],
+            Cue::Error => &[(220.0, 120), (0.0, 40), (220.0, 120)],
+        }
+    }
+
+    /// Select the cue for a change of the active layers
+    pub fn for_layers(before: &[LayerId], after: &[LayerId]) -> Option<Self> {
+        if after.iter().any(|l| !before.contains(l)) {
+            Some(Cue::LayerEnter)
+        } else if before.iter().any(|l| !after.contains(l)) {
+            Some(Cue::LayerExit)
+        } else {
+            None
+        }
+    }
+}
+
+/// Audio output for the feedback cues
+///
+/// Sound is only available when the crate is built with the `audio` feature,
+/// otherwise all cues are silently dropped. A missing sound server is not
+/// an error either, the driver keeps working without the feedback.
+pub struct AudioFeedback {
+    #[cfg(feature = "audio")]
+    output: Option<(rodio::OutputStream, rodio::Sink)>,
+}
+
+impl AudioFeedback {
+    #[cfg(feature = "audio")]
+    pub fn open() -> Self {
+        let output = rodio::OutputStream::try_default()
+            .map_err(|e| warn!("Audio feedback not available: {}", e))
+            .ok()
+            .and_then(|(stream, handle)| {
+                rodio::Sink::try_new(&handle)
+                    .map_err(|e| warn!("Audio feedback not available: {}", e))
+                    .ok()
+                    .map(|sink| (stream, sink))
+            });
+
+        Self { output }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn open() -> Self {
+        Self {}
+    }
+
+    /// Queue the cue for playing, this does not block
+    #[cfg(feature = "audio")]
+    pub fn play(&self, cue: Cue) {
+        use rodio::source::{SineWave, Source};
+        use std::time::Duration;
+
+        let Some((_, sink)) = &self.output else {
+            return;
+        };
+
+        for &(freq, ms) in cue.tones() {
+            let volume = if freq > 0.0 { VOLUME } else { 0.0 };
+            sink.append(
+                SineWave::new(freq)
+                    .take_duration(Duration::from_millis(ms))
+                    .amplify(volume),
+            );
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    pub fn play(&self, _cue: Cue) {}
+}
@@ -0,0 +1,116 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory next to the configuration files holding their backups
+const BACKUP_DIR: &str = "backups";
+
+/// How many backups of each file are kept
+const MAX_BACKUPS: usize = 10;
+
+/// Where the backups of `path` go, eg. `macros.toml` -> `backups/macros.toml.<ms>`
+fn backup_dir(path: &Path) -> PathBuf {
+    path.parent().unwrap_or(Path::new("")).join(BACKUP_DIR)
+}
+
+fn file_name(path: &Path) -> io::Result<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file"))
+}
+
+/// The timestamped backups of `path`, the newest first
+pub fn backups(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let prefix = format!("{}.", file_name(path)?);
+    let entries = match fs::read_dir(backup_dir(path)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut backups: Vec<(u128, PathBuf)> = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(stamp) = name.strip_prefix(&prefix).and_then(|s| s.parse().ok()) else {
+            continue;
+        };
+        backups.push((stamp, entry.path()));
+    }
+
+    backups.sort_by_key(|(stamp, _)| Reverse(*stamp));
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Keep a timestamped copy of `path` before it is overwritten. Only the
+/// newest backups are kept. Returns the backup, None when there was
+/// nothing to back up.
+pub fn backup(path: &Path) -> io::Result<Option<PathBuf>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let dir = backup_dir(path);
+    fs::create_dir_all(&dir)?;
+
+    // A newer stamp than the last backup, even when saved within the same ms
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let last = backups(path)?
+        .first()
+        .and_then(|b| b.extension())
+        .and_then(|ext| ext.to_str()?.parse::<u128>().ok());
+    let stamp = last.map_or(now, |last| now.max(last + 1));
+
+    let target = dir.join(format!("{}.{}", file_name(path)?, stamp));
+    fs::copy(path, &target)?;
+
+    for old in backups(path)?.into_iter().skip(MAX_BACKUPS) {
+        fs::remove_file(old)?;
+    }
+    Ok(Some(target))
+}
+
+/// Back up `path` after it was loaded successfully, so a later broken edit
+/// can be rolled back to it. Nothing is saved when the newest backup already
+/// holds the same content.
+pub fn known_good(path: &Path) -> io::Result<Option<PathBuf>> {
+    let content = fs::read(path)?;
+    if let Some(newest) = backups(path)?.first() {
+        if fs::read(newest)? == content {
+            return Ok(None);
+        }
+    }
+    backup(path)
+}
+
+/// Back up `path` and replace its content
+pub fn write(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    backup(path)?;
+    fs::write(path, content)
+}
+
+/// Restore the newest backup of `path` that differs from its content, eg.
+/// the known good copy of the running layout is skipped. The backups are
+/// consumed, so repeated rollbacks step further back. Returns the restored
+/// backup, None when there is none.
+pub fn rollback(path: &Path) -> io::Result<Option<PathBuf>> {
+    let content = fs::read(path).ok();
+    for newest in backups(path)? {
+        let same = content.as_ref().is_some_and(|c| fs::read(&newest).is_ok_and(|b| b == *c));
+        if !same {
+            fs::copy(&newest, path)?;
+            fs::remove_file(&newest)?;
+            return Ok(Some(newest));
+        }
+        fs::remove_file(&newest)?;
+    }
+    Ok(None)
+}
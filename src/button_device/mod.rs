@@ -0,0 +1,82 @@
+pub mod reader;
+
+use std::hash::Hash;
+use std::time::Instant;
+
+use enumset::{EnumSet, EnumSetType};
+
+use crate::kbd_events::{ChangeDetector, HasState};
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeyCoords;
+
+/// The outcome of waiting for a report of a button device
+#[derive(Debug, Clone, Copy)]
+pub enum ReadResult<B: EnumSetType> {
+    /// No report arrived in time
+    Timeout,
+    /// A report arrived, but it does not describe the buttons
+    TryAgain,
+    /// The buttons held down right now
+    Keys(EnumSet<B>),
+}
+
+/// A device with buttons that can drive the layout engine
+///
+/// The layout engine only sees the positions the buttons map to, so
+/// other shortcut remotes can be plugged in by implementing this trait.
+pub trait ButtonDevice: Sized {
+    /// The buttons of the device. Stateless ones (eg. a wheel) only click.
+    type Button: EnumSetType + Hash + HasState + Into<KeyCoords>;
+    type Error: std::error::Error;
+
+    /// Find the device and make it ready to report buttons
+    fn open() -> Result<Self, Self::Error>;
+
+    /// Wait at most `timeout` ms (-1 = forever) for the next report
+    fn read_timeout(&mut self, timeout: i32) -> Result<ReadResult<Self::Button>, Self::Error>;
+
+    /// All the buttons the device can report
+    fn buttons(&self) -> EnumSet<Self::Button> {
+        EnumSet::all()
+    }
+
+    /// Set the device up again, eg. after it lost power during a suspend
+    fn configure(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Undo `configure` before the device is left to the system, eg. for
+    /// a passthrough. The device is closed afterwards.
+    fn restore(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Wait for the next report of `device` and feed it to the change `detector`.
+/// A report without buttons only lets the time pass.
+pub fn read_into<D: ButtonDevice>(
+    device: &mut D,
+    detector: &mut ChangeDetector<D::Button>,
+    timeout: i32,
+    t: Instant,
+) -> Result<ReadResult<D::Button>, D::Error> {
+    let result = device.read_timeout(timeout)?;
+    if let ReadResult::Keys(buttons) = result {
+        detector.analyze(buttons, t);
+    } else {
+        detector.tick(t);
+    }
+    Ok(result)
+}
+
+/// Recover from a lost or misbehaving device, eg. it was unplugged, reset
+/// or the system was suspended. The layout releases every key it holds and
+/// the detector forgets the button states, the buttons still held are
+/// pressed again by the next report. The released keys still need a render.
+pub fn recover<B>(layout: &mut LayerSwitcher, detector: &mut ChangeDetector<B>)
+where
+    B: EnumSetType + Hash + HasState,
+{
+    layout.release_all();
+    detector.reset();
+}
@@ -0,0 +1,167 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::{ButtonDevice, ReadResult};
+
+/// The most reports waiting for the main loop, the reader waits when it is full
+pub(crate) const REPORT_QUEUE: usize = 64;
+
+/// How often the reader thread looks for the requests of the main loop (ms)
+pub(crate) const REQUEST_POLL_MS: i32 = 100;
+
+/// Something to do with the device in the reader thread
+pub(crate) type Request<D> = Box<dyn FnOnce(&mut D) + Send>;
+
+/// A report and the time it was read at, the main loop may take it from the
+/// queue much later
+pub(crate) type Report<D> =
+    Result<(ReadResult<<D as ButtonDevice>::Button>, Instant), <D as ButtonDevice>::Error>;
+
+/// Reads a button device in a thread of its own
+///
+/// The reports are pushed over a bounded channel and the main loop waits for
+/// them with the timeout of its nearest deadline, no matter how the device
+/// itself reads. Every report is timestamped right after the read, a backlog
+/// in the queue keeps the times apart. The timeouts of the device are not
+/// passed on. A read error is the last report, the thread ends with it or
+/// when the reader is dropped and the device is closed.
+///
+/// The device lives in the thread, `with` runs the other calls there,
+/// eg. configuring it again after a resume.
+pub struct DeviceReader<D: ButtonDevice> {
+    /// None only wakes the main loop up, see `waker`
+    reports: Receiver<Option<Report<D>>>,
+    requests: Sender<Request<D>>,
+    /// The sender of the current reports, shared with the wakers
+    wake: Arc<Mutex<SyncSender<Option<Report<D>>>>>,
+    /// None while the device is released
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<D> DeviceReader<D>
+where
+    D: ButtonDevice + Send + 'static,
+    ReadResult<D::Button>: Send,
+    D::Error: Send + From<io::Error> + 'static,
+{
+    pub fn start(device: D) -> Self {
+        let (report_tx, reports) = mpsc::sync_channel(REPORT_QUEUE);
+        let (requests, thread) = Self::spawn(device, report_tx.clone());
+        Self {
+            reports,
+            requests,
+            wake: Arc::new(Mutex::new(report_tx)),
+            thread: Some(thread),
+        }
+    }
+
+    /// Read another device, eg. the reconnected one. The old thread ends.
+    pub fn restart(&mut self, device: D) {
+        let (report_tx, reports) = mpsc::sync_channel(REPORT_QUEUE);
+        let (requests, thread) = Self::spawn(device, report_tx.clone());
+        (self.requests, self.thread) = (requests, Some(thread));
+        self.reports = reports;
+        *self.wake.lock().unwrap() = report_tx;
+    }
+
+    /// Restore the device and close it, eg. to leave it to the system for
+    /// a passthrough. Until `restart` gives it a device again, the reader
+    /// only reports the wake ups and the timeouts.
+    pub fn release(&mut self) -> Result<(), D::Error> {
+        let restored = self
+            .with(D::restore)
+            .unwrap_or_else(|| Err(stopped().into()));
+
+        // The thread ends with the closed channels and drops the device
+        let (report_tx, reports) = mpsc::sync_channel(REPORT_QUEUE);
+        (self.requests, _) = mpsc::channel();
+        self.reports = reports;
+        *self.wake.lock().unwrap() = report_tx;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        restored
+    }
+
+    /// Was the device released and not restarted yet?
+    pub fn is_released(&self) -> bool {
+        self.thread.is_none()
+    }
+
+    fn spawn(
+        mut device: D,
+        report_tx: SyncSender<Option<Report<D>>>,
+    ) -> (Sender<Request<D>>, JoinHandle<()>) {
+        let (requests, request_rx) = mpsc::channel::<Request<D>>();
+
+        let thread = thread::spawn(move || loop {
+            loop {
+                match request_rx.try_recv() {
+                    Ok(request) => request(&mut device),
+                    Err(TryRecvError::Empty) => break,
+                    // The reader was dropped
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            let result = device.read_timeout(REQUEST_POLL_MS);
+            let t = Instant::now();
+            if let Ok(ReadResult::Timeout) = result {
+                continue;
+            }
+            let failed = result.is_err();
+            if report_tx.send(Some(result.map(|r| (r, t)))).is_err() || failed {
+                // Nobody is interested anymore or the device is gone
+                return;
+            }
+        });
+
+        (requests, thread)
+    }
+
+    /// A callback waking the main loop up, eg. for a key of another input.
+    /// A wake up before the wait is not lost.
+    pub fn waker(&self) -> impl FnMut() + Send + 'static {
+        let wake = self.wake.clone();
+        // A full queue wakes the main loop up anyway
+        move || {
+            let _ = wake.lock().unwrap().try_send(None);
+        }
+    }
+
+    /// Wait at most `timeout` for the next report. A wake up is reported
+    /// as a timeout at the current time.
+    pub fn read_timeout(&self, timeout: Duration) -> Report<D> {
+        match self.reports.recv_timeout(timeout) {
+            Ok(Some(result)) => result,
+            // Only a panic ends the thread without reporting the error,
+            // the wakers keep the queue open
+            _ if self.thread.as_ref().is_some_and(JoinHandle::is_finished) => Err(stopped().into()),
+            Ok(None) | Err(RecvTimeoutError::Timeout) => Ok((ReadResult::Timeout, Instant::now())),
+            Err(RecvTimeoutError::Disconnected) => unreachable!("The reader holds a sender"),
+        }
+    }
+
+    /// Run `f` with the device in the reader thread and wait for its result.
+    /// None when the reader has already stopped.
+    pub fn with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut D) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let request = Box::new(move |device: &mut D| {
+            let _ = tx.send(f(device));
+        });
+        self.requests.send(request).ok()?;
+        rx.recv().ok()
+    }
+}
+
+/// The error of a reader that stopped without reporting one, the device
+/// is reopened like after any other read error
+pub(crate) fn stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "The device reader stopped")
+}
@@ -0,0 +1,158 @@
+use crate::keycodes::KeyCode;
+
+/// kVK_Shift, kVK_Control and the other modifiers, with the event flag
+/// they hold down
+const MODIFIERS: [(u16, u64); 9] = [
+    (0x38, 0x0002_0000),
+    (0x3c, 0x0002_0000),
+    (0x3b, 0x0004_0000),
+    (0x3e, 0x0004_0000),
+    (0x3a, 0x0008_0000),
+    (0x3d, 0x0008_0000),
+    (0x37, 0x0010_0000),
+    (0x36, 0x0010_0000),
+    (0x3f, 0x0080_0000),
+];
+
+/// The virtual key codes of Events.h. They name the position of a key on
+/// an ANSI keyboard, the layout of the desktop picks the character.
+const KEYS: &[(KeyCode, u16)] = &[
+    (KeyCode::KEY_A, 0x00),
+    (KeyCode::KEY_S, 0x01),
+    (KeyCode::KEY_D, 0x02),
+    (KeyCode::KEY_F, 0x03),
+    (KeyCode::KEY_H, 0x04),
+    (KeyCode::KEY_G, 0x05),
+    (KeyCode::KEY_Z, 0x06),
+    (KeyCode::KEY_X, 0x07),
+    (KeyCode::KEY_C, 0x08),
+    (KeyCode::KEY_V, 0x09),
+    (KeyCode::KEY_102ND, 0x0a),
+    (KeyCode::KEY_B, 0x0b),
+    (KeyCode::KEY_Q, 0x0c),
+    (KeyCode::KEY_W, 0x0d),
+    (KeyCode::KEY_E, 0x0e),
+    (KeyCode::KEY_R, 0x0f),
+    (KeyCode::KEY_Y, 0x10),
+    (KeyCode::KEY_T, 0x11),
+    (KeyCode::KEY_1, 0x12),
+    (KeyCode::KEY_2, 0x13),
+    (KeyCode::KEY_3, 0x14),
+    (KeyCode::KEY_4, 0x15),
+    (KeyCode::KEY_6, 0x16),
+    (KeyCode::KEY_5, 0x17),
+    (KeyCode::KEY_EQUAL, 0x18),
+    (KeyCode::KEY_9, 0x19),
+    (KeyCode::KEY_7, 0x1a),
+    (KeyCode::KEY_MINUS, 0x1b),
+    (KeyCode::KEY_8, 0x1c),
+    (KeyCode::KEY_0, 0x1d),
+    (KeyCode::KEY_RIGHTBRACE, 0x1e),
+    (KeyCode::KEY_O, 0x1f),
+    (KeyCode::KEY_U, 0x20),
+    (KeyCode::KEY_LEFTBRACE, 0x21),
+    (KeyCode::KEY_I, 0x22),
+    (KeyCode::KEY_P, 0x23),
+    (KeyCode::KEY_ENTER, 0x24),
+    (KeyCode::KEY_L, 0x25),
+    (KeyCode::KEY_J, 0x26),
+    (KeyCode::KEY_APOSTROPHE, 0x27),
+    (KeyCode::KEY_K, 0x28),
+    (KeyCode::KEY_SEMICOLON, 0x29),
+    (KeyCode::KEY_BACKSLASH, 0x2a),
+    (KeyCode::KEY_COMMA, 0x2b),
+    (KeyCode::KEY_SLASH, 0x2c),
+    (KeyCode::KEY_N, 0x2d),
+    (KeyCode::KEY_M, 0x2e),
+    (KeyCode::KEY_DOT, 0x2f),
+    (KeyCode::KEY_TAB, 0x30),
+    (KeyCode::KEY_SPACE, 0x31),
+    (KeyCode::KEY_GRAVE, 0x32),
+    (KeyCode::KEY_BACKSPACE, 0x33),
+    (KeyCode::KEY_ESC, 0x35),
+    (KeyCode::KEY_RIGHTMETA, 0x36),
+    (KeyCode::KEY_LEFTMETA, 0x37),
+    (KeyCode::KEY_LEFTSHIFT, 0x38),
+    (KeyCode::KEY_CAPSLOCK, 0x39),
+    (KeyCode::KEY_LEFTALT, 0x3a),
+    (KeyCode::KEY_LEFTCTRL, 0x3b),
+    (KeyCode::KEY_RIGHTSHIFT, 0x3c),
+    (KeyCode::KEY_RIGHTALT, 0x3d),
+    (KeyCode::KEY_RIGHTCTRL, 0x3e),
+    (KeyCode::KEY_FN, 0x3f),
+    (KeyCode::KEY_F17, 0x40),
+    (KeyCode::KEY_KPDOT, 0x41),
+    (KeyCode::KEY_KPASTERISK, 0x43),
+    (KeyCode::KEY_KPPLUS, 0x45),
+    (KeyCode::KEY_NUMLOCK, 0x47),
+    (KeyCode::KEY_VOLUMEUP, 0x48),
+    (KeyCode::KEY_VOLUMEDOWN, 0x49),
+    (KeyCode::KEY_MUTE, 0x4a),
+    (KeyCode::KEY_KPSLASH, 0x4b),
+    (KeyCode::KEY_KPENTER, 0x4c),
+    (KeyCode::KEY_KPMINUS, 0x4e),
+    (KeyCode::KEY_F18, 0x4f),
+    (KeyCode::KEY_F19, 0x50),
+    (KeyCode::KEY_KPEQUAL, 0x51),
+    (KeyCode::KEY_KP0, 0x52),
+    (KeyCode::KEY_KP1, 0x53),
+    (KeyCode::KEY_KP2, 0x54),
+    (KeyCode::KEY_KP3, 0x55),
+    (KeyCode::KEY_KP4, 0x56),
+    (KeyCode::KEY_KP5, 0x57),
+    (KeyCode::KEY_KP6, 0x58),
+    (KeyCode::KEY_KP7, 0x59),
+    (KeyCode::KEY_F20, 0x5a),
+    (KeyCode::KEY_KP8, 0x5b),
+    (KeyCode::KEY_KP9, 0x5c),
+    (KeyCode::KEY_YEN, 0x5d),
+    (KeyCode::KEY_RO, 0x5e),
+    (KeyCode::KEY_KPCOMMA, 0x5f),
+    (KeyCode::KEY_F5, 0x60),
+    (KeyCode::KEY_F6, 0x61),
+    (KeyCode::KEY_F7, 0x62),
+    (KeyCode::KEY_F3, 0x63),
+    (KeyCode::KEY_F8, 0x64),
+    (KeyCode::KEY_F9, 0x65),
+    (KeyCode::KEY_MUHENKAN, 0x66),
+    (KeyCode::KEY_F11, 0x67),
+    (KeyCode::KEY_KATAKANAHIRAGANA, 0x68),
+    (KeyCode::KEY_F13, 0x69),
+    (KeyCode::KEY_F16, 0x6a),
+    (KeyCode::KEY_F14, 0x6b),
+    (KeyCode::KEY_F10, 0x6d),
+    (KeyCode::KEY_COMPOSE, 0x6e),
+    (KeyCode::KEY_F12, 0x6f),
+    (KeyCode::KEY_F15, 0x71),
+    (KeyCode::KEY_INSERT, 0x72),
+    (KeyCode::KEY_HELP, 0x72),
+    (KeyCode::KEY_HOME, 0x73),
+    (KeyCode::KEY_PAGEUP, 0x74),
+    (KeyCode::KEY_DELETE, 0x75),
+    (KeyCode::KEY_F4, 0x76),
+    (KeyCode::KEY_END, 0x77),
+    (KeyCode::KEY_F2, 0x78),
+    (KeyCode::KEY_PAGEDOWN, 0x79),
+    (KeyCode::KEY_F1, 0x7a),
+    (KeyCode::KEY_LEFT, 0x7b),
+    (KeyCode::KEY_RIGHT, 0x7c),
+    (KeyCode::KEY_DOWN, 0x7d),
+    (KeyCode::KEY_UP, 0x7e),
+];
+
+/// The macOS virtual key code of `key`, None when a Mac keyboard has
+/// no such key
+///
+/// Insert is the Help key of the older Mac keyboards, Num Lock is the
+/// keypad Clear and the Menu key opens the context menu.
+pub fn mac_key(key: KeyCode) -> Option<u16> {
+    KEYS.iter().find(|(k, _)| *k == key).map(|(_, code)| *code)
+}
+
+/// The event flag a modifier holds down, None for the other keys
+pub fn modifier_flag(code: u16) -> Option<u64> {
+    MODIFIERS
+        .iter()
+        .find(|(modifier, _)| *modifier == code)
+        .map(|(_, flag)| *flag)
+}
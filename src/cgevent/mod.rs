@@ -0,0 +1,288 @@
+pub mod keymap;
+
+use std::ffi::c_void;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::keycodes::{KeyCode, RelativeAxisType};
+
+/// kCGHIDEventTap, the events enter where the ones of the real devices do
+const HID_EVENT_TAP: u32 = 0;
+
+const LEFT_MOUSE_DOWN: u32 = 1;
+const LEFT_MOUSE_UP: u32 = 2;
+const RIGHT_MOUSE_DOWN: u32 = 3;
+const RIGHT_MOUSE_UP: u32 = 4;
+const MOUSE_MOVED: u32 = 5;
+const LEFT_MOUSE_DRAGGED: u32 = 6;
+const RIGHT_MOUSE_DRAGGED: u32 = 7;
+const FLAGS_CHANGED: u32 = 12;
+const OTHER_MOUSE_DOWN: u32 = 25;
+const OTHER_MOUSE_UP: u32 = 26;
+const OTHER_MOUSE_DRAGGED: u32 = 27;
+
+const MOUSE_EVENT_DELTA_X: u32 = 4;
+const MOUSE_EVENT_DELTA_Y: u32 = 5;
+const KEYBOARD_EVENT_AUTOREPEAT: u32 = 8;
+
+const SCROLL_UNIT_PIXEL: u32 = 0;
+const SCROLL_UNIT_LINE: u32 = 1;
+
+/// REL_WHEEL_HI_RES counts 120 for a detent, scrolled as this many pixels
+const PIXELS_PER_DETENT: i32 = 10;
+
+/// struct CGPoint, a position in the global display coordinates
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+type CGEventRef = *mut c_void;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventCreate(source: *const c_void) -> CGEventRef;
+    fn CGEventCreateKeyboardEvent(source: *const c_void, key: u16, down: bool) -> CGEventRef;
+    fn CGEventCreateMouseEvent(
+        source: *const c_void,
+        kind: u32,
+        position: CGPoint,
+        button: u32,
+    ) -> CGEventRef;
+    fn CGEventCreateScrollWheelEvent2(
+        source: *const c_void,
+        units: u32,
+        count: u32,
+        wheel1: i32,
+        wheel2: i32,
+        wheel3: i32,
+    ) -> CGEventRef;
+    fn CGEventGetLocation(event: CGEventRef) -> CGPoint;
+    fn CGEventSetType(event: CGEventRef, kind: u32);
+    fn CGEventSetFlags(event: CGEventRef, flags: u64);
+    fn CGEventSetIntegerValueField(event: CGEventRef, field: u32, value: i64);
+    fn CGEventPost(tap: u32, event: CGEventRef);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(cf: *const c_void);
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+}
+
+/// A created event, released on drop
+struct Event(CGEventRef);
+
+impl Event {
+    fn checked(event: CGEventRef) -> io::Result<Self> {
+        if event.is_null() {
+            return Err(io::Error::other("CoreGraphics could not create the event"));
+        }
+        Ok(Self(event))
+    }
+
+    fn keyboard(key: u16, down: bool) -> io::Result<Self> {
+        // SAFETY: a null source is allowed, the result is checked
+        Self::checked(unsafe { CGEventCreateKeyboardEvent(std::ptr::null(), key, down) })
+    }
+
+    fn mouse(kind: u32, position: CGPoint, button: u32) -> io::Result<Self> {
+        // SAFETY: a null source is allowed, the result is checked
+        Self::checked(unsafe { CGEventCreateMouseEvent(std::ptr::null(), kind, position, button) })
+    }
+
+    fn scroll(units: u32, vertical: i32, horizontal: i32) -> io::Result<Self> {
+        // SAFETY: a null source is allowed, the result is checked
+        Self::checked(unsafe {
+            CGEventCreateScrollWheelEvent2(std::ptr::null(), units, 2, vertical, horizontal, 0)
+        })
+    }
+
+    fn set_type(&self, kind: u32) {
+        // SAFETY: the event is valid until dropped
+        unsafe { CGEventSetType(self.0, kind) }
+    }
+
+    fn set_field(&self, field: u32, value: i64) {
+        // SAFETY: the event is valid until dropped
+        unsafe { CGEventSetIntegerValueField(self.0, field, value) }
+    }
+
+    /// Post the event with the modifiers held at the moment
+    fn post(self, held: &Held) {
+        // SAFETY: the event is valid until dropped
+        unsafe {
+            CGEventSetFlags(self.0, held.flags());
+            CGEventPost(HID_EVENT_TAP, self.0);
+        }
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        // SAFETY: the event was created by us and is released once
+        unsafe { CFRelease(self.0) }
+    }
+}
+
+/// The modifiers and the mouse buttons the posted events hold down
+///
+/// A posted event does not carry the keys pressed before it, the flags
+/// and the drag type of every event are taken from here. All the
+/// keyboards and pointers type into the same desktop and share it.
+struct Held {
+    modifiers: Vec<u16>,
+    buttons: Vec<u32>,
+}
+
+impl Held {
+    fn flags(&self) -> u64 {
+        self.modifiers
+            .iter()
+            .filter_map(|code| keymap::modifier_flag(*code))
+            .fold(0, |flags, flag| flags | flag)
+    }
+}
+
+static HELD: Mutex<Held> = Mutex::new(Held {
+    modifiers: Vec::new(),
+    buttons: Vec::new(),
+});
+
+fn held() -> MutexGuard<'static, Held> {
+    HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Remove one press of `item`, another device may still hold it
+fn release<T: PartialEq>(items: &mut Vec<T>, item: &T) {
+    if let Some(i) = items.iter().position(|i| i == item) {
+        items.remove(i);
+    }
+}
+
+/// Is the process allowed to post events? Without the Accessibility
+/// permission they are dropped silently.
+pub fn trusted() -> bool {
+    // SAFETY: no arguments, only reads the permission
+    unsafe { AXIsProcessTrusted() }
+}
+
+/// The current position of the pointer
+fn location() -> io::Result<CGPoint> {
+    // SAFETY: a null source is allowed, the result is checked
+    let event = Event::checked(unsafe { CGEventCreate(std::ptr::null()) })?;
+    // SAFETY: the event is valid until dropped
+    Ok(unsafe { CGEventGetLocation(event.0) })
+}
+
+/// Post a key event of the virtual key `code`. A modifier changes the flags
+/// instead and stays held for the following events until released.
+pub fn post_key(code: u16, down: bool, repeat: bool) -> io::Result<()> {
+    let modifier = keymap::modifier_flag(code).is_some();
+    if modifier && repeat {
+        // Modifiers do not repeat
+        return Ok(());
+    }
+
+    let event = Event::keyboard(code, down)?;
+    let mut held = held();
+    if modifier {
+        if down {
+            held.modifiers.push(code);
+        } else {
+            release(&mut held.modifiers, &code);
+        }
+        event.set_type(FLAGS_CHANGED);
+    }
+    if repeat {
+        event.set_field(KEYBOARD_EVENT_AUTOREPEAT, 1);
+    }
+    event.post(&held);
+    Ok(())
+}
+
+/// The CoreGraphics number of a mouse button, None for the buttons
+/// a Mac mouse does not have
+pub fn mouse_button(button: KeyCode) -> Option<u32> {
+    match button {
+        KeyCode::BTN_LEFT => Some(0),
+        KeyCode::BTN_RIGHT => Some(1),
+        KeyCode::BTN_MIDDLE => Some(2),
+        KeyCode::BTN_SIDE | KeyCode::BTN_BACK => Some(3),
+        KeyCode::BTN_EXTRA | KeyCode::BTN_FORWARD => Some(4),
+        _ => None,
+    }
+}
+
+/// Press or release the mouse button numbered `button` where the pointer is
+pub fn post_button(button: u32, down: bool) -> io::Result<()> {
+    let kind = match (button, down) {
+        (0, true) => LEFT_MOUSE_DOWN,
+        (0, false) => LEFT_MOUSE_UP,
+        (1, true) => RIGHT_MOUSE_DOWN,
+        (1, false) => RIGHT_MOUSE_UP,
+        (_, true) => OTHER_MOUSE_DOWN,
+        (_, false) => OTHER_MOUSE_UP,
+    };
+    let event = Event::mouse(kind, location()?, button)?;
+    let mut held = held();
+    if down {
+        held.buttons.push(button);
+    } else {
+        release(&mut held.buttons, &button);
+    }
+    event.post(&held);
+    Ok(())
+}
+
+/// Move the pointer, it drags while a button is held
+fn post_move(dx: i32, dy: i32) -> io::Result<()> {
+    let from = location()?;
+    let to = CGPoint {
+        x: from.x + f64::from(dx),
+        y: from.y + f64::from(dy),
+    };
+    let held = held();
+    let (kind, button) = match held.buttons.first() {
+        None => (MOUSE_MOVED, 0),
+        Some(0) => (LEFT_MOUSE_DRAGGED, 0),
+        Some(1) => (RIGHT_MOUSE_DRAGGED, 1),
+        Some(other) => (OTHER_MOUSE_DRAGGED, *other),
+    };
+    let event = Event::mouse(kind, to, button)?;
+    event.set_field(MOUSE_EVENT_DELTA_X, dx.into());
+    event.set_field(MOUSE_EVENT_DELTA_Y, dy.into());
+    event.post(&held);
+    Ok(())
+}
+
+fn post_scroll(units: u32, vertical: i32, horizontal: i32) -> io::Result<()> {
+    let event = Event::scroll(units, vertical, horizontal)?;
+    event.post(&held());
+    Ok(())
+}
+
+/// Move the pointer or turn a wheel along a relative axis, the axes a Mac
+/// mouse does not have are ignored
+pub fn post_rel(axis: RelativeAxisType, delta: i32) -> io::Result<()> {
+    match axis {
+        RelativeAxisType::REL_X => post_move(delta, 0),
+        RelativeAxisType::REL_Y => post_move(0, delta),
+        // Up is positive like in evdev, right is negative
+        RelativeAxisType::REL_WHEEL => post_scroll(SCROLL_UNIT_LINE, delta, 0),
+        RelativeAxisType::REL_HWHEEL => post_scroll(SCROLL_UNIT_LINE, 0, -delta),
+        RelativeAxisType::REL_WHEEL_HI_RES => {
+            post_scroll(SCROLL_UNIT_PIXEL, delta * PIXELS_PER_DETENT / 120, 0)
+        }
+        RelativeAxisType::REL_HWHEEL_HI_RES => {
+            post_scroll(SCROLL_UNIT_PIXEL, 0, -delta * PIXELS_PER_DETENT / 120)
+        }
+        _ => Ok(()),
+    }
+}
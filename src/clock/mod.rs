@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The source of the current time of the event processing
+///
+/// The change detector timestamps the reports and the layout engine runs
+/// its timers using it. Tests and the repl use a `ManualClock` to decide
+/// when the time moves.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The monotonic clock of the system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to
+///
+/// The clones share the time, so a test can keep one and give the others
+/// to the change detector and the layout engine.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    t: Arc<Mutex<Instant>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// A clock stopped at the current time
+    pub fn new() -> Self {
+        Self {
+            t: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Move the time forward, returns the new time
+    pub fn advance(&self, d: Duration) -> Instant {
+        let mut t = self.t.lock().unwrap();
+        *t += d;
+        *t
+    }
+
+    pub fn advance_ms(&self, ms: u64) -> Instant {
+        self.advance(Duration::from_millis(ms))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.t.lock().unwrap()
+    }
+}
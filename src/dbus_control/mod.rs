@@ -0,0 +1,223 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use zbus::blocking::{connection, Connection};
+use zbus::object_server::SignalContext;
+use zbus::{fdo, interface};
+
+use crate::layout::serialization::profile_layout_path;
+use crate::layout::types::LayerId;
+
+/// The well known name of the service on the session bus
+pub const BUS_NAME: &str = "org.kymars.Ack05";
+
+const OBJECT_PATH: &str = "/org/kymars/Ack05";
+
+/// A request received over D-Bus, the main loop applies it
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlRequest {
+    /// Load the layout of the named profile
+    SwitchProfile(String),
+    ActivateLayer(LayerId),
+    DeactivateLayer(LayerId),
+    /// Read the current layout file again
+    Reload,
+    /// Leave the keypad to the system and bypass the layout, or take it back
+    Passthrough(bool),
+}
+
+/// What the service reports about the running layout
+#[derive(Default)]
+struct Status {
+    profile: String,
+    /// Names of all layers, the unnamed ones are empty
+    layers: Vec<String>,
+    active: Vec<LayerId>,
+}
+
+impl Status {
+    /// Find a layer by its name, or by its index for the unnamed layers
+    fn layer_id(&self, name: &str) -> fdo::Result<LayerId> {
+        self.layers
+            .iter()
+            .position(|l| !l.is_empty() && l == name)
+            .or_else(|| name.parse().ok().filter(|idx| *idx < self.layers.len()))
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unknown layer {}", name)))
+    }
+
+    fn layer_name(&self, idx: LayerId) -> String {
+        match self.layers.get(idx) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => idx.to_string(),
+        }
+    }
+
+    fn active_names(&self) -> Vec<String> {
+        self.active.iter().map(|l| self.layer_name(*l)).collect()
+    }
+}
+
+/// A callback told about every queued request, eg. to wake the main loop up
+type RequestHook = Box<dyn FnMut() + Send>;
+
+struct ControlInterface {
+    requests: Sender<ControlRequest>,
+    status: Arc<Mutex<Status>>,
+    hooks: Arc<Mutex<Vec<RequestHook>>>,
+}
+
+impl ControlInterface {
+    fn send(&self, request: ControlRequest) -> fdo::Result<()> {
+        self.requests
+            .send(request)
+            .map_err(|_| fdo::Error::Failed("The driver is stopping".to_string()))?;
+        for hook in self.hooks.lock().unwrap().iter_mut() {
+            hook();
+        }
+        Ok(())
+    }
+
+    fn layer_id(&self, name: &str) -> fdo::Result<LayerId> {
+        self.status.lock().unwrap().layer_id(name)
+    }
+}
+
+#[interface(name = "org.kymars.Ack05")]
+impl ControlInterface {
+    /// Load the layout of the profile, `profiles/<name>.toml` next to the layout file
+    fn switch_profile(&self, name: String) -> fdo::Result<()> {
+        if name.is_empty() || name.starts_with('.') || name.contains('/') {
+            return Err(fdo::Error::InvalidArgs(format!("Invalid profile {}", name)));
+        }
+        let path = profile_layout_path(&name);
+        if !path.exists() {
+            return Err(fdo::Error::FileNotFound(path.display().to_string()));
+        }
+        self.send(ControlRequest::SwitchProfile(name))
+    }
+
+    fn activate_layer(&self, name: &str) -> fdo::Result<()> {
+        let idx = self.layer_id(name)?;
+        self.send(ControlRequest::ActivateLayer(idx))
+    }
+
+    fn deactivate_layer(&self, name: &str) -> fdo::Result<()> {
+        let idx = self.layer_id(name)?;
+        self.send(ControlRequest::DeactivateLayer(idx))
+    }
+
+    /// Read the layout file again
+    fn reload(&self) -> fdo::Result<()> {
+        self.send(ControlRequest::Reload)
+    }
+
+    /// Leave the keypad in its standard keyboard mode, or take it back.
+    /// The keypad cannot turn its own passthrough off.
+    fn passthrough(&self, enabled: bool) -> fdo::Result<()> {
+        self.send(ControlRequest::Passthrough(enabled))
+    }
+
+    #[zbus(property)]
+    fn profile(&self) -> String {
+        self.status.lock().unwrap().profile.clone()
+    }
+
+    /// All layers of the layout, by name or index
+    #[zbus(property)]
+    fn layers(&self) -> Vec<String> {
+        let status = self.status.lock().unwrap();
+        (0..status.layers.len())
+            .map(|l| status.layer_name(l))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn active_layers(&self) -> Vec<String> {
+        self.status.lock().unwrap().active_names()
+    }
+
+    /// The active layers changed, eg. for a widget showing the current layer
+    #[zbus(signal)]
+    async fn layer_changed(ctxt: &SignalContext<'_>, active: Vec<String>) -> zbus::Result<()>;
+}
+
+/// Control and introspection of the driver over the session bus
+///
+/// The requests are queued for the main loop, the same way the sleep
+/// inhibitor hands over its events. The main loop publishes the state
+/// of the layout back.
+pub struct DbusControl {
+    connection: Connection,
+    requests: Receiver<ControlRequest>,
+    status: Arc<Mutex<Status>>,
+    hooks: Arc<Mutex<Vec<RequestHook>>>,
+}
+
+impl DbusControl {
+    pub fn start() -> zbus::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let status = Arc::new(Mutex::new(Status::default()));
+        let hooks = Arc::new(Mutex::new(Vec::new()));
+        let iface = ControlInterface {
+            requests: tx,
+            status: status.clone(),
+            hooks: hooks.clone(),
+        };
+        let connection = connection::Builder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, iface)?
+            .build()?;
+
+        Ok(Self {
+            connection,
+            requests: rx,
+            status,
+            hooks,
+        })
+    }
+
+    /// Register a callback called after every queued request, so the main
+    /// loop does not have to poll for them
+    pub fn on_request<F>(&self, hook: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// Get the pending request if there is one
+    pub fn poll(&self) -> Option<ControlRequest> {
+        self.requests.try_recv().ok()
+    }
+
+    /// Publish a newly loaded layout of the `profile`, `layers` are the
+    /// names of its layers
+    pub fn set_layout(&self, profile: &str, layers: Vec<String>) {
+        let mut status = self.status.lock().unwrap();
+        status.profile = profile.to_string();
+        status.layers = layers;
+        status.active.clear();
+    }
+
+    /// Publish the active layers, LayerChanged is emitted when they changed
+    pub fn set_active_layers(&self, active: &[LayerId]) -> zbus::Result<()> {
+        let names = {
+            let mut status = self.status.lock().unwrap();
+            if status.active == active {
+                return Ok(());
+            }
+            status.active = active.to_vec();
+            status.active_names()
+        };
+
+        let iface = self
+            .connection
+            .object_server()
+            .interface::<_, ControlInterface>(OBJECT_PATH)?;
+        let ctxt = iface.signal_context();
+        zbus::block_on(async {
+            ControlInterface::layer_changed(ctxt, names).await?;
+            iface.get().active_layers_changed(ctxt).await
+        })
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::Value;
+
+use crate::layout::geometry::Geometry;
+use crate::layout::keys::KeyGroup;
+use crate::layout::layer::Layer;
+use crate::layout::types::{KeymapEvent, LayerId};
+
+/// Application name reported to the notification server
+const APPLICATION_NAME: &str = "xppen-ack05";
+
+/// How long the notification stays on the screen
+const EXPIRE_TIMEOUT_MS: i32 = 2000;
+
+/// On-screen notifications of the active layer using the freedesktop
+/// notification service
+///
+/// Every notification replaces the previous one, so quick layer changes
+/// do not pile up on the screen.
+pub struct DesktopNotifications {
+    proxy: Proxy<'static>,
+    /// The notification currently shown, 0 for none
+    last_id: u32,
+}
+
+impl DesktopNotifications {
+    pub fn open() -> zbus::Result<Self> {
+        let connection = Connection::session()?;
+        let proxy = Proxy::new(
+            &connection,
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+        )?;
+        Ok(Self { proxy, last_id: 0 })
+    }
+
+    /// Show `summary` with the `body` lines below it
+    pub fn show(&mut self, summary: &str, body: &[String]) -> zbus::Result<()> {
+        // Transient notifications are not kept in the history
+        let hints = HashMap::from([("transient", Value::Bool(true))]);
+        self.last_id = self.proxy.call(
+            "Notify",
+            &(
+                APPLICATION_NAME,
+                self.last_id,
+                "input-keyboard",
+                summary,
+                body.join("\n"),
+                Vec::<&str>::new(),
+                hints,
+                EXPIRE_TIMEOUT_MS,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+/// The name of the layer, or its index when it has none
+pub fn layer_title(layers: &[Layer], layer: LayerId) -> String {
+    match layers.get(layer) {
+        Some(l) if !l.name.is_empty() => l.name.clone(),
+        _ => layer.to_string(),
+    }
+}
+
+/// A short description of what the action does, None for the keys
+/// that do nothing
+fn describe_action(layers: &[Layer], ev: &KeymapEvent) -> Option<String> {
+    let layer = |l: &LayerId| format!("layer {}", layer_title(layers, *l));
+    let text = match ev.action() {
+        KeymapEvent::No | KeymapEvent::Inh | KeymapEvent::Pass => return None,
+        KeymapEvent::Kg(kg)
+        | KeymapEvent::Kmul(kg, _, _)
+        | KeymapEvent::Kturbo(kg, _, _)
+        | KeymapEvent::Ktiers(kg, _) => kg.to_string(),
+        KeymapEvent::Oneshot(kg) => format!("{} once", kg),
+        KeymapEvent::TapDance(taps) => {
+            let taps: Vec<String> = taps.iter().map(KeyGroup::to_string).collect();
+            taps.join(" / ")
+        }
+        KeymapEvent::CapsWord => "caps word".to_string(),
+        KeymapEvent::Klong(short, long) => format!("{} / {}", short, long),
+        KeymapEvent::Khl(kg, l) | KeymapEvent::Khtl(kg, l) => format!("{} / {}", kg, layer(l)),
+        KeymapEvent::LhtK(l, kg) => format!("{} / {}", layer(l), kg),
+        KeymapEvent::LhtL(hold, tap) => format!("{} / {}", layer(hold), layer(tap)),
+        KeymapEvent::Lmove(l)
+        | KeymapEvent::Lactivate(l)
+        | KeymapEvent::Ltoggle(l)
+        | KeymapEvent::Lhold(l)
+        | KeymapEvent::Ltap(l) => layer(l),
+        KeymapEvent::Ldeactivate(l) | KeymapEvent::Ldisable(l) => format!("{} off", layer(l)),
+        KeymapEvent::Mplay(name) => format!("macro {}", name),
+        KeymapEvent::Mrec(name) => format!("record {}", name),
+        KeymapEvent::Cmd(cmd) => format!("run {}", cmd),
+        KeymapEvent::Leader(..) => "leader".to_string(),
+        KeymapEvent::If(l, then, otherwise) => {
+            let branch = |ev| describe_action(layers, ev).unwrap_or_else(|| "nothing".to_string());
+            format!("{} in {} / {}", branch(then), layer(l), branch(otherwise))
+        }
+        KeymapEvent::IfHeld(k, then, otherwise) => {
+            let branch = |ev| describe_action(layers, ev).unwrap_or_else(|| "nothing".to_string());
+            format!(
+                "{} while {:?} held / {}",
+                branch(then),
+                k,
+                branch(otherwise)
+            )
+        }
+        ev => format!("{:?}", ev),
+    };
+    Some(text)
+}
+
+/// The bindings of the `layer`, one "label: action" line per key
+pub fn cheat_sheet(layers: &[Layer], layer: LayerId, geometry: &Geometry) -> Vec<String> {
+    let Some(l) = layers.get(layer) else {
+        return vec![];
+    };
+    l.positions()
+        .filter_map(|(coords, ev)| {
+            let action = describe_action(layers, ev)?;
+            let label = geometry
+                .label(coords)
+                .map_or_else(|| format!("{:?}", coords), str::to_string);
+            Some(format!("{}: {}", label, action))
+        })
+        .collect()
+}
@@ -0,0 +1,201 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::kbd_events::KeyEvent;
+use crate::keycodes::KeyCode;
+use crate::layout::types::KeyCoords;
+
+/// The key codes of a keyboard block, the codes of a regular keyboard
+/// fit in the 256 columns of its only row
+pub const KEYBOARD_KEYS: u16 = 256;
+
+/// How often the reader thread looks whether it should stop (ms)
+#[cfg(target_os = "linux")]
+const STOP_POLL_MS: i32 = 100;
+
+/// A regular keyboard of the host feeding the layout, configured by
+/// the `[[keyboards]]` sections of the layout
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardSource {
+    /// The event device, eg. /dev/input/by-id/usb-...-event-kbd
+    pub path: PathBuf,
+    /// Take the keyboard over, the applications see only what the layout
+    /// sends then
+    pub grab: bool,
+    /// The geometry block of its keys
+    pub block: u8,
+}
+
+impl KeyboardSource {
+    /// The position of `key` in the block of the keyboard, [block, 0, code].
+    /// None for the codes above the regular keys (eg. the mouse buttons).
+    pub fn coords(&self, key: KeyCode) -> Option<KeyCoords> {
+        (key.code() < KEYBOARD_KEYS).then(|| KeyCoords(self.block, 0, key.code() as u8))
+    }
+}
+
+/// The labels of a keyboard block, the names of the keys by their code
+pub fn keyboard_labels() -> Vec<String> {
+    (0..KEYBOARD_KEYS)
+        .map(|code| {
+            let name = format!("{:?}", KeyCode::new(code));
+            if name.starts_with("unknown") {
+                code.to_string()
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+/// Reads a keyboard of the host as another source of key events
+///
+/// The keyboard is read in its own thread, the presses and releases are only
+/// collected when `poll` is called. They keep the time they were read at. The autorepeat of the keyboard is dropped,
+/// the keys sent by the layout are repeated by the virtual keyboard. A grabbed
+/// keyboard is released when the input is dropped.
+pub struct EvdevInput {
+    events: Receiver<KeyEvent<KeyCoords>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EvdevInput {
+    /// Start reading the keyboard, `wake` is called after every key event,
+    /// eg. to wake the main loop up
+    pub fn open<F>(source: &KeyboardSource, wake: F) -> io::Result<Self>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let (tx, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = read_keyboard(source, tx, stop.clone(), wake)?;
+
+        Ok(Self {
+            events,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The next key event since the last call
+    pub fn poll(&self) -> Option<KeyEvent<KeyCoords>> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for EvdevInput {
+    /// Wait for the thread to close the device, so the keyboard can be
+    /// grabbed again right away, eg. by the reloaded layout
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Open the keyboard and send its key events to `tx` from a thread of its own
+#[cfg(target_os = "linux")]
+fn read_keyboard<F>(
+    source: &KeyboardSource,
+    tx: Sender<KeyEvent<KeyCoords>>,
+    stopped: Arc<AtomicBool>,
+    mut wake: F,
+) -> io::Result<JoinHandle<()>>
+where
+    F: FnMut() + Send + 'static,
+{
+    use std::thread;
+    use std::time::Instant;
+
+    use evdev::{Device, InputEventKind};
+    use tracing::{info, warn};
+
+    use crate::kbd_events::KeyStateChange;
+
+    let mut device = Device::open(&source.path)?;
+    if source.grab {
+        device.grab()?;
+    }
+    info!(
+        "Reading keyboard {} {:?} as block {}{}",
+        source.path.display(),
+        device.name(),
+        source.block,
+        if source.grab { ", grabbed" } else { "" }
+    );
+
+    let source = source.clone();
+    let thread = thread::spawn(move || {
+        while !stopped.load(Ordering::Relaxed) {
+            if !readable(&device) {
+                continue;
+            }
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("Keyboard {} is gone: {}", source.path.display(), e);
+                    return;
+                }
+            };
+            let t = Instant::now();
+            for ev in events {
+                let InputEventKind::Key(k) = ev.kind() else {
+                    continue;
+                };
+                let Some(coords) = source.coords(k.into()) else {
+                    continue;
+                };
+                let change = match ev.value() {
+                    0 => KeyStateChange::Released(coords),
+                    1 => KeyStateChange::Pressed(coords),
+                    // Autorepeat
+                    _ => continue,
+                };
+                if tx.send(KeyEvent::new(change, t)).is_err() {
+                    // Nobody is interested anymore
+                    return;
+                }
+                wake();
+            }
+        }
+    });
+    Ok(thread)
+}
+
+/// The host keyboards are evdev devices, they cannot be read elsewhere
+#[cfg(not(target_os = "linux"))]
+fn read_keyboard<F>(
+    _source: &KeyboardSource,
+    _tx: Sender<KeyEvent<KeyCoords>>,
+    _stopped: Arc<AtomicBool>,
+    _wake: F,
+) -> io::Result<JoinHandle<()>>
+where
+    F: FnMut() + Send + 'static,
+{
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Reading the host keyboards is only available on Linux",
+    ))
+}
+
+/// Wait at most the stop poll for the device to have events, so a reader
+/// thread can look whether it should stop
+#[cfg(target_os = "linux")]
+pub(crate) fn readable(device: &evdev::Device) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fd = libc::pollfd {
+        fd: device.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // An error (eg. EINTR) is retried with the next round
+    unsafe { libc::poll(&mut fd, 1, STOP_POLL_MS) > 0 }
+}
@@ -0,0 +1,87 @@
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+
+/// Size of the fixed part of struct inotify_event, the name follows it
+const EVENT_HEADER_SIZE: usize = std::mem::size_of::<libc::inotify_event>();
+
+/// Notices changes of a single file using inotify
+///
+/// The directory is watched instead of the file itself, editors usually
+/// save by writing a new file and renaming it over the old one, which would
+/// end a watch on the file. The file does not have to exist yet.
+pub struct FileWatcher {
+    inotify: File,
+    name: OsString,
+}
+
+impl FileWatcher {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file"))?
+            .to_os_string();
+
+        // SAFETY: inotify_init1 takes no pointers
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor is fresh and owned by nothing else
+        let inotify = unsafe { File::from_raw_fd(fd) };
+
+        let dir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE;
+        // SAFETY: the path is a valid zero terminated string
+        if unsafe { libc::inotify_add_watch(inotify.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { inotify, name })
+    }
+
+    /// Was the file written, replaced or removed since the last call?
+    /// Never blocks.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let len = match self.inotify.read(&mut buf) {
+                Ok(len) if len > 0 => len,
+                // Nothing more to read
+                _ => return changed,
+            };
+
+            let mut offset = 0;
+            while offset + EVENT_HEADER_SIZE <= len {
+                // SAFETY: the kernel writes whole events, the header is in the buffer
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr() as *const _) };
+                let name_start = offset + EVENT_HEADER_SIZE;
+                let name_end = (name_start + event.len as usize).min(len);
+
+                // The name is padded with zeros
+                let name = buf[name_start..name_end].split(|b| *b == 0).next();
+                changed |= name.is_some_and(|name| OsStr::from_bytes(name) == self.name);
+                offset = name_end;
+            }
+        }
+    }
+}
+
+/// The inotify descriptor turns readable when the file may have changed,
+/// eg. for an event loop waiting for it
+impl AsRawFd for FileWatcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
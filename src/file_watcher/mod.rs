@@ -0,0 +1,9 @@
+#[cfg(target_os = "linux")]
+mod inotify;
+#[cfg(target_os = "linux")]
+pub use inotify::FileWatcher;
+
+#[cfg(not(target_os = "linux"))]
+mod polling;
+#[cfg(not(target_os = "linux"))]
+pub use polling::FileWatcher;
@@ -0,0 +1,37 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Notices changes of a single file by looking at it on every call
+///
+/// Without inotify the modification time and the size are compared to the
+/// previous ones. A missing file has neither, so its creation and removal
+/// are noticed as well.
+pub struct FileWatcher {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+}
+
+impl FileWatcher {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            stamp: stamp(path),
+        })
+    }
+
+    /// Was the file written, replaced or removed since the last call?
+    /// Never blocks.
+    pub fn changed(&mut self) -> bool {
+        let stamp = stamp(&self.path);
+        let changed = stamp != self.stamp;
+        self.stamp = stamp;
+        changed
+    }
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
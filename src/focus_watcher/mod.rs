@@ -0,0 +1,249 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::layout::serialization::{default_layout_path, DEFAULT_PROFILE};
+
+/// Name of the file mapping the applications to the profiles
+const FOCUS_FILE: &str = "profiles.toml";
+
+/// How often the focused window is checked unless configured
+const DEFAULT_POLL_MS: u64 = 500;
+
+/// A source of the focused application
+///
+/// Desktops differ in how they tell which window has the focus, every one
+/// of them needs its own backend.
+pub trait FocusBackend: Send {
+    /// The window class or app id of the focused window, None when there
+    /// is none or it cannot be found out
+    fn focused_app(&mut self) -> Option<String>;
+}
+
+/// The X11 (and XWayland) WM_CLASS of the active window, using xprop
+pub struct X11Backend;
+
+impl FocusBackend for X11Backend {
+    fn focused_app(&mut self) -> Option<String> {
+        let active = run(Command::new("xprop").args(["-root", "_NET_ACTIVE_WINDOW"]))?;
+        let window = parse_active_window(&active)?;
+        let class = run(Command::new("xprop").args(["-id", &window, "WM_CLASS"]))?;
+        parse_wm_class(&class)
+    }
+}
+
+/// The class of the active window in Hyprland, using hyprctl
+pub struct HyprlandBackend;
+
+impl FocusBackend for HyprlandBackend {
+    fn focused_app(&mut self) -> Option<String> {
+        parse_hyprctl_class(&run(Command::new("hyprctl").arg("activewindow"))?)
+    }
+}
+
+/// Any other desktop, a shell command printing the app id of the focused window
+pub struct CommandBackend {
+    pub command: String,
+}
+
+impl FocusBackend for CommandBackend {
+    fn focused_app(&mut self) -> Option<String> {
+        let output = run(Command::new("sh").args(["-c", &self.command]))?;
+        Some(output.trim().to_string()).filter(|app| !app.is_empty())
+    }
+}
+
+/// The standard output of a successful `cmd`
+fn run(cmd: &mut Command) -> Option<String> {
+    let output = cmd.output().ok().filter(|o| o.status.success())?;
+    String::from_utf8(output.stdout).ok()
+}
+
+/// `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007` -> `0x3a00007`,
+/// None when no window is active
+pub(crate) fn parse_active_window(output: &str) -> Option<String> {
+    let id = output.split('#').nth(1)?.split(',').next()?.trim();
+    (id.starts_with("0x") && id != "0x0").then(|| id.to_string())
+}
+
+/// `WM_CLASS(STRING) = "krita", "krita"` -> `krita`, the class is the second string
+pub(crate) fn parse_wm_class(output: &str) -> Option<String> {
+    let (_, values) = output.split_once('=')?;
+    let mut strings = values.split('"').skip(1).step_by(2);
+    let instance = strings.next()?;
+    Some(strings.next().unwrap_or(instance).to_string())
+}
+
+/// The `class: ...` line of `hyprctl activewindow`
+pub(crate) fn parse_hyprctl_class(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("class:"))
+        .map(|class| class.trim().to_string())
+        .filter(|class| !class.is_empty())
+}
+
+/// Does `app` match the `pattern`? The pattern matches the whole app id,
+/// ignoring case, and `*` stands for any text.
+pub fn matches(pattern: &str, app: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let app = app.to_lowercase();
+    let mut parts = pattern.split('*');
+    // There is always at least one part
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = app.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum BackendConfig {
+    X11,
+    Hyprland,
+    Command { command: String },
+}
+
+impl BackendConfig {
+    fn open(&self) -> Box<dyn FocusBackend> {
+        match self {
+            BackendConfig::X11 => Box::new(X11Backend),
+            BackendConfig::Hyprland => Box::new(HyprlandBackend),
+            BackendConfig::Command { command } => Box::new(CommandBackend {
+                command: command.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct WatcherConfig {
+    #[serde(flatten)]
+    pub backend: BackendConfig,
+    pub poll_ms: Option<u64>,
+}
+
+/// Applications matching the pattern use the profile
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ProfileRule {
+    pub app: String,
+    pub profile: String,
+}
+
+/// The mapping of the focused applications to the profiles
+///
+/// ```toml
+/// [focus]
+/// backend = "x11"
+///
+/// [[rules]]
+/// app = "krita"
+/// profile = "krita"
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct FocusConfig {
+    pub focus: WatcherConfig,
+    #[serde(default)]
+    pub rules: Vec<ProfileRule>,
+    /// The profile of the applications no rule matches
+    pub default_profile: Option<String>,
+}
+
+impl FocusConfig {
+    /// The profile of `app`, the first matching rule wins
+    pub fn profile_for(&self, app: &str) -> &str {
+        self.rules
+            .iter()
+            .find(|rule| matches(&rule.app, app))
+            .map(|rule| rule.profile.as_str())
+            .or(self.default_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(source)
+    }
+
+    /// Load the configuration, None when there is no file and so no
+    /// automatic switching
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::parse(&source)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The default location, `profiles.toml` next to the layout file
+    pub fn default_path() -> PathBuf {
+        default_layout_path().with_file_name(FOCUS_FILE)
+    }
+}
+
+/// Switches the profiles following the focused application
+///
+/// The backend is polled in its own thread, the profile is reported
+/// only when it changes.
+pub struct FocusWatcher {
+    profiles: Receiver<String>,
+}
+
+impl FocusWatcher {
+    pub fn start(config: FocusConfig) -> Self {
+        Self::with_backend(config.focus.backend.open(), config)
+    }
+
+    /// Watch the focus using a `backend` the configuration cannot name
+    pub fn with_backend(mut backend: Box<dyn FocusBackend>, config: FocusConfig) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let poll = Duration::from_millis(config.focus.poll_ms.unwrap_or(DEFAULT_POLL_MS));
+
+        thread::spawn(move || {
+            let mut current: Option<String> = None;
+            loop {
+                // Nothing focused, eg. the desktop itself, keeps the profile
+                if let Some(app) = backend.focused_app() {
+                    let profile = config.profile_for(&app);
+                    if current.as_deref() != Some(profile) {
+                        debug!("Focused {}, profile {}", app, profile);
+                        current = Some(profile.to_string());
+                        if tx.send(profile.to_string()).is_err() {
+                            return;
+                        }
+                    }
+                }
+                thread::sleep(poll);
+            }
+        });
+
+        Self { profiles: rx }
+    }
+
+    /// The profile of the newly focused application, if it changed
+    pub fn poll(&self) -> Option<String> {
+        self.profiles.try_iter().last()
+    }
+}
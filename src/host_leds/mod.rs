@@ -0,0 +1,97 @@
+use std::collections::BTreeSet;
+
+#[cfg(target_os = "linux")]
+use evdev::Device;
+#[cfg(target_os = "linux")]
+use tracing::info;
+
+use crate::keycodes::{KeyCode, LedType};
+
+/// Name of our own virtual device, it must not be used as a LED source.
+/// Additional output devices use it as a prefix of their names.
+pub const VIRTUAL_KEYBOARD_NAME: &str = "XP-Pen ACK05 driver";
+
+/// Is this one of our own virtual output devices?
+#[cfg(target_os = "linux")]
+pub(crate) fn is_own_device(device: &Device) -> bool {
+    device
+        .name()
+        .is_some_and(|name| name.starts_with(VIRTUAL_KEYBOARD_NAME))
+}
+
+/// Host keyboards with Caps Lock / Num Lock indicators
+///
+/// The LED state is shared by all keyboards connected to the host,
+/// so reading any of them is enough, but all of them are consulted
+/// in case some device does not report its state properly.
+pub struct HostLeds {
+    #[cfg(target_os = "linux")]
+    devices: Vec<Device>,
+}
+
+#[cfg(target_os = "linux")]
+impl HostLeds {
+    pub fn open() -> Self {
+        let mut devices = Vec::new();
+
+        for (path, device) in evdev::enumerate() {
+            if is_own_device(&device) {
+                continue;
+            }
+
+            let has_leds = device.supported_leds().is_some_and(|leds| {
+                leds.contains(LedType::LED_NUML.into()) || leds.contains(LedType::LED_CAPSL.into())
+            });
+
+            if has_leds {
+                info!(
+                    "Reading LED state from {} {:?}",
+                    path.display(),
+                    device.name()
+                );
+                devices.push(device);
+            }
+        }
+
+        Self { devices }
+    }
+
+    /// Get the set of currently lit LEDs
+    pub fn read(&self) -> BTreeSet<LedType> {
+        let mut leds = BTreeSet::new();
+        for device in &self.devices {
+            if let Ok(state) = device.get_led_state() {
+                leds.extend(state.iter().map(LedType::from));
+            }
+        }
+        leds
+    }
+
+    /// Get the set of keys currently held on the host keyboards
+    pub fn read_keys(&self) -> BTreeSet<KeyCode> {
+        let mut keys = BTreeSet::new();
+        for device in &self.devices {
+            if let Ok(state) = device.get_key_state() {
+                keys.extend(state.iter().map(KeyCode::from));
+            }
+        }
+        keys
+    }
+}
+
+/// The LEDs of the host keyboards are only readable through evdev,
+/// elsewhere they all look off and no key looks held
+#[cfg(not(target_os = "linux"))]
+impl HostLeds {
+    pub fn open() -> Self {
+        Self {}
+    }
+
+    pub fn read(&self) -> BTreeSet<LedType> {
+        BTreeSet::new()
+    }
+
+    pub fn read_keys(&self) -> BTreeSet<KeyCode> {
+        BTreeSet::new()
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// Rotary encoder acceleration. A detent that quickly follows the previous
+/// one in the same direction is reported as a `Turn` worth several steps,
+/// so a fast spin covers a bigger distance than a slow one.
+pub struct RotaryAccelerator {
+    /// Position of the clockwise tick
+    cw: KeyCoords,
+    /// Position of the counter-clockwise tick
+    ccw: KeyCoords,
+    /// The steps of a detent following the previous one within the interval,
+    /// the shortest interval first
+    tiers: Vec<(Duration, u8)>,
+    /// The previous detent, true = clockwise
+    last: Option<(bool, Instant)>,
+}
+
+impl RotaryAccelerator {
+    /// Create an accelerator for rotary ticks reported as `cw` and `ccw`
+    /// clicks. A detent within the interval of a tier after the previous one
+    /// counts as the steps of the tier, the detents slower than all tiers
+    /// stay single clicks.
+    pub fn new<I>(cw: KeyCoords, ccw: KeyCoords, tiers: I) -> Self
+    where
+        I: IntoIterator<Item = (Duration, u8)>,
+    {
+        let mut tiers: Vec<(Duration, u8)> =
+            tiers.into_iter().filter(|(_, steps)| *steps > 1).collect();
+        tiers.sort();
+        Self {
+            cw,
+            ccw,
+            tiers,
+            last: None,
+        }
+    }
+
+    /// Replace the rotary clicks by turns with the steps of the current speed,
+    /// the other events pass unchanged
+    pub fn process(
+        &mut self,
+        ev: KeyStateChange<KeyCoords>,
+        t: Instant,
+    ) -> KeyStateChange<KeyCoords> {
+        let (k, cw) = match ev {
+            KeyStateChange::Click(k) if k == self.cw => (k, true),
+            KeyStateChange::Click(k) if k == self.ccw => (k, false),
+            _ => return ev,
+        };
+
+        // A reversal starts slow again
+        let interval = self
+            .last
+            .filter(|(dir, _)| *dir == cw)
+            .map(|(_, t0)| t.saturating_duration_since(t0));
+        self.last = Some((cw, t));
+
+        let steps = interval.and_then(|interval| {
+            self.tiers
+                .iter()
+                .find(|(within, _)| interval <= *within)
+                .map(|(_, steps)| *steps)
+        });
+        match steps {
+            Some(steps) => KeyStateChange::Turn(k, steps),
+            None => ev,
+        }
+    }
+}
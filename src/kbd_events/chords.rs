@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// The default time window all the keys of a chord have to be pressed in
+const CHORD_WINDOW: Duration = Duration::from_millis(50);
+
+/// Physical keys pressed together, reported at a logical position of its own
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chord {
+    pub keys: Vec<KeyCoords>,
+    pub coords: KeyCoords,
+}
+
+/// Resolves chords of physical keys before the layout sees them
+///
+/// When all the keys of a chord are pressed within the window, the chord
+/// is reported as a press of its own position instead, so it can be bound
+/// to an action the same way as any physical key. The chord is released
+/// with the first of its keys. Presses of the chord keys are held back for
+/// the window, a key that cannot form a chord passes right away.
+pub struct ChordResolver {
+    chords: Vec<Chord>,
+    window: Duration,
+    /// Presses of chord keys that may still complete a chord
+    pending: Vec<(KeyCoords, Instant)>,
+    /// Keys of the chords being held with the chord position, None once
+    /// the chord was released by another of its keys
+    held: Vec<(KeyCoords, Option<KeyCoords>)>,
+    /// Events ready to be passed on
+    ready: VecDeque<(KeyStateChange<KeyCoords>, Instant)>,
+}
+
+impl ChordResolver {
+    pub fn new(chords: Vec<Chord>) -> Self {
+        Self {
+            chords,
+            window: CHORD_WINDOW,
+            pending: Vec::new(),
+            held: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Configure the time window the keys of a chord have to be pressed in
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Process a key event, the resulting events are taken by `next`
+    pub fn process(&mut self, ev: KeyStateChange<KeyCoords>, t: Instant) {
+        let chord = |k: KeyCoords| {
+            self.held
+                .iter()
+                .find(|(key, _)| *key == k)
+                .map(|(_, chord)| *chord)
+        };
+        match ev {
+            KeyStateChange::Pressed(k) if self.chords.iter().any(|c| c.keys.contains(&k)) => {
+                self.press(k, t)
+            }
+            KeyStateChange::Released(k) if chord(k).is_some() => self.release(k, t),
+            KeyStateChange::LongPress(k, held) if chord(k).is_some() => {
+                // Only the first key of a held chord stands for it
+                if let Some(c) = chord(k).flatten() {
+                    let first = self.held.iter().find(|(_, chord)| *chord == Some(c));
+                    if first.is_some_and(|(first, _)| *first == k) {
+                        self.ready
+                            .push_back((KeyStateChange::LongPress(c, held), t));
+                    }
+                }
+            }
+            ev => {
+                self.flush();
+                self.ready.push_back((ev, t));
+            }
+        }
+    }
+
+    fn press(&mut self, k: KeyCoords, t: Instant) {
+        let mut keys: Vec<KeyCoords> = self.pending.iter().map(|(k, _)| *k).collect();
+        keys.push(k);
+        let possible = |keys: &[KeyCoords]| {
+            self.chords
+                .iter()
+                .any(|c| keys.iter().all(|k| c.keys.contains(k)))
+        };
+        if !possible(&keys) {
+            // The held back keys cannot form a chord with this one
+            self.flush();
+            keys = vec![k];
+        }
+        self.pending.push((k, t));
+
+        let complete = self
+            .chords
+            .iter()
+            .find(|c| c.keys.len() == keys.len() && keys.iter().all(|k| c.keys.contains(k)));
+        if let Some(chord) = complete {
+            let coords = chord.coords;
+            self.pending.clear();
+            self.held
+                .extend(keys.into_iter().map(|k| (k, Some(coords))));
+            self.ready.push_back((KeyStateChange::Pressed(coords), t));
+        }
+    }
+
+    fn release(&mut self, k: KeyCoords, t: Instant) {
+        let Some(idx) = self.held.iter().position(|(key, _)| *key == k) else {
+            return;
+        };
+        let (_, chord) = self.held.remove(idx);
+        if let Some(coords) = chord {
+            self.ready.push_back((KeyStateChange::Released(coords), t));
+            // The other keys of the chord are swallowed until released
+            for (_, chord) in self.held.iter_mut().filter(|(_, c)| *c == Some(coords)) {
+                *chord = None;
+            }
+        }
+    }
+
+    /// Pass the held back presses on as they are
+    fn flush(&mut self) {
+        for (k, t) in self.pending.drain(..) {
+            self.ready.push_back((KeyStateChange::Pressed(k), t));
+        }
+    }
+
+    /// Time tick, passes on the held back presses once the window is over
+    pub fn tick(&mut self, t: Instant) {
+        if self
+            .pending
+            .first()
+            .is_some_and(|(_, t0)| t - *t0 >= self.window)
+        {
+            self.flush();
+        }
+    }
+
+    /// Are presses held back? The caller has to keep ticking then.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Forget the held back presses, the held chords and the unread events,
+    /// eg. the releases were lost during a suspend
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.held.clear();
+        self.ready.clear();
+    }
+}
+
+/// The resolved events, in order
+impl Iterator for ChordResolver {
+    type Item = (KeyStateChange<KeyCoords>, Instant);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ready.pop_front()
+    }
+}
@@ -0,0 +1,68 @@
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// Number of positions of a full dial turn, the same as the touch rings
+/// of drawing tablets use
+pub const DIAL_POSITIONS: i32 = 72;
+
+/// Absolute axis emulation for a rotary encoder
+///
+/// The ticks are accumulated into a position that wraps around after
+/// a full turn, so applications that support tablet ring style controls
+/// get smooth values instead of discrete key presses.
+pub struct WheelDial {
+    /// Position of the clockwise tick
+    cw: KeyCoords,
+    /// Position of the counter-clockwise tick
+    ccw: KeyCoords,
+    /// Positions moved per tick
+    step: i32,
+    /// Number of positions of a full turn
+    positions: i32,
+    /// The current position, 0 <= value < positions
+    value: i32,
+}
+
+impl WheelDial {
+    /// Create a dial driven by rotary ticks reported as `cw` and `ccw` clicks
+    pub fn new(cw: KeyCoords, ccw: KeyCoords) -> Self {
+        Self {
+            cw,
+            ccw,
+            step: 1,
+            positions: DIAL_POSITIONS,
+            value: 0,
+        }
+    }
+
+    /// Configure the positions moved per tick and the positions of a full turn
+    pub fn set_resolution(&mut self, step: i32, positions: i32) {
+        self.positions = positions.max(1);
+        self.step = step;
+        self.value = self.value.rem_euclid(self.positions);
+    }
+
+    /// Number of positions of a full turn
+    pub fn positions(&self) -> i32 {
+        self.positions
+    }
+
+    /// The current position
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    /// Turn the dial when `ev` is a rotary tick and return the new position.
+    /// Other events are ignored.
+    pub fn turn(&mut self, ev: &KeyStateChange<KeyCoords>) -> Option<i32> {
+        let step = match ev {
+            KeyStateChange::Click(k) if *k == self.cw => self.step,
+            KeyStateChange::Click(k) if *k == self.ccw => -self.step,
+            _ => return None,
+        };
+
+        self.value = (self.value + step).rem_euclid(self.positions);
+        Some(self.value)
+    }
+}
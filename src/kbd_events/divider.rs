@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// The default pause after which the counted detents are forgotten
+pub const DIVIDER_IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Rotary encoder detent divider. Only every N-th detent in the same
+/// direction is passed on as a click, for the bindings where every detent
+/// is too sensitive, eg. a brush size.
+pub struct RotaryDivider {
+    /// Position of the clockwise tick
+    cw: KeyCoords,
+    /// Position of the counter-clockwise tick
+    ccw: KeyCoords,
+    /// Number of detents per passed click
+    detents: u8,
+    /// The counted detents are forgotten after this long without a detent
+    idle: Duration,
+    /// The direction of the counted detents (true = clockwise), their count
+    /// and the time of the last one
+    pending: Option<(bool, u8, Instant)>,
+}
+
+impl RotaryDivider {
+    /// Create a divider for rotary ticks reported as `cw` and `ccw` clicks
+    pub fn new(cw: KeyCoords, ccw: KeyCoords, detents: u8, idle: Duration) -> Self {
+        Self {
+            cw,
+            ccw,
+            detents: detents.max(1),
+            idle,
+            pending: None,
+        }
+    }
+
+    /// Count the rotary clicks and pass every N-th one on, the other
+    /// events pass unchanged
+    pub fn process(
+        &mut self,
+        ev: KeyStateChange<KeyCoords>,
+        t: Instant,
+    ) -> Option<KeyStateChange<KeyCoords>> {
+        let cw = match ev {
+            KeyStateChange::Click(k) if k == self.cw => true,
+            KeyStateChange::Click(k) if k == self.ccw => false,
+            _ => return Some(ev),
+        };
+
+        // A direction change or a pause starts counting anew
+        let count = match self.pending {
+            Some((dir, count, t0)) if dir == cw && t.saturating_duration_since(t0) <= self.idle => {
+                count + 1
+            }
+            _ => 1,
+        };
+        if count < self.detents {
+            self.pending = Some((cw, count, t));
+            return None;
+        }
+
+        self.pending = None;
+        Some(ev)
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// The default time window a gesture has to fit in
+pub const GESTURE_WINDOW: Duration = Duration::from_millis(300);
+/// The default number of same direction ticks forming a spin
+pub const SPIN_TICKS: usize = 4;
+/// The default number of alternating ticks forming a wiggle
+pub const WIGGLE_TICKS: usize = 2;
+
+/// Higher level rotary encoder gestures. Each gesture is reported
+/// as a Click of its own logical key, so it can be bound to an action
+/// the same way as any physical key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RotaryGesture {
+    /// Several consecutive ticks clockwise
+    SpinCW,
+    /// Several consecutive ticks counter-clockwise
+    SpinCCW,
+    /// A quick back and forth movement
+    Wiggle,
+}
+
+pub struct GestureDetector {
+    /// Position of the clockwise tick
+    cw: KeyCoords,
+    /// Position of the counter-clockwise tick
+    ccw: KeyCoords,
+    /// Logical positions reported for the recognized gestures
+    spin_cw: KeyCoords,
+    spin_ccw: KeyCoords,
+    wiggle: KeyCoords,
+    /// Number of same direction ticks forming a spin
+    spin_ticks: usize,
+    /// Number of alternating ticks forming a wiggle
+    wiggle_ticks: usize,
+    /// All the ticks of a gesture must fit in this window
+    window: Duration,
+    /// Recent ticks, true = clockwise
+    history: VecDeque<(bool, Instant)>,
+}
+
+impl GestureDetector {
+    /// Create a detector for rotary ticks reported as `cw` and `ccw` clicks.
+    /// `gestures` are the positions reported for spin CW, spin CCW and wiggle.
+    pub fn new(cw: KeyCoords, ccw: KeyCoords, gestures: [KeyCoords; 3]) -> Self {
+        Self {
+            cw,
+            ccw,
+            spin_cw: gestures[0],
+            spin_ccw: gestures[1],
+            wiggle: gestures[2],
+            spin_ticks: SPIN_TICKS,
+            wiggle_ticks: WIGGLE_TICKS,
+            window: GESTURE_WINDOW,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Configure the number of ticks forming a spin and a wiggle
+    /// and the time window they have to fit in
+    pub fn set_thresholds(&mut self, spin_ticks: usize, wiggle_ticks: usize, window: Duration) {
+        self.spin_ticks = spin_ticks.max(2);
+        self.wiggle_ticks = wiggle_ticks.max(2);
+        self.window = window;
+    }
+
+    /// Get the position a gesture is reported at
+    pub fn coords(&self, gesture: RotaryGesture) -> KeyCoords {
+        match gesture {
+            RotaryGesture::SpinCW => self.spin_cw,
+            RotaryGesture::SpinCCW => self.spin_ccw,
+            RotaryGesture::Wiggle => self.wiggle,
+        }
+    }
+
+    /// Observe a key event and return the gesture click it completed, if any.
+    /// The ticks themselves are not consumed.
+    pub fn process(
+        &mut self,
+        ev: &KeyStateChange<KeyCoords>,
+        t: Instant,
+    ) -> Option<KeyStateChange<KeyCoords>> {
+        let cw = match ev {
+            KeyStateChange::Click(k) if *k == self.cw => true,
+            KeyStateChange::Click(k) if *k == self.ccw => false,
+            _ => return None,
+        };
+
+        // Forget ticks that are too old to be part of a gesture
+        while let Some((_, t0)) = self.history.front() {
+            if t - *t0 > self.window {
+                self.history.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.history.push_back((cw, t));
+
+        let gesture = self.recognize()?;
+        self.history.clear();
+        Some(KeyStateChange::Click(self.coords(gesture)))
+    }
+
+    fn recognize(&self) -> Option<RotaryGesture> {
+        let spin = self.history.len() >= self.spin_ticks
+            && self
+                .history
+                .iter()
+                .rev()
+                .take(self.spin_ticks)
+                .all(|(dir, _)| *dir == self.history.back().unwrap().0);
+        if spin {
+            return if self.history.back().unwrap().0 {
+                Some(RotaryGesture::SpinCW)
+            } else {
+                Some(RotaryGesture::SpinCCW)
+            };
+        }
+
+        let last: Vec<bool> = self
+            .history
+            .iter()
+            .rev()
+            .take(self.wiggle_ticks)
+            .map(|(dir, _)| *dir)
+            .collect();
+        let wiggle = last.len() == self.wiggle_ticks && last.windows(2).all(|w| w[0] != w[1]);
+        if wiggle {
+            return Some(RotaryGesture::Wiggle);
+        }
+
+        None
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use crate::layout::types::KeyCoords;
+
+use super::panic::PanicChord;
+use super::KeyStateChange;
+
+/// How long the lock chord has to be held by default
+pub const LOCK_HOLD: Duration = Duration::from_secs(2);
+
+/// A callback told about the lock state, true when locked
+type LockHook = Box<dyn FnMut(bool) + Send>;
+
+/// Suspends the whole layout while the device is eg. cleaned
+///
+/// Holding all the chord keys together for the hold time locks the input,
+/// holding them again unlocks it. The caller drops all the key events while
+/// locked, only the lock (and the panic chord) still sees them.
+pub struct InputLock {
+    /// The chord is held the same way as the panic chord
+    chord: PanicChord,
+    locked: bool,
+    hooks: Vec<LockHook>,
+}
+
+impl InputLock {
+    pub fn new(keys: Vec<KeyCoords>, hold: Duration) -> Self {
+        Self {
+            chord: PanicChord::new(keys, hold),
+            locked: false,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Register a callback told about every lock and unlock, eg. for an OSD
+    pub fn on_lock_change<F>(&mut self, hook: F)
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Observe a key event
+    pub fn process(&mut self, ev: &KeyStateChange<KeyCoords>, t: Instant) {
+        self.chord.process(ev, t);
+    }
+
+    /// Lock or unlock when the chord is held long enough.
+    /// Returns true when the lock state changed.
+    pub fn tick(&mut self, t: Instant) -> bool {
+        if !self.chord.tick(t) {
+            return false;
+        }
+
+        self.locked = !self.locked;
+        for hook in self.hooks.iter_mut() {
+            hook(self.locked);
+        }
+        true
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Is the chord being held? The caller has to keep ticking then.
+    pub fn is_pending(&self) -> bool {
+        self.chord.is_pending()
+    }
+
+    /// Forget the held chord keys, the input stays locked or unlocked
+    pub fn reset(&mut self) {
+        self.chord.reset();
+    }
+}
@@ -1,14 +1,29 @@
-use core::time;
+pub(crate) mod acceleration;
+pub(crate) mod chords;
+pub(crate) mod dial;
+pub(crate) mod divider;
+pub(crate) mod gestures;
+pub(crate) mod lock;
+pub(crate) mod morse;
+pub(crate) mod panic;
+pub(crate) mod scanning;
+
 use enumset::{EnumSet, EnumSetType};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::hash::Hash;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+
+/// The default delay before a held key starts sending LongPress events,
+/// it is also the default hold threshold of the layouts
+pub const LONG_PRESS_THRESHOLD: Duration = Duration::from_millis(200);
 
 pub trait HasState {
     fn has_state(self) -> bool;
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum KeyStateChange<T> {
     /// Key was pressed and is held down
     Pressed(T),
@@ -16,20 +31,97 @@ pub enum KeyStateChange<T> {
     Released(T),
     /// Key does not support state and was triggered
     Click(T),
-    /// Key is still held down. This must be sent multiple
-    /// times after each long press timeout elapses if
-    /// the key is still in the pressed state.
-    LongPress(T),
+    /// Key is still held down for the given time since the press.
+    /// This must be sent multiple times after each long press
+    /// timeout elapses if the key is still in the pressed state.
+    LongPress(T, Duration),
+    /// A rotary key spun quickly, the click counts as the given number
+    /// of steps
+    Turn(T, u8),
+}
+
+impl<T> KeyStateChange<T> {
+    /// Convert the key of the event, eg. to `KeyCoords`
+    pub fn map<U, F>(self, f: F) -> KeyStateChange<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        match self {
+            KeyStateChange::Pressed(k) => KeyStateChange::Pressed(f(k)),
+            KeyStateChange::Released(k) => KeyStateChange::Released(f(k)),
+            KeyStateChange::Click(k) => KeyStateChange::Click(f(k)),
+            KeyStateChange::LongPress(k, held) => KeyStateChange::LongPress(f(k), held),
+            KeyStateChange::Turn(k, steps) => KeyStateChange::Turn(f(k), steps),
+        }
+    }
+}
+
+/// A key event of an input together with the time its report was read at
+///
+/// The time is taken by the thread reading the device and travels with the
+/// event, so the tap/hold decisions are not skewed by the reports waiting in
+/// the queue or by the processing of the earlier events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEvent<T> {
+    pub change: KeyStateChange<T>,
+    pub t: Instant,
+}
+
+impl<T> KeyEvent<T> {
+    pub fn new(change: KeyStateChange<T>, t: Instant) -> Self {
+        Self { change, t }
+    }
+
+    /// Convert the key of the event, eg. to `KeyCoords`
+    pub fn map<U, F>(self, f: F) -> KeyEvent<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        KeyEvent::new(self.change.map(f), self.t)
+    }
 }
 
 pub struct ChangeDetector<T>
 where
     T: EnumSetType + Hash,
 {
-    /// T -> time of press, short(F)/long(T)
-    state: HashMap<T, (Instant, bool)>,
-    /// Computed events that were not yet consumed
-    events: Vec<KeyStateChange<T>>,
+    /// T -> time of press, number of long press tiers reached
+    state: HashMap<T, (Instant, usize)>,
+    /// Computed events that were not yet consumed, in the order
+    /// they happened, with the time of the report they were detected in
+    events: VecDeque<KeyEvent<T>>,
+    /// T -> time of the last release
+    released: HashMap<T, Instant>,
+    /// Presses ignored as contact chatter, the keys are ignored
+    /// until they are released
+    chatter: EnumSet<T>,
+    /// Minimal time between a release and the next press of the same key
+    debounce: Duration,
+    /// Per key override of `debounce`
+    key_debounce: HashMap<T, Duration>,
+    /// Bounce keys, minimal time between two accepted presses of the same key
+    bounce: Duration,
+    /// T -> time of the last accepted press
+    last_press: HashMap<T, Instant>,
+    /// Slow keys, a press only counts after the key is held this long
+    slow_keys: Duration,
+    /// T -> time of a press that was not held long enough yet
+    pending: HashMap<T, Instant>,
+    /// The last analyzed input and its timestamp
+    last_input: Option<(EnumSet<T>, Instant)>,
+    /// Identical reports with stateless keys arriving within this
+    /// window are considered duplicates and discarded, zero keeps them
+    duplicate_window: Duration,
+    /// Ascending long press thresholds, LongPress events are sent
+    /// after the first one elapses
+    long_press_tiers: Vec<Duration>,
+    /// The two directions of a rotary encoder and the time window
+    /// a reversal is considered spurious in
+    reversal: Option<(T, T, Duration)>,
+    /// The last accepted rotary tick and its timestamp
+    last_tick: Option<(T, Instant)>,
+    /// Timestamps the reports passed to `analyze_now`
+    clock: Box<dyn Clock + Send>,
 }
 
 impl<T> ChangeDetector<T>
@@ -39,62 +131,273 @@ where
     pub fn new() -> Self {
         Self {
             state: HashMap::new(),
-            events: Vec::new(),
+            events: VecDeque::new(),
+            released: HashMap::new(),
+            chatter: EnumSet::empty(),
+            debounce: Duration::ZERO,
+            key_debounce: HashMap::new(),
+            bounce: Duration::ZERO,
+            last_press: HashMap::new(),
+            slow_keys: Duration::ZERO,
+            pending: HashMap::new(),
+            last_input: None,
+            duplicate_window: Duration::ZERO,
+            long_press_tiers: vec![LONG_PRESS_THRESHOLD],
+            reversal: None,
+            last_tick: None,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Take the time of `analyze_now` and `tick_now` from the `clock`
+    pub fn set_clock<C>(&mut self, clock: C)
+    where
+        C: Clock + Send + 'static,
+    {
+        self.clock = Box::new(clock);
+    }
+
+    /// The current time of the clock
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Configure the long press thresholds. LongPress events are sent
+    /// repeatedly once the shortest one elapses and the key is considered
+    /// short pressed until the longest one elapses.
+    pub fn set_long_press_tiers<I>(&mut self, tiers: I)
+    where
+        I: IntoIterator<Item = Duration>,
+    {
+        let mut tiers: Vec<Duration> = tiers.into_iter().collect();
+        tiers.sort();
+        tiers.dedup();
+        if tiers.is_empty() {
+            tiers.push(LONG_PRESS_THRESHOLD);
+        }
+        self.long_press_tiers = tiers;
+    }
+
+    /// Ignore presses that arrive sooner than `debounce` after the previous
+    /// release of the same key. Those are most likely caused by contact chatter.
+    pub fn set_debounce(&mut self, debounce: Duration) {
+        self.debounce = debounce;
+    }
+
+    /// Override the debounce time for key `k`
+    pub fn set_key_debounce(&mut self, k: T, debounce: Duration) {
+        self.key_debounce.insert(k, debounce);
+    }
+
+    /// Bounce keys accessibility filter: ignore presses of a key that arrive
+    /// sooner than `bounce` after its previous accepted press
+    pub fn set_bounce_keys(&mut self, bounce: Duration) {
+        self.bounce = bounce;
+    }
+
+    /// Slow keys accessibility filter: a press only counts once the key
+    /// was held for `slow_keys`, shorter presses are ignored completely
+    pub fn set_slow_keys(&mut self, slow_keys: Duration) {
+        self.slow_keys = slow_keys;
+    }
+
+    /// Set the window for detecting duplicate reports, zero (the default)
+    /// disables the detection. A fast spin of the wheel sends identical
+    /// reports as well, the window has to stay below the time between them.
+    pub fn set_duplicate_window(&mut self, window: Duration) {
+        self.duplicate_window = window;
+    }
+
+    /// Ignore a tick of the stateless key `cw` or `ccw` that arrives sooner
+    /// than `window` after a tick in the opposite direction. Cheap encoders
+    /// emit such ticks when the direction changes or the knob stops.
+    /// Zero window disables the filter.
+    pub fn set_reversal_filter(&mut self, cw: T, ccw: T, window: Duration) {
+        self.reversal = (!window.is_zero()).then_some((cw, ccw, window));
+    }
+
+    /// Is this tick a spurious reversal? Only accepted ticks are remembered,
+    /// so a single bad tick does not suppress the good ones around it.
+    fn is_reversal(&mut self, k: T, t: Instant) -> bool {
+        let Some((cw, ccw, window)) = self.reversal else {
+            return false;
+        };
+        if k != cw && k != ccw {
+            return false;
+        }
+
+        let reversed = self
+            .last_tick
+            .is_some_and(|(last, last_t)| last != k && t - last_t < window);
+        if !reversed {
+            self.last_tick = Some((k, t));
+        }
+        reversed
+    }
+
+    /// The device sometimes re-sends an identical report. This is harmless
+    /// for stateful keys, but stateless keys (the rotary encoder) would fire
+    /// twice for a single detent.
+    fn is_duplicate(&self, input: EnumSet<T>, t: Instant) -> bool {
+        let Some((last, last_t)) = self.last_input else {
+            return false;
+        };
+
+        last == input && input.iter().any(|k| !k.has_state()) && t - last_t < self.duplicate_window
+    }
+
+    /// Is this press of a stateful key just a contact chatter?
+    fn is_chatter(&self, k: T, t: Instant) -> bool {
+        let debounce = *self.key_debounce.get(&k).unwrap_or(&self.debounce);
+        let bounced = self
+            .last_press
+            .get(&k)
+            .is_some_and(|press_t| t - *press_t < self.bounce);
+        bounced
+            || self
+                .released
+                .get(&k)
+                .is_some_and(|released_t| t - *released_t < debounce)
+    }
+
+    /// Accept the slow key presses that were held long enough
+    fn accept_pending(&mut self, t: Instant) -> bool {
+        let accepted: Vec<T> = self
+            .pending
+            .iter()
+            .filter(|(_, press_t)| t - **press_t >= self.slow_keys)
+            .map(|(k, _)| *k)
+            .collect();
+
+        for k in &accepted {
+            self.pending.remove(k);
+            self.press(*k, t);
         }
+
+        !accepted.is_empty()
+    }
+
+    /// Record an accepted press of a stateful key
+    fn press(&mut self, k: T, t: Instant) {
+        self.events.push_back(KeyEvent::new(KeyStateChange::Pressed(k), t));
+        self.last_press.insert(k, t);
+        self.state.insert(k, (t, 0));
+    }
+
+    /// Forget all pressed keys and pending events, eg. after the system
+    /// was suspended or the device was re-initialized
+    pub fn reset(&mut self) {
+        self.state.clear();
+        self.events.clear();
+        self.released.clear();
+        self.chatter = EnumSet::empty();
+        self.last_press.clear();
+        self.pending.clear();
+        self.last_input = None;
+        self.last_tick = None;
+    }
+
+    /// Time tick at the current time of the clock
+    pub fn tick_now(&mut self) {
+        let t = self.now();
+        self.tick(t);
     }
 
     /// Time tick, checks for long presses
     pub fn tick(&mut self, t: Instant) {
+        self.accept_pending(t);
+
         let keys = Vec::from_iter(self.state.keys().map(|k| *k));
         for k in keys {
-            let (press_t, long_p) = self.state.get(&k).unwrap();
-            // check press timestamp and send LongPress
-            if t - *press_t > time::Duration::from_millis(200) {
-                self.events.push(KeyStateChange::LongPress(k));
-
-                if !long_p {
-                    // Update the record to indicate long press was already sent
-                    self.state.insert(k, (*press_t, true));
-                }
+            self.check_long_press(k, t);
+        }
+    }
+
+    /// Send LongPress when the key is held for longer than the first tier
+    /// and record the highest tier reached so far
+    fn check_long_press(&mut self, k: T, t: Instant) {
+        let (press_t, tier) = *self.state.get(&k).unwrap();
+        let elapsed = t - press_t;
+
+        // check press timestamp and send LongPress
+        if elapsed > self.long_press_tiers[0] {
+            self.events.push_back(KeyEvent::new(KeyStateChange::LongPress(k, elapsed), t));
+
+            let reached = self
+                .long_press_tiers
+                .iter()
+                .filter(|d| elapsed > **d)
+                .count();
+            if reached > tier {
+                // Update the record to indicate the tier was already reached
+                self.state.insert(k, (press_t, reached));
             }
         }
     }
 
+    /// Analyze keyboard state received at the current time of the clock
+    pub fn analyze_now(&mut self, input: EnumSet<T>) -> bool {
+        let t = self.now();
+        self.analyze(input, t)
+    }
+
     /// Analyze keyboard state and detect Press, Release and LongPress events
     /// Return true when new key is pressed so potentially a long press
     /// timer can be set up.
     pub fn analyze(&mut self, input: EnumSet<T>, t: Instant) -> bool {
         let mut new_presses_detected = false;
 
+        if self.is_duplicate(input, t) {
+            return false;
+        }
+        self.last_input = Some((input, t));
+
         // Retrieve released keys
         for k in self.state.keys() {
             if !input.contains(*k) && k.has_state() {
-                self.events.push(KeyStateChange::Released(*k))
+                self.events.push_back(KeyEvent::new(KeyStateChange::Released(*k), t));
+                self.released.insert(*k, t);
+            }
+        }
+
+        // Forget chattering keys once they are released
+        self.chatter &= input;
+
+        // Ignore contact chatter
+        let input = input - self.chatter;
+        for k in input {
+            if k.has_state() && !self.state.contains_key(&k) && self.is_chatter(k, t) {
+                self.chatter |= k;
+            }
+        }
+        let input = input - self.chatter;
+
+        // Slow keys, new presses wait until they are held long enough.
+        // Keys released before that are forgotten without any event.
+        self.pending.retain(|k, _| input.contains(*k));
+        if !self.slow_keys.is_zero() {
+            for k in input {
+                if k.has_state() && !self.state.contains_key(&k) && !self.pending.contains_key(&k) {
+                    self.pending.insert(k, t);
+                }
             }
+            new_presses_detected |= self.accept_pending(t);
         }
+        let input = input - self.pending.keys().copied().collect::<EnumSet<T>>();
 
         // Retrieve pressed keys
         for k in input {
             if !self.state.contains_key(&k) || !k.has_state() {
                 if k.has_state() {
-                    self.events.push(KeyStateChange::Pressed(k));
+                    self.press(k, t);
                     new_presses_detected = true;
-                } else {
-                    self.events.push(KeyStateChange::Click(k));
+                } else if !self.is_reversal(k, t) {
+                    self.events.push_back(KeyEvent::new(KeyStateChange::Click(k), t));
                 }
             }
 
             if self.state.contains_key(&k) && k.has_state() {
-                let (press_t, long_p) = self.state.get(&k).unwrap();
-                // check press timestamp and send LongPress
-                if t - *press_t > time::Duration::from_millis(200) {
-                    self.events.push(KeyStateChange::LongPress(k));
-
-                    if !long_p {
-                        // Update the record to indicate long press was already sent
-                        self.state.insert(k, (*press_t, true));
-                    }
-                }
+                self.check_long_press(k, t);
             }
         }
 
@@ -105,22 +408,44 @@ where
         // Insert all newly pressed keys with timestamp
         for k in input {
             if !self.state.contains_key(&k) {
-                self.state.insert(k, (t, false));
+                self.state.insert(k, (t, 0));
             }
         }
 
         return new_presses_detected;
     }
 
-    pub fn next(&mut self) -> Option<KeyStateChange<T>> {
-        self.events.pop()
+    /// Take all pending events, oldest first. The events are removed even
+    /// when the iterator is dropped before reaching the end.
+    pub fn drain(&mut self) -> impl Iterator<Item = KeyEvent<T>> + '_ {
+        self.events.drain(..)
     }
 
     pub fn has_pressed(&self) -> bool {
-        !self.state.is_empty()
+        !self.state.is_empty() || !self.pending.is_empty()
     }
 
+    /// Is any key held, but not yet past all the long press tiers?
+    /// Slow key presses that were not accepted yet count as well.
     pub fn has_short_pressed(&self) -> bool {
-        (&self.state).into_iter().any(|i| !i.1 .1)
+        !self.pending.is_empty()
+            || self
+                .state
+                .iter()
+                .any(|i| i.1 .1 < self.long_press_tiers.len())
+    }
+}
+
+/// The pending events, oldest first, together with the time they were
+/// detected at. Use that time for processing instead of the current time,
+/// so tap/hold decisions are not skewed by processing delays.
+impl<T> Iterator for ChangeDetector<T>
+where
+    T: EnumSetType + Hash,
+{
+    type Item = KeyEvent<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.pop_front()
     }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::keycodes::KeyCode;
+
+use crate::layout::keys::{KeyGroup, G};
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// Presses longer than this are dashes
+pub const DASH_THRESHOLD: Duration = Duration::from_millis(250);
+
+/// Silence after the last release that finishes a letter
+pub const LETTER_GAP: Duration = Duration::from_millis(600);
+
+/// International Morse code letters and digits
+const MORSE_CODE: [(&str, KeyCode); 36] = [
+    (".-", KeyCode::KEY_A),
+    ("-...", KeyCode::KEY_B),
+    ("-.-.", KeyCode::KEY_C),
+    ("-..", KeyCode::KEY_D),
+    (".", KeyCode::KEY_E),
+    ("..-.", KeyCode::KEY_F),
+    ("--.", KeyCode::KEY_G),
+    ("....", KeyCode::KEY_H),
+    ("..", KeyCode::KEY_I),
+    (".---", KeyCode::KEY_J),
+    ("-.-", KeyCode::KEY_K),
+    (".-..", KeyCode::KEY_L),
+    ("--", KeyCode::KEY_M),
+    ("-.", KeyCode::KEY_N),
+    ("---", KeyCode::KEY_O),
+    (".--.", KeyCode::KEY_P),
+    ("--.-", KeyCode::KEY_Q),
+    (".-.", KeyCode::KEY_R),
+    ("...", KeyCode::KEY_S),
+    ("-", KeyCode::KEY_T),
+    ("..-", KeyCode::KEY_U),
+    ("...-", KeyCode::KEY_V),
+    (".--", KeyCode::KEY_W),
+    ("-..-", KeyCode::KEY_X),
+    ("-.--", KeyCode::KEY_Y),
+    ("--..", KeyCode::KEY_Z),
+    ("-----", KeyCode::KEY_0),
+    (".----", KeyCode::KEY_1),
+    ("..---", KeyCode::KEY_2),
+    ("...--", KeyCode::KEY_3),
+    ("....-", KeyCode::KEY_4),
+    (".....", KeyCode::KEY_5),
+    ("-....", KeyCode::KEY_6),
+    ("--...", KeyCode::KEY_7),
+    ("---..", KeyCode::KEY_8),
+    ("----.", KeyCode::KEY_9),
+];
+
+/// Named actions that have no character of their own
+const MORSE_ACTIONS: [(&str, KeyCode); 3] = [
+    ("..--", KeyCode::KEY_SPACE),
+    (".-.-", KeyCode::KEY_ENTER),
+    ("----", KeyCode::KEY_BACKSPACE),
+];
+
+/// Morse-style input on a single button
+///
+/// Short presses of the designated key are dots, long presses dashes.
+/// A letter is finished once the key stays released for the letter gap
+/// and its code is then looked up in the code table.
+pub struct MorseDecoder {
+    /// The designated key, its events are consumed
+    key: KeyCoords,
+    /// Presses longer than this are dashes
+    dash: Duration,
+    /// Silence that finishes a letter
+    letter_gap: Duration,
+    /// Code -> keys to tap
+    table: HashMap<String, KeyGroup>,
+    /// Dots and dashes of the current letter
+    code: String,
+    /// Time of the press of the designated key, while it is held
+    pressed: Option<Instant>,
+    /// Time of the last release of the designated key
+    released: Option<Instant>,
+}
+
+impl MorseDecoder {
+    /// Create a decoder for `key` with the international Morse code table
+    pub fn new(key: KeyCoords) -> Self {
+        let table = MORSE_CODE
+            .iter()
+            .chain(MORSE_ACTIONS.iter())
+            .map(|(code, k)| (code.to_string(), G().k(*k)))
+            .collect();
+
+        Self {
+            key,
+            dash: DASH_THRESHOLD,
+            letter_gap: LETTER_GAP,
+            table,
+            code: String::new(),
+            pressed: None,
+            released: None,
+        }
+    }
+
+    /// Configure the dot/dash threshold and the letter gap
+    pub fn set_timing(&mut self, dash: Duration, letter_gap: Duration) {
+        self.dash = dash;
+        self.letter_gap = letter_gap;
+    }
+
+    /// Add or replace the keys tapped for `code`, eg. "-.-.-" -> Ctrl+S
+    pub fn set_code(&mut self, code: &str, keys: KeyGroup) {
+        self.table.insert(code.to_string(), keys);
+    }
+
+    /// All keycodes that can be emitted by the code table
+    pub fn get_used_keys(&self) -> Vec<KeyCode> {
+        self.table
+            .values()
+            .flat_map(|kg| kg.get_used_keys())
+            .collect()
+    }
+
+    /// Is a letter being entered? The caller has to keep ticking until it is finished.
+    pub fn is_composing(&self) -> bool {
+        self.pressed.is_some() || !self.code.is_empty()
+    }
+
+    /// Consume the events of the designated key. All other events
+    /// are returned unchanged.
+    pub fn process(
+        &mut self,
+        ev: KeyStateChange<KeyCoords>,
+        t: Instant,
+    ) -> Option<KeyStateChange<KeyCoords>> {
+        match ev {
+            KeyStateChange::Pressed(k) if k == self.key => {
+                self.pressed = Some(t);
+                None
+            }
+            KeyStateChange::Released(k) if k == self.key => {
+                if let Some(press_t) = self.pressed.take() {
+                    self.code
+                        .push(if t - press_t > self.dash { '-' } else { '.' });
+                    self.released = Some(t);
+                }
+                None
+            }
+            KeyStateChange::LongPress(k, _) | KeyStateChange::Click(k) if k == self.key => None,
+            _ => Some(ev),
+        }
+    }
+
+    /// Time tick, finishes the letter after the letter gap. Returns
+    /// the keys to tap or the code that is not in the table.
+    pub fn tick(&mut self, t: Instant) -> Option<Result<&KeyGroup, String>> {
+        if self.pressed.is_some() || self.code.is_empty() {
+            return None;
+        }
+
+        if self
+            .released
+            .is_some_and(|released_t| t - released_t < self.letter_gap)
+        {
+            return None;
+        }
+
+        let code = std::mem::take(&mut self.code);
+        Some(self.table.get(&code).ok_or(code))
+    }
+}
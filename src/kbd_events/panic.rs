@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// How long the panic chord has to be held by default
+pub const PANIC_HOLD: Duration = Duration::from_secs(2);
+
+/// Escape hatch that works with any layout
+///
+/// When all the chord keys are held together for the hold time, the caller
+/// is asked to release every emitted key, cancel the running macro and reset
+/// the layers. The keys are observed only, their events still reach the layout.
+pub struct PanicChord {
+    keys: Vec<KeyCoords>,
+    hold: Duration,
+    /// Chord keys currently held
+    held: HashSet<KeyCoords>,
+    /// When was the chord completed
+    since: Option<Instant>,
+    /// The panic was already reported for the current chord press
+    fired: bool,
+}
+
+impl PanicChord {
+    pub fn new(keys: Vec<KeyCoords>, hold: Duration) -> Self {
+        Self {
+            keys,
+            hold,
+            held: HashSet::new(),
+            since: None,
+            fired: false,
+        }
+    }
+
+    /// The ACK05 default, the top-left and the bottom-right buttons
+    pub fn ack05() -> Self {
+        Self::new(vec![KeyCoords(0, 0, 0), KeyCoords(0, 0, 9)], PANIC_HOLD)
+    }
+
+    /// Observe a key event
+    pub fn process(&mut self, ev: &KeyStateChange<KeyCoords>, t: Instant) {
+        match ev {
+            KeyStateChange::Pressed(k) if self.keys.contains(k) => {
+                self.held.insert(*k);
+                if self.held.len() == self.keys.len() {
+                    self.since = Some(t);
+                }
+            }
+            KeyStateChange::Released(k) if self.keys.contains(k) => {
+                self.held.remove(k);
+                self.since = None;
+                self.fired = false;
+            }
+            _ => {}
+        }
+    }
+
+    /// Is the chord held long enough? Reported only once per chord press.
+    pub fn tick(&mut self, t: Instant) -> bool {
+        if self.fired || self.keys.is_empty() {
+            return false;
+        }
+
+        self.fired = self.since.is_some_and(|since| t - since >= self.hold);
+        self.fired
+    }
+
+    /// Is the chord being held? The caller has to keep ticking then.
+    pub fn is_pending(&self) -> bool {
+        self.since.is_some() && !self.fired
+    }
+
+    /// Forget the held keys, eg. their releases were lost during a suspend
+    pub fn reset(&mut self) {
+        self.held.clear();
+        self.since = None;
+        self.fired = false;
+    }
+}
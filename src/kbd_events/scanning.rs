@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+use crate::layout::types::KeyCoords;
+
+use super::KeyStateChange;
+
+/// The default time each target stays highlighted
+pub const SCAN_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// Switch access scanning for single-switch users
+///
+/// The scanner cycles through the configured target positions on a timer.
+/// Pressing the switch key presses the highlighted target instead, so any
+/// action bound to the target can be selected with a single button. The
+/// highlight stays on the selected target while the switch is held, so
+/// hold actions keep working.
+pub struct SwitchScanner {
+    /// The key used to select the highlighted target
+    switch: KeyCoords,
+    /// Positions cycled through, their actions are taken from the layout
+    targets: Vec<KeyCoords>,
+    /// How long each target stays highlighted
+    interval: Duration,
+    /// Index of the highlighted target and the time it was highlighted at
+    highlighted: Option<(usize, Instant)>,
+    /// The target pressed by the switch, until the switch is released
+    selected: Option<KeyCoords>,
+}
+
+impl SwitchScanner {
+    pub fn new(switch: KeyCoords, targets: Vec<KeyCoords>) -> Self {
+        Self {
+            switch,
+            targets,
+            interval: SCAN_INTERVAL,
+            highlighted: None,
+            selected: None,
+        }
+    }
+
+    /// Configure how long each target stays highlighted
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// The currently highlighted target
+    pub fn highlighted(&self) -> Option<KeyCoords> {
+        self.highlighted.map(|(idx, _)| self.targets[idx])
+    }
+
+    /// Time tick, moves the highlight. Returns the newly highlighted
+    /// target, so it can be announced.
+    pub fn tick(&mut self, t: Instant) -> Option<KeyCoords> {
+        if self.targets.is_empty() || self.selected.is_some() {
+            return None;
+        }
+
+        let Some((idx, since)) = self.highlighted else {
+            self.highlighted = Some((0, t));
+            return self.highlighted();
+        };
+
+        if self.interval.is_zero() || t - since < self.interval {
+            return None;
+        }
+
+        // Skip the steps missed while nobody was ticking
+        let steps = ((t - since).as_millis() / self.interval.as_millis()) as u32;
+        let idx = (idx + steps as usize) % self.targets.len();
+        self.highlighted = Some((idx, since + self.interval * steps));
+        self.highlighted()
+    }
+
+    /// Translate the switch events to the events of the highlighted target.
+    /// All other events are returned unchanged.
+    pub fn process(
+        &mut self,
+        ev: KeyStateChange<KeyCoords>,
+        t: Instant,
+    ) -> Option<KeyStateChange<KeyCoords>> {
+        match ev {
+            KeyStateChange::Pressed(k) | KeyStateChange::Click(k) if k == self.switch => {
+                let target = self.highlighted()?;
+                if let KeyStateChange::Pressed(_) = ev {
+                    self.selected = Some(target);
+                } else {
+                    self.restart(t);
+                }
+                Some(ev.map(|_| target))
+            }
+            KeyStateChange::LongPress(k, held) if k == self.switch => {
+                self.selected.map(|s| KeyStateChange::LongPress(s, held))
+            }
+            KeyStateChange::Released(k) if k == self.switch => {
+                let target = self.selected.take()?;
+                self.restart(t);
+                Some(KeyStateChange::Released(target))
+            }
+            _ => Some(ev),
+        }
+    }
+
+    /// Start scanning from the first target again
+    fn restart(&mut self, t: Instant) {
+        self.highlighted = None;
+        self.tick(t);
+    }
+}
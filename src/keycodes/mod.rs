@@ -0,0 +1,156 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+/// Declare the named codes of a code type, parsed from and printed as
+/// their names the same way the evdev crate does it
+macro_rules! input_codes {
+    ($t:ident, $($c:ident = $val:expr,)*) => {
+        impl $t {
+            $(pub const $c: Self = Self($val);)*
+
+            /// The name of the code, None for the unknown ones
+            pub fn name(self) -> Option<&'static str> {
+                #[allow(unreachable_patterns)]
+                match self {
+                    $(Self::$c => Some(stringify!($c)),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl std::str::FromStr for $t {
+            type Err = $crate::keycodes::UnknownCode;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $(stringify!($c) => Ok(Self::$c),)*
+                    _ => Err($crate::keycodes::UnknownCode(s.to_string())),
+                }
+            }
+        }
+    };
+}
+
+mod codes;
+
+/// A key or a button, numbered like linux/input-event-codes.h
+///
+/// The engine uses it on every platform, the output backends translate
+/// it to the codes of the system.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyCode(pub u16);
+
+/// A relative axis of a pointer, eg. REL_X or REL_WHEEL
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RelativeAxisType(pub u16);
+
+/// An absolute axis of a gamepad or a dial, eg. ABS_X or ABS_WHEEL
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AbsoluteAxisType(pub u16);
+
+/// A keyboard LED, eg. LED_CAPSL
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LedType(pub u16);
+
+impl KeyCode {
+    #[inline]
+    pub const fn new(code: u16) -> Self {
+        Self(code)
+    }
+
+    #[inline]
+    pub const fn code(self) -> u16 {
+        self.0
+    }
+}
+
+/// A name that is not one of the codes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownCode(pub String);
+
+impl fmt::Display for UnknownCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown code {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownCode {}
+
+/// The code types printed and parsed by name
+macro_rules! named_codes {
+    ($($t:ident),*) => {$(
+        impl fmt::Debug for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self.name() {
+                    Some(name) => f.pad(name),
+                    None => write!(f, "unknown key: {}", self.0),
+                }
+            }
+        }
+
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let name = self
+                    .name()
+                    .ok_or_else(|| ser::Error::custom(format!("unknown code {}", self.0)))?;
+                serializer.serialize_str(name)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_str(CodeVisitor::<$t>(std::marker::PhantomData))
+            }
+        }
+    )*};
+}
+
+named_codes!(KeyCode, RelativeAxisType, AbsoluteAxisType, LedType);
+
+/// Deserializes a code from its name in any case, like the evdev crate
+struct CodeVisitor<T>(std::marker::PhantomData<T>);
+
+impl<T: FromStr> Visitor<'_> for CodeVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a string with the name of a code")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<T, E> {
+        T::from_str(&s.to_uppercase())
+            .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(s), &self))
+    }
+}
+
+/// The evdev types share the numbering, the Linux backends convert losslessly
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{AbsoluteAxisType, KeyCode, LedType, RelativeAxisType};
+
+    macro_rules! evdev_codes {
+        ($($t:ident => $evdev:ident),*) => {$(
+            impl From<$t> for evdev::$evdev {
+                fn from(code: $t) -> Self {
+                    evdev::$evdev(code.0)
+                }
+            }
+
+            impl From<evdev::$evdev> for $t {
+                fn from(code: evdev::$evdev) -> Self {
+                    $t(code.0)
+                }
+            }
+        )*};
+    }
+
+    evdev_codes!(
+        KeyCode => Key,
+        RelativeAxisType => RelativeAxisType,
+        AbsoluteAxisType => AbsoluteAxisType,
+        LedType => LedType
+    );
+}
@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use super::layer::Layer;
+use super::types::{
+    ActivationDebounce, KeyCoords, Keymap, KeymapEvent, LayerCondition, LayerId, LayerStatus,
+};
+use super::validation::LayoutError;
+
+/// Builds a layer key by key, for layouts made in code
+///
+/// ```ignore
+/// LayerBuilder::new()
+///     .name("tools")
+///     .inherits("base")
+///     .on_active([KeyCode::KEY_LEFTSHIFT])
+///     .key(KeyCoords(0, 0, 1), G().k(KeyCode::KEY_E).p())
+/// ```
+///
+/// The keys that are not set use the default action, `Pass` unless
+/// configured.
+#[derive(Clone)]
+pub struct LayerBuilder {
+    name: String,
+    status_on_reset: Option<LayerStatus>,
+    inherit: Option<String>,
+    on_active_keys: Vec<KeyCode>,
+    disable_active_on_press: bool,
+    on_timeout_layer: Option<String>,
+    timeout: Option<Duration>,
+    condition: Option<LayerCondition>,
+    activation_debounce: Option<ActivationDebounce>,
+    output: Option<String>,
+    keys: Vec<(KeyCoords, KeymapEvent)>,
+    default_action: KeymapEvent,
+}
+
+impl Default for LayerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LayerBuilder {
+    pub fn new() -> Self {
+        Self {
+            name: String::new(),
+            status_on_reset: None,
+            inherit: None,
+            on_active_keys: Vec::new(),
+            disable_active_on_press: false,
+            on_timeout_layer: None,
+            timeout: None,
+            condition: None,
+            activation_debounce: None,
+            output: None,
+            keys: Vec::new(),
+            default_action: KeymapEvent::Pass,
+        }
+    }
+
+    /// Name the layer, the other layers refer to it by the name
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// The status on reset, the first layer is active and the others
+    /// are passthrough by default
+    pub fn status(mut self, status: LayerStatus) -> Self {
+        self.status_on_reset = Some(status);
+        self
+    }
+
+    /// Resolve the `Inh` keys in the layer named `name`
+    pub fn inherits(mut self, name: &str) -> Self {
+        self.inherit = Some(name.to_string());
+        self
+    }
+
+    /// Hold the `keys` while the layer is active
+    pub fn on_active<I>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        self.on_active_keys.extend(keys);
+        self
+    }
+
+    /// Release the active keys when a key of the layer is pressed
+    pub fn disable_active_on_press(mut self) -> Self {
+        self.disable_active_on_press = true;
+        self
+    }
+
+    /// Leave the layer the `timeout` after it was entered
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Activate the layer named `name` when the timeout elapses
+    pub fn on_timeout(mut self, name: &str) -> Self {
+        self.on_timeout_layer = Some(name.to_string());
+        self
+    }
+
+    /// Activate and deactivate the layer following the host state
+    pub fn condition(mut self, condition: LayerCondition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// Ignore the presses for a while after the layer gets activated
+    pub fn activation_debounce(mut self, debounce: ActivationDebounce) -> Self {
+        self.activation_debounce = Some(debounce);
+        self
+    }
+
+    /// Send the keys of the layer through the named output device
+    pub fn output(mut self, name: &str) -> Self {
+        self.output = Some(name.to_string());
+        self
+    }
+
+    /// The action of the keys that are not set
+    pub fn default_action(mut self, ev: KeymapEvent) -> Self {
+        self.default_action = ev;
+        self
+    }
+
+    /// Bind the key `coords`, a key bound twice keeps the last action
+    pub fn key(mut self, coords: KeyCoords, ev: KeymapEvent) -> Self {
+        self.keys.retain(|(c, _)| *c != coords);
+        self.keys.push((coords, ev));
+        self
+    }
+
+    /// The dense keymap, the gaps between the bound keys are filled
+    /// with the default action
+    fn keymap(&self) -> Keymap {
+        let mut keymap: Keymap = Vec::new();
+        for (KeyCoords(b, r, c), ev) in &self.keys {
+            let (b, r, c) = (*b as usize, *r as usize, *c as usize);
+            if keymap.len() <= b {
+                keymap.resize(b + 1, Vec::new());
+            }
+            let block = &mut keymap[b];
+            if block.len() <= r {
+                block.resize(r + 1, Vec::new());
+            }
+            let row = &mut block[r];
+            if row.len() <= c {
+                row.resize(c + 1, self.default_action.clone());
+            }
+            row[c] = ev.clone();
+        }
+        keymap
+    }
+}
+
+/// Builds a layout out of layers that refer to each other by their names
+#[derive(Clone, Default)]
+pub struct LayoutBuilder {
+    layers: Vec<LayerBuilder>,
+}
+
+impl LayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next layer, the first one is the base layer
+    pub fn layer(mut self, layer: LayerBuilder) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    /// The index of the layer named `name`, for the actions switching to it
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.layers
+            .iter()
+            .position(|l| !l.name.is_empty() && l.name == name)
+    }
+
+    /// The layers, failing when a layer refers to an unknown layer name
+    pub fn build(self) -> Result<Vec<Layer>, LayoutError> {
+        let resolve = |layer: LayerId, name: &Option<String>| {
+            name.as_ref()
+                .map(|name| {
+                    self.layer_id(name)
+                        .ok_or_else(|| LayoutError::UnknownLayerName {
+                            layer,
+                            name: name.clone(),
+                        })
+                })
+                .transpose()
+        };
+
+        let mut layers = Vec::new();
+        for (idx, l) in self.layers.iter().enumerate() {
+            let status_on_reset = match l.status_on_reset {
+                Some(status) => status,
+                None if idx == 0 => LayerStatus::LayerActive,
+                None => LayerStatus::LayerPassthrough,
+            };
+            layers.push(Layer {
+                name: l.name.clone(),
+                status_on_reset,
+                inherit: resolve(idx, &l.inherit)?,
+                on_active_keys: l.on_active_keys.clone(),
+                disable_active_on_press: l.disable_active_on_press,
+                on_timeout_layer: resolve(idx, &l.on_timeout_layer)?,
+                timeout: l.timeout,
+                condition: l.condition,
+                activation_debounce: l.activation_debounce,
+                output: l.output.clone(),
+                keymap: l.keymap(),
+                default_action: l.default_action.clone(),
+            });
+        }
+        Ok(layers)
+    }
+}
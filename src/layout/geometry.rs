@@ -0,0 +1,147 @@
+use serde::Deserialize;
+
+use super::layer::Layer;
+use super::types::{KeyCoords, LayerId};
+
+/// Physical description of the device: which blocks, rows and columns exist
+/// and how the individual positions are called by humans.
+///
+/// The geometry is optional in layout files. When it is missing the ACK05
+/// geometry is assumed.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Geometry {
+    pub blocks: Vec<BlockGeometry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct BlockGeometry {
+    /// Human readable name of the block
+    #[serde(default)]
+    pub name: String,
+    /// Labels of all positions in the block, [Row, Col]
+    pub rows: Vec<Vec<String>>,
+    /// Keys in this block have no state, they only ever Click
+    /// (eg. rotary encoder pulses)
+    #[serde(default)]
+    pub stateless: bool,
+}
+
+impl Geometry {
+    /// The XP-Pen ACK05 layout
+    ///
+    /// ```text
+    /// ( CCW=1.0.0 )   [ 0 ][ 1 ][ 2 ][ 6 ]
+    /// (    ROT    )   [ 3 ][ 4 ][ 5 ][ _ ]
+    /// (  CW=1.0.1 )   [ 7 ][    8   ][ 9 ]
+    ///
+    /// or in the other orientation
+    ///
+    ///  [ 9 ][    8   ][ 7 ]
+    ///  [ 6 |[ 5 ][ 4 ][ 3 ]
+    ///  | _ ][ 2 ][ 1 ][ 0 ]  ( CCW=1.0.0 ROT CW=1.0.1 )
+    /// ```
+    ///
+    /// Block 0 holds the buttons 0-9, block 1 is the stateless rotary encoder
+    /// with CCW=(1, 0, 0) and CW=(1, 0, 1). The second row of the rotary
+    /// block holds the logical rotary gestures.
+    pub fn ack05() -> Self {
+        let labels = [
+            "top-left",
+            "top-middle",
+            "top-right",
+            "middle-left",
+            "middle-center",
+            "middle-right",
+            "top-far-right",
+            "bottom-left",
+            "bottom-wide",
+            "bottom-right",
+        ];
+        let wheel = ["wheel CCW", "wheel CW"];
+        let gestures = ["wheel spin CW", "wheel spin CCW", "wheel wiggle"];
+
+        Self {
+            blocks: vec![
+                BlockGeometry {
+                    name: "buttons".to_string(),
+                    rows: vec![labels.iter().map(|l| l.to_string()).collect()],
+                    stateless: false,
+                },
+                BlockGeometry {
+                    name: "wheel".to_string(),
+                    rows: vec![
+                        wheel.iter().map(|l| l.to_string()).collect(),
+                        gestures.iter().map(|l| l.to_string()).collect(),
+                    ],
+                    stateless: true,
+                },
+            ],
+        }
+    }
+
+    /// Does the key at `coords` only ever Click?
+    pub fn is_stateless(&self, coords: KeyCoords) -> bool {
+        self.blocks
+            .get(coords.0 as usize)
+            .is_some_and(|block| block.stateless)
+    }
+
+    /// Find hold and tap actions bound to stateless positions. Those can
+    /// never trigger, because a Click has no duration.
+    pub fn misplaced_hold_actions(&self, layers: &[Layer]) -> Vec<(LayerId, KeyCoords)> {
+        let mut misplaced = Vec::new();
+        for (l_idx, layer) in layers.iter().enumerate() {
+            for (coords, ev) in layer.positions() {
+                if ev.needs_state() && self.is_stateless(coords) {
+                    misplaced.push((l_idx, coords));
+                }
+            }
+        }
+        misplaced
+    }
+
+    /// Is `coords` a physically existing position?
+    pub fn contains(&self, coords: KeyCoords) -> bool {
+        self.label(coords).is_some()
+    }
+
+    /// Human readable label of the position `coords`
+    pub fn label(&self, coords: KeyCoords) -> Option<&str> {
+        self.blocks
+            .get(coords.0 as usize)
+            .and_then(|block| block.rows.get(coords.1 as usize))
+            .and_then(|row| row.get(coords.2 as usize))
+            .map(|l| l.as_str())
+    }
+
+    /// Find the position labeled `label`
+    pub fn find(&self, label: &str) -> Option<KeyCoords> {
+        for (b_idx, block) in self.blocks.iter().enumerate() {
+            for (r_idx, row) in block.rows.iter().enumerate() {
+                for (c_idx, l) in row.iter().enumerate() {
+                    if l == label {
+                        return Some(KeyCoords(b_idx as u8, r_idx as u8, c_idx as u8));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Iterate over all physical positions in block, row, column order
+    pub fn positions(&self) -> impl Iterator<Item = (KeyCoords, &str)> {
+        self.blocks.iter().enumerate().flat_map(|(b_idx, block)| {
+            block.rows.iter().enumerate().flat_map(move |(r_idx, row)| {
+                row.iter().enumerate().map(move |(c_idx, l)| {
+                    (KeyCoords(b_idx as u8, r_idx as u8, c_idx as u8), l.as_str())
+                })
+            })
+        })
+    }
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self::ack05()
+    }
+}
@@ -1,27 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::keycodes::KeyCode;
+
 use super::types::KeymapEvent;
 
-#[derive(Clone, Hash, Debug, PartialEq)]
+/// Short names of keys that are not a plain suffix of the KEY_ constant
+const KEY_ALIASES: &[(&str, KeyCode)] = &[
+    ("ctrl", KeyCode::KEY_LEFTCTRL),
+    ("control", KeyCode::KEY_LEFTCTRL),
+    ("shift", KeyCode::KEY_LEFTSHIFT),
+    ("alt", KeyCode::KEY_LEFTALT),
+    ("altgr", KeyCode::KEY_RIGHTALT),
+    ("meta", KeyCode::KEY_LEFTMETA),
+    ("super", KeyCode::KEY_LEFTMETA),
+    ("win", KeyCode::KEY_LEFTMETA),
+    ("return", KeyCode::KEY_ENTER),
+    ("del", KeyCode::KEY_DELETE),
+    ("ins", KeyCode::KEY_INSERT),
+    ("pgup", KeyCode::KEY_PAGEUP),
+    ("pgdn", KeyCode::KEY_PAGEDOWN),
+    ("-", KeyCode::KEY_MINUS),
+    ("=", KeyCode::KEY_EQUAL),
+    ("[", KeyCode::KEY_LEFTBRACE),
+    ("]", KeyCode::KEY_RIGHTBRACE),
+    (";", KeyCode::KEY_SEMICOLON),
+    ("'", KeyCode::KEY_APOSTROPHE),
+    ("`", KeyCode::KEY_GRAVE),
+    ("\\", KeyCode::KEY_BACKSLASH),
+    (",", KeyCode::KEY_COMMA),
+    (".", KeyCode::KEY_DOT),
+    ("/", KeyCode::KEY_SLASH),
+    ("play", KeyCode::KEY_PLAYPAUSE),
+    ("stop", KeyCode::KEY_STOPCD),
+    ("next", KeyCode::KEY_NEXTSONG),
+    ("prev", KeyCode::KEY_PREVIOUSSONG),
+    ("volup", KeyCode::KEY_VOLUMEUP),
+    ("voldown", KeyCode::KEY_VOLUMEDOWN),
+];
+
+/// A key name that does not match any key
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownKey(pub String);
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown key {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownKey {}
+
+/// Find a key by its name. The full constant name (KEY_F12, BTN_LEFT),
+/// the name without the KEY_ prefix in any case (f12, leftalt) and
+/// the common short names (ctrl, alt, del, -) are accepted.
+pub fn parse_key(name: &str) -> Result<KeyCode, UnknownKey> {
+    let name = name.trim();
+    if let Some((_, key)) = KEY_ALIASES.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(name)) {
+        return Ok(*key);
+    }
+
+    let upper = name.to_uppercase();
+    KeyCode::from_str(&upper)
+        .or_else(|_| KeyCode::from_str(&format!("KEY_{}", upper)))
+        .map_err(|_| UnknownKey(name.to_string()))
+}
+
+/// Serialized with the keys named like the evdev constants, eg. KEY_A
+#[derive(Clone, Hash, Debug, PartialEq, Serialize, Deserialize)]
 pub struct KeyGroup {
     /// Sequential or a group?
     pub(super) sequential: bool,
 
-    pub(super) keys: Vec<evdev::Key>,
+    pub(super) keys: Vec<KeyCode>,
 
     /// Key event with mask. First a key release event is sent for each mask key,
     /// then a click (press followed by release) of keys and at the end the mask
     /// is replayed as keypress events in reverse order (the same as Kg)
-    pub(super) mask: Vec<evdev::Key>,
+    pub(super) mask: Vec<KeyCode>,
 }
 
 impl KeyGroup {
-    pub fn get_used_keys(&self) -> Vec<evdev::Key> {
+    pub fn get_used_keys(&self) -> Vec<KeyCode> {
         let mut keys = Vec::new();
         keys.extend(&self.keys);
         keys.extend(&self.mask);
         keys
     }
 
-    pub fn k(self, ky: evdev::Key) -> Self {
+    pub fn k(self, ky: KeyCode) -> Self {
         let mut keys = Vec::from_iter(self.keys);
         keys.push(ky);
 
@@ -31,7 +100,7 @@ impl KeyGroup {
         }
     }
 
-    pub fn m(self, ky: evdev::Key) -> Self {
+    pub fn m(self, ky: KeyCode) -> Self {
         let mut mask = Vec::from_iter(self.mask);
         mask.push(ky);
 
@@ -46,6 +115,45 @@ impl KeyGroup {
     }
 }
 
+/// Keys joined by `+` are pressed together, eg. "ctrl+shift+a". Keys separated
+/// by spaces are typed one after another, eg. "h e l l o".
+impl FromStr for KeyGroup {
+    type Err = UnknownKey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let names: Vec<&str> = s.split_whitespace().collect();
+
+        let (group, names) = match names.len() {
+            0 => return Err(UnknownKey(s.to_string())),
+            1 => (G(), s.split('+').collect()),
+            _ => (S(), names),
+        };
+
+        names.into_iter()
+            .try_fold(group, |group, name| Ok(group.k(parse_key(name)?)))
+    }
+}
+
+/// The name `parse_key` accepts, the short one when there is one
+fn key_name(key: KeyCode) -> String {
+    match KEY_ALIASES.iter().find(|(_, k)| *k == key) {
+        Some((alias, _)) => alias.to_string(),
+        None => {
+            let name = format!("{:?}", key);
+            name.strip_prefix("KEY_").unwrap_or(&name).to_lowercase()
+        }
+    }
+}
+
+/// The group spelled the way `from_str` parses it, the mask is left out
+impl fmt::Display for KeyGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<String> = self.keys.iter().map(|k| key_name(*k)).collect();
+        f.write_str(&names.join(if self.sequential { " " } else { "+" }))
+    }
+}
+
 pub fn G() -> KeyGroup {
     KeyGroup {
         sequential: false,
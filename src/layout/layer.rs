@@ -1,11 +1,31 @@
 use std::time::Duration;
 
-use evdev::Key;
+use serde::{Deserialize, Serialize};
 
-use super::types::{KeyCoords, Keymap, KeymapEvent, LayerId, LayerStatus};
+use crate::keycodes::KeyCode;
 
-#[derive(Clone)]
+use super::types::{
+    ActivationDebounce, KeyCoords, Keymap, KeymapEvent, LayerCondition, LayerId, LayerStatus,
+};
+
+/// The last keycode of a regular keyboard (KEY_MICMUTE)
+const KEY_MAX_RECORDABLE: u16 = 248;
+
+/// The binding itself followed by the actions it wraps
+fn wrappers(ev: &KeymapEvent) -> impl Iterator<Item = &KeymapEvent> {
+    std::iter::successors(Some(ev), |ev| match ev {
+        KeymapEvent::Cooldown(inner, _)
+        | KeymapEvent::HoldThreshold(inner, _)
+        | KeymapEvent::Output(inner, _) => Some(inner),
+        _ => None,
+    })
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Layer {
+    // Name to reference the layer by in layout files, empty when unnamed
+    pub(crate) name: String,
+
     // Should be active on reset?
     pub(crate) status_on_reset: LayerStatus,
 
@@ -13,7 +33,7 @@ pub struct Layer {
     pub(crate) inherit: Option<LayerId>,
 
     // A key event to send when this layer is active
-    pub(crate) on_active_keys: Vec<Key>,
+    pub(crate) on_active_keys: Vec<KeyCode>,
 
     // Are active keys disabled when a key is pressed when the layer is active?
     pub(crate) disable_active_on_press: bool,
@@ -21,9 +41,18 @@ pub struct Layer {
     // A layer switch when timer expires
     pub(crate) on_timeout_layer: Option<LayerId>,
 
-    // Timeout to setup when layer is entered
+    // Timeout to setup when layer is entered, the layer is left when it expires
     pub(crate) timeout: Option<Duration>,
 
+    // External state (host LEDs) that activates and deactivates this layer
+    pub(crate) condition: Option<LayerCondition>,
+
+    // Presses to ignore for a while after this layer gets activated
+    pub(crate) activation_debounce: Option<ActivationDebounce>,
+
+    // Named output device for the keys of this layer, None is the main keyboard
+    pub(crate) output: Option<String>,
+
     // Keymap definition when this layer is active
     pub(crate) keymap: Keymap,
 
@@ -32,17 +61,70 @@ pub struct Layer {
 
 impl Layer {
     pub fn get_key_event(&self, coords: KeyCoords) -> &KeymapEvent {
+        self.get_binding(coords)
+            .map(KeymapEvent::action)
+            .unwrap_or(&self.default_action)
+    }
+
+    /// The binding of key `coords` including its wrappers (Cooldown, Output)
+    fn get_binding(&self, coords: KeyCoords) -> Option<&KeymapEvent> {
         self.keymap.get(coords.0 as usize)
             .and_then(|block| block.get(coords.1 as usize))
             .and_then(|row| row.get(coords.2 as usize))
-            .unwrap_or(&self.default_action)
     }
 
-    pub fn get_used_keys(&self) -> Vec<Key> {
+    /// The cooldown configured for key `coords`, if any
+    pub fn get_cooldown(&self, coords: KeyCoords) -> Option<Duration> {
+        wrappers(self.get_binding(coords)?).find_map(|ev| match ev {
+            KeymapEvent::Cooldown(_, cooldown) => Some(*cooldown),
+            _ => None,
+        })
+    }
+
+    /// The hold threshold configured for key `coords`, if any
+    pub fn get_hold_threshold(&self, coords: KeyCoords) -> Option<Duration> {
+        wrappers(self.get_binding(coords)?).find_map(|ev| match ev {
+            KeymapEvent::HoldThreshold(_, threshold) => Some(*threshold),
+            _ => None,
+        })
+    }
+
+    /// The output device the binding of key `coords` is routed to, if any
+    pub fn get_output(&self, coords: KeyCoords) -> Option<&str> {
+        wrappers(self.get_binding(coords)?).find_map(|ev| match ev {
+            KeymapEvent::Output(_, output) => Some(output.as_str()),
+            _ => None,
+        })
+    }
+
+    /// All named output devices this layer routes to
+    pub fn get_outputs(&self) -> Vec<&str> {
+        let bindings = self.keymap.iter().flatten().flatten();
+        let routed = bindings.filter_map(|ev| {
+            wrappers(ev).find_map(|ev| match ev {
+                KeymapEvent::Output(_, output) => Some(output.as_str()),
+                _ => None,
+            })
+        });
+        self.output.iter().map(String::as_str).chain(routed).collect()
+    }
+
+    /// Iterate over all keymap positions and their events
+    pub fn positions(&self) -> impl Iterator<Item = (KeyCoords, &KeymapEvent)> {
+        self.keymap.iter().enumerate().flat_map(|(b_idx, block)| {
+            block.iter().enumerate().flat_map(move |(r_idx, row)| {
+                row.iter().enumerate().map(move |(c_idx, ev)| {
+                    (KeyCoords(b_idx as u8, r_idx as u8, c_idx as u8), ev.action())
+                })
+            })
+        })
+    }
+
+    pub fn get_used_keys(&self) -> Vec<KeyCode> {
         let mut keys = Vec::new();
         for b in &self.keymap {
             for r in b {
-                for ev in r {
+                for ev in r.iter().flat_map(KeymapEvent::actions) {
                     match ev {
                         KeymapEvent::No => {},
                         KeymapEvent::Inh => {},
@@ -52,10 +134,28 @@ impl Layer {
                             keys.extend(k_s.get_used_keys());
                             keys.extend(k_l.get_used_keys());
                         },
+                        KeymapEvent::Ktiers(k, tiers) => {
+                            keys.extend(k.get_used_keys());
+                            for (_, k_t) in tiers {
+                                keys.extend(k_t.get_used_keys());
+                            }
+                        },
+                        KeymapEvent::Kmul(k, _, _) => keys.extend(k.get_used_keys()),
+                        KeymapEvent::Kturbo(k, _, _) => keys.extend(k.get_used_keys()),
+                        KeymapEvent::TapDance(taps) => {
+                            for k in taps {
+                                keys.extend(k.get_used_keys());
+                            }
+                        },
+                        KeymapEvent::Oneshot(k) => keys.extend(k.get_used_keys()),
+                        KeymapEvent::CapsWord => keys.push(KeyCode::KEY_LEFTSHIFT),
                         KeymapEvent::Khtl(k, _) => keys.extend(k.get_used_keys()),
                         KeymapEvent::Khl(k, _) => keys.extend(k.get_used_keys()),
 
                         KeymapEvent::LhtK(_, k) => keys.extend(k.get_used_keys()),
+                        KeymapEvent::Pbtn(k) => keys.push(*k),
+                        // Anything can be recorded, register the whole keyboard
+                        KeymapEvent::Mrec(_) => keys.extend((1..=KEY_MAX_RECORDABLE).map(KeyCode::new)),
                         _ => {}
                     }
                 }
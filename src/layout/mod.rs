@@ -1,6 +1,9 @@
-pub mod types;
-pub mod serialization;
-pub mod process;
-pub mod layer;
-pub mod switcher;
-pub mod keys;
+pub(crate) mod types;
+pub(crate) mod serialization;
+pub(crate) mod process;
+pub(crate) mod layer;
+pub(crate) mod switcher;
+pub(crate) mod keys;
+pub(crate) mod geometry;
+pub(crate) mod validation;
+pub(crate) mod builder;
@@ -1,27 +1,867 @@
-use evdev::Key;
-use toml;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use super::keys::{G, S};
+use serde::{de, Deserialize};
+
+use crate::evdev_input::{keyboard_labels, KeyboardSource};
+use crate::kbd_events::chords::Chord;
+use crate::kbd_events::dial::DIAL_POSITIONS;
+use crate::kbd_events::divider::DIVIDER_IDLE_TIMEOUT;
+use crate::kbd_events::gestures::{GESTURE_WINDOW, SPIN_TICKS, WIGGLE_TICKS};
+use crate::kbd_events::lock::LOCK_HOLD;
+use crate::kbd_events::morse::{DASH_THRESHOLD, LETTER_GAP};
+use crate::kbd_events::panic::PANIC_HOLD;
+use crate::kbd_events::scanning::SCAN_INTERVAL;
+use crate::keycodes::{AbsoluteAxisType, KeyCode, LedType};
+use crate::macros::{Macro, MacroLibrary};
+use crate::xppen_hid::report_map::{KeyboardReportMap, ReportMap};
+
+use super::geometry::{BlockGeometry, Geometry};
+use super::keys::{parse_key, KeyGroup, UnknownKey, G};
 use super::layer::Layer;
+use super::types::{
+    ActivationDebounce, DialOutput, KeyCoords, KeyRepeat, KeymapEvent, LayerCondition, LayerId,
+    LayerStatus, LayoutSettings, LeaderSequence, LongPressRace, MorseInput, TapHold,
+};
 use super::types::KeymapEvent::{
     Inh, Kg, Klong, Lactivate, Ldisable, Lhold, LhtK, Lmove, Ltap, No, Pass,
 };
 
-/*
+/// Name of the layout file in the configuration directory
+const LAYOUT_FILE: &str = "layout.toml";
+
+/// Directory next to the layout file holding the named profiles
+const PROFILE_DIR: &str = "profiles";
+
+/// The profile using the default layout file
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The sections of a layout file
+#[derive(Deserialize)]
+struct LayoutSections {
+    #[serde(default)]
+    layers: Vec<LayerDef>,
+    geometry: Option<Geometry>,
+    #[serde(default)]
+    macros: Vec<Macro>,
+    report: Option<ReportMap>,
+    fallback: Option<KeyboardReportMap>,
+    #[serde(default)]
+    settings: SettingsDef,
+    #[serde(default)]
+    chords: Vec<ChordDef>,
+    #[serde(default)]
+    keyboards: Vec<KeyboardDef>,
+}
+
+/// One `[[chords]]` section, `keys` are [block, row, column] like the keymap
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChordDef {
+    keys: Vec<(u8, u8, u8)>,
+    /// Label of the chord position, the labels of its keys by default
+    name: Option<String>,
+}
+
+impl ChordDef {
+    fn keys(&self) -> Vec<KeyCoords> {
+        self.keys.iter().map(|(b, r, c)| KeyCoords(*b, *r, *c)).collect()
+    }
+}
+
+/// One `[[keyboards]]` section, a regular keyboard feeding the layout
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyboardDef {
+    path: PathBuf,
+    #[serde(default)]
+    grab: bool,
+    /// Name of its block, the file name of the device by default
+    name: Option<String>,
+}
+
+/// The `[settings]` section
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsDef {
+    hold_threshold_ms: Option<u64>,
+    key_repeat: Option<KeyRepeatDef>,
+    tap_hold: Option<TapHoldDef>,
+    #[serde(default)]
+    layer_notifications: bool,
+    #[serde(default)]
+    cheat_sheet: bool,
+    #[serde(default)]
+    scancodes: bool,
+    #[serde(default)]
+    rotary_acceleration: Vec<RotaryAccelerationDef>,
+    rotary_divider: Option<RotaryDividerDef>,
+    idle_timeout_ms: Option<u64>,
+    input_lock: Option<InputLockDef>,
+    device_path: Option<String>,
+    device_serial: Option<String>,
+    debounce_ms: Option<u64>,
+    #[serde(default)]
+    key_debounce: Vec<KeyDebounceDef>,
+    duplicate_window_ms: Option<u64>,
+    long_press_race: Option<LongPressRaceDef>,
+    rotary_gestures: Option<RotaryGesturesDef>,
+    bounce_keys_ms: Option<u64>,
+    slow_keys_ms: Option<u64>,
+    scanning: Option<ScanningDef>,
+    morse: Option<MorseDef>,
+    panic_chord: Option<PanicChordDef>,
+    rotary_reversal_ms: Option<u64>,
+    dial: Option<DialDef>,
+    kernel_repeat: Option<KeyRepeatDef>,
+    watchdog_try_again: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyRepeatDef {
+    delay_ms: u64,
+    period_ms: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotaryAccelerationDef {
+    within_ms: u64,
+    steps: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotaryDividerDef {
+    detents: u8,
+    idle_ms: Option<u64>,
+}
+
+/// `{ spin_ticks = 4, wiggle_ticks = 2, window_ms = 300 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotaryGesturesDef {
+    spin_ticks: Option<usize>,
+    wiggle_ticks: Option<usize>,
+    window_ms: Option<u64>,
+}
+
+/// `{ switch = [0, 0, 9], targets = [[0, 0, 0], [0, 0, 1]], interval_ms = 1500 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScanningDef {
+    switch: (u8, u8, u8),
+    targets: Vec<(u8, u8, u8)>,
+    interval_ms: Option<u64>,
+}
+
+/// `{ key = [0, 0, 9], dash_ms = 250, letter_gap_ms = 600, codes = [...] }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MorseDef {
+    key: (u8, u8, u8),
+    dash_ms: Option<u64>,
+    letter_gap_ms: Option<u64>,
+    #[serde(default)]
+    codes: Vec<MorseCodeDef>,
+}
+
+/// `{ code = "-.-.-", keys = "ctrl+s" }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MorseCodeDef {
+    code: String,
+    keys: KeysDef,
+}
+
+/// `{ key = [0, 0, 3], ms = 40 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyDebounceDef {
+    key: (u8, u8, u8),
+    ms: u64,
+}
+
+/// `{ keys = [[0, 0, 0], [0, 0, 9]], hold_ms = 2000 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InputLockDef {
+    keys: Vec<(u8, u8, u8)>,
+    hold_ms: Option<u64>,
+}
+
+/// `dial = { axis = "ABS_WHEEL", step = 1, positions = 72 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DialDef {
+    axis: Option<DialAxisDef>,
+    step: Option<i32>,
+    positions: Option<i32>,
+}
+
+#[derive(Deserialize)]
+enum DialAxisDef {
+    #[serde(rename = "ABS_WHEEL")]
+    Wheel,
+    #[serde(rename = "ABS_MISC")]
+    Misc,
+}
+
+impl From<DialAxisDef> for AbsoluteAxisType {
+    fn from(axis: DialAxisDef) -> Self {
+        match axis {
+            DialAxisDef::Wheel => AbsoluteAxisType::ABS_WHEEL,
+            DialAxisDef::Misc => AbsoluteAxisType::ABS_MISC,
+        }
+    }
+}
+
+/// `panic_chord = { keys = [[0, 0, 0], [0, 0, 9]], hold_ms = 2000 }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PanicChordDef {
+    keys: Vec<(u8, u8, u8)>,
+    hold_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TapHoldDef {
+    Timeout,
+    HoldOnOtherPress,
+    PermissiveHold,
+}
+
+impl From<TapHoldDef> for TapHold {
+    fn from(def: TapHoldDef) -> Self {
+        match def {
+            TapHoldDef::Timeout => TapHold::Timeout,
+            TapHoldDef::HoldOnOtherPress => TapHold::HoldOnOtherPress,
+            TapHoldDef::PermissiveHold => TapHold::PermissiveHold,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LongPressRaceDef {
+    TapWins,
+    HoldWins,
+}
+
+impl From<LongPressRaceDef> for LongPressRace {
+    fn from(def: LongPressRaceDef) -> Self {
+        match def {
+            LongPressRaceDef::TapWins => LongPressRace::TapWins,
+            LongPressRaceDef::HoldWins => LongPressRace::HoldWins,
+        }
+    }
+}
+
+/// A key spelled by its name, see `parse_key`
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct KeyName(KeyCode);
+
+impl TryFrom<String> for KeyName {
+    type Error = UnknownKey;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        parse_key(&name).map(KeyName)
+    }
+}
 
-( CCW=10 ROT CW=11 ) [ 0 ][ 1 ][ 2 ][ 6 ]
-                     [ 3 ][ 4 ][ 5 ][ _ ]
-                     [ 7 ][    8   ][ 9 ]
+/// A key group spelled as a string, see `KeyGroup::from_str`
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct KeyGroupName(KeyGroup);
 
- or in the other orientation
+impl TryFrom<String> for KeyGroupName {
+    type Error = UnknownKey;
 
- [ 9 ][    8   ][ 7 ]
- [ 6 |[ 5 ][ 4 ][ 3 ]
- | _ ][ 2 ][ 1 ][ 0 ]  ( CCW=10 ROT CW=11 )
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse().map(KeyGroupName)
+    }
+}
+
+fn keys(names: Vec<KeyName>) -> Vec<KeyCode> {
+    names.into_iter().map(|KeyName(key)| key).collect()
+}
+
+/// Keys of a binding, a key group string, a list of keys pressed together
+/// or a table with all the key group options, eg. `"ctrl+z"`, `"h e l l o"`,
+/// `["KEY_LEFTCTRL", "KEY_Z"]`, `{ keys = ["1"], mask = ["shift"] }`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeysDef {
+    Name(KeyGroupName),
+    Keys(Vec<KeyName>),
+    Group {
+        keys: Vec<KeyName>,
+        #[serde(default)]
+        mask: Vec<KeyName>,
+        #[serde(default)]
+        sequential: bool,
+    },
+}
+
+impl From<KeysDef> for KeyGroup {
+    fn from(def: KeysDef) -> Self {
+        match def {
+            KeysDef::Name(KeyGroupName(group)) => group,
+            KeysDef::Keys(names) => KeyGroup { keys: keys(names), ..G() },
+            KeysDef::Group { keys: names, mask, sequential } => {
+                KeyGroup { sequential, keys: keys(names), mask: keys(mask) }
+            }
+        }
+    }
+}
+
+/// A layer referenced by its index or by its name
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LayerRef {
+    Index(LayerId),
+    Name(String),
+}
+
+impl LayerRef {
+    /// The index of the layer, `names` lists the names of all the layers
+    fn resolve<E: de::Error>(self, names: &[Option<String>]) -> Result<LayerId, E> {
+        match self {
+            LayerRef::Index(idx) => Ok(idx),
+            LayerRef::Name(name) => names.iter()
+                .position(|n| n.as_ref() == Some(&name))
+                .ok_or_else(|| E::custom(format!("Unknown layer {:?}", name))),
+        }
+    }
+}
+
+/// A keymap binding. Plain keys are sent as a key group, the other actions
+/// are spelled like the `KeymapEvent` variants with durations in ms and layers
+/// referenced by their index or name, eg.
+/// `"No"`, `{ Lhold = 1 }`, `{ LhtK = ["tools", "KEY_E"] }` or `{ Kmul = ["KEY_EQUAL", 5, 20] }`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ActionDef {
+    Keys(KeysDef),
+    Action(EventDef),
+}
+
+#[derive(Deserialize)]
+enum EventDef {
+    No,
+    Inh,
+    Pass,
+    Kg(KeysDef),
+    Klong(KeysDef, KeysDef),
+    Ktiers(KeysDef, Vec<(u64, KeysDef)>),
+    Kmul(KeysDef, u8, u64),
+    Kturbo(KeysDef, u64, u64),
+    TapDance(Vec<KeysDef>),
+    Oneshot(KeysDef),
+    CapsWord,
+    Khl(KeysDef, LayerRef),
+    Khtl(KeysDef, LayerRef),
+    Lmove(LayerRef),
+    Lactivate(LayerRef),
+    Ldeactivate(LayerRef),
+    Ltoggle(LayerRef),
+    Ldisable(LayerRef),
+    Lhold(LayerRef),
+    Ltap(LayerRef),
+    LhtL(LayerRef, LayerRef),
+    LhtK(LayerRef, KeysDef),
+    Mplay(String),
+    Mrec(String),
+    Mcancel,
+    Rollback,
+    Passthrough,
+    Cmd(String),
+    Leader(Vec<LeaderSequenceDef>, u64),
+    If(LayerRef, Box<ActionDef>, Box<ActionDef>),
+    IfHeld((u8, u8, u8), Box<ActionDef>, Box<ActionDef>),
+    Gbtn(KeyCode),
+    Gaxis(AbsoluteAxisType, i32),
+    Gnudge(AbsoluteAxisType, i32),
+    Pbtn(KeyCode),
+    Pmove(i32, i32),
+    Pscroll(i32, i32),
+    Cooldown(Box<ActionDef>, u64),
+    HoldThreshold(Box<ActionDef>, u64),
+    Output(Box<ActionDef>, String),
+}
+
+/// `{ keys = [[0, 0, 1], [0, 0, 2]], action = "KEY_A" }`, the keys are
+/// [block, row, column] like the keymap
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LeaderSequenceDef {
+    keys: Vec<(u8, u8, u8)>,
+    action: ActionDef,
+}
 
- */
+/// The sequences of a leader, checking that none of them is a prefix of another
+fn leader_sequences<E: de::Error>(
+    defs: Vec<LeaderSequenceDef>,
+    names: &[Option<String>],
+) -> Result<Vec<LeaderSequence>, E> {
+    let sequences = defs.into_iter()
+        .map(|def| Ok(LeaderSequence {
+            keys: def.keys.into_iter().map(|(b, r, c)| KeyCoords(b, r, c)).collect(),
+            action: def.action.into_event(names)?,
+        }))
+        .collect::<Result<Vec<_>, E>>()?;
 
-pub fn load_layout(s: &str) -> Vec<Layer> {
+    for (idx, s) in sequences.iter().enumerate() {
+        if s.keys.is_empty() {
+            return Err(E::custom("Empty leader sequence"));
+        }
+        let shadowed = sequences.iter().enumerate()
+            .any(|(other, o)| other != idx && o.keys.starts_with(&s.keys));
+        if shadowed {
+            return Err(E::custom(format!("Leader sequence {:?} starts another sequence", s.keys)));
+        }
+    }
+    Ok(sequences)
+}
+
+impl ActionDef {
+    fn into_event<E: de::Error>(self, names: &[Option<String>]) -> Result<KeymapEvent, E> {
+        let ms = Duration::from_millis;
+        let ev = match self {
+            ActionDef::Keys(keys) => return Ok(Kg(keys.into())),
+            ActionDef::Action(ev) => ev,
+        };
+
+        Ok(match ev {
+            EventDef::No => No,
+            EventDef::Inh => Inh,
+            EventDef::Pass => Pass,
+            EventDef::Kg(k) => Kg(k.into()),
+            EventDef::Klong(k_s, k_l) => Klong(k_s.into(), k_l.into()),
+            EventDef::Ktiers(k, tiers) => KeymapEvent::Ktiers(
+                k.into(),
+                tiers.into_iter().map(|(t, k_t)| (ms(t), k_t.into())).collect(),
+            ),
+            EventDef::Kmul(k, n, delay) => KeymapEvent::Kmul(k.into(), n, ms(delay)),
+            EventDef::Kturbo(k, interval, ramp_up) => {
+                KeymapEvent::Kturbo(k.into(), ms(interval), ms(ramp_up))
+            }
+            EventDef::TapDance(taps) => {
+                KeymapEvent::TapDance(taps.into_iter().map(KeyGroup::from).collect())
+            }
+            EventDef::Oneshot(k) => KeymapEvent::Oneshot(k.into()),
+            EventDef::CapsWord => KeymapEvent::CapsWord,
+            EventDef::Khl(k, l) => KeymapEvent::Khl(k.into(), l.resolve(names)?),
+            EventDef::Khtl(k, l) => KeymapEvent::Khtl(k.into(), l.resolve(names)?),
+            EventDef::Lmove(l) => Lmove(l.resolve(names)?),
+            EventDef::Lactivate(l) => Lactivate(l.resolve(names)?),
+            EventDef::Ldeactivate(l) => KeymapEvent::Ldeactivate(l.resolve(names)?),
+            EventDef::Ltoggle(l) => KeymapEvent::Ltoggle(l.resolve(names)?),
+            EventDef::Ldisable(l) => Ldisable(l.resolve(names)?),
+            EventDef::Lhold(l) => Lhold(l.resolve(names)?),
+            EventDef::Ltap(l) => Ltap(l.resolve(names)?),
+            EventDef::LhtL(l_hold, l_tap) => {
+                KeymapEvent::LhtL(l_hold.resolve(names)?, l_tap.resolve(names)?)
+            }
+            EventDef::LhtK(l, k) => LhtK(l.resolve(names)?, k.into()),
+            EventDef::Mplay(name) => KeymapEvent::Mplay(name),
+            EventDef::Mrec(name) => KeymapEvent::Mrec(name),
+            EventDef::Mcancel => KeymapEvent::Mcancel,
+            EventDef::Rollback => KeymapEvent::Rollback,
+            EventDef::Passthrough => KeymapEvent::Passthrough,
+            EventDef::Cmd(cmd) => KeymapEvent::Cmd(cmd),
+            EventDef::Leader(sequences, timeout) => {
+                KeymapEvent::Leader(leader_sequences(sequences, names)?, ms(timeout))
+            }
+            EventDef::If(l, then, otherwise) => KeymapEvent::If(
+                l.resolve(names)?,
+                Box::new(then.into_event(names)?),
+                Box::new(otherwise.into_event(names)?),
+            ),
+            EventDef::IfHeld((b, r, c), then, otherwise) => KeymapEvent::IfHeld(
+                KeyCoords(b, r, c),
+                Box::new(then.into_event(names)?),
+                Box::new(otherwise.into_event(names)?),
+            ),
+            EventDef::Gbtn(btn) => KeymapEvent::Gbtn(btn),
+            EventDef::Gaxis(axis, value) => KeymapEvent::Gaxis(axis, value),
+            EventDef::Gnudge(axis, value) => KeymapEvent::Gnudge(axis, value),
+            EventDef::Pbtn(btn) => KeymapEvent::Pbtn(btn),
+            EventDef::Pmove(dx, dy) => KeymapEvent::Pmove(dx, dy),
+            EventDef::Pscroll(v, h) => KeymapEvent::Pscroll(v, h),
+            EventDef::Cooldown(ev, cooldown) => {
+                KeymapEvent::Cooldown(Box::new(ev.into_event(names)?), ms(cooldown))
+            }
+            EventDef::HoldThreshold(ev, threshold) => {
+                KeymapEvent::HoldThreshold(Box::new(ev.into_event(names)?), ms(threshold))
+            }
+            EventDef::Output(ev, output) => {
+                KeymapEvent::Output(Box::new(ev.into_event(names)?), output)
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StatusDef {
+    Active,
+    Passthrough,
+    Disabled,
+}
+
+/// `"pen_near"`, `"pen_away"`, `{ led_on = "LED_NUML" }` or `{ led_off = "LED_CAPSL" }`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConditionDef {
+    LedOn(LedType),
+    LedOff(LedType),
+    PenNear,
+    PenAway,
+}
+
+/// `{ trigger_key_ms = 150 }` or `{ all_keys_ms = 150 }`
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DebounceDef {
+    TriggerKeyMs(u64),
+    AllKeysMs(u64),
+}
+
+/// One `[[layers]]` section, the layers are numbered in the order of the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayerDef {
+    /// Name to reference the layer by instead of its index
+    name: Option<String>,
+    /// Active on reset, the first layer is active and the others are not by default
+    status: Option<StatusDef>,
+    inherit: Option<LayerRef>,
+    #[serde(default)]
+    on_active_keys: Vec<KeyName>,
+    #[serde(default)]
+    disable_active_on_press: bool,
+    timeout_ms: Option<u64>,
+    on_timeout_layer: Option<LayerRef>,
+    condition: Option<ConditionDef>,
+    activation_debounce: Option<DebounceDef>,
+    output: Option<String>,
+    default_action: Option<ActionDef>,
+    /// [block][row][column] like the device geometry
+    keymap: Vec<Vec<Vec<ActionDef>>>,
+}
+
+impl LayerDef {
+    fn into_layer<E: de::Error>(self, idx: LayerId, names: &[Option<String>]) -> Result<Layer, E> {
+        let status_on_reset = match self.status {
+            Some(StatusDef::Active) => LayerStatus::LayerActive,
+            Some(StatusDef::Passthrough) => LayerStatus::LayerPassthrough,
+            Some(StatusDef::Disabled) => LayerStatus::LayerDisabled,
+            None if idx == 0 => LayerStatus::LayerActive,
+            None => LayerStatus::LayerPassthrough,
+        };
+
+        let keymap = self.keymap.into_iter()
+            .map(|block| block.into_iter()
+                .map(|row| row.into_iter().map(|ev| ev.into_event(names)).collect())
+                .collect())
+            .collect::<Result<_, E>>()?;
+
+        Ok(Layer {
+            name: self.name.unwrap_or_default(),
+            status_on_reset,
+            inherit: self.inherit.map(|l| l.resolve(names)).transpose()?,
+            on_active_keys: keys(self.on_active_keys),
+            disable_active_on_press: self.disable_active_on_press,
+            on_timeout_layer: self.on_timeout_layer.map(|l| l.resolve(names)).transpose()?,
+            timeout: self.timeout_ms.map(Duration::from_millis),
+            condition: self.condition.map(|c| match c {
+                ConditionDef::LedOn(led) => LayerCondition::LedOn(led),
+                ConditionDef::LedOff(led) => LayerCondition::LedOff(led),
+                ConditionDef::PenNear => LayerCondition::PenNear,
+                ConditionDef::PenAway => LayerCondition::PenAway,
+            }),
+            activation_debounce: self.activation_debounce.map(|d| match d {
+                DebounceDef::TriggerKeyMs(t) => ActivationDebounce::TriggerKey(Duration::from_millis(t)),
+                DebounceDef::AllKeysMs(t) => ActivationDebounce::AllKeys(Duration::from_millis(t)),
+            }),
+            output: self.output,
+            keymap,
+            default_action: match self.default_action {
+                Some(ev) => ev.into_event(names)?,
+                None => Pass,
+            },
+        })
+    }
+}
+
+/// Parse the `[[layers]]` sections of a layout file, eg.
+///
+/// ```toml
+/// [[layers]]
+/// keymap = [[["No", { Lhold = "tools" }, ["KEY_LEFTCTRL", "KEY_Z"]]]]
+///
+/// [[layers]]
+/// name = "tools"
+/// on_active_keys = ["KEY_LEFTSHIFT"]
+/// disable_active_on_press = true
+/// keymap = [[["Pass", "Pass", "KEY_ESC"]]]
+/// ```
+///
+/// When there are no layers the built-in layout is returned.
+pub fn parse_layout(source: &str) -> Result<Vec<Layer>, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    if sections.layers.is_empty() {
+        return Ok(builtin_layout());
+    }
+
+    // Layers can reference the layers defined after them
+    let names: Vec<Option<String>> = sections.layers.iter().map(|l| l.name.clone()).collect();
+    for (idx, name) in names.iter().enumerate() {
+        if let Some(name) = name {
+            if names[..idx].iter().flatten().any(|n| n == name) {
+                return Err(de::Error::custom(format!("Duplicate layer name {:?}", name)));
+            }
+        }
+    }
+    sections.layers.into_iter()
+        .enumerate()
+        .map(|(idx, layer)| layer.into_layer(idx, &names))
+        .collect()
+}
+
+/// Load the layers of a layout file, a missing file means the built-in layout
+pub fn load_layout(path: &Path) -> io::Result<Vec<Layer>> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(builtin_layout()),
+        Err(e) => return Err(e),
+    };
+    parse_layout(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The default location of the layout, `$XDG_CONFIG_HOME/xppen-ack05/layout.toml`
+pub fn default_layout_path() -> PathBuf {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    config.join("xppen-ack05").join(LAYOUT_FILE)
+}
+
+/// The layout file of the named profile, `profiles/<name>.toml` next to
+/// the default layout. The profile "default" is the default layout.
+pub fn profile_layout_path(name: &str) -> PathBuf {
+    let layout = default_layout_path();
+    if name == DEFAULT_PROFILE {
+        return layout;
+    }
+    layout.with_file_name(PROFILE_DIR).join(format!("{}.toml", name))
+}
+
+/// Parse the optional `[geometry]` section of a layout file. When the section
+/// is missing the ACK05 geometry is returned (see `Geometry::ack05` for
+/// the numbering of keys).
+///
+/// The `[[chords]]` form an extra block after the blocks of the device,
+/// with one row holding the chords in the order of the file. Every one of
+/// the `[[keyboards]]` adds a block after them, see `parse_keyboards`.
+pub fn parse_geometry(source: &str) -> Result<Geometry, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    let mut geometry = sections.geometry.unwrap_or_default();
+    if !sections.chords.is_empty() {
+        let labels = sections.chords.iter()
+            .map(|chord| chord.name.clone().unwrap_or_else(|| {
+                let keys: Vec<&str> = chord.keys().into_iter()
+                    .map(|k| geometry.label(k).unwrap_or("?"))
+                    .collect();
+                keys.join("+")
+            }))
+            .collect();
+        geometry.blocks.push(BlockGeometry {
+            name: "chords".to_string(),
+            rows: vec![labels],
+            stateless: false,
+        });
+    }
+    for keyboard in &sections.keyboards {
+        let name = keyboard.name.clone().unwrap_or_else(|| {
+            let file = keyboard.path.file_name().unwrap_or(keyboard.path.as_os_str());
+            file.to_string_lossy().into_owned()
+        });
+        geometry.blocks.push(BlockGeometry {
+            name,
+            rows: vec![keyboard_labels()],
+            stateless: false,
+        });
+    }
+    Ok(geometry)
+}
+
+/// Parse the optional `[[chords]]` sections of a layout file, eg.
+///
+/// ```toml
+/// [[chords]]
+/// keys = [[0, 0, 0], [0, 0, 1]]
+/// ```
+///
+/// Each chord is reported at its position in the extra block of the geometry
+/// (see `parse_geometry`), eg. the first ACK05 chord is bound at [2, 0, 0].
+pub fn parse_chords(source: &str) -> Result<Vec<Chord>, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    let block = sections.geometry.unwrap_or_default().blocks.len() as u8;
+    sections.chords.iter()
+        .enumerate()
+        .map(|(idx, chord)| {
+            let keys = chord.keys();
+            if keys.len() < 2 {
+                return Err(de::Error::custom(format!("Chord {:?} needs at least two keys", keys)));
+            }
+            Ok(Chord { keys, coords: KeyCoords(block, 0, idx as u8) })
+        })
+        .collect()
+}
+
+/// Parse the optional `[[keyboards]]` sections of a layout file, eg.
+///
+/// ```toml
+/// [[keyboards]]
+/// path = "/dev/input/by-id/usb-Keyboard-event-kbd"
+/// grab = true
+/// ```
+///
+/// The keys of each keyboard are reported in a block of their own after the
+/// chord block (see `parse_geometry`), the key code is the column of the only
+/// row, eg. KEY_ESC of the first keyboard is [2, 0, 1] without chords.
+pub fn parse_keyboards(source: &str) -> Result<Vec<KeyboardSource>, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    let first = sections.geometry.unwrap_or_default().blocks.len()
+        + usize::from(!sections.chords.is_empty());
+    Ok(sections.keyboards.into_iter()
+        .enumerate()
+        .map(|(idx, keyboard)| KeyboardSource {
+            path: keyboard.path,
+            grab: keyboard.grab,
+            block: (first + idx) as u8,
+        })
+        .collect())
+}
+
+/// Parse the optional `[[macros]]` sections of a layout file. They are merged
+/// over the shared macro library, so a layout can override a shared macro.
+pub fn parse_macros(source: &str) -> Result<MacroLibrary, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    Ok(MacroLibrary {
+        macros: sections.macros,
+    })
+}
+
+/// Parse the optional `[report]` section of a layout file describing the raw
+/// reports of the device. When the section is missing None is returned,
+/// the protocol of the firmware is used then.
+pub fn parse_report_map(source: &str) -> Result<Option<ReportMap>, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    Ok(sections.report)
+}
+
+/// Parse the optional `[fallback]` section of a layout file describing the keys
+/// the buttons type in the standard keyboard mode of the device. When the
+/// section is missing the factory keys of the ACK05 are assumed.
+pub fn parse_fallback_map(source: &str) -> Result<KeyboardReportMap, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    Ok(sections.fallback.unwrap_or_default())
+}
+
+/// Parse the optional `[settings]` section of a layout file. The missing
+/// settings are left to the global defaults.
+pub fn parse_settings(source: &str) -> Result<LayoutSettings, toml::de::Error> {
+    let sections: LayoutSections = toml::from_str(source)?;
+    Ok(LayoutSettings {
+        hold_threshold: sections.settings.hold_threshold_ms.map(Duration::from_millis),
+        key_repeat: sections.settings.key_repeat.map(|r| KeyRepeat {
+            delay: Duration::from_millis(r.delay_ms),
+            period: Duration::from_millis(r.period_ms),
+        }),
+        tap_hold: sections.settings.tap_hold.map(TapHold::from).unwrap_or_default(),
+        layer_notifications: sections.settings.layer_notifications,
+        cheat_sheet: sections.settings.cheat_sheet,
+        scancodes: sections.settings.scancodes,
+        rotary_acceleration: sections
+            .settings
+            .rotary_acceleration
+            .iter()
+            .map(|a| (Duration::from_millis(a.within_ms), a.steps))
+            .collect(),
+        rotary_divider: sections.settings.rotary_divider.map(|d| {
+            let idle = d.idle_ms.map_or(DIVIDER_IDLE_TIMEOUT, Duration::from_millis);
+            (d.detents, idle)
+        }),
+        idle_timeout: sections.settings.idle_timeout_ms.map(Duration::from_millis),
+        input_lock: sections.settings.input_lock.map(|lock| {
+            let keys = lock.keys.iter().map(|(b, r, c)| KeyCoords(*b, *r, *c)).collect();
+            (keys, lock.hold_ms.map_or(LOCK_HOLD, Duration::from_millis))
+        }),
+        device_path: sections.settings.device_path,
+        device_serial: sections.settings.device_serial,
+        debounce: sections.settings.debounce_ms.map(Duration::from_millis),
+        key_debounce: sections
+            .settings
+            .key_debounce
+            .iter()
+            .map(|d| (KeyCoords(d.key.0, d.key.1, d.key.2), Duration::from_millis(d.ms)))
+            .collect(),
+        duplicate_window: sections.settings.duplicate_window_ms.map(Duration::from_millis),
+        long_press_race: sections.settings.long_press_race.map(LongPressRace::from),
+        rotary_gestures: sections.settings.rotary_gestures.map(|g| {
+            (
+                g.spin_ticks.unwrap_or(SPIN_TICKS),
+                g.wiggle_ticks.unwrap_or(WIGGLE_TICKS),
+                g.window_ms.map_or(GESTURE_WINDOW, Duration::from_millis),
+            )
+        }),
+        bounce_keys: sections.settings.bounce_keys_ms.map(Duration::from_millis),
+        slow_keys: sections.settings.slow_keys_ms.map(Duration::from_millis),
+        scanning: sections.settings.scanning.map(|scan| {
+            let (b, r, c) = scan.switch;
+            let targets = scan.targets.iter().map(|(b, r, c)| KeyCoords(*b, *r, *c)).collect();
+            let interval = scan.interval_ms.map_or(SCAN_INTERVAL, Duration::from_millis);
+            (KeyCoords(b, r, c), targets, interval)
+        }),
+        morse: sections.settings.morse.map(|morse| {
+            let (b, r, c) = morse.key;
+            MorseInput {
+                key: KeyCoords(b, r, c),
+                dash: morse.dash_ms.map_or(DASH_THRESHOLD, Duration::from_millis),
+                letter_gap: morse.letter_gap_ms.map_or(LETTER_GAP, Duration::from_millis),
+                codes: morse.codes.into_iter().map(|c| (c.code, c.keys.into())).collect(),
+            }
+        }),
+        panic_chord: sections.settings.panic_chord.map(|chord| {
+            let keys = chord.keys.iter().map(|(b, r, c)| KeyCoords(*b, *r, *c)).collect();
+            (keys, chord.hold_ms.map_or(PANIC_HOLD, Duration::from_millis))
+        }),
+        rotary_reversal: sections.settings.rotary_reversal_ms.map(Duration::from_millis),
+        dial: sections.settings.dial.map(|dial| DialOutput {
+            axis: dial.axis.map_or(AbsoluteAxisType::ABS_WHEEL, AbsoluteAxisType::from),
+            step: dial.step.unwrap_or(1),
+            positions: dial.positions.unwrap_or(DIAL_POSITIONS),
+        }),
+        kernel_repeat: sections.settings.kernel_repeat.map(|r| KeyRepeat {
+            delay: Duration::from_millis(r.delay_ms),
+            period: Duration::from_millis(r.period_ms),
+        }),
+        watchdog_try_again: sections.settings.watchdog_try_again,
+    })
+}
+
+/// The layout used when there is no layout file.
+/// See `Geometry::ack05` for the numbering of keys
+pub fn builtin_layout() -> Vec<Layer> {
     // Layer 0 - default
     let keymap_default = vec![
         // blocks
@@ -33,39 +873,48 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
                 /*  1  */
                 No,
                 /*  2  */
-                Klong(G(), G().k(Key::KEY_DELETE)),
+                Klong(G(), G().k(KeyCode::KEY_DELETE)),
                 /*  3  */
                 Lhold(3),
                 /*  4  */
-                LhtK(1, G().k(Key::KEY_B)),
+                LhtK(1, G().k(KeyCode::KEY_B)),
                 /*  5  */
                 LhtK(4, G()),
                 /*  6  */
-                G().k(Key::KEY_LEFTCTRL).k(Key::KEY_Z).p(),
+                G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_Z).p(),
                 /*  7  */
-                LhtK(5, G().k(Key::KEY_INSERT)),
+                LhtK(5, G().k(KeyCode::KEY_INSERT)),
                 /*  8  */
-                LhtK(2, G().k(Key::KEY_LEFTSHIFT).k(Key::KEY_E)),
+                LhtK(2, G().k(KeyCode::KEY_LEFTSHIFT).k(KeyCode::KEY_E)),
                 /*  9  */
                 Klong(
-                    G().k(Key::KEY_F12),
-                    G().k(Key::KEY_LEFTCTRL).k(Key::KEY_LEFTSHIFT).k(Key::KEY_A),
+                    G().k(KeyCode::KEY_F12),
+                    G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_LEFTSHIFT).k(KeyCode::KEY_A),
                 ),
+            ],
+        ],
+        vec![
+            // rows
+            vec![
                 /* CCW */
-                G().k(Key::KEY_MINUS).p(),
+                G().k(KeyCode::KEY_MINUS).p(),
                 /*  CW */
-                G().k(Key::KEY_SLASH).p(), // should be minus and equals
+                G().k(KeyCode::KEY_SLASH).p(), // should be minus and equals
             ],
         ],
     ];
 
     let default_layer = Layer {
+        name: "base".to_string(),
         status_on_reset: super::types::LayerStatus::LayerActive,
         inherit: None,
         on_active_keys: vec![],
         disable_active_on_press: false,
         on_timeout_layer: None,
         timeout: None,
+        condition: None,
+        activation_debounce: None,
+        output: None,
         keymap: keymap_default,
         default_action: super::types::KeymapEvent::Pass,
     };
@@ -84,7 +933,7 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
                 /*  2  */
                 No,
                 /*  3  */
-                G().k(Key::KEY_K).p(),
+                G().k(KeyCode::KEY_K).p(),
                 /*  4  */
                 No,
                 /*  5  */
@@ -92,22 +941,28 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
                 /*  6  */
                 No,
                 /*  7  */
-                G().k(Key::KEY_L).p(),
+                G().k(KeyCode::KEY_L).p(),
                 /*  8  */
-                G().k(Key::KEY_LEFTCTRL).k(Key::KEY_SPACE).p(),
+                G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_SPACE).p(),
                 /*  9  */
                 No,
+            ],
+        ],
+        vec![
+            // rows
+            vec![
                 /* CCW */
-                G().k(Key::KEY_RIGHTBRACE).p(),
+                G().k(KeyCode::KEY_RIGHTBRACE).p(),
                 /*  CW */
-                G().k(Key::KEY_LEFTBRACE).p(),
+                G().k(KeyCode::KEY_LEFTBRACE).p(),
             ],
         ],
     ];
 
     let color_layer = Layer {
+        name: "color".to_string(),
         status_on_reset: super::types::LayerStatus::LayerPassthrough,
-        on_active_keys: vec![Key::KEY_LEFTCTRL],
+        on_active_keys: vec![KeyCode::KEY_LEFTCTRL],
         disable_active_on_press: true,
         keymap: keymap_color,
         ..default_layer.clone()
@@ -121,25 +976,30 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
             // rows
             vec![
                 /*  0  */
-                G().k(Key::KEY_ESC).p(),
+                G().k(KeyCode::KEY_ESC).p(),
                 /*  1  */
-                G().k(Key::KEY_5).p(),
+                G().k(KeyCode::KEY_5).p(),
                 /*  2  */
-                G().k(Key::KEY_LEFTCTRL).k(Key::KEY_T).p(),
+                G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_T).p(),
                 /*  3  */
                 No,
                 /*  4  */
-                G().k(Key::KEY_ENTER).p(),
+                G().k(KeyCode::KEY_ENTER).p(),
                 /*  5  */
                 No,
                 /*  6  */
                 No,
                 /*  7  */
-                G().k(Key::KEY_LEFTCTRL).k(Key::KEY_SPACE).p(),
+                G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_SPACE).p(),
                 /*  8  */
                 No,
                 /*  9  */
-                G().k(Key::KEY_T).p(),
+                G().k(KeyCode::KEY_T).p(),
+            ],
+        ],
+        vec![
+            // rows
+            vec![
                 /* CCW */
                 No,
                 /*  CW */
@@ -149,8 +1009,9 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
     ];
 
     let tools_layer = Layer {
+        name: "tools".to_string(),
         status_on_reset: super::types::LayerStatus::LayerPassthrough,
-        on_active_keys: vec![Key::KEY_LEFTSHIFT],
+        on_active_keys: vec![KeyCode::KEY_LEFTSHIFT],
         disable_active_on_press: true,
         keymap: keymap_tools,
         ..default_layer.clone()
@@ -172,31 +1033,37 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
                 /*  3  */
                 No,
                 /*  4  */
-                G().k(Key::KEY_5).p(),
+                G().k(KeyCode::KEY_5).p(),
                 /*  5  */
                 No,
                 /*  6  */
-                G().k(Key::KEY_LEFTCTRL)
-                    .k(Key::KEY_LEFTSHIFT)
-                    .k(Key::KEY_Z)
+                G().k(KeyCode::KEY_LEFTCTRL)
+                    .k(KeyCode::KEY_LEFTSHIFT)
+                    .k(KeyCode::KEY_Z)
                     .p(),
                 /*  7  */
                 No,
                 /*  8  */
-                G().k(Key::KEY_LEFTCTRL).k(Key::KEY_SPACE).p(),
+                G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_SPACE).p(),
                 /*  9  */
                 No,
+            ],
+        ],
+        vec![
+            // rows
+            vec![
                 /* CCW */
-                G().k(Key::KEY_6).p(),
+                G().k(KeyCode::KEY_6).p(),
                 /*  CW */
-                G().k(Key::KEY_4).p(),
+                G().k(KeyCode::KEY_4).p(),
             ],
         ],
     ];
 
     let view_layer = Layer {
+        name: "view".to_string(),
         status_on_reset: super::types::LayerStatus::LayerPassthrough,
-        on_active_keys: vec![Key::KEY_SPACE],
+        on_active_keys: vec![KeyCode::KEY_SPACE],
         disable_active_on_press: true,
         keymap: keymap_view,
         ..default_layer.clone()
@@ -211,14 +1078,19 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
             vec![
                 /*  0  */ Pass, /*  1  */ Pass, /*  2  */ Pass, /*  3  */ Pass,
                 /*  4  */ Pass, /*  5  */ Pass, /*  6  */ Pass, /*  7  */ Pass,
-                /*  8  */ Pass, /*  9  */ Pass, /* CCW */ Pass, /*  CW */ Pass,
+                /*  8  */ Pass, /*  9  */ Pass,
             ],
         ],
+        vec![
+            // rows
+            vec![/* CCW */ Pass, /*  CW */ Pass],
+        ],
     ];
 
     let draw_layer = Layer {
+        name: "drawing".to_string(),
         status_on_reset: super::types::LayerStatus::LayerPassthrough,
-        on_active_keys: vec![Key::KEY_V],
+        on_active_keys: vec![KeyCode::KEY_V],
         disable_active_on_press: true,
         keymap: keymap_pass,
         ..default_layer.clone()
@@ -247,9 +1119,14 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
                 /*  7  */
                 Pass,
                 /*  8  */
-                G().k(Key::KEY_LEFTCTRL).k(Key::KEY_E).p(),
+                G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_E).p(),
                 /*  9  */
                 Pass,
+            ],
+        ],
+        vec![
+            // rows
+            vec![
                 /* CCW */
                 Pass,
                 /*  CW */
@@ -259,6 +1136,7 @@ pub fn load_layout(s: &str) -> Vec<Layer> {
     ];
 
     let layers_layer = Layer {
+        name: "layers".to_string(),
         status_on_reset: super::types::LayerStatus::LayerPassthrough,
         on_active_keys: vec![],
         disable_active_on_press: true,
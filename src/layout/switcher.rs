@@ -1,18 +1,42 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use evdev::Key;
+use tracing::{debug, warn};
 
-use crate::kbd_events::KeyStateChange;
+use crate::clock::{Clock, SystemClock};
+use crate::kbd_events::{KeyStateChange, LONG_PRESS_THRESHOLD};
+use crate::keycodes::{AbsoluteAxisType, KeyCode, LedType, RelativeAxisType};
+
+use crate::macros::{Macro, MacroLibrary, MacroRepeat, MacroStep};
+use crate::virtual_gamepad::{GamepadEvent, GAMEPAD_AXIS_MAX};
 
 use super::keys::KeyGroup;
 use super::layer::Layer;
-use super::types::{KeyCoords, KeymapEvent, LayerId, LayerStatus};
+use super::types::{
+    ActivationDebounce, KeyCoords, KeyRepeat, KeymapEvent, LayerCondition, LayerId, LayerStatus,
+    LeaderSequence, LongPressRace, TapHold,
+};
 
 const LAYER_KEY: KeyCoords = KeyCoords(255, 255, 255);
 
-/// The key press duration threshold to distinguish between tap and hold
-const HOLD_THRESHOLD_MS: Duration = Duration::from_millis(200);
+/// How often is the condition of a waiting macro step re-checked
+const MACRO_WAIT_POLL: Duration = Duration::from_millis(20);
+
+/// Turbo clicks start this many times slower than the full speed
+const TURBO_RAMP_START: u128 = 4;
+
+/// The keys that continue a caps word, any other key press ends it
+#[rustfmt::skip]
+const CAPS_WORD_KEYS: [KeyCode; 40] = [
+    KeyCode::KEY_A, KeyCode::KEY_B, KeyCode::KEY_C, KeyCode::KEY_D, KeyCode::KEY_E, KeyCode::KEY_F, KeyCode::KEY_G,
+    KeyCode::KEY_H, KeyCode::KEY_I, KeyCode::KEY_J, KeyCode::KEY_K, KeyCode::KEY_L, KeyCode::KEY_M, KeyCode::KEY_N,
+    KeyCode::KEY_O, KeyCode::KEY_P, KeyCode::KEY_Q, KeyCode::KEY_R, KeyCode::KEY_S, KeyCode::KEY_T, KeyCode::KEY_U,
+    KeyCode::KEY_V, KeyCode::KEY_W, KeyCode::KEY_X, KeyCode::KEY_Y, KeyCode::KEY_Z,
+    KeyCode::KEY_1, KeyCode::KEY_2, KeyCode::KEY_3, KeyCode::KEY_4, KeyCode::KEY_5,
+    KeyCode::KEY_6, KeyCode::KEY_7, KeyCode::KEY_8, KeyCode::KEY_9, KeyCode::KEY_0,
+    KeyCode::KEY_MINUS, KeyCode::KEY_BACKSPACE, KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_RIGHTSHIFT,
+];
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum KeyReleaseMode {
@@ -20,55 +44,647 @@ pub enum KeyReleaseMode {
     ForceClick,
 }
 
-pub struct LayerSwitcher<'a> {
+/// Why a layer was activated or deactivated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerChangeReason {
+    /// An action of the key, or its release
+    Key(KeyCoords),
+    /// The timeout of the layer elapsed
+    Timeout,
+    /// The host keyboard LED or the pen proximity the layer is conditioned on
+    Condition,
+    /// A step of a played macro
+    Macro,
+    /// The host application, eg. over D-Bus, or a reset of the layout
+    Host,
+    /// No key was pressed for the idle timeout
+    Idle,
+}
+
+/// A layer was activated or deactivated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerChange<'a> {
+    pub layer: LayerId,
+    /// The name of the layer, empty when it has none
+    pub name: &'a str,
+    pub active: bool,
+    pub reason: LayerChangeReason,
+}
+
+/// A callback told about the layer changes
+type LayerHook = Box<dyn FnMut(&LayerChange) + Send>;
+
+/// The layout engine. It owns (a shared handle to) its layers, so it can
+/// be moved to the thread processing the key events.
+pub struct LayerSwitcher {
     /// Static configuration of layers
-    pub(super) layers: &'a Vec<Layer>,
+    pub(super) layers: Arc<Vec<Layer>>,
     /// Runtime status of layers
     pub(super) layer_stack: Vec<LayerStackEntry>,
     /// Currently pressed keys needing release
     /// with their originating layer and release keycodes
-    pub(super) presses: Vec<(
-        LayerId,
-        KeyCoords,
-        KeyReleaseMode,
-        Option<&'a KeyGroup>,
-        Instant,
-    )>,
+    pub(super) presses: HashMap<KeyCoords, PressEntry>,
 
     /// Queue of generated keycodes to issue to the OS
-    emitted_codes: VecDeque<(Key, bool)>,
+    /// together with the output device to issue them through
+    emitted_codes: VecDeque<(KeyCode, bool, Option<Arc<str>>)>,
+    /// Names of the output devices the layers route to, shared by the
+    /// queued keycodes
+    output_names: Vec<Arc<str>>,
+    /// Output device of the keys emitted by each key, recorded on press
+    /// and kept until the next press. Keys not listed use the main keyboard.
+    outputs: HashMap<KeyCoords, Option<Arc<str>>>,
+
+    /// Last known state of host keyboard LEDs, None when the conditioned
+    /// layers need to be re-evaluated
+    leds: Option<BTreeSet<LedType>>,
+
+    /// Is the tablet pen in proximity, for the pen conditioned layers
+    pen_near: bool,
+
+    /// The key event currently being processed and its timestamp
+    current_event: Option<(KeyCoords, Instant)>,
+    /// What the layer changes happening now are caused by
+    change_reason: LayerChangeReason,
+    /// Callbacks told about the layer changes
+    layer_hooks: Vec<LayerHook>,
+
+    /// Presses ignored by an activation debounce, their releases
+    /// must be ignored too
+    debounced: HashSet<KeyCoords>,
+
+    /// Keys that received a LongPress past the hold threshold
+    long_pressed: HashSet<KeyCoords>,
+
+    /// When did the keys with a cooldown last fire their action
+    last_fired: HashMap<KeyCoords, Instant>,
+
+    /// Policy for the LongPress vs Release race
+    long_press_race: LongPressRace,
+    /// The key press duration threshold to distinguish between tap and hold
+    hold_threshold: Duration,
+    /// The hold thresholds of the pressed keys that have their own
+    hold_thresholds: HashMap<KeyCoords, Duration>,
+    /// Resolution of the dual role keys held while other keys are pressed
+    tap_hold: TapHold,
+    /// Return to the base layer after this long without a key event
+    idle_timeout: Option<Duration>,
+    /// The time of the last key event, None once the idle return happened
+    last_input: Option<Instant>,
+    /// Presses waiting for the decision of a held dual role key
+    held_back: Vec<(KeyCoords, Instant)>,
+    /// The branches chosen by the pressed If actions, kept until the next press
+    branches: HashMap<KeyCoords, KeymapEvent>,
+    /// The physical keys currently held, for IfHeld
+    held: HashSet<KeyCoords>,
+
+    /// Macros referenced by Mplay
+    macros: MacroLibrary,
+    /// Name of the macro being recorded
+    recording: Option<String>,
+    /// The Rollback action was pressed, the host did not act on it yet
+    rollback: bool,
+    /// The layout is bypassed by the Passthrough action
+    bypassed: bool,
+    /// Commands of the pressed Cmd actions, the host did not run them yet
+    commands: Vec<String>,
+    /// Shift is held for a caps word
+    caps_word: bool,
+    /// The tap dance waiting for more taps
+    tap_dance: Option<TapDance>,
+    /// The leader waiting for the rest of its key sequence
+    leader: Option<LeaderCapture>,
+    /// The macro being played
+    playing: Option<MacroPlayback>,
+    /// Keys held on the host keyboards, for macros waiting on modifiers
+    host_keys: BTreeSet<KeyCode>,
+
+    /// Held turbo keys, their clicks are emitted by `tick`
+    turbo: Vec<Turbo>,
+    /// Multiplied clicks waiting for their pause, emitted by `tick`
+    multiplied: Vec<Multiplied>,
+
+    /// Repeat of the held key groups, None disables it
+    key_repeat: Option<KeyRepeat>,
+    /// Held key groups with the time of their next repeat
+    repeats: Vec<(KeyCoords, Instant)>,
+
+    /// Pressed sticky modifiers with the keys that pressed them
+    oneshot: Vec<(KeyCoords, KeyGroup)>,
+    /// The key whose release releases the sticky modifiers
+    oneshot_target: Option<KeyCoords>,
+
+    /// Queue of generated gamepad events
+    gamepad_events: VecDeque<GamepadEvent>,
+    /// Keys holding a gamepad button or axis with the action to undo on release
+    gamepad_presses: Vec<(KeyCoords, KeymapEvent)>,
+    /// Current gamepad axis positions, missing axes are centered
+    gamepad_axes: Vec<(AbsoluteAxisType, i32)>,
+
+    /// Queue of generated relative pointer movements
+    pointer_events: VecDeque<(RelativeAxisType, i32)>,
+    /// Keys holding a mouse button with the button
+    pointer_presses: Vec<(KeyCoords, KeyCode)>,
+
+    /// The time of `tick_now` and `next_timer_in`
+    clock: Box<dyn Clock + Send>,
+}
+
+/// The relative axes a pointer action moves, with the steps
+fn pointer_axes(ev: &KeymapEvent) -> Vec<(RelativeAxisType, i32)> {
+    match ev {
+        KeymapEvent::Pmove(dx, dy) => {
+            vec![
+                (RelativeAxisType::REL_X, *dx),
+                (RelativeAxisType::REL_Y, *dy),
+            ]
+        }
+        KeymapEvent::Pscroll(v, h) => vec![
+            (RelativeAxisType::REL_WHEEL, *v),
+            (RelativeAxisType::REL_HWHEEL, *h),
+        ],
+        _ => vec![],
+    }
+}
+
+/// State of a held turbo key
+struct Turbo {
+    coords: KeyCoords,
+    srclayer: LayerId,
+    kg: KeyGroup,
+    /// Interval between the clicks at full speed
+    interval: Duration,
+    /// Time it takes to reach the full speed
+    ramp: Duration,
+    /// When was the key pressed
+    pressed: Instant,
+    /// The time the next click is due at
+    due: Instant,
+}
+
+impl Turbo {
+    /// Interval between the clicks after the key was held for `held`. It
+    /// shrinks linearly from a multiple of the full speed interval.
+    fn interval_at(&self, held: Duration) -> Duration {
+        if held >= self.ramp {
+            return self.interval;
+        }
+
+        // Computed in micros, floats would make the intervals drift
+        let remaining = (self.ramp - held).as_micros();
+        let extra =
+            self.interval.as_micros() * (TURBO_RAMP_START - 1) * remaining / self.ramp.as_micros();
+        self.interval + Duration::from_micros(extra as u64)
+    }
+}
+
+/// The clicks of a multiplied key left after the first one
+struct Multiplied {
+    coords: KeyCoords,
+    srclayer: LayerId,
+    kg: KeyGroup,
+    /// Clicks still to send
+    remaining: u8,
+    /// Pause between the clicks
+    delay: Duration,
+    /// The time the next click is due at
+    due: Instant,
+}
+
+/// A tap dance key counting its taps
+struct TapDance {
+    coords: KeyCoords,
+    srclayer: LayerId,
+    taps: Vec<KeyGroup>,
+    /// The taps so far
+    count: usize,
+    /// The dance ends when the key is not tapped again until then
+    due: Instant,
+}
+
+/// A leader key collecting its sequence
+struct LeaderCapture {
+    sequences: Vec<LeaderSequence>,
+    /// The keys pressed since the leader
+    keys: Vec<KeyCoords>,
+    /// The layer and the output device of the leader, the selected
+    /// action behaves as if it was bound next to it
+    layer: LayerId,
+    output: Option<Arc<str>>,
+    timeout: Duration,
+    /// The leader is aborted when no key is pressed until then
+    due: Instant,
+}
+
+/// State of a macro playback, the steps are emitted by `tick`
+struct MacroPlayback {
+    m: Macro,
+    /// The key that started the playback and whether it is still held
+    coords: KeyCoords,
+    held: bool,
+    /// Number of finished iterations
+    iteration: u32,
+    /// Steps being played with the index of the next step. The branches
+    /// of conditionals are nested on top of the macro steps.
+    frames: Vec<(Vec<MacroStep>, usize)>,
+    /// Was the pause before the next step added to `due` already?
+    delayed: bool,
+    /// The time the next step is due at
+    due: Instant,
+    /// Keys pressed by the macro and not released yet
+    pressed: Vec<KeyCode>,
+}
+
+/// A recorded press of a key needing release
+pub(super) struct PressEntry {
+    /// The layer the press originates from
+    pub(super) layer: LayerId,
+    pub(super) mode: KeyReleaseMode,
+    /// The keys to release, or to click on release for ForceClick
+    pub(super) kg: Option<KeyGroup>,
+    /// When was the key pressed
+    pub(super) t: Instant,
 }
 
 #[derive(Clone)]
 pub struct LayerStackEntry {
     pub(super) status: LayerStatus,
     pub(super) active_keys: bool,
+    /// When and by which key was the layer last activated
+    pub(super) activated: Option<(Instant, KeyCoords)>,
 }
 
-impl<'a> LayerSwitcher<'a> {
-    pub fn new(layers: &'a Vec<Layer>) -> Self {
+impl LayerSwitcher {
+    /// A switcher of a copy of the `layers`
+    pub fn new(layers: &[Layer]) -> Self {
+        Self::from_shared(Arc::new(layers.to_vec()))
+    }
+
+    /// A switcher of the `layers` shared with the rest of the application
+    pub fn from_shared(layers: Arc<Vec<Layer>>) -> Self {
+        let mut output_names: Vec<Arc<str>> = Vec::new();
+        for output in layers.iter().flat_map(|l| l.get_outputs()) {
+            if !output_names.iter().any(|name| **name == *output) {
+                output_names.push(output.into());
+            }
+        }
         Self {
             layers,
             layer_stack: Vec::new(),
-            presses: Vec::new(),
+            presses: HashMap::new(),
             emitted_codes: VecDeque::new(),
+            output_names,
+            outputs: HashMap::new(),
+            leds: None,
+            pen_near: false,
+            current_event: None,
+            change_reason: LayerChangeReason::Host,
+            layer_hooks: Vec::new(),
+            debounced: HashSet::new(),
+            long_pressed: HashSet::new(),
+            last_fired: HashMap::new(),
+            long_press_race: LongPressRace::HoldWins,
+            hold_threshold: LONG_PRESS_THRESHOLD,
+            hold_thresholds: HashMap::new(),
+            tap_hold: TapHold::Timeout,
+            idle_timeout: None,
+            last_input: None,
+            held_back: Vec::new(),
+            branches: HashMap::new(),
+            held: HashSet::new(),
+            macros: MacroLibrary::default(),
+            recording: None,
+            rollback: false,
+            bypassed: false,
+            commands: Vec::new(),
+            caps_word: false,
+            tap_dance: None,
+            leader: None,
+            playing: None,
+            host_keys: BTreeSet::new(),
+            turbo: Vec::new(),
+            multiplied: Vec::new(),
+            key_repeat: None,
+            repeats: Vec::new(),
+            oneshot: Vec::new(),
+            oneshot_target: None,
+            gamepad_events: VecDeque::new(),
+            gamepad_presses: Vec::new(),
+            gamepad_axes: Vec::new(),
+            pointer_events: VecDeque::new(),
+            pointer_presses: Vec::new(),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Update the keys held on the host keyboards
+    pub fn set_host_keys(&mut self, keys: &BTreeSet<KeyCode>) {
+        self.host_keys = keys.clone();
+    }
+
+    /// Set the macros available to Mplay
+    pub fn set_macros(&mut self, macros: MacroLibrary) {
+        self.macros = macros;
+    }
+
+    pub fn macros(&self) -> &MacroLibrary {
+        &self.macros
+    }
+
+    /// Add a (freshly recorded) macro to the library
+    pub fn store_macro(&mut self, m: Macro) {
+        self.macros.insert(m);
+    }
+
+    /// Name of the macro being recorded. The caller captures the keys
+    /// while this is set and stores the macro once it is cleared.
+    pub fn recording(&self) -> Option<&str> {
+        self.recording.as_deref()
+    }
+
+    /// Was the configuration rollback requested since the last call?
+    pub fn take_rollback(&mut self) -> bool {
+        std::mem::take(&mut self.rollback)
+    }
+
+    /// Is the layout bypassed by the Passthrough action?
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Bypass the layout or turn it back on the way the Passthrough action
+    /// does, eg. on a request of the control interface
+    pub fn set_bypassed(&mut self, bypassed: bool) {
+        self.bypassed = bypassed;
+        if bypassed {
+            self.release_all();
+        }
+    }
+
+    /// The commands to run, in the order their keys were pressed
+    pub fn take_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.commands)
+    }
+
+    /// Select how a release racing with a pending long press is resolved
+    pub fn set_long_press_race(&mut self, policy: LongPressRace) {
+        self.long_press_race = policy;
+    }
+
+    /// Set the press duration that tells a hold from a tap. The long press
+    /// detector has to be reconfigured with `get_long_press_tiers` afterwards.
+    pub fn set_hold_threshold(&mut self, threshold: Duration) {
+        self.hold_threshold = threshold;
+    }
+
+    pub fn get_hold_threshold(&self) -> Duration {
+        self.hold_threshold
+    }
+
+    /// Register a callback told about every layer activation and deactivation.
+    /// It runs in the middle of the event processing, anything slow (eg. an
+    /// OSD) should rather get the change sent through a channel.
+    pub fn on_layer_change<F>(&mut self, hook: F)
+    where
+        F: FnMut(&LayerChange) + Send + 'static,
+    {
+        self.layer_hooks.push(Box::new(hook));
+    }
+
+    /// Tell the callbacks the layer `idx` was activated or deactivated
+    fn report_layer_change(&mut self, idx: LayerId, active: bool) {
+        let layers = Arc::clone(&self.layers);
+        let change = LayerChange {
+            layer: idx,
+            name: &layers[idx].name,
+            active,
+            reason: self.change_reason,
+        };
+        for hook in &mut self.layer_hooks {
+            hook(&change);
         }
     }
 
+    /// Select how the dual role keys held while other keys are pressed
+    /// are resolved
+    pub fn set_tap_hold(&mut self, policy: TapHold) {
+        self.tap_hold = policy;
+    }
+
+    /// Return to the base layer, and release the sticky modifiers, when no
+    /// key event comes for the `timeout`. None never returns.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Repeat the last key of a held key group after the delay and then
+    /// every period, like a held keyboard key. This is an alternative to
+    /// the kernel repeat of the virtual keyboard. A zero period disables it.
+    pub fn set_key_repeat(&mut self, repeat: Option<KeyRepeat>) {
+        self.key_repeat = repeat.filter(|r| !r.period.is_zero());
+    }
+
     /// Initialize (reset) the switcher state
     /// MUST be called before any keys are processed
     pub fn start(&mut self) {
+        self.emitted_codes.clear();
+        self.reset();
+        self.apply_conditions();
+    }
+
+    /// Queue release events for all keys the switcher pressed (key groups and
+    /// layer active keys) and return to the initial layer state. Use this before
+    /// the output goes away or when the system is going to sleep.
+    pub fn release_all(&mut self) {
+        self.macro_cancel();
+        self.held.clear();
+
+        // The keys are released in the reverse order of their presses
+        let mut presses: Vec<(KeyCoords, PressEntry)> = self.presses.drain().collect();
+        presses.sort_by_key(|(coords, press)| (press.t, *coords));
+        while let Some((coords, press)) = presses.pop() {
+            if let (KeyReleaseMode::Reverse, Some(kg)) = (press.mode, press.kg) {
+                if kg.sequential {
+                    continue;
+                }
+                for k in kg.keys.iter().rev() {
+                    self.emit_keycodes(coords, k, false);
+                }
+            }
+        }
+
+        for idx in (0..self.layer_stack.len()).rev() {
+            let l = &self.layer_stack[idx];
+            if !l.active_keys
+                || l.status == LayerStatus::LayerDisabled
+                || l.status == LayerStatus::LayerPassthrough
+            {
+                continue;
+            }
+
+            let layers = Arc::clone(&self.layers);
+            for k in layers[idx].on_active_keys.iter().rev() {
+                self.emit_keycodes(LAYER_KEY, k, false);
+            }
+        }
+
+        self.oneshot_release();
+        self.caps_word_end();
+
+        while let Some((coords, ev)) = self.gamepad_presses.pop() {
+            self.gamepad_release(coords, &ev);
+        }
+        for idx in 0..self.gamepad_axes.len() {
+            let axis = self.gamepad_axes[idx].0;
+            self.gamepad_axis(axis, 0);
+        }
+
+        while let Some((coords, btn)) = self.pointer_presses.pop() {
+            self.emit_keycodes(coords, &btn, false);
+        }
+
+        self.reset();
+        // Conditioned layers are re-evaluated with the next LED update
+        self.leds = None;
+    }
+
+    /// Queue a click of `kg` that does not originate from the keymap,
+    /// eg. a decoded Morse letter
+    pub fn tap(&mut self, kg: &KeyGroup) {
+        for k in &kg.mask {
+            self.emit_keycodes(LAYER_KEY, k, false);
+        }
+
+        for k in &kg.keys {
+            self.emit_keycodes(LAYER_KEY, k, true);
+            if kg.sequential {
+                self.emit_keycodes(LAYER_KEY, k, false);
+            }
+        }
+
+        if !kg.sequential {
+            for k in kg.keys.iter().rev() {
+                self.emit_keycodes(LAYER_KEY, k, false);
+            }
+        }
+
+        for k in kg.mask.iter().rev() {
+            self.emit_keycodes(LAYER_KEY, k, true);
+        }
+    }
+
+    /// Activate the layer from outside of the keymap, eg. over D-Bus.
+    /// Unknown layers are ignored.
+    pub fn activate_layer(&mut self, idx: LayerId) {
+        if idx < self.layer_stack.len() {
+            self.layer_activate(idx);
+        }
+    }
+
+    /// Deactivate the layer from outside of the keymap, the base layer
+    /// stays active
+    pub fn deactivate_layer(&mut self, idx: LayerId) {
+        if idx < self.layer_stack.len() {
+            self.layer_deactivate(idx);
+        }
+    }
+
+    /// Reset the runtime state to the initial layer configuration
+    fn reset(&mut self) {
+        let previous = self.get_active_layers();
         self.layer_stack.clear();
-        for layer in self.layers {
+        for layer in self.layers.iter() {
             self.layer_stack.push(LayerStackEntry {
                 status: layer.status_on_reset,
                 active_keys: layer.status_on_reset != LayerStatus::LayerDisabled
                     && layer.status_on_reset != LayerStatus::LayerPassthrough,
+                activated: None,
             })
         }
         self.layer_stack[0].status = LayerStatus::LayerActive;
         self.presses.clear();
-        self.emitted_codes.clear();
+        self.debounced.clear();
+        self.long_pressed.clear();
+        self.playing = None;
+        self.leader = None;
+        self.tap_dance = None;
+        self.caps_word = false;
+        self.turbo.clear();
+        self.multiplied.clear();
+        self.repeats.clear();
+        self.oneshot.clear();
+        self.oneshot_target = None;
+        self.gamepad_presses.clear();
+        self.pointer_presses.clear();
+        self.outputs.clear();
+        self.hold_thresholds.clear();
+        self.held_back.clear();
+        self.branches.clear();
+        self.held.clear();
+
+        let current = self.get_active_layers();
+        for idx in previous.iter().filter(|idx| !current.contains(idx)) {
+            self.report_layer_change(*idx, false);
+        }
+        for idx in current.iter().filter(|idx| !previous.contains(idx)) {
+            self.report_layer_change(*idx, true);
+        }
+    }
+
+    /// Update the known state of host keyboard LEDs and (de)activate
+    /// layers that are conditioned on it.
+    pub fn set_leds<I>(&mut self, leds: I)
+    where
+        I: IntoIterator<Item = LedType>,
+    {
+        let leds: BTreeSet<LedType> = leds.into_iter().collect();
+        if let Some(known) = &self.leds {
+            if leds.iter().eq(known.iter()) {
+                return;
+            }
+        }
+
+        self.leds = Some(leds);
+        self.apply_conditions();
+    }
+
+    /// Is the host keyboard LED (eg. Caps Lock) lit? False until
+    /// the LED state is known.
+    pub fn is_led_on(&self, led: LedType) -> bool {
+        self.leds.as_ref().is_some_and(|leds| leds.contains(&led))
+    }
+
+    /// Update the known tablet pen proximity and (de)activate
+    /// layers that are conditioned on it.
+    pub fn set_pen_proximity(&mut self, near: bool) {
+        if self.pen_near == near {
+            return;
+        }
+
+        self.pen_near = near;
+        self.apply_conditions();
+    }
+
+    /// Activate layers whose condition holds and deactivate the ones
+    /// whose condition stopped holding.
+    fn apply_conditions(&mut self) {
+        let reason = std::mem::replace(&mut self.change_reason, LayerChangeReason::Condition);
+        for idx in 0..self.layers.len() {
+            let holds = match self.layers[idx].condition {
+                None => continue,
+                Some(LayerCondition::LedOn(led)) => self.is_led_on(led),
+                Some(LayerCondition::LedOff(led)) => !self.is_led_on(led),
+                Some(LayerCondition::PenNear) => self.pen_near,
+                Some(LayerCondition::PenAway) => !self.pen_near,
+            };
+
+            if holds {
+                self.layer_activate(idx);
+            } else {
+                self.layer_deactivate(idx);
+            }
+        }
+        self.change_reason = reason;
     }
 
     /// Disable layer for good. No activation will enable it
@@ -112,6 +728,7 @@ impl<'a> LayerSwitcher<'a> {
         self.layer_stack[idx].status = LayerStatus::LayerPassthrough;
 
         self.on_layer_deactivation(idx);
+        self.report_layer_change(idx, false);
     }
 
     /// Activate layer, keypress rules will be processed
@@ -126,8 +743,23 @@ impl<'a> LayerSwitcher<'a> {
             return;
         }
 
+        // A held layer gets latched, it was active already
+        let newly = self.layer_stack[idx].status == LayerStatus::LayerPassthrough;
         self.layer_stack[idx].status = LayerStatus::LayerActive;
         self.on_layer_activation(idx);
+        if newly {
+            self.report_layer_change(idx, true);
+        }
+    }
+
+    /// Latch the layer on, or off when it was latched already. A layer held
+    /// by a key gets latched on.
+    fn layer_toggle(&mut self, idx: LayerId) {
+        if self.layer_stack[idx].status == LayerStatus::LayerActive {
+            self.layer_deactivate(idx);
+        } else {
+            self.layer_activate(idx);
+        }
     }
 
     /// Activate layer and keep it activated until `coords` key is kept pressed
@@ -144,6 +776,7 @@ impl<'a> LayerSwitcher<'a> {
 
         self.layer_stack[idx].status = LayerStatus::LayerActiveUntilKeyRelease(coords);
         self.on_layer_activation(idx);
+        self.report_layer_change(idx, true);
     }
 
     /// Activate layer and keep it activated while `coords` is pressed,
@@ -161,11 +794,12 @@ impl<'a> LayerSwitcher<'a> {
 
         self.layer_stack[idx].status = LayerStatus::LayerActiveUntilKeyReleaseTap(coords);
         self.on_layer_activation(idx);
+        self.report_layer_change(idx, true);
     }
 
     /// Activate layer `idx` and keep it activated while `coords` is pressed.
     /// At `coords` release check elapsed time and activate layer `idx2` when
-    /// the press duration was shorter than `HOLD_THRESHOLD_MS`
+    /// the press duration was shorter than the hold threshold
     fn layer_hold_tap(&mut self, idx: LayerId, idx2: LayerId, coords: KeyCoords, t: Instant) {
         // Disabled layer, ignore action
         if self.layer_stack[idx].status == LayerStatus::LayerDisabled {
@@ -179,11 +813,12 @@ impl<'a> LayerSwitcher<'a> {
 
         self.layer_stack[idx].status = LayerStatus::LayerHoldAndTapToL(coords, t, idx2);
         self.on_layer_activation(idx);
+        self.report_layer_change(idx, true);
     }
 
     /// Activate layer `idx` and keep it activated while `coords` is pressed.
     /// At `coords` release check elapsed time and emit configured keys when
-    /// the press duration was shorter than `HOLD_THRESHOLD_MS`
+    /// the press duration was shorter than the hold threshold
     fn layer_hold_key(
         &mut self,
         activate_idx: LayerId,
@@ -204,6 +839,7 @@ impl<'a> LayerSwitcher<'a> {
         self.layer_stack[activate_idx].status =
             LayerStatus::LayerHoldAndTapKey(coords, t, key_layer);
         self.on_layer_activation(activate_idx);
+        self.report_layer_change(activate_idx, true);
     }
 
     /// Activate layer `idx` after all other layers were deactivated (except base layer)
@@ -213,7 +849,7 @@ impl<'a> LayerSwitcher<'a> {
             return;
         }
 
-        for (l_idx, _l) in self.layer_stack.clone().into_iter().enumerate() {
+        for l_idx in 0..self.layer_stack.len() {
             if idx == l_idx {
                 continue;
             }
@@ -225,11 +861,38 @@ impl<'a> LayerSwitcher<'a> {
 
     /// Perform this on each layer activation
     fn on_layer_activation(&mut self, idx: LayerId) {
-        let keys = &self.layers[idx].on_active_keys;
-        for k in keys {
-            self.emit_keycodes(LAYER_KEY, &k, true);
+        let layers = Arc::clone(&self.layers);
+        for k in &layers[idx].on_active_keys {
+            self.emit_keycodes(LAYER_KEY, k, true);
         }
         self.layer_stack[idx].active_keys = true;
+        self.layer_stack[idx].activated = self.current_event.map(|(coords, t)| (t, coords));
+    }
+
+    /// Check whether a press of `coords` falls into the activation
+    /// debounce window of any active layer
+    fn is_debounced(&self, coords: KeyCoords, t: Instant) -> bool {
+        for (idx, l) in self.layer_stack.iter().enumerate() {
+            if l.status == LayerStatus::LayerDisabled || l.status == LayerStatus::LayerPassthrough {
+                continue;
+            }
+
+            let Some((t0, trigger)) = l.activated else {
+                continue;
+            };
+
+            let ignored = match self.layers[idx].activation_debounce {
+                None => false,
+                Some(ActivationDebounce::TriggerKey(window)) => trigger == coords && t - t0 < window,
+                Some(ActivationDebounce::AllKeys(window)) => t - t0 < window,
+            };
+
+            if ignored {
+                return true;
+            }
+        }
+
+        false
     }
 
     /// Perform this on each layer deactivation
@@ -240,15 +903,16 @@ impl<'a> LayerSwitcher<'a> {
             return;
         }
 
-        let keys = &self.layers[idx].on_active_keys;
-        for k in keys {
+        let layers = Arc::clone(&self.layers);
+        for k in &layers[idx].on_active_keys {
             self.emit_keycodes(LAYER_KEY, k, false);
         }
     }
 
     fn before_key_press(&mut self, layer: LayerId) {
-        if self.layers[layer].disable_active_on_press && (&self.layer_stack)[layer].active_keys {
-            for k in (&self.layers[layer].on_active_keys).into_iter().rev() {
+        let layers = Arc::clone(&self.layers);
+        if layers[layer].disable_active_on_press && (&self.layer_stack)[layer].active_keys {
+            for k in (&layers[layer].on_active_keys).into_iter().rev() {
                 self.emit_keycodes(LAYER_KEY, &k, false);
             }
             self.layer_stack[layer].active_keys = false;
@@ -274,7 +938,8 @@ impl<'a> LayerSwitcher<'a> {
         }
 
         // Re-enable active keys
-        for k in &self.layers[layer].on_active_keys {
+        let layers = Arc::clone(&self.layers);
+        for k in &layers[layer].on_active_keys {
             self.emit_keycodes(LAYER_KEY, &k, true);
         }
         self.layer_stack[layer].active_keys = true;
@@ -282,7 +947,7 @@ impl<'a> LayerSwitcher<'a> {
 
     fn keygroup_press(
         &mut self,
-        kg: &'a KeyGroup,
+        kg: &KeyGroup,
         coords: KeyCoords,
         srclayer: LayerId,
         t: Instant,
@@ -312,9 +977,15 @@ impl<'a> LayerSwitcher<'a> {
             }
 
             self.after_key_release(srclayer);
+            self.oneshot_release();
         } else {
-            self.presses
-                .push((srclayer, coords, KeyReleaseMode::Reverse, Some(kg), t));
+            self.record_press(coords, srclayer, KeyReleaseMode::Reverse, kg, t);
+            if let Some(repeat) = self.key_repeat {
+                self.repeats.push((coords, t + repeat.delay));
+            }
+            if !self.oneshot.is_empty() {
+                self.oneshot_target.get_or_insert(coords);
+            }
         }
     }
 
@@ -332,32 +1003,138 @@ impl<'a> LayerSwitcher<'a> {
         }
 
         self.after_key_release(srclayer);
+        if self.oneshot_target == Some(coords) {
+            self.oneshot_release();
+        }
+    }
+
+    /// Press the sticky modifiers of `coords`, or release them when they
+    /// are pressed already
+    fn oneshot_press(&mut self, kg: &KeyGroup, coords: KeyCoords) {
+        if let Some(idx) = self.oneshot.iter().position(|(c, _)| *c == coords) {
+            let (_, kg) = self.oneshot.remove(idx);
+            for k in kg.keys.iter().rev() {
+                self.emit_keycodes(coords, k, false);
+            }
+            return;
+        }
+
+        for k in &kg.keys {
+            self.emit_keycodes(coords, k, true);
+        }
+        self.oneshot.push((coords, kg.clone()));
+    }
+
+    /// Release all the pressed sticky modifiers
+    fn oneshot_release(&mut self) {
+        self.oneshot_target = None;
+        while let Some((coords, kg)) = self.oneshot.pop() {
+            for k in kg.keys.iter().rev() {
+                self.emit_keycodes(coords, k, false);
+            }
+        }
+    }
+
+    /// Record the press of `coords` with the key group to release
+    /// (or click) once the key is released
+    fn record_press(
+        &mut self,
+        coords: KeyCoords,
+        layer: LayerId,
+        mode: KeyReleaseMode,
+        kg: &KeyGroup,
+        t: Instant,
+    ) {
+        let press = PressEntry {
+            layer,
+            mode,
+            kg: Some(kg.clone()),
+            t,
+        };
+        self.presses.insert(coords, press);
     }
 
     /// Get the number of currently recorded presses originating from `layer`
     pub(crate) fn active_keys_from_layer(&self, layer: LayerId) -> usize {
-        self.presses.iter().fold(
-            0,
-            |acc, (a, _, _, _, _)| {
-                if (*a) == layer {
-                    acc + 1
-                } else {
-                    acc
-                }
-            },
-        )
+        self.presses
+            .values()
+            .filter(|press| press.layer == layer)
+            .count()
     }
 
     /// This is the main keypress handling function
     fn process_keyevent_press(&mut self, coords: KeyCoords, t: Instant) {
-        // Identify the action associated with the current event
-        let (srclayer, ev) = self.get_key_event(coords);
-        if ev.is_none() {
+        if self.is_debounced(coords, t) {
+            self.debounced.insert(coords);
+            return;
+        }
+        match self.tap_hold {
+            TapHold::Timeout => {}
+            TapHold::HoldOnOtherPress => {
+                for held in self.undecided_holds(coords) {
+                    self.decide_hold(held, t);
+                }
+            }
+            TapHold::PermissiveHold => {
+                // Wait until it is known whether the held key is a tap or a hold
+                if !self.held_back.is_empty() || !self.undecided_holds(coords).is_empty() {
+                    self.held_back.push((coords, t));
+                    return;
+                }
+            }
+        }
+        // Another key ends the tap dance, its keys go first
+        if self
+            .tap_dance
+            .as_ref()
+            .is_some_and(|dance| dance.coords != coords)
+        {
+            self.tap_dance_end();
+        }
+        // The keys of a leader sequence are collected, not resolved
+        if self.leader.is_some() {
+            self.leader_capture(coords, t);
+            return;
+        }
+        if self.is_cooling_down(coords, t) {
+            self.debounced.insert(coords);
+            return;
+        }
+        self.current_event = Some((coords, t));
+        let output = self.get_key_output(coords);
+        self.outputs.insert(coords, output);
+        match self.get_key_hold_threshold(coords) {
+            Some(threshold) => self.hold_thresholds.insert(coords, threshold),
+            None => self.hold_thresholds.remove(&coords),
+        };
+
+        // Identify the action associated with the current event, it is borrowed
+        // from a handle of the layers as the action changes the switcher
+        let layers = Arc::clone(&self.layers);
+        let Some((srclayer, layerid)) = self.get_key_event_layers(coords) else {
             return;
+        };
+        let (_, ev) = Self::get_key_event_inheritance(&layers, coords, layerid);
+        debug!(layer = srclayer, ?coords, "Resolved {:?}", ev);
+
+        self.process_action_press(ev, coords, srclayer, t);
+
+        // Push forward Tap layers - a tap layer remains active only until next keypress
+        for idx in 0..self.layer_stack.len() {
+            if LayerStatus::LayerActiveUntilAnyKeyPress == self.layer_stack[idx].status {
+                self.layer_disable(idx);
+            }
         }
-        let ev = ev.unwrap();
+    }
 
-        // Process the event
+    /// Fire the action `ev` of the key `coords` found in the layer `srclayer`
+    fn process_action_press(
+        &mut self,
+        ev: &KeymapEvent,
+        coords: KeyCoords,
+        srclayer: LayerId,
+        t: Instant,
+    ) {
         match ev {
             // Nothing or indirection leading nowhere
             KeymapEvent::No => {}
@@ -369,24 +1146,63 @@ impl<'a> LayerSwitcher<'a> {
             }
             KeymapEvent::Klong(kshort, _) => {
                 // Record the press with a short key release entry
-                self.presses.push((
-                    srclayer,
+                self.record_press(coords, srclayer, KeyReleaseMode::ForceClick, kshort, t);
+            }
+
+            KeymapEvent::Ktiers(kshort, _) => {
+                // Record the press with a short key release entry
+                self.record_press(coords, srclayer, KeyReleaseMode::ForceClick, kshort, t);
+            }
+
+            KeymapEvent::Kmul(kg, count, delay) => {
+                // Without a pause all the clicks are sent right away
+                let now = if delay.is_zero() {
+                    *count
+                } else {
+                    (*count).min(1)
+                };
+                for _ in 0..now {
+                    self.keygroup_press(kg, coords, srclayer, t, true);
+                }
+                if *count > now {
+                    self.multiplied.push(Multiplied {
+                        coords,
+                        srclayer,
+                        kg: kg.clone(),
+                        remaining: *count - now,
+                        delay: *delay,
+                        due: t + *delay,
+                    });
+                }
+            }
+
+            KeymapEvent::Kturbo(kg, interval, ramp) => {
+                self.keygroup_press(kg, coords, srclayer, t, true);
+                let mut turbo = Turbo {
                     coords,
-                    KeyReleaseMode::ForceClick,
-                    Some(kshort),
-                    t,
-                ));
+                    srclayer,
+                    kg: kg.clone(),
+                    interval: *interval,
+                    ramp: *ramp,
+                    pressed: t,
+                    due: t,
+                };
+                turbo.due += turbo.interval_at(Duration::ZERO);
+                self.turbo.push(turbo);
             }
 
+            KeymapEvent::TapDance(taps) => self.tap_dance_press(taps, coords, srclayer, t),
+
+            KeymapEvent::Oneshot(kg) => self.oneshot_press(kg, coords),
+            KeymapEvent::CapsWord => self.caps_word_toggle(),
+
             KeymapEvent::Khl(k, _) => {
                 // Record the press with a short key release entry
-                self.presses
-                    .push((srclayer, coords, KeyReleaseMode::ForceClick, Some(k), t));
+                self.record_press(coords, srclayer, KeyReleaseMode::ForceClick, k, t);
             }
             KeymapEvent::Khtl(k, _) => {
                 // Record the press with a short key release entry
-                self.presses
-                    .push((srclayer, coords, KeyReleaseMode::ForceClick, Some(k), t));
+                self.record_press(coords, srclayer, KeyReleaseMode::ForceClick, k, t);
             }
 
             KeymapEvent::Lmove(idx) => self.layer_move(*idx),
@@ -400,87 +1216,435 @@ impl<'a> LayerSwitcher<'a> {
             KeymapEvent::Ldeactivate(idx) => {
                 self.layer_deactivate(*idx);
             }
+            KeymapEvent::Ltoggle(idx) => self.layer_toggle(*idx),
             KeymapEvent::LhtL(idx, idx2) => self.layer_hold_tap(*idx, *idx2, coords, t),
             KeymapEvent::LhtK(idx, _) => self.layer_hold_key(*idx, coords, t, srclayer),
-        }
 
-        // Push forward Tap layers - a tap layer remains active only until next keypress
-        for (idx, l) in self.layer_stack.clone().into_iter().enumerate() {
-            if LayerStatus::LayerActiveUntilAnyKeyPress == l.status {
-                self.layer_disable(idx);
+            KeymapEvent::Mplay(name) => self.macro_play(name, coords, t),
+            KeymapEvent::Mcancel => self.macro_cancel(),
+            KeymapEvent::Rollback => self.rollback = true,
+            KeymapEvent::Passthrough => self.bypassed = true,
+            KeymapEvent::Cmd(cmd) => self.commands.push(cmd.clone()),
+            KeymapEvent::If(layer, then, otherwise) => {
+                let branch = if self.get_active_layers().contains(layer) {
+                    then.action()
+                } else {
+                    otherwise.action()
+                };
+                self.branches.insert(coords, branch.clone());
+                self.process_action_press(branch, coords, srclayer, t);
+            }
+            KeymapEvent::IfHeld(key, then, otherwise) => {
+                let branch = if self.held.contains(key) {
+                    then.action()
+                } else {
+                    otherwise.action()
+                };
+                self.branches.insert(coords, branch.clone());
+                self.process_action_press(branch, coords, srclayer, t);
+            }
+            KeymapEvent::Leader(sequences, timeout) => {
+                self.leader = Some(LeaderCapture {
+                    sequences: sequences.clone(),
+                    keys: Vec::new(),
+                    layer: srclayer,
+                    output: self.outputs.get(&coords).cloned().flatten(),
+                    timeout: *timeout,
+                    due: t + *timeout,
+                });
             }
-        }
-    }
-
-    fn process_keyevent_long_press(&mut self, coords: KeyCoords, t: Instant) {
-        // Identify the action associated with the current event
-        let press = self.find_press(coords);
-        if press.is_none() {
-            return;
-        }
-        let press = press.unwrap();
-
-        // Long press was still too short, wait for another one
-        if t - press.4 <= HOLD_THRESHOLD_MS {
-            return;
-        }
 
-        // In case no release events were recorded consult the keymap and press the long keys
-        match self.layers[press.1].get_key_event(coords) {
-            KeymapEvent::Klong(_, klong) => {
-                // When LongPress arrives for the first time, the short click is configured.
-                // Replace it with the Long press.
-                // When LongPress arrives for the second time, the long press is configured
-                // without force_click, use that as a hint that no change is needed.
-                if press.2 == KeyReleaseMode::ForceClick {
-                    // Remove the short press entry
-                    self.presses.swap_remove(press.0);
-
-                    // Emit and record the long press entry
-                    self.keygroup_press(&klong, coords, press.1, t, true);
-                }
+            KeymapEvent::Gbtn(k) => {
+                self.gamepad_events
+                    .push_back(GamepadEvent::Button(*k, true));
+                self.gamepad_presses.push((coords, ev.clone()));
             }
-            KeymapEvent::Khtl(_, l) => {
-                // Remove the short press entry
-                self.presses.swap_remove(press.0);
-                self.layer_tap(*l, coords);
-                self.layer_stack[*l].status = LayerStatus::LayerActiveUntilAnyKeyPress;
+            KeymapEvent::Gaxis(axis, value) => {
+                self.gamepad_axis(*axis, *value);
+                self.gamepad_presses.push((coords, ev.clone()));
             }
-            KeymapEvent::Khl(_, l) => {
-                // Remove the short press entry
-                self.presses.swap_remove(press.0);
-                self.layer_activate(*l);
+            KeymapEvent::Gnudge(axis, delta) => {
+                let value = self.gamepad_axis_value(*axis) + delta;
+                self.gamepad_axis(*axis, value);
+            }
+            KeymapEvent::Pbtn(btn) => {
+                self.emit_keycodes(coords, btn, true);
+                self.pointer_presses.push((coords, *btn));
+            }
+            KeymapEvent::Pmove(..) | KeymapEvent::Pscroll(..) => {
+                for (axis, delta) in pointer_axes(ev) {
+                    if delta != 0 {
+                        self.pointer_events.push_back((axis, delta));
+                    }
+                }
+            }
+            // Unwrapped by the layer already
+            KeymapEvent::Cooldown(..)
+            | KeymapEvent::HoldThreshold(..)
+            | KeymapEvent::Output(..) => {}
+            KeymapEvent::Mrec(name) => {
+                if self.recording.take().is_none() {
+                    self.recording = Some(name.clone());
+                }
             }
-            _ => {}
         }
     }
 
-    /// Find if there is an associated recorded key release entry for `coords`
-    fn find_press(
-        &self,
+    /// Start a caps word, or end the current one
+    fn caps_word_toggle(&mut self) {
+        if self.caps_word {
+            self.caps_word_end();
+        } else {
+            self.emit_keycodes(LAYER_KEY, &KeyCode::KEY_LEFTSHIFT, true);
+            self.caps_word = true;
+        }
+    }
+
+    /// Release the Shift of the caps word
+    fn caps_word_end(&mut self) {
+        if std::mem::take(&mut self.caps_word) {
+            self.emit_keycodes(LAYER_KEY, &KeyCode::KEY_LEFTSHIFT, false);
+        }
+    }
+
+    /// Count a tap of the tap dance key, the last key group is clicked
+    /// right away as there is nothing to wait for
+    fn tap_dance_press(
+        &mut self,
+        taps: &[KeyGroup],
         coords: KeyCoords,
-    ) -> Option<(
-        usize,
-        LayerId,
-        KeyReleaseMode,
-        Option<&'a KeyGroup>,
-        Instant,
-    )> {
-        for (idx, (layer, coord, release_mode, kgroup, t)) in
-            (&self.presses).into_iter().enumerate()
+        srclayer: LayerId,
+        t: Instant,
+    ) {
+        let count = match self.tap_dance.take() {
+            Some(dance) if dance.coords == coords => dance.count + 1,
+            _ => 1,
+        };
+        self.tap_dance = Some(TapDance {
+            coords,
+            srclayer,
+            taps: taps.to_vec(),
+            count,
+            due: t + self.hold_threshold_of(coords),
+        });
+        if count >= taps.len() {
+            self.tap_dance_end();
+        }
+    }
+
+    /// Click the key group of the number of taps
+    fn tap_dance_end(&mut self) {
+        let Some(dance) = self.tap_dance.take() else {
+            return;
+        };
+        debug!(coords = ?dance.coords, "Tap dance of {} taps", dance.count);
+        if let Some(kg) = dance.taps.get(dance.count - 1) {
+            self.keygroup_press(kg, dance.coords, dance.srclayer, dance.due, true);
+        }
+    }
+
+    /// End the tap dance when the key was not tapped again before time `t`
+    fn tap_dance_timeout(&mut self, t: Instant) {
+        if self.tap_dance.as_ref().is_some_and(|dance| dance.due <= t) {
+            self.tap_dance_end();
+        }
+    }
+
+    /// Add the key to the sequence of the waiting leader. The action is fired
+    /// once the sequence is complete, the leader is aborted when no sequence
+    /// starts with the keys.
+    fn leader_capture(&mut self, coords: KeyCoords, t: Instant) {
+        let Some(mut leader) = self.leader.take() else {
+            return;
+        };
+        leader.keys.push(coords);
+
+        let sequences = std::mem::take(&mut leader.sequences);
+        match sequences.iter().find(|s| s.keys.starts_with(&leader.keys)) {
+            Some(s) if s.keys == leader.keys => {
+                debug!(keys = ?leader.keys, "Leader selected {:?}", s.action);
+                self.current_event = Some((coords, t));
+                self.outputs.insert(coords, leader.output);
+                self.process_action_press(&s.action, coords, leader.layer, t);
+            }
+            Some(_) => {
+                self.debounced.insert(coords);
+                leader.sequences = sequences;
+                leader.due = t + leader.timeout;
+                self.leader = Some(leader);
+            }
+            None => {
+                debug!(keys = ?leader.keys, "Unknown leader sequence");
+                self.debounced.insert(coords);
+            }
+        }
+    }
+
+    /// Abort the leader when no key was pressed before the timeout at time `t`
+    fn leader_timeout(&mut self, t: Instant) {
+        if self.leader.as_ref().is_some_and(|leader| leader.due <= t) {
+            debug!("Leader timed out");
+            self.leader = None;
+        }
+    }
+
+    fn process_keyevent_long_press(&mut self, coords: KeyCoords, t: Instant) {
+        self.current_event = Some((coords, t));
+
+        // Remember the hold decision for dual role layer keys
+        for l in &self.layer_stack {
+            match l.status {
+                LayerStatus::LayerHoldAndTapKey(wait_coords, t0, _)
+                | LayerStatus::LayerHoldAndTapToL(wait_coords, t0, _)
+                    if wait_coords == coords && t - t0 > self.hold_threshold_of(coords) =>
+                {
+                    self.long_pressed.insert(coords);
+                }
+                _ => {}
+            }
+        }
+
+        // Identify the action associated with the current event
+        let Some((layer, mode, t0)) = self.find_press(coords) else {
+            return;
+        };
+
+        // Graded long press has its own thresholds, the longest tier
+        // is clicked as soon as it is reached
+        if let KeymapEvent::Ktiers(_, tiers) = self.pressed_event(layer, coords).clone() {
+            if let Some((threshold, klong)) = tiers.last() {
+                if mode == KeyReleaseMode::ForceClick && t - t0 > *threshold {
+                    self.presses.remove(&coords);
+                    self.keygroup_press(klong, coords, layer, t, true);
+                }
+            }
+            return;
+        }
+
+        // Long press was still too short, wait for another one
+        if t - t0 <= self.hold_threshold_of(coords) {
+            return;
+        }
+
+        self.hold_press(coords, t);
+    }
+
+    /// Resolve the recorded press of the dual role key `coords` as a hold
+    fn hold_press(&mut self, coords: KeyCoords, t: Instant) {
+        let Some((layer, mode, _)) = self.find_press(coords) else {
+            return;
+        };
+
+        // In case no release events were recorded consult the keymap and press the long keys
+        match self.pressed_event(layer, coords).clone() {
+            // When LongPress arrives for the first time, the short click is configured.
+            // Replace it with the Long press.
+            // When LongPress arrives for the second time, the long press is configured
+            // without force_click, use that as a hint that no change is needed.
+            KeymapEvent::Klong(_, klong) if mode == KeyReleaseMode::ForceClick => {
+                // Remove the short press entry
+                self.presses.remove(&coords);
+
+                // Emit and record the long press entry
+                self.keygroup_press(&klong, coords, layer, t, true);
+            }
+            KeymapEvent::Khtl(_, l) => {
+                // Remove the short press entry
+                self.presses.remove(&coords);
+                self.layer_tap(l, coords);
+                self.layer_stack[l].status = LayerStatus::LayerActiveUntilAnyKeyPress;
+            }
+            KeymapEvent::Khl(_, l) => {
+                // Remove the short press entry
+                self.presses.remove(&coords);
+                self.layer_activate(l);
+            }
+            _ => {}
+        }
+    }
+
+    /// The dual role keys other than `coords` still waiting for the tap
+    /// or hold decision
+    fn undecided_holds(&self, coords: KeyCoords) -> Vec<KeyCoords> {
+        let mut presses: Vec<(&KeyCoords, &PressEntry)> = self
+            .presses
+            .iter()
+            .filter(|(coords, press)| {
+                press.mode == KeyReleaseMode::ForceClick
+                    && matches!(
+                        self.pressed_event(press.layer, **coords),
+                        KeymapEvent::Klong(..) | KeymapEvent::Khl(..) | KeymapEvent::Khtl(..)
+                    )
+            })
+            .collect();
+        // The holds are decided in the order of their presses
+        presses.sort_by_key(|(coords, press)| (press.t, **coords));
+        let layers = self.layer_stack.iter().filter_map(|l| match l.status {
+            LayerStatus::LayerHoldAndTapKey(wait_coords, _, _)
+            | LayerStatus::LayerHoldAndTapToL(wait_coords, _, _)
+                if !self.long_pressed.contains(&wait_coords) =>
+            {
+                Some(wait_coords)
+            }
+            _ => None,
+        });
+        presses
+            .into_iter()
+            .map(|(coords, _)| *coords)
+            .chain(layers)
+            .filter(|held| *held != coords)
+            .collect()
+    }
+
+    /// Resolve the held dual role key `coords` as a hold before its
+    /// hold threshold elapsed
+    fn decide_hold(&mut self, coords: KeyCoords, t: Instant) {
+        self.current_event = Some((coords, t));
+        self.long_pressed.insert(coords);
+        if self
+            .find_press(coords)
+            .is_some_and(|(_, mode, _)| mode == KeyReleaseMode::ForceClick)
         {
-            if *coord == coords {
-                return Some((idx, *layer, *release_mode, *kgroup, *t));
+            self.hold_press(coords, t);
+        }
+    }
+
+    /// Pass the held back presses on once no dual role key waits for
+    /// the decision anymore
+    fn release_held_back(&mut self) {
+        if self.held_back.is_empty() || !self.undecided_holds(LAYER_KEY).is_empty() {
+            return;
+        }
+        // A replayed dual role key holds back the presses after it again
+        for (coords, t) in std::mem::take(&mut self.held_back) {
+            self.process_keyevent_press(coords, t);
+        }
+        self.current_event = None;
+    }
+
+    /// Get the key group of the highest graded long press tier reached
+    /// after holding `coords` for `elapsed`
+    fn reached_tier(
+        &self,
+        layer: LayerId,
+        coords: KeyCoords,
+        elapsed: Duration,
+    ) -> Option<&KeyGroup> {
+        match self.pressed_event(layer, coords) {
+            KeymapEvent::Ktiers(_, tiers) => tiers
+                .iter()
+                .rev()
+                .find(|(threshold, _)| elapsed > *threshold)
+                .map(|(_, kg)| kg),
+            _ => None,
+        }
+    }
+
+    /// Return all the thresholds used by graded long press keys together
+    /// with the hold thresholds. The long press detector needs to
+    /// keep reporting long presses until all of them elapse.
+    pub fn get_long_press_tiers(&self) -> Vec<Duration> {
+        let mut tiers = vec![self.hold_threshold];
+        for l in self.layers.iter() {
+            tiers.extend(
+                l.positions()
+                    .filter_map(|(coords, _)| l.get_hold_threshold(coords)),
+            );
+            for b in &l.keymap {
+                for r in b {
+                    for ev in r.iter().flat_map(KeymapEvent::actions) {
+                        if let KeymapEvent::Ktiers(_, t) = ev {
+                            tiers.extend(t.iter().map(|(threshold, _)| *threshold));
+                        }
+                    }
+                }
+            }
+        }
+        tiers
+    }
+
+    /// Find if there is an associated recorded key release entry for `coords`,
+    /// returns its layer, release mode and the time of the press
+    fn find_press(&self, coords: KeyCoords) -> Option<(LayerId, KeyReleaseMode, Instant)> {
+        self.presses
+            .get(&coords)
+            .map(|press| (press.layer, press.mode, press.t))
+    }
+
+    /// Decide whether a dual role key pressed at `t0` and released at `t`
+    /// was a tap
+    fn is_tap(&self, coords: KeyCoords, t0: Instant, t: Instant) -> bool {
+        match self.long_press_race {
+            LongPressRace::HoldWins => {
+                t - t0 < self.hold_threshold_of(coords) && !self.long_pressed.contains(&coords)
             }
+            LongPressRace::TapWins => !self.long_pressed.contains(&coords),
         }
-        return None;
     }
 
     /// This is the main key release handling function
     fn process_keyevent_release(&mut self, coords: KeyCoords, t: Instant) {
+        // The press was ignored, ignore the release as well
+        if self.debounced.remove(&coords) {
+            return;
+        }
+
+        // A full tap of a held back key makes the held dual role keys holds
+        while self.held_back.iter().any(|(c, _)| *c == coords) {
+            for held in self.undecided_holds(coords) {
+                self.decide_hold(held, t);
+            }
+            self.release_held_back();
+        }
+
+        // The window for the next tap starts with the release
+        let window = self.hold_threshold_of(coords);
+        if let Some(dance) = self
+            .tap_dance
+            .as_mut()
+            .filter(|dance| dance.coords == coords)
+        {
+            dance.due = t + window;
+        }
+
+        // Stop clicking a held turbo key
+        self.turbo.retain(|turbo| turbo.coords != coords);
+        self.repeats.retain(|(c, _)| *c != coords);
+
+        // Release the held gamepad buttons and axes
+        if let Some(idx) = self.gamepad_presses.iter().position(|(c, _)| *c == coords) {
+            let (coords, ev) = self.gamepad_presses.remove(idx);
+            self.gamepad_release(coords, &ev);
+        }
+
+        // Release the held mouse buttons
+        if let Some(idx) = self.pointer_presses.iter().position(|(c, _)| *c == coords) {
+            let (coords, btn) = self.pointer_presses.remove(idx);
+            self.emit_keycodes(coords, &btn, false);
+        }
+
+        // Stop looping a macro played while held
+        if let Some(playing) = self.playing.as_mut() {
+            if playing.coords == coords {
+                playing.held = false;
+            }
+        }
+
+        // The hold threshold elapsed, but the LongPress did not arrive yet.
+        // Resolve the hold before the release.
+        if self.long_press_race == LongPressRace::HoldWins {
+            if let Some((_, mode, t0)) = self.find_press(coords) {
+                if mode == KeyReleaseMode::ForceClick && t - t0 > self.hold_threshold_of(coords) {
+                    self.process_keyevent_long_press(coords, t);
+                }
+            }
+        }
+
+        self.current_event = Some((coords, t));
+
         // Deactivate layers
-        for (idx, l) in self.layer_stack.clone().into_iter().enumerate() {
-            match l.status {
+        for idx in 0..self.layer_stack.len() {
+            match self.layer_stack[idx].status {
                 LayerStatus::LayerActiveUntilKeyRelease(wait_coords) => {
                     if wait_coords == coords {
                         self.layer_deactivate(idx);
@@ -495,9 +1659,8 @@ impl<'a> LayerSwitcher<'a> {
                     if wait_coords == coords {
                         self.layer_deactivate(idx);
 
-                        let elapsed = t - t0;
-                        if elapsed < HOLD_THRESHOLD_MS {
-                            let kev = self.layers[lidx].get_key_event(wait_coords);
+                        if self.is_tap(coords, t0, t) {
+                            let kev = self.pressed_event(lidx, wait_coords).clone();
                             match kev {
                                 KeymapEvent::LhtK(_, k) => {
                                     self.keygroup_press(&k, coords, lidx, t, true);
@@ -511,8 +1674,7 @@ impl<'a> LayerSwitcher<'a> {
                     if wait_coords == coords {
                         self.layer_deactivate(idx);
 
-                        let elapsed = t - t0;
-                        if elapsed < HOLD_THRESHOLD_MS {
+                        if self.is_tap(coords, t0, t) {
                             self.layer_tap(next_layer, coords);
                             // This is the first release already, just wait for next key
                             self.layer_stack[next_layer].status =
@@ -523,59 +1685,87 @@ impl<'a> LayerSwitcher<'a> {
                 _ => {}
             }
         }
-
-        // Identify the action associated with the current event
-        let press = self.find_press(coords);
-        if press.is_none() {
-            return;
-        }
-        let press = press.unwrap();
+        self.long_pressed.remove(&coords);
 
         // Release key if recorded as pressed
-        self.presses.swap_remove(press.0);
-
-        if let Some(kg) = press.3 {
-            if press.2 == KeyReleaseMode::ForceClick {
-                // consult the keymap and send the short keys as full click
-                self.keygroup_press(&kg, coords, press.1, t, true);
+        let Some(press) = self.presses.remove(&coords) else {
+            return;
+        };
+
+        if let Some(kg) = press.kg {
+            if press.mode == KeyReleaseMode::ForceClick {
+                // consult the keymap and send the short keys (or the keys of
+                // the reached long press tier) as full click
+                let kg = self
+                    .reached_tier(press.layer, coords, t - press.t)
+                    .cloned()
+                    .unwrap_or(kg);
+                self.keygroup_press(&kg, coords, press.layer, t, true);
             } else {
-                self.keygroup_release(&kg, coords, press.1);
+                self.keygroup_release(&kg, coords, press.layer);
             }
         }
 
         // Reactivate on_active key when needed
-        self.after_key_release(press.1);
+        self.after_key_release(press.layer);
     }
 
+    /// The action of `coords` in the layer `idx` or the layers it inherits from,
+    /// with the layer it was found in (0 for the default action)
     fn get_key_event_inheritance(
-        &self,
+        layers: &[Layer],
         coords: KeyCoords,
         idx: LayerId,
-    ) -> (LayerId, &'a KeymapEvent) {
+    ) -> (LayerId, &KeymapEvent) {
         let mut layer_idx = idx;
         loop {
-            let ev = (&self.layers)[layer_idx].get_key_event(coords);
+            let ev = layers[layer_idx].get_key_event(coords);
             match ev {
-                KeymapEvent::No => return (idx, ev),
-
-                KeymapEvent::Kg(_) => return (idx, ev),
-                KeymapEvent::Klong(..) => return (idx, ev),
-
-                KeymapEvent::Khl(..) => return (idx, ev),
-                KeymapEvent::Khtl(..) => return (idx, ev),
-
-                KeymapEvent::Lmove(_) => return (idx, ev),
-                KeymapEvent::Lhold(_) => return (idx, ev),
-                KeymapEvent::Ltap(_) => return (idx, ev),
-                KeymapEvent::Lactivate(_) => return (idx, ev),
-                KeymapEvent::Ldeactivate(_) => return (idx, ev),
-                KeymapEvent::Ldisable(_) => return (idx, ev),
-                KeymapEvent::LhtL(..) => return (idx, ev),
-                KeymapEvent::LhtK(..) => return (idx, ev),
+                KeymapEvent::No => return (layer_idx, ev),
+
+                KeymapEvent::Kg(_) => return (layer_idx, ev),
+                KeymapEvent::Klong(..) => return (layer_idx, ev),
+                KeymapEvent::Ktiers(..) => return (layer_idx, ev),
+                KeymapEvent::Kmul(..) => return (layer_idx, ev),
+                KeymapEvent::Kturbo(..) => return (layer_idx, ev),
+                KeymapEvent::TapDance(_) => return (layer_idx, ev),
+                KeymapEvent::Oneshot(_) => return (layer_idx, ev),
+                KeymapEvent::CapsWord => return (layer_idx, ev),
+
+                KeymapEvent::Khl(..) => return (layer_idx, ev),
+                KeymapEvent::Khtl(..) => return (layer_idx, ev),
+
+                KeymapEvent::Lmove(_) => return (layer_idx, ev),
+                KeymapEvent::Lhold(_) => return (layer_idx, ev),
+                KeymapEvent::Ltap(_) => return (layer_idx, ev),
+                KeymapEvent::Lactivate(_) => return (layer_idx, ev),
+                KeymapEvent::Ldeactivate(_) => return (layer_idx, ev),
+                KeymapEvent::Ltoggle(_) => return (layer_idx, ev),
+                KeymapEvent::Ldisable(_) => return (layer_idx, ev),
+                KeymapEvent::LhtL(..) => return (layer_idx, ev),
+                KeymapEvent::LhtK(..) => return (layer_idx, ev),
+                KeymapEvent::Mplay(_) => return (layer_idx, ev),
+                KeymapEvent::Mrec(_) => return (layer_idx, ev),
+                KeymapEvent::Mcancel => return (layer_idx, ev),
+                KeymapEvent::Rollback => return (layer_idx, ev),
+                KeymapEvent::Passthrough => return (layer_idx, ev),
+                KeymapEvent::Cmd(_) => return (layer_idx, ev),
+                KeymapEvent::Leader(..) => return (layer_idx, ev),
+                KeymapEvent::If(..) => return (layer_idx, ev),
+                KeymapEvent::IfHeld(..) => return (layer_idx, ev),
+                KeymapEvent::Gbtn(_) => return (layer_idx, ev),
+                KeymapEvent::Gaxis(..) => return (layer_idx, ev),
+                KeymapEvent::Gnudge(..) => return (layer_idx, ev),
+                KeymapEvent::Pbtn(_) => return (layer_idx, ev),
+                KeymapEvent::Pmove(..) => return (layer_idx, ev),
+                KeymapEvent::Pscroll(..) => return (layer_idx, ev),
+                KeymapEvent::Cooldown(..) => return (layer_idx, ev),
+                KeymapEvent::HoldThreshold(..) => return (layer_idx, ev),
+                KeymapEvent::Output(..) => return (layer_idx, ev),
 
                 KeymapEvent::Inh => {
                     // find the layer this inherits from
-                    if let Some(next_p_idx) = (&self.layers)[layer_idx].inherit {
+                    if let Some(next_p_idx) = layers[layer_idx].inherit {
                         // TODO check that the parent layer ID is valid
                         layer_idx = next_p_idx;
                     } else {
@@ -586,20 +1776,102 @@ impl<'a> LayerSwitcher<'a> {
             }
         }
 
-        return (0, &(&self.layers)[layer_idx].default_action);
+        (0, &layers[layer_idx].default_action)
+    }
+
+    /// Check whether the action of `coords` fired less than its cooldown
+    /// ago. The time of the press is remembered when it is allowed.
+    fn is_cooling_down(&mut self, coords: KeyCoords, t: Instant) -> bool {
+        let Some(cooldown) = self.get_key_cooldown(coords) else {
+            return false;
+        };
+
+        if self
+            .last_fired
+            .get(&coords)
+            .is_some_and(|t0| t - *t0 < cooldown)
+        {
+            return true;
+        }
+        self.last_fired.insert(coords, t);
+        false
+    }
+
+    /// Resolve which layers the keymap event currently mapped to key `coords`
+    /// comes from. Returns the active layer and the layer the event was found
+    /// in, they differ when the event is inherited.
+    fn get_key_event_layers(&self, coords: KeyCoords) -> Option<(LayerId, LayerId)> {
+        for (idx, l) in self.layer_stack.iter().enumerate().rev() {
+            // Skip disabled layers
+            if l.status == LayerStatus::LayerDisabled || l.status == LayerStatus::LayerPassthrough {
+                continue;
+            }
+
+            let (layerid, ev) = Self::get_key_event_inheritance(&self.layers, coords, idx);
+            if *ev != KeymapEvent::Pass {
+                return Some((idx, layerid));
+            }
+        }
+
+        None
+    }
+
+    /// Resolve the cooldown of the keymap event currently mapped to key `coords`.
+    /// The cooldown is taken from the layer the event was found in.
+    fn get_key_cooldown(&self, coords: KeyCoords) -> Option<Duration> {
+        let (_, layerid) = self.get_key_event_layers(coords)?;
+        self.layers[layerid].get_cooldown(coords)
+    }
+
+    /// The action of the pressed key `coords` found in `layer` or the layers
+    /// it inherits from, an If is replaced by the branch chosen on press
+    fn pressed_event(&self, layer: LayerId, coords: KeyCoords) -> &KeymapEvent {
+        let (_, ev) = Self::get_key_event_inheritance(&self.layers, coords, layer);
+        match ev {
+            KeymapEvent::If(..) | KeymapEvent::IfHeld(..) => {
+                self.branches.get(&coords).unwrap_or(ev)
+            }
+            _ => ev,
+        }
+    }
+
+    /// Resolve the hold threshold of the keymap event currently mapped to key
+    /// `coords`. The threshold is taken from the layer the event was found in.
+    fn get_key_hold_threshold(&self, coords: KeyCoords) -> Option<Duration> {
+        let (_, layerid) = self.get_key_event_layers(coords)?;
+        self.layers[layerid].get_hold_threshold(coords)
+    }
+
+    /// The hold threshold of the pressed key `coords`, its own or the one
+    /// of the layout
+    fn hold_threshold_of(&self, coords: KeyCoords) -> Duration {
+        self.hold_thresholds
+            .get(&coords)
+            .copied()
+            .unwrap_or(self.hold_threshold)
+    }
+
+    /// Resolve the output device of the keymap event currently mapped to key
+    /// `coords`. The binding wins over the layer, None is the main keyboard.
+    fn get_key_output(&self, coords: KeyCoords) -> Option<Arc<str>> {
+        let (idx, layerid) = self.get_key_event_layers(coords)?;
+        let name = self.layers[layerid]
+            .get_output(coords)
+            .or(self.layers[idx].output.as_deref())?;
+        self.output_names.iter().find(|n| ***n == *name).cloned()
     }
 
     /// Resolve the keymap event currently mapped to key `coords`. Take into
     /// account the state of all layers and inheritance.
     /// Returns the keymap event and the layer it came from
-    fn get_key_event(&self, coords: KeyCoords) -> (LayerId, Option<&'a KeymapEvent>) {
+    fn get_key_event(&self, coords: KeyCoords) -> (LayerId, Option<&KeymapEvent>) {
         for (idx, l) in (&self.layer_stack).into_iter().enumerate().rev() {
             // Skip disabled layers
             if l.status == LayerStatus::LayerDisabled || l.status == LayerStatus::LayerPassthrough {
                 continue;
             }
 
-            let (_layerid, ev) = self.get_key_event_inheritance(coords, idx);
+            let (_layerid, ev) = Self::get_key_event_inheritance(&self.layers, coords, idx);
             if *ev != KeymapEvent::Pass {
                 return (idx, Some(ev));
             }
@@ -608,9 +1880,352 @@ impl<'a> LayerSwitcher<'a> {
         (0, None)
     }
 
+    /// Start playing a macro, a macro that is already running is cancelled.
+    /// The steps that are due are queued immediately, the rest by `tick`.
+    fn macro_play(&mut self, name: &str, coords: KeyCoords, t: Instant) {
+        self.macro_cancel();
+
+        let Some(m) = self.macros.get(name) else {
+            warn!("Unknown macro {}", name);
+            return;
+        };
+        if m.steps.is_empty() {
+            return;
+        }
+
+        self.playing = Some(MacroPlayback {
+            m: m.clone(),
+            coords,
+            held: true,
+            iteration: 0,
+            frames: vec![(m.steps.clone(), 0)],
+            delayed: false,
+            due: t,
+            pressed: Vec::new(),
+        });
+        self.macro_advance(t);
+    }
+
+    /// Abort the running macro and release the keys it holds
+    fn macro_cancel(&mut self) {
+        let Some(playing) = self.playing.take() else {
+            return;
+        };
+
+        for k in playing.pressed.iter().rev() {
+            self.emit_keycodes(LAYER_KEY, k, false);
+        }
+    }
+
+    /// Execute all macro steps due at time `t`
+    fn macro_advance(&mut self, t: Instant) {
+        while let Some(playing) = self.playing.as_mut() {
+            let Some((steps, next)) = playing.frames.last_mut() else {
+                playing.iteration += 1;
+                let again = match playing.m.repeat {
+                    MacroRepeat::Times(n) => playing.iteration < n,
+                    MacroRepeat::WhileHeld => playing.held,
+                };
+                if !again {
+                    // Keys left pressed by the macro are released at the end
+                    self.macro_cancel();
+                    return;
+                }
+
+                playing.frames.push((playing.m.steps.clone(), 0));
+                if playing.m.repeat == MacroRepeat::WhileHeld {
+                    // Give the release a chance to stop the loop
+                    return;
+                }
+                continue;
+            };
+
+            if *next == steps.len() {
+                playing.frames.pop();
+                continue;
+            }
+
+            let step = steps[*next].clone();
+            if !playing.delayed {
+                playing.due += playing.m.delay(&step);
+                playing.delayed = true;
+            }
+            if playing.due > t {
+                return;
+            }
+
+            if let MacroStep::WaitKey { wait_key, pressed } = step {
+                if self.host_keys.contains(&wait_key) != pressed {
+                    playing.due = t + MACRO_WAIT_POLL;
+                    return;
+                }
+            }
+
+            *next += 1;
+            playing.delayed = false;
+
+            match step {
+                MacroStep::Key { key, pressed, .. } => {
+                    if pressed {
+                        playing.pressed.push(key);
+                    } else {
+                        playing.pressed.retain(|k| *k != key);
+                    }
+                    self.emit_keycodes(LAYER_KEY, &key, pressed);
+                }
+                MacroStep::Layer { layer, active } if layer < self.layers.len() => {
+                    let reason =
+                        std::mem::replace(&mut self.change_reason, LayerChangeReason::Macro);
+                    if active {
+                        self.layer_activate(layer);
+                    } else {
+                        self.layer_deactivate(layer);
+                    }
+                    self.change_reason = reason;
+                }
+                MacroStep::If {
+                    if_layer,
+                    then,
+                    otherwise,
+                } => {
+                    let branch = if self.get_active_layers().contains(&if_layer) {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    if let Some(playing) = self.playing.as_mut() {
+                        playing.frames.push((branch, 0));
+                    }
+                }
+                MacroStep::Layer { .. } | MacroStep::Delay { .. } | MacroStep::WaitKey { .. } => {}
+            }
+        }
+    }
+
+    /// Take the time of `tick_now` and `next_timer_in` from the `clock`
+    pub fn set_clock<C>(&mut self, clock: C)
+    where
+        C: Clock + Send + 'static,
+    {
+        self.clock = Box::new(clock);
+    }
+
+    /// Time tick at the current time of the clock
+    pub fn tick_now(&mut self) {
+        let t = self.clock.now();
+        self.tick(t);
+    }
+
+    /// Time tick, plays the running macro, clicks the held turbo keys
+    /// and the multiplied keys and leaves the layers whose timeout elapsed
+    pub fn tick(&mut self, t: Instant) {
+        self.idle_return(t);
+        self.layer_timeouts(t);
+        self.leader_timeout(t);
+        self.tap_dance_timeout(t);
+        self.macro_advance(t);
+        self.turbo_advance(t);
+        self.multiplied_advance(t);
+        self.repeat_advance(t);
+    }
+
+    /// When does the active layer `idx` time out?
+    fn layer_deadline(&self, idx: LayerId) -> Option<Instant> {
+        let l = &self.layer_stack[idx];
+        if idx == 0
+            || l.status == LayerStatus::LayerDisabled
+            || l.status == LayerStatus::LayerPassthrough
+        {
+            return None;
+        }
+
+        let (t0, _) = l.activated?;
+        Some(t0 + self.layers[idx].timeout?)
+    }
+
+    /// Deactivate the layers whose timeout elapsed at time `t` and activate
+    /// their `on_timeout_layer` instead
+    fn layer_timeouts(&mut self, t: Instant) {
+        let reason = std::mem::replace(&mut self.change_reason, LayerChangeReason::Timeout);
+        for idx in 0..self.layer_stack.len() {
+            if self.layer_deadline(idx).is_none_or(|deadline| deadline > t) {
+                continue;
+            }
+
+            self.layer_deactivate(idx);
+            if let Some(next) = self.layers[idx].on_timeout_layer {
+                // The next layer's own timeout counts from now
+                self.current_event = Some((LAYER_KEY, t));
+                self.layer_activate(next);
+                self.current_event = None;
+            }
+        }
+        self.change_reason = reason;
+    }
+
+    /// Leave all the layers but the base one, and the ones active by default,
+    /// once no key event came for the idle timeout at time `t`. A layer
+    /// following a condition is kept when the condition holds.
+    fn idle_return(&mut self, t: Instant) {
+        if self.idle_deadline().is_none_or(|deadline| deadline > t) {
+            return;
+        }
+        self.last_input = None;
+        // A held key is not idle, it only does not report
+        if !self.held.is_empty() {
+            return;
+        }
+
+        debug!("Idle, returning to the base layer");
+        let reason = std::mem::replace(&mut self.change_reason, LayerChangeReason::Idle);
+        self.oneshot_release();
+        self.caps_word_end();
+        for idx in 1..self.layer_stack.len() {
+            if self.layers[idx].status_on_reset != LayerStatus::LayerActive {
+                self.layer_deactivate(idx);
+            }
+        }
+        self.change_reason = reason;
+        self.apply_conditions();
+    }
+
+    /// When does the idle timeout elapse?
+    fn idle_deadline(&self) -> Option<Instant> {
+        Some(self.last_input? + self.idle_timeout?)
+    }
+
+    /// When is the next step of the running macro due? The caller
+    /// has to call `tick` at that time.
+    pub fn next_macro_step(&self) -> Option<Instant> {
+        self.playing.as_ref().map(|playing| playing.due)
+    }
+
+    /// When is the next macro step, turbo or multiplied click, layer or idle
+    /// timeout due? The caller has to call `tick` at that time.
+    pub fn next_timer(&self) -> Option<Instant> {
+        self.turbo
+            .iter()
+            .map(|turbo| turbo.due)
+            .chain(self.multiplied.iter().map(|m| m.due))
+            .chain(self.repeats.iter().map(|(_, due)| *due))
+            .chain(self.next_macro_step())
+            .chain(self.leader.as_ref().map(|leader| leader.due))
+            .chain(self.tap_dance.as_ref().map(|dance| dance.due))
+            .chain((0..self.layer_stack.len()).filter_map(|idx| self.layer_deadline(idx)))
+            .chain(self.idle_deadline())
+            .min()
+    }
+
+    /// How long until the next timer is due by the clock, zero when
+    /// it is overdue
+    pub fn next_timer_in(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.next_timer()
+            .map(|due| due.saturating_duration_since(now))
+    }
+
+    /// Click the turbo keys that are due at time `t`. Clicks missed
+    /// while nobody was ticking are skipped, not sent in a burst.
+    fn turbo_advance(&mut self, t: Instant) {
+        for idx in 0..self.turbo.len() {
+            let turbo = &self.turbo[idx];
+            if turbo.due > t {
+                continue;
+            }
+
+            let (kg, coords, srclayer) = (turbo.kg.clone(), turbo.coords, turbo.srclayer);
+            self.keygroup_press(&kg, coords, srclayer, t, true);
+
+            let turbo = &mut self.turbo[idx];
+            let next = turbo.due + turbo.interval_at(turbo.due - turbo.pressed);
+            turbo.due = if next > t {
+                next
+            } else {
+                t + turbo.interval_at(t - turbo.pressed)
+            };
+        }
+    }
+
+    /// Send the multiplied clicks that are due at time `t`. Unlike turbo
+    /// clicks the missed ones are all sent, the count matters.
+    fn multiplied_advance(&mut self, t: Instant) {
+        for idx in 0..self.multiplied.len() {
+            while self.multiplied[idx].remaining > 0 && self.multiplied[idx].due <= t {
+                let m = &self.multiplied[idx];
+                let (kg, coords, srclayer) = (m.kg.clone(), m.coords, m.srclayer);
+                self.keygroup_press(&kg, coords, srclayer, t, true);
+
+                let m = &mut self.multiplied[idx];
+                m.remaining -= 1;
+                m.due += m.delay;
+            }
+        }
+        self.multiplied.retain(|m| m.remaining > 0);
+    }
+
+    /// Repeat the last key of the held key groups that are due at time `t`.
+    /// Like turbo clicks, repeats missed while nobody was ticking are skipped.
+    fn repeat_advance(&mut self, t: Instant) {
+        let Some(repeat) = self.key_repeat else {
+            return;
+        };
+
+        for idx in 0..self.repeats.len() {
+            let (coords, due) = self.repeats[idx];
+            if due > t {
+                continue;
+            }
+
+            // Another press of a key that is down already, the virtual
+            // keyboard sends it as an autorepeat (value 2)
+            if let Some(k) = self
+                .presses
+                .get(&coords)
+                .and_then(|press| press.kg.as_ref()?.keys.last().copied())
+            {
+                self.emit_keycodes(coords, &k, true);
+            }
+
+            let next = due + repeat.period;
+            self.repeats[idx].1 = if next > t { next } else { t + repeat.period };
+        }
+    }
+
+    /// Undo the gamepad action of a released key
+    fn gamepad_release(&mut self, _coords: KeyCoords, ev: &KeymapEvent) {
+        match ev {
+            KeymapEvent::Gbtn(k) => self
+                .gamepad_events
+                .push_back(GamepadEvent::Button(*k, false)),
+            KeymapEvent::Gaxis(axis, _) => self.gamepad_axis(*axis, 0),
+            _ => {}
+        }
+    }
+
+    fn gamepad_axis_value(&self, axis: AbsoluteAxisType) -> i32 {
+        self.gamepad_axes
+            .iter()
+            .find(|(a, _)| *a == axis)
+            .map_or(0, |(_, value)| *value)
+    }
+
+    /// Move a gamepad axis, the value is clamped to the axis range
+    fn gamepad_axis(&mut self, axis: AbsoluteAxisType, value: i32) {
+        let value = value.clamp(-GAMEPAD_AXIS_MAX, GAMEPAD_AXIS_MAX);
+        self.gamepad_axes.retain(|(a, _)| *a != axis);
+        self.gamepad_axes.push((axis, value));
+        self.gamepad_events
+            .push_back(GamepadEvent::Axis(axis, value));
+    }
+
     /// Record a keycode event to be sent to the OS
-    fn emit_keycodes(&mut self, _coords: KeyCoords, k: &evdev::Key, pressed: bool) {
-        self.emitted_codes.push_back((*k, pressed));
+    fn emit_keycodes(&mut self, coords: KeyCoords, k: &KeyCode, pressed: bool) {
+        // The switcher watches its own keys for the end of a caps word
+        if pressed && self.caps_word && !CAPS_WORD_KEYS.contains(k) {
+            self.caps_word_end();
+        }
+        let output = self.outputs.get(&coords).cloned().flatten();
+        self.emitted_codes.push_back((*k, pressed, output));
     }
 
     /// This is the input entrypoint for external key events. Right now everything is processed
@@ -623,43 +2238,196 @@ impl<'a> LayerSwitcher<'a> {
             self.layer_stack.len() > 0,
             "The layout engine was not started."
         );
+        let t = t.into();
+        self.idle_return(t);
+        self.last_input = Some(t);
+        self.layer_timeouts(t);
+        self.leader_timeout(t);
+        self.tap_dance_timeout(t);
+        let ev: KeyStateChange<KeyCoords> = ev.map(Into::into);
+        let (KeyStateChange::Pressed(k)
+        | KeyStateChange::Released(k)
+        | KeyStateChange::Click(k)
+        | KeyStateChange::LongPress(k, _)
+        | KeyStateChange::Turn(k, _)) = ev;
+        self.change_reason = LayerChangeReason::Key(k);
+        if self.bypassed {
+            self.bypassed_keyevent(ev);
+            self.change_reason = LayerChangeReason::Host;
+            return;
+        }
         match ev {
-            KeyStateChange::Pressed(k) => self.process_keyevent_press(k.into(), t.into()),
-            KeyStateChange::Released(k) => self.process_keyevent_release(k.into(), t.into()),
+            KeyStateChange::Pressed(k) => self.held.insert(k),
+            KeyStateChange::Released(k) => self.held.remove(&k),
+            _ => false,
+        };
+        match ev {
+            KeyStateChange::Pressed(k) => self.process_keyevent_press(k, t),
+            KeyStateChange::Released(k) => self.process_keyevent_release(k, t),
             KeyStateChange::Click(k) => {
-                let k = k.into();
-                let ti = t.into();
-                self.process_keyevent_press(k, ti);
-                self.process_keyevent_release(k, ti);
+                self.process_keyevent_press(k, t);
+                self.process_keyevent_release(k, t);
+            }
+            KeyStateChange::LongPress(k, _) => self.process_keyevent_long_press(k, t),
+            KeyStateChange::Turn(k, steps) => {
+                for _ in 0..steps {
+                    self.process_keyevent_press(k, t);
+                    self.process_keyevent_release(k, t);
+                }
             }
-            KeyStateChange::LongPress(k) => self.process_keyevent_long_press(k.into(), t.into()),
+        }
+        self.release_held_back();
+        self.current_event = None;
+        self.change_reason = LayerChangeReason::Host;
+
+        // Nothing stays held while the layout is bypassed
+        if self.bypassed {
+            self.release_all();
+        }
+    }
+
+    /// Only the Passthrough action is resolved while the layout is bypassed,
+    /// its press turns the layout back on
+    fn bypassed_keyevent(&mut self, ev: KeyStateChange<KeyCoords>) {
+        let (KeyStateChange::Pressed(k) | KeyStateChange::Click(k)) = ev else {
+            return;
+        };
+        if let (_, Some(KeymapEvent::Passthrough)) = self.get_key_event(k) {
+            self.bypassed = false;
         }
     }
 
-    /// Consume all queued keycode events via the `renderer` closure.
+    /// Consume all queued keycode events via the `renderer` closure
     pub fn render<F>(&mut self, mut renderer: F)
     where
-        F: FnMut(Key, bool),
+        F: FnMut(KeyCode, bool),
     {
-        while let Some(k) = self.emitted_codes.pop_front() {
-            renderer(k.0, k.1)
+        self.render_routed(|_, k, pressed| renderer(k, pressed));
+    }
+
+    /// Same as `render`, but the closure also receives the name of the output
+    /// device the keycode is routed to, None is the main keyboard
+    pub fn render_routed<F>(&mut self, mut renderer: F)
+    where
+        F: FnMut(Option<&str>, KeyCode, bool),
+    {
+        while let Some((k, pressed, output)) = self.emitted_codes.pop_front() {
+            renderer(output.as_deref(), k, pressed)
+        }
+    }
+
+    /// Same as `render_routed`, but the keycodes are grouped into frames the
+    /// output device reports at once (terminated by a SYN_REPORT), so the
+    /// applications never see a key group half pressed. A frame ends before
+    /// a change of the output device or another event of a key that is
+    /// in the frame already.
+    pub fn render_frames<F>(&mut self, mut renderer: F)
+    where
+        F: FnMut(Option<&str>, &[(KeyCode, bool)]),
+    {
+        let mut frame: Vec<(KeyCode, bool)> = Vec::new();
+        let mut frame_output = None;
+        while let Some((k, pressed, output)) = self.emitted_codes.pop_front() {
+            let split = output != frame_output || frame.iter().any(|(key, _)| *key == k);
+            if split && !frame.is_empty() {
+                renderer(frame_output.as_deref(), &frame);
+                frame.clear();
+            }
+            frame_output = output;
+            frame.push((k, pressed));
+        }
+        if !frame.is_empty() {
+            renderer(frame_output.as_deref(), &frame);
+        }
+    }
+
+    /// Names of all output devices the layers route keys to,
+    /// besides the main keyboard
+    pub fn get_outputs(&self) -> Vec<&str> {
+        self.output_names.iter().map(|name| &**name).collect()
+    }
+
+    /// Consume all queued gamepad events via the `renderer` closure
+    pub fn render_gamepad<F>(&mut self, mut renderer: F)
+    where
+        F: FnMut(GamepadEvent),
+    {
+        while let Some(ev) = self.gamepad_events.pop_front() {
+            renderer(ev)
         }
     }
 
+    /// Return all gamepad buttons and axes used by the layers, both
+    /// are empty when the layout does not need a gamepad
+    pub fn get_used_gamepad(&self) -> (HashSet<KeyCode>, Vec<AbsoluteAxisType>) {
+        let mut buttons = HashSet::new();
+        let mut axes = Vec::new();
+        for l in self.layers.iter() {
+            for ev in l.positions().flat_map(|(_, ev)| ev.actions()) {
+                match ev {
+                    KeymapEvent::Gbtn(k) => {
+                        buttons.insert(*k);
+                    }
+                    KeymapEvent::Gaxis(axis, _) | KeymapEvent::Gnudge(axis, _)
+                        if !axes.contains(axis) =>
+                    {
+                        axes.push(*axis);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        (buttons, axes)
+    }
+
+    /// Consume all queued relative pointer movements via the `renderer` closure.
+    /// The mouse buttons are rendered with the keys.
+    pub fn render_pointer<F>(&mut self, mut renderer: F)
+    where
+        F: FnMut(RelativeAxisType, i32),
+    {
+        while let Some((axis, delta)) = self.pointer_events.pop_front() {
+            renderer(axis, delta)
+        }
+    }
+
+    /// Return all relative axes the layers move, empty when
+    /// the layout does not drive the pointer or the wheel
+    pub fn get_used_pointer_axes(&self) -> Vec<RelativeAxisType> {
+        let mut axes = Vec::new();
+        for l in self.layers.iter() {
+            for ev in l.positions().flat_map(|(_, ev)| ev.actions()) {
+                for (axis, _) in pointer_axes(ev) {
+                    if !axes.contains(&axis) {
+                        axes.push(axis);
+                    }
+                }
+            }
+        }
+        axes
+    }
+
     /// Parse all layers and return all keycodes that could be emitted
     /// from them. This is needed to be able to register the virtual
     /// keyboard to the OS.
-    pub fn get_used_keys(&self) -> HashSet<Key> {
+    pub fn get_used_keys(&self) -> HashSet<KeyCode> {
         let mut keyset = HashSet::new();
-        for l in self.layers {
+        for l in self.layers.iter() {
             keyset.extend(&l.get_used_keys());
             keyset.extend(&l.on_active_keys);
         }
+        keyset.extend(self.macros.get_used_keys());
         return keyset;
     }
 
-    /// Get list of currently active layers. Needed for tests.
-    pub(crate) fn get_active_layers(&self) -> Vec<LayerId> {
+    /// The action a press of `coords` would trigger right now, with the
+    /// layer state and inheritance taken into account
+    pub fn resolve(&self, coords: KeyCoords) -> Option<&KeymapEvent> {
+        self.get_key_event(coords).1.map(KeymapEvent::action)
+    }
+
+    /// Get list of currently active layers
+    pub fn get_active_layers(&self) -> Vec<LayerId> {
         let mut active = Vec::new();
         for (idx, l) in (&self.layer_stack).into_iter().enumerate() {
             if l.status != LayerStatus::LayerDisabled && l.status != LayerStatus::LayerPassthrough {
@@ -668,4 +2436,18 @@ impl<'a> LayerSwitcher<'a> {
         }
         active
     }
+
+    /// Find a layer by its name, for layouts built in code
+    pub fn get_layer_id(&self, name: &str) -> Option<LayerId> {
+        self.layers
+            .iter()
+            .position(|l| !l.name.is_empty() && l.name == name)
+    }
+
+    pub fn get_layer_name(&self, layer: LayerId) -> Option<&str> {
+        self.layers
+            .get(layer)
+            .map(|l| l.name.as_str())
+            .filter(|name| !name.is_empty())
+    }
 }
@@ -1,11 +1,16 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::keycodes::{AbsoluteAxisType, KeyCode, LedType};
 
 use super::keys::KeyGroup;
 
 pub type LayerId = usize;
 pub type EventCount = u32;
 
-#[derive(Clone, Copy, PartialEq)]
+/// The runtime states with the time of the press cannot be serialized
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum LayerStatus {
     /// Layer active. Can only be deactivated explicitly.
     LayerActive,
@@ -20,21 +25,181 @@ pub enum LayerStatus {
     LayerActiveUntilAnyKeyPress,
     /// Layer active while the activation key is being held down. On release this
     /// can trigger another layer activation if the duration of the press was short.
+    #[serde(skip)]
     LayerHoldAndTapToL(KeyCoords, Instant, LayerId),
     /// Layer active while the recorded key is being held down. On release this
     /// can trigger key group press and release if the duration of the press was short.
+    #[serde(skip)]
     LayerHoldAndTapKey(KeyCoords, Instant, LayerId), // The key action is retrieved from the keymap
     /// Layer unconditionally disabled, does not participate in key resolution
     /// And can only be enabled explicitly
     LayerDisabled,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// External state a layer can be tied to. A conditioned layer is activated
+/// when the condition starts to hold and deactivated when it stops.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LayerCondition {
+    /// Layer active while the host keyboard LED is lit (eg. Num Lock)
+    LedOn(LedType),
+    /// Layer active while the host keyboard LED is off
+    LedOff(LedType),
+    /// Layer active while the tablet pen is in proximity of the tablet
+    PenNear,
+    /// Layer active while the tablet pen is away from the tablet
+    PenAway,
+}
+
+/// Grace period after a layer activation during which presses are ignored.
+/// It absorbs accidental double activations when fumbling for the hold key.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ActivationDebounce {
+    /// Ignore presses of the key that activated the layer
+    TriggerKey(Duration),
+    /// Ignore presses of all keys
+    AllKeys(Duration),
+}
+
+/// How a dual role key (Klong, Khl, Khtl, LhtK, LhtL) is resolved when
+/// another key is pressed while it is held
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TapHold {
+    /// Only the press duration decides between the tap and the hold
+    #[default]
+    Timeout,
+    /// Pressing another key resolves the hold right away
+    HoldOnOtherPress,
+    /// A full tap of another key resolves the hold. The other key waits
+    /// for the decision, a release of the dual role key makes it a tap.
+    PermissiveHold,
+}
+
+/// What to do when a dual role key is released after the hold threshold
+/// elapsed, but before the LongPress event was processed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LongPressRace {
+    /// The key resolves as a tap unless a LongPress event was received
+    TapWins,
+    /// The key resolves as a hold whenever the press was longer
+    /// than the hold threshold
+    HoldWins,
+}
+
+/// Typematic repeat of held keys, by the layout engine or by the kernel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyRepeat {
+    /// Time before the first repeat
+    pub delay: Duration,
+    /// Time between the repeats
+    pub period: Duration,
+}
+
+/// Morse input on a single key, see `MorseDecoder`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorseInput {
+    /// The Morse key
+    pub key: KeyCoords,
+    /// Presses longer than this are dashes
+    pub dash: Duration,
+    /// Silence that finishes a letter
+    pub letter_gap: Duration,
+    /// Codes added to or replacing the international Morse code
+    pub codes: Vec<(String, KeyGroup)>,
+}
+
+/// The wheel as an absolute dial axis, see `WheelDial` and `VirtualDial`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DialOutput {
+    /// ABS_WHEEL or ABS_MISC
+    pub axis: AbsoluteAxisType,
+    /// Positions moved per rotary tick
+    pub step: i32,
+    /// Positions of a full turn
+    pub positions: i32,
+}
+
+/// Options of the whole layout, None keeps the global default
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LayoutSettings {
+    /// The press duration that tells a hold from a tap
+    pub hold_threshold: Option<Duration>,
+    /// Repeat of the held key groups
+    pub key_repeat: Option<KeyRepeat>,
+    /// Resolution of the dual role keys held while other keys are pressed
+    pub tap_hold: TapHold,
+    /// Show a desktop notification when the active layer changes
+    pub layer_notifications: bool,
+    /// List the bindings of the layer in the notification
+    pub cheat_sheet: bool,
+    /// Send the HID scancodes (MSC_SCAN) with the keys, the consumer page
+    /// for the media keys
+    pub scancodes: bool,
+    /// The steps of a rotary detent following the previous one within
+    /// the interval
+    pub rotary_acceleration: Vec<(Duration, u8)>,
+    /// The number of rotary detents per click and the pause after which
+    /// the counted detents are forgotten
+    pub rotary_divider: Option<(u8, Duration)>,
+    /// Return to the base layer after this long without a key event
+    pub idle_timeout: Option<Duration>,
+    /// The keys locking and unlocking the input when held together
+    /// and how long they have to be held
+    pub input_lock: Option<(Vec<KeyCoords>, Duration)>,
+    /// The hidraw node of the keypad to drive
+    pub device_path: Option<String>,
+    /// The serial number of the keypad to drive
+    pub device_serial: Option<String>,
+    /// Minimal time between a release and the next press of the same key,
+    /// shorter presses are contact chatter
+    pub debounce: Option<Duration>,
+    /// Per key override of the debounce time
+    pub key_debounce: Vec<(KeyCoords, Duration)>,
+    /// Identical reports within this window are duplicates, zero disables
+    /// the detection
+    pub duplicate_window: Option<Duration>,
+    /// The resolution of a release racing with a pending long press
+    pub long_press_race: Option<LongPressRace>,
+    /// The ticks forming a rotary spin and wiggle and the window
+    /// they have to fit in
+    pub rotary_gestures: Option<(usize, usize, Duration)>,
+    /// Ignore the presses of a key sooner than this after its previous press
+    pub bounce_keys: Option<Duration>,
+    /// Accept only the presses held at least this long
+    pub slow_keys: Option<Duration>,
+    /// The switch key, the positions it scans through and how long
+    /// each of them stays highlighted
+    pub scanning: Option<(KeyCoords, Vec<KeyCoords>, Duration)>,
+    /// Morse input on a single key
+    pub morse: Option<MorseInput>,
+    /// The keys resetting everything when held together and how long they
+    /// have to be held, the ACK05 corner buttons when not configured
+    pub panic_chord: Option<(Vec<KeyCoords>, Duration)>,
+    /// A rotary pulse reversing the direction within this window is ignored,
+    /// zero disables the filter
+    pub rotary_reversal: Option<Duration>,
+    /// Turn the wheel into an absolute dial instead of key presses
+    pub dial: Option<DialOutput>,
+    /// Let the kernel repeat the keys held on the virtual keyboards
+    pub kernel_repeat: Option<KeyRepeat>,
+    /// Unrecognized reports in a row that reset the device, zero disables
+    /// the watchdog
+    pub watchdog_try_again: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct KeyCoords(pub u8, pub u8, pub u8); // Block, row, column
 
 pub type Keymap = Vec<Vec<Vec<KeymapEvent>>>; // [Block, Row, Col] - > default KeyEvent(None)
 
-#[derive(Clone, PartialEq)]
+/// A key sequence of a leader and the action it selects. No sequence of
+/// a leader can start with another one, the shorter one would always win.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LeaderSequence {
+    pub keys: Vec<KeyCoords>,
+    pub action: KeymapEvent,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum KeymapEvent {
     /// No effect, no inheritance
     No,
@@ -48,6 +213,29 @@ pub enum KeymapEvent {
     /// but when it is still pressed after the timeout, press the second key
     /// and release it on key release.
     Klong(KeyGroup, KeyGroup),
+    /// Graded long press. A short press sends the first key group, holding the key
+    /// past a threshold selects the key group of that tier instead. The key group
+    /// of the highest reached tier is clicked on release, the longest tier is clicked
+    /// as soon as its threshold elapses. Tiers must be sorted by the threshold.
+    Ktiers(KeyGroup, Vec<(Duration, KeyGroup)>),
+    /// Click the key group the given number of times per press, waiting
+    /// the delay between the clicks. Eg. one wheel detent zooming in five steps.
+    Kmul(KeyGroup, u8, Duration),
+    /// Click the key group repeatedly while the key is held, every given interval.
+    /// The clicks start slower and speed up to the interval over the ramp-up time.
+    Kturbo(KeyGroup, Duration, Duration),
+    /// Tap dance. Each tap waits for the next one for the hold threshold,
+    /// the key group of the number of taps (1, 2, 3, ...) is clicked once
+    /// the taps stop, the last one right away. Another key ends the dance.
+    TapDance(Vec<KeyGroup>),
+    /// Sticky modifiers. Press the keys and keep them pressed until the keys
+    /// of the next pressed key are released, eg. a sticky shift for one capital
+    /// letter. Pressing it again before that releases the keys.
+    Oneshot(KeyGroup),
+    /// Caps word. Hold Shift until a key ending the word (space, enter,
+    /// punctuation, a shortcut, ...) is sent, letters, digits, minus and
+    /// backspace keep it. Pressing it again ends the word early.
+    CapsWord,
     /// A short press for key, long press for activating a layer
     Khl(KeyGroup, LayerId),
     /// A short press for key, long press for activating a tap layer (Ltap)
@@ -59,6 +247,10 @@ pub enum KeymapEvent {
     Lactivate(LayerId),
     /// Deactivate a layer
     Ldeactivate(LayerId),
+    /// Activate a layer when it is not active, deactivate it otherwise.
+    /// The key has to stay reachable on the toggled layer (Inh or Pass)
+    /// for the second press to turn it off.
+    Ltoggle(LayerId),
     /// Permanently disable a layer
     Ldisable(LayerId),
     /// Activate layer while the initiating key is kept pressed. Deactivate on release.
@@ -72,4 +264,120 @@ pub enum KeymapEvent {
     /// Activate the first mentioned layer on press and deactivate on release. Additionally,
     /// if the elapsed time between press and release was short, send a press+release key event.
     LhtK(LayerId, KeyGroup),
+
+    /// Play the named macro from the macro library
+    Mplay(String),
+    /// Start recording the named macro from the physical keyboard,
+    /// the next press stops the recording and stores the macro
+    Mrec(String),
+    /// Abort the running macro and release the keys it holds
+    Mcancel,
+
+    /// Revert the configuration files to their previous backup and reload
+    /// them, for when a freshly loaded layout turns out to be broken
+    Rollback,
+
+    /// Bypass the layout until pressed again, the other keys do nothing
+    /// meanwhile and the keys held by the layout are released
+    Passthrough,
+
+    /// Run a shell command, eg. a screenshot tool. The driver does not
+    /// wait for it to finish.
+    Cmd(String),
+
+    /// Leader key. The next presses are not resolved, the keys are collected
+    /// into a sequence (vim-style, eg. one or two keys) selecting the action
+    /// to fire. An unknown sequence, or no press within the timeout, aborts
+    /// the leader.
+    Leader(Vec<LeaderSequence>, Duration),
+
+    /// Fire the first action when the layer is active and the second one
+    /// otherwise. The branch is chosen on press and kept until the release.
+    If(LayerId, Box<KeymapEvent>, Box<KeymapEvent>),
+    /// Fire the first action while the physical key is held and the second
+    /// one otherwise, eg. the wheel zooming instead of scrolling while a button
+    /// is held. The branch is chosen on press and kept until the release.
+    IfHeld(KeyCoords, Box<KeymapEvent>, Box<KeymapEvent>),
+
+    /// Hold a gamepad button (BTN_SOUTH, ...) while the key is held
+    Gbtn(KeyCode),
+    /// Deflect a gamepad axis to the value while the key is held, it returns
+    /// to the center on release
+    Gaxis(AbsoluteAxisType, i32),
+    /// Move a gamepad axis by the value and leave it there, eg. steering with the wheel
+    Gnudge(AbsoluteAxisType, i32),
+
+    /// Hold a mouse button (BTN_LEFT, BTN_RIGHT, BTN_MIDDLE) while the key is held
+    Pbtn(KeyCode),
+    /// Move the mouse pointer by the given steps (REL_X, REL_Y) with every press
+    Pmove(i32, i32),
+    /// Turn the vertical and the horizontal scroll wheel (REL_WHEEL, REL_HWHEEL)
+    /// by the given detents with every press, positive scrolls up and right
+    Pscroll(i32, i32),
+
+    /// Fire the wrapped action at most once per the given interval, presses
+    /// coming sooner are ignored. Guards destructive actions against mashing
+    /// and chattering buttons.
+    Cooldown(Box<KeymapEvent>, Duration),
+    /// Decide between the tap and the hold of the wrapped action (Klong, Khl,
+    /// Khtl, LhtK, LhtL, ...) after the given time instead of the hold
+    /// threshold of the layout
+    HoldThreshold(Box<KeymapEvent>, Duration),
+    /// Send the keys of the wrapped action through the named output device
+    /// instead of the one of the layer, eg. media keys on a "Consumer Control" device
+    Output(Box<KeymapEvent>, String),
+}
+
+impl KeymapEvent {
+    /// Does the action depend on the press duration or on the key being held?
+    /// Such actions only make sense on keys with state.
+    pub fn needs_state(&self) -> bool {
+        if let KeymapEvent::If(_, then, otherwise) | KeymapEvent::IfHeld(_, then, otherwise) =
+            self.action()
+        {
+            return then.needs_state() || otherwise.needs_state();
+        }
+        matches!(
+            self.action(),
+            KeymapEvent::Klong(..)
+                | KeymapEvent::Ktiers(..)
+                | KeymapEvent::Kturbo(..)
+                | KeymapEvent::Gaxis(..)
+                | KeymapEvent::Pbtn(_)
+                | KeymapEvent::Khl(..)
+                | KeymapEvent::Khtl(..)
+                | KeymapEvent::Lhold(_)
+                | KeymapEvent::Ltap(_)
+                | KeymapEvent::LhtL(..)
+                | KeymapEvent::LhtK(..)
+        )
+    }
+
+    /// The action itself, without the cooldown wrapper
+    pub fn action(&self) -> &KeymapEvent {
+        match self {
+            KeymapEvent::Cooldown(ev, _)
+            | KeymapEvent::HoldThreshold(ev, _)
+            | KeymapEvent::Output(ev, _) => ev.action(),
+            ev => ev,
+        }
+    }
+
+    /// The action followed by all the actions it can fire, ie. the actions
+    /// of the leader sequences and both branches of a condition
+    pub fn actions(&self) -> Vec<&KeymapEvent> {
+        let ev = self.action();
+        let mut actions = vec![ev];
+        match ev {
+            KeymapEvent::Leader(sequences, _) => {
+                actions.extend(sequences.iter().flat_map(|s| s.action.actions()));
+            }
+            KeymapEvent::If(_, then, otherwise) | KeymapEvent::IfHeld(_, then, otherwise) => {
+                actions.extend(then.actions());
+                actions.extend(otherwise.actions());
+            }
+            _ => {}
+        }
+        actions
+    }
 }
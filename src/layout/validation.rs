@@ -0,0 +1,162 @@
+use std::fmt;
+
+use super::geometry::Geometry;
+use super::layer::Layer;
+use super::types::{KeyCoords, KeymapEvent, LayerId};
+
+/// A mistake in a layout that would make it misbehave at runtime
+#[derive(Clone, Debug, PartialEq)]
+pub enum LayoutError {
+    /// An action of the key (or an option of the layer when there is no key)
+    /// refers to a layer that does not exist
+    UnknownLayer {
+        layer: LayerId,
+        coords: Option<KeyCoords>,
+        target: LayerId,
+    },
+    /// The layer refers to a layer by a name no layer has
+    UnknownLayerName { layer: LayerId, name: String },
+    /// The layers inherit from each other in a loop
+    InheritCycle(Vec<LayerId>),
+    /// The keymap binds a key the device does not have
+    UnknownPosition { layer: LayerId, coords: KeyCoords },
+    /// An action that needs the key held is bound to a key that only clicks
+    HoldOnStateless { layer: LayerId, coords: KeyCoords },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::UnknownLayer {
+                layer,
+                coords: Some(coords),
+                target,
+            } => write!(
+                f,
+                "Layer {} {}: refers to the unknown layer {}",
+                layer,
+                describe(*coords),
+                target
+            ),
+            LayoutError::UnknownLayer {
+                layer,
+                coords: None,
+                target,
+            } => write!(f, "Layer {}: refers to the unknown layer {}", layer, target),
+            LayoutError::UnknownLayerName { layer, name } => {
+                write!(f, "Layer {}: refers to the unknown layer {:?}", layer, name)
+            }
+            LayoutError::InheritCycle(layers) => {
+                let layers: Vec<String> = layers.iter().map(|l| l.to_string()).collect();
+                write!(
+                    f,
+                    "Layers {} inherit from each other in a loop",
+                    layers.join(" -> ")
+                )
+            }
+            LayoutError::UnknownPosition { layer, coords } => write!(
+                f,
+                "Layer {} {}: the device has no such key",
+                layer,
+                describe(*coords)
+            ),
+            LayoutError::HoldOnStateless { layer, coords } => write!(
+                f,
+                "Layer {} {}: the key only clicks, it cannot be held",
+                layer,
+                describe(*coords)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+fn describe(coords: KeyCoords) -> String {
+    format!("block {} row {} column {}", coords.0, coords.1, coords.2)
+}
+
+/// The layers an action switches or depends on
+fn target_layers(ev: &KeymapEvent) -> Vec<LayerId> {
+    match ev {
+        KeymapEvent::Khl(_, l)
+        | KeymapEvent::Khtl(_, l)
+        | KeymapEvent::Lmove(l)
+        | KeymapEvent::Lactivate(l)
+        | KeymapEvent::Ldeactivate(l)
+        | KeymapEvent::Ltoggle(l)
+        | KeymapEvent::Ldisable(l)
+        | KeymapEvent::Lhold(l)
+        | KeymapEvent::Ltap(l)
+        | KeymapEvent::LhtK(l, _) => vec![*l],
+        KeymapEvent::LhtL(l_hold, l_tap) => vec![*l_hold, *l_tap],
+        KeymapEvent::Leader(sequences, _) => sequences
+            .iter()
+            .flat_map(|s| target_layers(s.action.action()))
+            .collect(),
+        KeymapEvent::If(l, then, otherwise) => std::iter::once(*l)
+            .chain(target_layers(then.action()))
+            .chain(target_layers(otherwise.action()))
+            .collect(),
+        KeymapEvent::IfHeld(_, then, otherwise) => target_layers(then.action())
+            .into_iter()
+            .chain(target_layers(otherwise.action()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Check the layers against each other and against the device geometry.
+/// All the mistakes found are returned, not just the first one.
+pub fn validate(layers: &[Layer], geometry: &Geometry) -> Result<(), Vec<LayoutError>> {
+    let mut errors = Vec::new();
+    let unknown = |target: LayerId| target >= layers.len();
+
+    for (idx, layer) in layers.iter().enumerate() {
+        let options = [layer.inherit, layer.on_timeout_layer];
+        for target in options.into_iter().flatten().filter(|l| unknown(*l)) {
+            errors.push(LayoutError::UnknownLayer {
+                layer: idx,
+                coords: None,
+                target,
+            });
+        }
+
+        for (coords, ev) in layer.positions() {
+            if !geometry.contains(coords) {
+                errors.push(LayoutError::UnknownPosition { layer: idx, coords });
+            }
+            for target in target_layers(ev).into_iter().filter(|l| unknown(*l)) {
+                errors.push(LayoutError::UnknownLayer {
+                    layer: idx,
+                    coords: Some(coords),
+                    target,
+                });
+            }
+        }
+    }
+
+    for (layer, coords) in geometry.misplaced_hold_actions(layers) {
+        errors.push(LayoutError::HoldOnStateless { layer, coords });
+    }
+
+    // Every cycle is reported once, starting from its lowest layer
+    for start in 0..layers.len() {
+        let mut chain = vec![start];
+        let mut next = layers[start].inherit;
+        while let Some(l) = next.filter(|l| !unknown(*l) && !chain.contains(l)) {
+            chain.push(l);
+            next = layers[l].inherit;
+        }
+        if next == Some(start) && chain.iter().all(|l| *l >= start) {
+            chain.push(start);
+            errors.push(LayoutError::InheritCycle(chain));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
@@ -1,7 +1,81 @@
-pub mod virtual_keyboard;
+// The layout engine, its API is the prelude
+mod clock;
+mod kbd_events;
+mod keycodes;
+mod layout;
+mod macros;
+pub mod prelude;
+
+// The device and host integration of the driver binary. It is only public
+// with the `driver` feature, the engine keeps the parts it uses private.
+#[cfg(feature = "driver")]
+pub mod button_device;
+#[cfg(not(feature = "driver"))]
+#[allow(dead_code)]
+mod button_device;
+#[cfg(feature = "driver")]
 pub mod xppen_hid;
-pub mod kbd_events;
-pub mod layout;
+#[cfg(not(feature = "driver"))]
+#[allow(dead_code)]
+mod xppen_hid;
+#[cfg(feature = "driver")]
+pub mod virtual_gamepad;
+#[cfg(not(feature = "driver"))]
+#[allow(dead_code, unused_imports)]
+mod virtual_gamepad;
+#[cfg(feature = "driver")]
+pub mod host_leds;
+#[cfg(not(feature = "driver"))]
+#[allow(dead_code)]
+mod host_leds;
+#[cfg(feature = "driver")]
+pub mod evdev_input;
+#[cfg(not(feature = "driver"))]
+#[allow(dead_code)]
+mod evdev_input;
+#[cfg(feature = "driver")]
+pub mod backup;
+#[cfg(not(feature = "driver"))]
+#[allow(dead_code)]
+mod backup;
+
+// Only the driver binary uses these
+#[cfg(feature = "driver")]
+pub mod virtual_keyboard;
+#[cfg(feature = "driver")]
+pub mod virtual_dial;
+#[cfg(feature = "driver")]
+pub mod virtual_pointer;
+#[cfg(all(feature = "driver", windows))]
+pub mod sendinput;
+#[cfg(all(feature = "driver", target_os = "macos"))]
+pub mod cgevent;
+#[cfg(feature = "driver")]
+pub mod pen_proximity;
+#[cfg(feature = "driver")]
+pub mod sleep_inhibitor;
+#[cfg(feature = "driver")]
+pub mod audio_feedback;
+#[cfg(feature = "driver")]
+pub mod speech_feedback;
+#[cfg(feature = "driver")]
+pub mod repl;
+#[cfg(feature = "driver")]
+pub mod stats;
+#[cfg(feature = "driver")]
+pub mod file_watcher;
+#[cfg(feature = "driver")]
+pub mod systemd;
+#[cfg(feature = "driver")]
+pub mod dbus_control;
+#[cfg(feature = "driver")]
+pub mod focus_watcher;
+#[cfg(feature = "driver")]
+pub mod desktop_notifications;
+#[cfg(feature = "driver")]
+pub mod shell_command;
+#[cfg(all(feature = "driver", feature = "tokio", target_os = "linux"))]
+pub mod async_frontend;
 
-#[cfg(test)]
-mod tests;
\ No newline at end of file
+#[cfg(all(test, feature = "driver"))]
+mod tests;
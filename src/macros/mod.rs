@@ -0,0 +1,259 @@
+pub(crate) mod recorder;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+use crate::backup;
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::types::{KeyCoords, KeymapEvent, LayerId};
+
+/// Name of the file the macros are stored in
+const MACRO_FILE: &str = "macros.toml";
+
+/// One step of a macro. The kind of the step is recognized by its fields,
+/// eg. `{ key = "KEY_A", pressed = true }` or `{ delay_ms = 100 }`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MacroStep {
+    /// Press or release a key
+    Key {
+        key: KeyCode,
+        pressed: bool,
+        /// Pause before the event in milliseconds
+        #[serde(default)]
+        delay_ms: u64,
+    },
+    /// Pause the playback
+    Delay { delay_ms: u64 },
+    /// Activate or deactivate a layer
+    Layer { layer: LayerId, active: bool },
+    /// Wait until the key is pressed (or released) on the host keyboard,
+    /// eg. until the user lets go of a modifier
+    WaitKey { wait_key: KeyCode, pressed: bool },
+    /// Play `then` when the layer is active, `else` otherwise
+    If {
+        if_layer: LayerId,
+        then: Vec<MacroStep>,
+        #[serde(default, rename = "else")]
+        otherwise: Vec<MacroStep>,
+    },
+}
+
+impl MacroStep {
+    /// Pause before the step in milliseconds
+    pub fn delay_ms(&self) -> u64 {
+        match self {
+            MacroStep::Key { delay_ms, .. } | MacroStep::Delay { delay_ms } => *delay_ms,
+            _ => 0,
+        }
+    }
+
+    /// All keycodes the step can emit
+    pub fn get_used_keys(&self) -> Vec<KeyCode> {
+        match self {
+            MacroStep::Key { key, .. } => vec![*key],
+            MacroStep::If {
+                then, otherwise, ..
+            } => then
+                .iter()
+                .chain(otherwise.iter())
+                .flat_map(|s| s.get_used_keys())
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// How many times is a macro played per press
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MacroRepeat {
+    /// Play the macro the given number of times
+    Times(u32),
+    /// Keep playing the macro while its key is held,
+    /// the running iteration is finished after the release
+    WhileHeld,
+}
+
+impl Default for MacroRepeat {
+    fn default() -> Self {
+        MacroRepeat::Times(1)
+    }
+}
+
+/// A named sequence of key events
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+    /// Playback speed multiplier, 2.0 plays the macro twice as fast
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub repeat: MacroRepeat,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+impl Macro {
+    /// A macro played once at the recorded speed
+    pub fn new(name: &str, steps: Vec<MacroStep>) -> Self {
+        Self {
+            name: name.to_string(),
+            steps,
+            speed: default_speed(),
+            repeat: MacroRepeat::default(),
+        }
+    }
+
+    /// The pause before `step`, adjusted for the playback speed
+    pub fn delay(&self, step: &MacroStep) -> Duration {
+        let delay_ms = step.delay_ms();
+        if self.speed > 0.0 && self.speed != 1.0 {
+            Duration::from_micros((delay_ms as f64 * 1000.0 / self.speed as f64).round() as u64)
+        } else {
+            Duration::from_millis(delay_ms)
+        }
+    }
+
+    /// All keycodes the macro can emit
+    pub fn get_used_keys(&self) -> Vec<KeyCode> {
+        self.steps.iter().flat_map(|s| s.get_used_keys()).collect()
+    }
+
+    /// Store the macro in the `[[macros]]` of the layout file at `path`,
+    /// replacing the macro of the same name. The rest of the layout is kept
+    /// as it was, comments included, the previous version as a backup.
+    pub fn save_to_layout(&self, path: &Path) -> io::Result<()> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut layout: DocumentMut = source
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let recorded: DocumentMut = toml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // The steps are written inline, one macro is one section
+        let mut section = Table::new();
+        for (key, item) in recorded.iter() {
+            let item = item
+                .clone()
+                .into_value()
+                .map_or_else(|item| item, Item::Value);
+            section.insert(key, item);
+        }
+
+        let macros = layout
+            .entry("macros")
+            .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+            .as_array_of_tables_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "macros is not a list"))?;
+        let name = Some(self.name.as_str());
+        let old = macros
+            .iter()
+            .position(|m| m.get("name").and_then(|n| n.as_str()) == name);
+        match old.and_then(|idx| macros.get_mut(idx)) {
+            Some(old) => *old = section,
+            None => macros.push(section),
+        }
+        backup::write(path, &layout.to_string())
+    }
+}
+
+/// All known macros, referenced by name from the keymap
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MacroLibrary {
+    #[serde(default)]
+    pub macros: Vec<Macro>,
+}
+
+impl MacroLibrary {
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.iter().find(|m| m.name == name)
+    }
+
+    /// Add a macro, an existing macro with the same name is replaced
+    pub fn insert(&mut self, m: Macro) {
+        match self.macros.iter_mut().find(|old| old.name == m.name) {
+            Some(old) => *old = m,
+            None => self.macros.push(m),
+        }
+    }
+
+    pub fn get_used_keys(&self) -> Vec<KeyCode> {
+        self.macros.iter().flat_map(|m| m.get_used_keys()).collect()
+    }
+
+    /// Add all macros from `other`, they replace the macros with the same name
+    pub fn merge(&mut self, other: MacroLibrary) {
+        for m in other.macros {
+            self.insert(m);
+        }
+    }
+
+    /// Find Mplay actions referencing macros that are not in the library.
+    /// Macros recorded by Mrec do not have to exist yet.
+    pub fn missing_macros(&self, layers: &[Layer]) -> Vec<(LayerId, KeyCoords, String)> {
+        let recordable: Vec<&String> = layers
+            .iter()
+            .flat_map(|l| l.positions())
+            .flat_map(|(_, ev)| ev.actions())
+            .filter_map(|ev| match ev {
+                KeymapEvent::Mrec(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+
+        let mut missing = Vec::new();
+        for (l_idx, layer) in layers.iter().enumerate() {
+            for (coords, ev) in layer.positions() {
+                for ev in ev.actions() {
+                    if let KeymapEvent::Mplay(name) = ev {
+                        if self.get(name).is_none() && !recordable.contains(&name) {
+                            missing.push((l_idx, coords, name.clone()));
+                        }
+                    }
+                }
+            }
+        }
+        missing
+    }
+
+    /// Load the library, a missing file is an empty library
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        toml::from_str(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Save the library, the previous version is kept as a backup
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let source =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        backup::write(path, &source)
+    }
+
+    /// The default location of the library, `$XDG_CONFIG_HOME/xppen-ack05/macros.toml`
+    pub fn default_path() -> PathBuf {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default();
+        config.join("xppen-ack05").join(MACRO_FILE)
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use super::{Macro, MacroStep};
+use crate::keycodes::KeyCode;
+
+/// A key typed on a host keyboard, pressed or released at the time
+type KeyEvent = (KeyCode, bool, SystemTime);
+
+/// Records key events typed on the physical keyboards of the host
+///
+/// Every keyboard is read in its own thread, the events are only collected
+/// when `poll` is called. The keyboards are not grabbed, the typed keys
+/// still reach the applications. The threads are stopped when the recording
+/// is finished or dropped.
+pub struct MacroRecorder {
+    events: Receiver<KeyEvent>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    /// Key, pressed, pause before the event in ms
+    steps: Vec<(KeyCode, bool, u64)>,
+    last: Option<SystemTime>,
+}
+
+impl MacroRecorder {
+    pub fn start() -> Self {
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let threads = record_keyboards(tx, &stop);
+
+        Self {
+            events: rx,
+            stop,
+            threads,
+            steps: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// Collect the events typed since the last call
+    pub fn poll(&mut self) {
+        while let Ok((key, pressed, t)) = self.events.try_recv() {
+            let delay = self
+                .last
+                .and_then(|last| t.duration_since(last).ok())
+                .unwrap_or(Duration::ZERO);
+            self.last = Some(t);
+            self.steps.push((key, pressed, delay.as_millis() as u64));
+        }
+    }
+
+    /// Stop recording and return the macro. Releases of keys pressed
+    /// before the recording started are dropped.
+    pub fn finish(mut self, name: &str) -> Macro {
+        self.poll();
+
+        let mut held = Vec::new();
+        let mut steps = Vec::new();
+        for (key, pressed, delay_ms) in std::mem::take(&mut self.steps) {
+            if pressed {
+                held.push(key);
+            } else if let Some(idx) = held.iter().position(|k| *k == key) {
+                held.remove(idx);
+            } else {
+                continue;
+            }
+
+            // The first step is played without a delay
+            let delay_ms = if steps.is_empty() { 0 } else { delay_ms };
+            steps.push(MacroStep::Key {
+                key,
+                pressed,
+                delay_ms,
+            });
+        }
+
+        Macro::new(name, steps)
+    }
+}
+
+impl Drop for MacroRecorder {
+    /// Stop reading the keyboards right away, not with their next key event
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Read every keyboard of the host in a thread until `stop` is set
+#[cfg(target_os = "linux")]
+fn record_keyboards(tx: Sender<KeyEvent>, stop: &Arc<AtomicBool>) -> Vec<JoinHandle<()>> {
+    use std::thread;
+
+    use evdev::{InputEventKind, Key};
+    use tracing::info;
+
+    use crate::evdev_input::readable;
+    use crate::host_leds::is_own_device;
+
+    let mut threads = Vec::new();
+    for (path, mut device) in evdev::enumerate() {
+        if is_own_device(&device) {
+            continue;
+        }
+
+        let is_keyboard = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(Key::KEY_A) && keys.contains(Key::KEY_ENTER));
+        if !is_keyboard {
+            continue;
+        }
+
+        info!("Recording from {} {:?}", path.display(), device.name());
+        let tx = tx.clone();
+        let stopped = stop.clone();
+        threads.push(thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                if !readable(&device) {
+                    continue;
+                }
+                let Ok(events) = device.fetch_events() else {
+                    return;
+                };
+                for ev in events {
+                    // Autorepeat (value 2) is generated by the host again on playback
+                    if let InputEventKind::Key(k) = ev.kind() {
+                        if ev.value() == 2 {
+                            continue;
+                        }
+                        if tx
+                            .send((k.into(), ev.value() == 1, ev.timestamp()))
+                            .is_err()
+                        {
+                            // The recording is over
+                            return;
+                        }
+                    }
+                }
+            }
+        }));
+    }
+    threads
+}
+
+/// The host keyboards can only be read through evdev, nothing is recorded
+#[cfg(not(target_os = "linux"))]
+fn record_keyboards(_tx: Sender<KeyEvent>, _stop: &Arc<AtomicBool>) -> Vec<JoinHandle<()>> {
+    tracing::warn!("Recording macros is only available on Linux");
+    Vec::new()
+}
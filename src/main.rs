@@ -1,53 +1,1365 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
 use std::thread::sleep;
-use std::time::{self, Duration};
+use std::time::{Duration, Instant};
 
-use xppen_ack05::layout::switcher::LayerSwitcher;
-use xppen_ack05::xppen_hid::{XpPenAck05, XpPenResult};
+use clap::{Parser, Subcommand};
+use enumset::EnumSet;
+use tracing::{debug, debug_span, error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use xppen_ack05::prelude::{
+    builtin_layout, default_layout_path, parse_chords, parse_fallback_map, parse_geometry, parse_keyboards,
+    parse_layout, parse_macros, parse_report_map, parse_settings, profile_layout_path, validate, ChangeDetector, ChordResolver,
+    GestureDetector, InputLock, KeyCoords, KeyEvent, KeyStateChange, LayerSwitcher, LayoutSettings, MacroLibrary,
+    MacroRecorder, MorseDecoder, PanicChord, RotaryAccelerator, RotaryDivider, SwitchScanner,
+    WheelDial, DEFAULT_PROFILE,
+};
+#[cfg(not(all(feature = "tokio", target_os = "linux")))]
+use xppen_ack05::button_device::reader::DeviceReader as Frontend;
+#[cfg(all(feature = "tokio", target_os = "linux"))]
+use xppen_ack05::async_frontend::AsyncFrontend as Frontend;
+use xppen_ack05::button_device::{read_into, recover, ButtonDevice};
+use xppen_ack05::xppen_hid::report_map::describe_report;
+use xppen_ack05::xppen_hid::watchdog::Watchdog;
+use xppen_ack05::xppen_hid::{
+    list_devices, restore_standard_mode, DeviceSelector, XpPenAck05, XpPenButtons, XpPenError, XpPenResult, XP_ROTARY_GESTURES,
+    XP_ROTARY_REVERSAL_FILTER,
+};
 use xppen_ack05::virtual_keyboard::VirtualKeyboard;
-use xppen_ack05::kbd_events::ChangeDetector;
-use xppen_ack05::layout::serialization::load_layout;
+use xppen_ack05::virtual_gamepad::VirtualGamepad;
+use xppen_ack05::virtual_dial::VirtualDial;
+use xppen_ack05::virtual_pointer::{is_pointer_button, VirtualPointer};
+use xppen_ack05::host_leds::HostLeds;
+use xppen_ack05::pen_proximity::PenProximity;
+use xppen_ack05::evdev_input::EvdevInput;
+use xppen_ack05::sleep_inhibitor::{ResumeDetector, SleepEvent, SleepInhibitor};
+use xppen_ack05::audio_feedback::{AudioFeedback, Cue};
+use xppen_ack05::speech_feedback::{self, SpeechFeedback};
+use xppen_ack05::repl::Repl;
+use xppen_ack05::stats::UsageStats;
+use xppen_ack05::backup;
+use xppen_ack05::file_watcher::FileWatcher;
+use xppen_ack05::systemd;
+use xppen_ack05::dbus_control::{ControlRequest, DbusControl};
+use xppen_ack05::focus_watcher::{FocusConfig, FocusWatcher};
+use xppen_ack05::shell_command;
+use xppen_ack05::desktop_notifications::{cheat_sheet, layer_title, DesktopNotifications};
+
+/// How often to wake up and check for system sleep and the stop signal
+/// when no key is pressed
+const IDLE_POLL: Duration = Duration::from_millis(500);
+
+/// How often to move the switch scanning highlight
+const SCAN_POLL: Duration = Duration::from_millis(50);
+
+/// How often to look for a long press while a key is pressed
+const SHORT_PRESS_POLL: Duration = Duration::from_millis(25);
+
+/// How many presses to count before the usage statistics are written
+const STATS_SAVE_PRESSES: u64 = 50;
+
+/// How long to wait before looking for the keypad again
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// How long to wait before creating a lost virtual device again,
+/// it doubles with every failure up to RECONNECT_DELAY
+const RECREATE_DELAY: Duration = Duration::from_millis(50);
+
+/// User space driver of the XP-Pen ACK05 keypad
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// The layout file [default: ~/.config/xppen-ack05/layout.toml]
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// The hidraw node of the keypad when more of them are connected, eg. /dev/hidraw3
+    #[arg(long, alias = "device", global = true, value_name = "PATH")]
+    device_path: Option<String>,
+    /// The serial number of the keypad when more of them are connected
+    #[arg(long, global = true, value_name = "SERIAL")]
+    device_serial: Option<String>,
+    /// Log every resolved action, emitted key and all the HID devices,
+    /// RUST_LOG overrides it
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Count the presses for the usage heatmap
+    #[arg(long, global = true)]
+    stats: bool,
+    /// Presses longer than this count as a hold, unless the layout sets its own threshold
+    #[arg(long, global = true, value_name = "MS")]
+    hold_threshold: Option<u64>,
+    /// Run as a systemd service: log to the journal, report readiness and
+    /// release all keys when stopped
+    #[arg(long)]
+    daemon: bool,
+    /// Log the keys instead of emitting them, no virtual devices are created
+    #[arg(long)]
+    dry_run: bool,
+    /// Leave the keypad in its standard keyboard mode and bypass the layout,
+    /// the official XP-Pen mappings stay in effect
+    ///
+    /// A keypad left in the key bit mode by an earlier run is reset.
+    #[arg(long)]
+    passthrough: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Drive the virtual keyboard with the keypad, the default
+    Run,
+    /// List the connected HID devices
+    ListDevices,
+    /// Parse and validate a layout file
+    CheckConfig { file: PathBuf },
+    /// Print which button was pressed
+    Identify,
+    /// Print every raw HID report with the set bits and the decoded buttons,
+    /// to write a report map for another firmware
+    DebugReports,
+    /// Print the version
+    Version,
+    /// Type the button events by hand and watch what the layout does
+    Repl,
+    /// Print the usage heatmap over the device geometry as SVG
+    Heatmap,
+    /// Revert the configuration files to their previous backup
+    Rollback,
+}
+
+impl Cli {
+    fn layout_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(default_layout_path)
+    }
+}
+
+/// The main virtual keyboard and the named output devices the layers route to,
+/// there are none in a dry run
+struct Outputs {
+    kbd: Option<VirtualKeyboard>,
+    named: HashMap<String, VirtualKeyboard>,
+    /// The mouse buttons and the pointer movements, only registered
+    /// when the layout uses them
+    pointer: Option<VirtualPointer>,
+}
+
+fn render(
+    layout_runtime: &mut LayerSwitcher,
+    outputs: &mut Outputs,
+    gamepad: &mut Option<VirtualGamepad>,
+) {
+    layout_runtime.render_frames(|output, keys| {
+        let Some(main) = outputs.kbd.as_mut() else {
+            info!(output = output.unwrap_or("main"), "Dry run {:?}", keys);
+            return;
+        };
+        // The mouse buttons always go through the pointer device
+        let (buttons, keys): (Vec<_>, Vec<_>) =
+            keys.iter().partition(|(k, _)| is_pointer_button(*k));
+        if let Some(pointer) = outputs.pointer.as_mut().filter(|_| !buttons.is_empty()) {
+            debug!("Emit {:?}", buttons);
+            if let Err(e) = pointer.emit_buttons(&buttons) {
+                warn!("Cannot emit {:?}: {}", buttons, e);
+            }
+        }
+        if keys.is_empty() {
+            return;
+        }
+        debug!(output = output.unwrap_or("main"), "Emit {:?}", keys);
+        let kbd = output
+            .and_then(|name| outputs.named.get_mut(name))
+            .unwrap_or(main);
+        if let Err(e) = kbd.emit_keys(&keys) {
+            // Eg. the uinput module was reloaded, the device is gone
+            warn!("Cannot emit {:?}: {}, recreating the device.", keys, e);
+            retry_with_backoff("the virtual keyboard", || kbd.recover());
+        }
+        sleep(Duration::from_millis(2));
+    });
+    layout_runtime.render_pointer(|axis, delta| {
+        let Some(pointer) = outputs.pointer.as_mut() else {
+            info!(delta, "Dry run {:?}", axis);
+            return;
+        };
+        debug!(delta, "Emit {:?}", axis);
+        if let Err(e) = pointer.emit_rel(axis, delta) {
+            warn!("Cannot emit {:?}: {}", axis, e);
+        }
+    });
+    layout_runtime.render_gamepad(|ev| {
+        if outputs.kbd.is_none() {
+            info!("Dry run gamepad {:?}", ev);
+            return;
+        }
+        debug!("Emit gamepad {:?}", ev);
+        if let Some(Err(e)) = gamepad.as_mut().map(|gamepad| gamepad.emit(ev)) {
+            warn!("Cannot emit gamepad {:?}: {}", ev, e);
+        }
+    });
+}
+
+/// The virtual devices of a layout, see `create_outputs`
+type OutputDevices = (Outputs, Option<VirtualGamepad>, Option<(WheelDial, VirtualDial)>);
+
+/// Create the virtual keyboard and the other output devices with the keys
+/// the layout uses, `scancodes` adds MSC_SCAN to the key events
+fn create_outputs(
+    layout_runtime: &LayerSwitcher,
+    morse: Option<&MorseDecoder>,
+    settings: &LayoutSettings,
+    dry_run: bool,
+) -> io::Result<OutputDevices> {
+    if dry_run {
+        let outputs = Outputs {
+            kbd: None,
+            named: HashMap::new(),
+            pointer: None,
+        };
+        return Ok((outputs, None, None));
+    }
+    let scancodes = settings.scancodes;
+
+    let (buttons, used_keys): (Vec<_>, Vec<_>) = layout_runtime
+        .get_used_keys()
+        .into_iter()
+        .chain(morse.iter().flat_map(|m| m.get_used_keys()))
+        .partition(|k| is_pointer_button(*k));
+    // Additional output devices the layers route keys to, every one
+    // of them can emit all the keys to keep things simple
+    let named = layout_runtime
+        .get_outputs()
+        .into_iter()
+        .map(|name| {
+            let mut kbd = VirtualKeyboard::output(name, used_keys.iter().copied())?;
+            kbd.set_scancodes(scancodes);
+            kbd.set_repeat(settings.kernel_repeat)?;
+            Ok((name.to_string(), kbd))
+        })
+        .collect::<io::Result<_>>()?;
+    let mut kbd = VirtualKeyboard::new(used_keys)?;
+    kbd.set_scancodes(scancodes);
+    kbd.set_repeat(settings.kernel_repeat)?;
+    // Mouse buttons and pointer movements get a device of their own
+    let axes = layout_runtime.get_used_pointer_axes();
+    let pointer = (!buttons.is_empty() || !axes.is_empty())
+        .then(|| VirtualPointer::new(buttons, axes))
+        .transpose()?;
+    let outputs = Outputs {
+        kbd: Some(kbd),
+        named,
+        pointer,
+    };
+
+    // Gamepad output, only registered when the layout uses it
+    let (gamepad_buttons, gamepad_axes) = layout_runtime.get_used_gamepad();
+    let gamepad = (!gamepad_buttons.is_empty() || !gamepad_axes.is_empty())
+        .then(|| VirtualGamepad::new(gamepad_buttons, gamepad_axes))
+        .transpose()
+        .or_else(skip_unsupported)?;
+
+    // The wheel as an absolute dial axis instead of key presses,
+    // the built-in layout does not enable it
+    let dial = settings
+        .dial
+        .map(|d| {
+            let mut wheel =
+                WheelDial::new(XpPenButtons::XpRoCW.into(), XpPenButtons::XpRoCCW.into());
+            wheel.set_resolution(d.step, d.positions);
+            let device = VirtualDial::new(d.axis, wheel.positions())?;
+            Ok::<_, io::Error>((wheel, device))
+        })
+        .transpose()
+        .or_else(skip_unsupported)?;
+
+    Ok((outputs, gamepad, dial))
+}
+
+/// An output device the system does not have is left out, the layout
+/// still works without it
+fn skip_unsupported<T>(e: io::Error) -> io::Result<Option<T>> {
+    if e.kind() != io::ErrorKind::Unsupported {
+        return Err(e);
+    }
+    warn!("{}", e);
+    Ok(None)
+}
+
+/// The keypad picked on the command line or else by the layout settings
+fn device_selector(cli: &Cli, layout_source: &str) -> DeviceSelector {
+    let settings = parse_settings(layout_source).unwrap_or_default();
+    DeviceSelector {
+        path: cli.device_path.clone().or(settings.device_path),
+        serial: cli.device_serial.clone().or(settings.device_serial),
+    }
+}
+
+/// Run `create` until it succeeds, the wait grows after every failure like
+/// the one for the keypad. Missing permissions end the driver.
+fn retry_with_backoff<T>(what: &str, mut create: impl FnMut() -> io::Result<T>) -> T {
+    let mut delay = RECREATE_DELAY;
+    let mut waiting = false;
+    loop {
+        match create() {
+            Ok(created) => return created,
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                error!("Cannot create {}: {}", what, e);
+                process::exit(1);
+            }
+            Err(e) => {
+                if systemd::stop_requested() {
+                    process::exit(0);
+                }
+                if !waiting {
+                    warn!("Cannot create {}: {}, retrying.", what, e);
+                    waiting = true;
+                }
+                sleep(delay);
+                delay = (delay * 2).min(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Open the keypad with the report format of the layout. Wait for it when it
+/// is not connected, problems only the user can fix end the driver.
+fn open_device(cli: &Cli, layout_source: &str) -> XpPenAck05 {
+    let selector = device_selector(cli, layout_source);
+    let mut waiting = false;
+    loop {
+        match XpPenAck05::open_selected(&selector) {
+            Ok(mut xppen) => {
+                xppen.set_report_map(parse_report_map(layout_source).unwrap_or_default());
+                let fallback = parse_fallback_map(layout_source).unwrap_or_default();
+                if xppen.is_degraded() && fallback.is_empty() {
+                    warn!("The [fallback] section of the layout has no keys, no button will be recognized.");
+                }
+                xppen.set_fallback_map(fallback);
+                // Wait for a HID event when reading from XP Pen (= block)
+                xppen.set_blocking();
+                return xppen;
+            }
+            Err(e @ (XpPenError::NotFound | XpPenError::Io(_))) => {
+                // All keys were released when the keypad went away
+                if systemd::stop_requested() {
+                    process::exit(0);
+                }
+                if !waiting {
+                    warn!("{}, waiting for the keypad.", e);
+                    waiting = true;
+                }
+                sleep(RECONNECT_DELAY);
+            }
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Read and validate the layout file, None when there is none
+fn read_layout(path: &Path) -> Result<Option<String>, String> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Cannot read the layout {}: {}", path.display(), e)),
+    };
+
+    // All the sections are parsed together, one check covers them all
+    let layers = parse_layout(&source)
+        .map_err(|e| format!("Cannot parse the layout {}: {}", path.display(), e))?;
+    parse_chords(&source)
+        .map_err(|e| format!("Invalid chords in {}: {}", path.display(), e))?;
+    let geometry = parse_geometry(&source).unwrap_or_default();
+    match validate(&layers, &geometry) {
+        Ok(()) => Ok(Some(source)),
+        Err(errors) => {
+            let errors: Vec<String> = errors.iter().map(|e| format!("  {}", e)).collect();
+            Err(format!("Invalid layout {}:\n{}", path.display(), errors.join("\n")))
+        }
+    }
+}
+
+/// The content of the layout file. A missing or broken file is reported
+/// and the built-in layout is used instead.
+fn layout_source(path: &Path) -> String {
+    read_layout(path).unwrap_or_else(|e| {
+        warn!("{}", e);
+        None
+    })
+    .unwrap_or_default()
+}
+
+/// Apply the settings of the layout file. The hold threshold falls back
+/// to the global one given as `--hold-threshold <ms>`.
+fn apply_settings(
+    layout_runtime: &mut LayerSwitcher,
+    cli: &Cli,
+    layout_source: &str,
+) -> LayoutSettings {
+    let global = cli.hold_threshold.map(Duration::from_millis);
+    let settings = parse_settings(layout_source).unwrap_or_default();
+    if let Some(threshold) = settings.hold_threshold.or(global) {
+        layout_runtime.set_hold_threshold(threshold);
+    }
+    layout_runtime.set_key_repeat(settings.key_repeat);
+    layout_runtime.set_tap_hold(settings.tap_hold);
+    layout_runtime.set_idle_timeout(settings.idle_timeout);
+    if let Some(policy) = settings.long_press_race {
+        layout_runtime.set_long_press_race(policy);
+    }
+    settings
+}
+
+/// The state machine of the keypad buttons with the filters configured
+/// by the layout and the long press tiers of its bindings
+fn change_detector(
+    settings: &LayoutSettings,
+    layout_runtime: &LayerSwitcher,
+) -> ChangeDetector<XpPenButtons> {
+    let mut detector = ChangeDetector::new();
+    detector.set_reversal_filter(
+        XpPenButtons::XpRoCW,
+        XpPenButtons::XpRoCCW,
+        settings.rotary_reversal.unwrap_or(XP_ROTARY_REVERSAL_FILTER),
+    );
+    if let Some(debounce) = settings.debounce {
+        detector.set_debounce(debounce);
+    }
+    if let Some(window) = settings.duplicate_window {
+        detector.set_duplicate_window(window);
+    }
+    if let Some(bounce) = settings.bounce_keys {
+        detector.set_bounce_keys(bounce);
+    }
+    if let Some(slow_keys) = settings.slow_keys {
+        detector.set_slow_keys(slow_keys);
+    }
+    for button in EnumSet::<XpPenButtons>::all() {
+        let coords: KeyCoords = button.into();
+        if let Some((_, debounce)) = settings.key_debounce.iter().find(|(k, _)| *k == coords) {
+            detector.set_key_debounce(button, *debounce);
+        }
+    }
+    detector.set_long_press_tiers(layout_runtime.get_long_press_tiers());
+    detector
+}
+
+/// The rotary encoder acceleration configured by the layout
+fn rotary_accelerator(settings: &LayoutSettings) -> RotaryAccelerator {
+    RotaryAccelerator::new(
+        XpPenButtons::XpRoCW.into(),
+        XpPenButtons::XpRoCCW.into(),
+        settings.rotary_acceleration.iter().copied(),
+    )
+}
+
+/// The rotary encoder gestures, the thresholds configured by the layout
+fn gesture_detector(settings: &LayoutSettings) -> GestureDetector {
+    let mut gestures = GestureDetector::new(
+        XpPenButtons::XpRoCW.into(),
+        XpPenButtons::XpRoCCW.into(),
+        XP_ROTARY_GESTURES,
+    );
+    if let Some((spin_ticks, wiggle_ticks, window)) = settings.rotary_gestures {
+        gestures.set_thresholds(spin_ticks, wiggle_ticks, window);
+    }
+    gestures
+}
+
+/// The rotary detent divider configured by the layout, if any
+fn rotary_divider(settings: &LayoutSettings) -> Option<RotaryDivider> {
+    let (detents, idle) = settings.rotary_divider?;
+    Some(RotaryDivider::new(
+        XpPenButtons::XpRoCW.into(),
+        XpPenButtons::XpRoCCW.into(),
+        detents,
+        idle,
+    ))
+}
+
+/// The device watchdog with the limit configured by the layout
+fn device_watchdog(settings: &LayoutSettings) -> Watchdog {
+    let mut watchdog = Watchdog::new();
+    if let Some(limit) = settings.watchdog_try_again {
+        watchdog.set_limit(limit);
+    }
+    watchdog
+}
+
+/// The panic chord configured by the layout, the ACK05 corner buttons by default
+fn panic_keys(settings: &LayoutSettings) -> PanicChord {
+    match settings.panic_chord.clone() {
+        Some((keys, hold)) => PanicChord::new(keys, hold),
+        None => PanicChord::ack05(),
+    }
+}
+
+/// The input lock chord configured by the layout, if any
+fn lock_chord(settings: &LayoutSettings) -> Option<InputLock> {
+    let (keys, hold) = settings.input_lock.clone()?;
+    Some(InputLock::new(keys, hold))
+}
+
+/// The switch access scanning configured by the layout, if any
+fn switch_scanner(settings: &LayoutSettings) -> Option<SwitchScanner> {
+    let (switch, targets, interval) = settings.scanning.clone()?;
+    let mut scanner = SwitchScanner::new(switch, targets);
+    scanner.set_interval(interval);
+    Some(scanner)
+}
+
+/// The Morse input configured by the layout, if any
+fn morse_decoder(settings: &LayoutSettings) -> Option<MorseDecoder> {
+    let config = settings.morse.as_ref()?;
+    let mut morse = MorseDecoder::new(config.key);
+    morse.set_timing(config.dash, config.letter_gap);
+    for (code, keys) in &config.codes {
+        morse.set_code(code, keys.clone());
+    }
+    Some(morse)
+}
+
+/// The keyboards of the layout feeding it next to the keypad, their
+/// key events wake the main loop up
+fn open_keyboards(layout_source: &str, xppen: &Frontend<XpPenAck05>) -> Vec<EvdevInput> {
+    parse_keyboards(layout_source)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|keyboard| {
+            EvdevInput::open(keyboard, xppen.waker())
+                .map_err(|e| warn!("Cannot read the keyboard {}: {}", keyboard.path.display(), e))
+                .ok()
+        })
+        .collect()
+}
+
+/// The shared macro library with the macros of the layout file over it
+fn load_macros(path: &Path, layout_source: &str) -> io::Result<MacroLibrary> {
+    let mut macros = MacroLibrary::load(path)?;
+    macros.merge(parse_macros(layout_source).unwrap_or_default());
+    Ok(macros)
+}
+
+/// Type the button events by hand and watch what the layout does,
+/// no device is needed
+fn run_repl(cli: &Cli) {
+    let source = layout_source(&cli.layout_path());
+    let layout = parse_layout(&source).unwrap_or_else(|_| builtin_layout());
+    let geometry = parse_geometry(&source).unwrap_or_default();
+    let mut layout_runtime = LayerSwitcher::new(&layout);
+    apply_settings(&mut layout_runtime, cli, &source);
+    let mut repl = Repl::new(layout_runtime, geometry);
+
+    println!("Layout REPL, type help for the list of commands");
+    let mut lines = io::stdin().lines();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            return;
+        };
+
+        match repl.execute(&line) {
+            Ok(out) => out.iter().for_each(|l| println!("  {}", l)),
+            Err(e) => println!("  {}", e),
+        }
+    }
+}
+
+/// Print the usage heatmap over the device geometry as SVG
+fn print_heatmap(cli: &Cli) {
+    let stats_path = UsageStats::default_path();
+    match UsageStats::load(&stats_path) {
+        Ok(stats) => {
+            let geometry = parse_geometry(&layout_source(&cli.layout_path())).unwrap_or_default();
+            print!("{}", stats.heatmap(&geometry))
+        }
+        Err(e) => println!("Cannot load statistics from {}: {}", stats_path.display(), e),
+    }
+}
+
+/// The configuration files written by the driver, they are backed up before
+/// every write and can be rolled back
+fn config_files(layout_path: &Path) -> Vec<PathBuf> {
+    vec![layout_path.to_path_buf(), MacroLibrary::default_path()]
+}
+
+/// Revert all configuration files to their newest backup
+fn rollback_config(layout_path: &Path) {
+    for path in config_files(layout_path) {
+        match backup::rollback(&path) {
+            Ok(Some(from)) => info!("Restored {} from {}", path.display(), from.display()),
+            Ok(None) => info!("No backup of {}", path.display()),
+            Err(e) => error!("Cannot restore {}: {}", path.display(), e),
+        }
+    }
+}
 
+/// Tell the D-Bus clients about a newly loaded layout
+fn publish_layout(
+    control: Option<&DbusControl>,
+    profile: &str,
+    layout_runtime: &LayerSwitcher,
+    layers: usize,
+) {
+    if let Some(control) = control {
+        let names = (0..layers)
+            .map(|l| layout_runtime.get_layer_name(l).unwrap_or_default().to_string())
+            .collect();
+        control.set_layout(profile, names);
+        if let Err(e) = control.set_active_layers(&layout_runtime.get_active_layers()) {
+            warn!("Cannot publish the active layers: {}", e);
+        }
+    }
+}
+
+/// Parse and validate the layout `file`, exit with an error when it is broken
+fn check_config(file: &Path) {
+    match read_layout(file) {
+        Ok(Some(source)) => {
+            let layers = parse_layout(&source).unwrap_or_default();
+            println!("{}: {} layers, OK", file.display(), layers.len());
+        }
+        Ok(None) => {
+            println!("{}: no such file", file.display());
+            process::exit(1);
+        }
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Print the buttons as they are pressed, to find out how the keypad
+/// buttons are numbered in the layout
+fn identify(cli: &Cli) {
+    let source = layout_source(&cli.layout_path());
+    let geometry = parse_geometry(&source).unwrap_or_default();
+    let mut xppen = open_device(cli, &source);
+    let mut detector = ChangeDetector::new();
+
+    println!("Press the buttons, Ctrl+C ends");
+    loop {
+        let t = detector.now();
+        if let Err(e) = read_into(&mut xppen, &mut detector, -1, t) {
+            println!("Cannot read the keypad: {}", e);
+            process::exit(1);
+        }
+        for ev in detector.drain() {
+            let (KeyStateChange::Pressed(button) | KeyStateChange::Click(button)) = ev.change else {
+                continue;
+            };
+            let coords: KeyCoords = button.into();
+            let label = geometry.label(coords).unwrap_or("-");
+            println!(
+                "{:?}: block {} row {} column {} ({})",
+                button, coords.0, coords.1, coords.2, label
+            );
+        }
+    }
+}
+
+fn debug_reports(cli: &Cli) {
+    let source = layout_source(&cli.layout_path());
+    let mut xppen = open_device(cli, &source);
+    let start = Instant::now();
+    let mut buf = [0u8; 32];
+
+    println!("Press the buttons, Ctrl+C ends");
+    println!("seconds | bytes | set bits (byte.bit) | buttons");
+    loop {
+        let len = match xppen.read_raw(&mut buf, -1) {
+            Ok(0) => continue,
+            Ok(len) => len,
+            Err(e) => {
+                println!("Cannot read the keypad: {}", e);
+                process::exit(1);
+            }
+        };
+        let report = &buf[..len];
+        let buttons = match xppen.parse(report) {
+            XpPenResult::Keys(keys) => format!("{:?}", keys.iter().collect::<Vec<_>>()),
+            _ => "not a button report".to_string(),
+        };
+        println!(
+            "{:.3} | {} | {}",
+            start.elapsed().as_secs_f64(),
+            describe_report(report),
+            buttons
+        );
+    }
+}
+
+/// Log to the standard output, only the driver's own messages unless
+/// RUST_LOG asks for more. The journal adds its own timestamps.
+fn init_logging(cli: &Cli) {
+    let default = if cli.verbose {
+        "warn,xppen_ack05=debug"
+    } else {
+        "warn,xppen_ack05=info"
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    if cli.daemon {
+        subscriber.without_time().with_ansi(false).init();
+    } else {
+        subscriber.init();
+    }
+}
 
 fn main() {
+    let cli = Cli::parse();
+    init_logging(&cli);
+    match cli.command.as_ref().unwrap_or(&Command::Run) {
+        Command::Run => run(&cli),
+        Command::ListDevices => match list_devices() {
+            Ok(devices) => devices.iter().for_each(|d| println!("{}", d)),
+            Err(e) => {
+                println!("{}", e);
+                process::exit(1);
+            }
+        },
+        Command::CheckConfig { file } => check_config(file),
+        Command::Identify => identify(&cli),
+        Command::DebugReports => debug_reports(&cli),
+        Command::Version => println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        Command::Repl => run_repl(&cli),
+        Command::Heatmap => print_heatmap(&cli),
+        Command::Rollback => rollback_config(&cli.layout_path()),
+    }
+}
+
+/// Leave the keypad alone until stopped. It is neither switched to the key bit
+/// mode nor grabbed, so it keeps typing the keys of its standard HID keyboard.
+fn run_passthrough(cli: &Cli) {
+    info!("Passthrough, the keypad keeps its own key mapping.");
+    // The mode switch of an earlier run survives until the keypad loses power
+    let selector = device_selector(cli, &layout_source(&cli.layout_path()));
+    if let Err(e) = restore_standard_mode(&selector) {
+        warn!("Cannot restore the standard keyboard mode: {}", e);
+    }
+    if let Err(e) = systemd::notify("READY=1") {
+        warn!("Cannot notify systemd: {}", e);
+    }
+    while !systemd::stop_requested() {
+        sleep(RECONNECT_DELAY);
+    }
+    info!("Stopping the passthrough.");
+}
+
+/// Drive the virtual keyboard with the keypad
+fn run(cli: &Cli) {
+    if cli.daemon {
+        if let Err(e) = systemd::log_to_journal(env!("CARGO_PKG_NAME")) {
+            warn!("Cannot log to the journal: {}", e);
+        }
+    }
+    // Killed mid-hold the driver would leave eg. Shift held system-wide
+    if let Err(e) = systemd::handle_stop_signals() {
+        warn!("Cannot handle the stop signals: {}", e);
+    }
+    for device in list_devices().unwrap_or_default() {
+        debug!("HID device {}", device);
+    }
+    if cli.dry_run {
+        info!("Dry run, the keys are only logged.");
+    }
+    if cli.passthrough {
+        run_passthrough(cli);
+        return;
+    }
+
+    // The layout and the device description
+    let mut layout_path = cli.layout_path();
+    let mut profile = DEFAULT_PROFILE.to_string();
+    let mut source = layout_source(&layout_path);
+
     // Open XPPen ACK05
-    let xppen = XpPenAck05::new();
+    let mut xppen = Frontend::start(open_device(cli, &source));
 
-    // XPPen State machine
-    let mut xppen_events = ChangeDetector::new();
+    // Buttons pressed together as chords of their own
+    let mut chords = ChordResolver::new(parse_chords(&source).unwrap_or_default());
 
-    let layout = load_layout("test");
-    let mut layout_runtime = LayerSwitcher::new(&layout);
+    // The layout is replaced when the file changes, the switcher shares it
+    // with the notifications
+    let mut layout = Arc::new(parse_layout(&source).unwrap_or_else(|_| builtin_layout()));
+    let mut layout_runtime = LayerSwitcher::from_shared(Arc::clone(&layout));
+    let mut settings = apply_settings(&mut layout_runtime, cli, &source);
     layout_runtime.start();
+    let mut divider = rotary_divider(&settings);
+    let mut accelerator = rotary_accelerator(&settings);
+    let mut gestures = gesture_detector(&settings);
+    let mut input_lock = lock_chord(&settings);
+
+    // XPPen State machine
+    let mut xppen_events = change_detector(&settings, &layout_runtime);
+
+    // Recorded macros
+    let macro_path = MacroLibrary::default_path();
+    match load_macros(&macro_path, &source) {
+        Ok(macros) => layout_runtime.set_macros(macros),
+        Err(e) => warn!("Cannot load macros from {}: {}", macro_path.display(), e),
+    }
+    for (layer, coords, name) in layout_runtime.macros().missing_macros(&layout) {
+        warn!("Layer {} key {:?} plays an unknown macro {}", layer, coords, name);
+    }
+    let mut recorder: Option<(String, MacroRecorder)> = None;
+
+    // Usage statistics are only counted when asked for
+    let stats_path = UsageStats::default_path();
+    let mut stats = if cli.stats {
+        match UsageStats::load(&stats_path) {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                warn!("Cannot load statistics from {}: {}", stats_path.display(), e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut unsaved_presses = 0;
+
+    // Morse input on a single key, the built-in layout does not enable it
+    let mut morse = morse_decoder(&settings);
+
+    // Create a virtual keyboard and the other outputs
+    let (mut outputs, mut gamepad, mut dial) = retry_with_backoff("the virtual devices", || {
+        create_outputs(&layout_runtime, morse.as_ref(), &settings, cli.dry_run)
+    });
+
+    // Host keyboard LEDs for layers conditioned on Caps Lock / Num Lock
+    let host_leds = HostLeds::open();
+    let mut leds = host_leds.read();
+    layout_runtime.set_leds(leds.iter().copied());
+
+    // Regular keyboards using the layers of the keypad
+    let mut keyboards = open_keyboards(&source, &xppen);
+
+    // Tablet pen proximity for layers conditioned on it
+    let mut pen = PenProximity::open();
+    layout_runtime.set_pen_proximity(pen.poll());
+    render(&mut layout_runtime, &mut outputs, &mut gamepad);
+
+    // Audible layer and lock changes
+    let audio = AudioFeedback::open();
+    let speech = SpeechFeedback::open();
+    let mut notifications = DesktopNotifications::open()
+        .map_err(|e| debug!("Desktop notifications not available: {}", e))
+        .ok();
+    let mut active_layers = layout_runtime.get_active_layers();
 
-    // Create a virtual keyboard
-    let mut kbd = VirtualKeyboard::new(layout_runtime.get_used_keys());
+    // Long press of the two corner buttons resets everything
+    let mut panic_chord = panic_keys(&settings);
 
-    // Wait for a HID event when reading from XP Pen (= block)
-    xppen.set_blocking();
+    // Switch access scanning, the built-in layout does not enable it
+    let mut geometry = parse_geometry(&source).unwrap_or_default();
+    let mut scanner = switch_scanner(&settings);
+    let mut highlighted = None;
+
+    // Release all keys before the system goes to sleep
+    let sleep_inhibitor = SleepInhibitor::start()
+        .map_err(|e| warn!("Sleep inhibitor not available: {}", e))
+        .ok();
+
+    // Reopen the device after a suspend, logind tells about it when available
+    let mut resume_detector = ResumeDetector::new();
+
+    // Reset the device when it gets stuck
+    let mut watchdog = device_watchdog(&settings);
+
+    // Profile and layer switching over D-Bus
+    let control = DbusControl::start()
+        .map_err(|e| warn!("D-Bus control not available: {}", e))
+        .ok();
+    publish_layout(control.as_ref(), &profile, &layout_runtime, layout.len());
+    // The requests wake the async main loop up instead of waiting for the idle poll
+    #[cfg(all(feature = "tokio", target_os = "linux"))]
+    if let Some(control) = control.as_ref() {
+        control.on_request(xppen.waker());
+    }
+
+    // Automatic profile switching following the focused application
+    let focus_path = FocusConfig::default_path();
+    let focus = match FocusConfig::load(&focus_path) {
+        Ok(config) => config.map(FocusWatcher::start),
+        Err(e) => {
+            warn!("Cannot load the profiles from {}: {}", focus_path.display(), e);
+            None
+        }
+    };
+
+    // Reload the layout when the file changes
+    let mut layout_watcher = FileWatcher::open(&layout_path)
+        .map_err(|e| warn!("Cannot watch the layout {}: {}", layout_path.display(), e))
+        .ok();
+    #[cfg(all(feature = "tokio", target_os = "linux"))]
+    xppen.watch(layout_watcher.as_ref());
+
+    // Everything is set up, a Type=notify service counts as started now
+    if let Err(e) = systemd::notify("READY=1") {
+        warn!("Cannot notify systemd: {}", e);
+    }
 
     loop {
-        // Read state data from device
-        // When any button is pressed use read timeout so the long press can be
-        // analyzed in between messages.
-        let result = xppen.read(!xppen_events.has_short_pressed());
-        //println!("{:?}", result);
+        // Wait for the next report of the device. When any button is pressed
+        // wake up in between, so the long press can be analyzed.
+        let timeout = if let Some(wait) = layout_runtime.next_timer_in() {
+            // Wake up for the next macro step or turbo click
+            wait.min(SCAN_POLL)
+        } else if scanner.is_some()
+            || recorder.is_some()
+            || panic_chord.is_pending()
+            || input_lock.as_ref().is_some_and(|l| l.is_pending())
+            || chords.is_pending()
+            || morse.as_ref().is_some_and(|m| m.is_composing())
+        {
+            SCAN_POLL
+        } else if xppen_events.has_short_pressed() {
+            SHORT_PRESS_POLL
+        } else {
+            IDLE_POLL
+        };
+        let result = xppen.read_timeout(timeout);
+        // No wait is longer than the idle poll, no key may stay held after the exit
+        if systemd::stop_requested() {
+            info!("Stopping, releasing all keys.");
+            let _ = systemd::notify("STOPPING=1");
+            layout_runtime.release_all();
+            render(&mut layout_runtime, &mut outputs, &mut gamepad);
+            if let Some(stats) = stats.as_ref() {
+                let _ = stats.save(&stats_path);
+            }
+
+            // Remove the virtual devices before the exit instead of leaving it to the kernel
+            drop((outputs, gamepad, dial));
+            info!("Virtual devices removed.");
+            return;
+        }
+        let resumed = match sleep_inhibitor.as_ref().and_then(|s| s.poll()) {
+            Some(SleepEvent::Suspending(ready)) => {
+                info!("Going to sleep, releasing all keys.");
+                if let Some(stats) = stats.as_ref() {
+                    let _ = stats.save(&stats_path);
+                }
+                recover(&mut layout_runtime, &mut xppen_events);
+                render(&mut layout_runtime, &mut outputs, &mut gamepad);
+                let _ = ready.send(());
+                continue;
+            }
+            Some(SleepEvent::Resumed) => true,
+            None => false,
+        };
+        // Without logind the suspend is noticed by the clocks
+        if resume_detector.check() || resumed {
+            info!("Resumed from sleep, reopening the keypad.");
+            // Nothing held before the suspend is held any more
+            recover(&mut layout_runtime, &mut xppen_events);
+            render(&mut layout_runtime, &mut outputs, &mut gamepad);
+            chords.reset();
+            panic_chord.reset();
+            if let Some(lock) = input_lock.as_mut() {
+                lock.reset();
+            }
+            // The old handle is often stale and the keypad forgot the key bit
+            // mode, a report read across the suspend is dropped
+            xppen.restart(open_device(cli, &source));
+            layout_runtime.start();
+            continue;
+        }
+
+        // The reader timestamped the report right after reading it, all decisions
+        // are based on that time and not on when the report was taken here
+        let (result, t) = match result {
+            Ok(report) => report,
+            Err(e) => {
+                // Eg. the keypad was unplugged, none of its keys is held any more
+                warn!("Cannot read the keypad: {}", e);
+                audio.play(Cue::Error);
+                recover(&mut layout_runtime, &mut xppen_events);
+                render(&mut layout_runtime, &mut outputs, &mut gamepad);
+                xppen.restart(open_device(cli, &source));
+                continue;
+            }
+        };
+
+        if watchdog.check(&result) {
+            warn!("The device stopped reporting properly, resetting it.");
+            audio.play(Cue::Error);
+            recover(&mut layout_runtime, &mut xppen_events);
+            render(&mut layout_runtime, &mut outputs, &mut gamepad);
+            if let Some(Err(e)) = xppen.with(XpPenAck05::configure) {
+                error!("Cannot re-initialize the device: {}", e);
+            }
+            continue;
+        }
+
+        let mut reload = layout_watcher.as_mut().is_some_and(|w| w.changed());
+        let mut switch_to = focus.as_ref().and_then(|f| f.poll());
+        match control.as_ref().and_then(|c| c.poll()) {
+            Some(ControlRequest::SwitchProfile(name)) => switch_to = Some(name),
+            Some(ControlRequest::ActivateLayer(idx)) => layout_runtime.activate_layer(idx),
+            Some(ControlRequest::DeactivateLayer(idx)) => layout_runtime.deactivate_layer(idx),
+            Some(ControlRequest::Reload) => reload = true,
+            Some(ControlRequest::Passthrough(enabled)) => layout_runtime.set_bypassed(enabled),
+            None => {}
+        }
+        if let Some(name) = switch_to.filter(|name| *name != profile) {
+            let path = profile_layout_path(&name);
+            if path.exists() {
+                info!("Switching to the profile {}", name);
+                layout_watcher = FileWatcher::open(&path)
+                    .map_err(|e| warn!("Cannot watch the layout {}: {}", path.display(), e))
+                    .ok();
+                #[cfg(all(feature = "tokio", target_os = "linux"))]
+                xppen.watch(layout_watcher.as_ref());
+                layout_path = path;
+                profile = name;
+                reload = true;
+            } else {
+                warn!("Profile {} not found at {}", name, path.display());
+            }
+        }
+
+        if reload {
+            match read_layout(&layout_path) {
+                Ok(new_source) => {
+                    // The new layout is set up next to the current one
+                    let known_good = new_source.is_some();
+                    let new_source = new_source.unwrap_or_default();
+                    let new_layout =
+                        Arc::new(parse_layout(&new_source).unwrap_or_else(|_| builtin_layout()));
+                    let mut new_runtime = LayerSwitcher::from_shared(Arc::clone(&new_layout));
+                    let new_settings = apply_settings(&mut new_runtime, cli, &new_source);
+                    new_runtime.start();
+                    match load_macros(&macro_path, &new_source) {
+                        Ok(macros) => new_runtime.set_macros(macros),
+                        Err(e) => warn!("Cannot load macros from {}: {}", macro_path.display(), e),
+                    }
+                    new_runtime.set_leds(leds.iter().copied());
+                    new_runtime.set_pen_proximity(pen.poll());
+
+                    // The new layout may use other keys, the devices are created anew.
+                    // The current layout and its devices stay when that fails.
+                    let new_morse = morse_decoder(&new_settings);
+                    match create_outputs(
+                        &new_runtime,
+                        new_morse.as_ref(),
+                        &new_settings,
+                        cli.dry_run,
+                    ) {
+                        Ok(new_outputs) => {
+                            info!("Reloading the layout {}", layout_path.display());
+                            layout_runtime.release_all();
+                            render(&mut layout_runtime, &mut outputs, &mut gamepad);
+                            xppen_events.reset();
+
+                            // A later broken edit can be rolled back to this version
+                            if known_good {
+                                if let Err(e) = backup::known_good(&layout_path) {
+                                    warn!(
+                                    "Cannot back up the layout {}: {}",
+                                    layout_path.display(),
+                                    e
+                                );
+                                }
+                            }
+                            source = new_source;
+                            layout = new_layout;
+                            layout_runtime = new_runtime;
+                            settings = new_settings;
+                            morse = new_morse;
+                            (outputs, gamepad, dial) = new_outputs;
+                            divider = rotary_divider(&settings);
+                            accelerator = rotary_accelerator(&settings);
+                            gestures = gesture_detector(&settings);
+                            input_lock = lock_chord(&settings);
+                            panic_chord = panic_keys(&settings);
+                            watchdog = device_watchdog(&settings);
+                            scanner = switch_scanner(&settings);
+                            highlighted = None;
+                            xppen_events = change_detector(&settings, &layout_runtime);
+                            let map = parse_report_map(&source).unwrap_or_default();
+                            let fallback = parse_fallback_map(&source).unwrap_or_default();
+                            xppen.with(move |xppen| {
+                                xppen.set_report_map(map);
+                                xppen.set_fallback_map(fallback);
+                            });
+                            geometry = parse_geometry(&source).unwrap_or_default();
+                            chords =
+                                ChordResolver::new(parse_chords(&source).unwrap_or_default());
+                            // The old keyboards are released first, they may be grabbed again
+                            keyboards.clear();
+                            keyboards = open_keyboards(&source, &xppen);
+
+                            render(&mut layout_runtime, &mut outputs, &mut gamepad);
+                            active_layers = layout_runtime.get_active_layers();
+                            publish_layout(
+                                control.as_ref(),
+                                &profile,
+                                &layout_runtime,
+                                layout.len(),
+                            );
+                            continue;
+                        }
+                        Err(e) => {
+                            error!(
+                                "Cannot create the virtual devices of the layout {}, keeping the current one: {}",
+                                layout_path.display(),
+                                e
+                            );
+                            audio.play(Cue::Error);
+                        }
+                    }
+                }
+                Err(e) => {
+                    // Keep the current layout until the file is fixed
+                    warn!("{}", e);
+                    audio.play(Cue::Error);
+                }
+            }
+        }
 
         if let XpPenResult::Keys(buttons) = result {
             // Compute state changes
-            xppen_events.analyze(buttons, time::Instant::now());
+            xppen_events.analyze(buttons, t);
         } else {
-            xppen_events.tick(time::Instant::now());
+            xppen_events.tick(t);
+        }
+        if layout_runtime.next_macro_step().is_some() {
+            layout_runtime.set_host_keys(&host_leds.read_keys());
+        }
+        layout_runtime.tick(t);
+
+        // LED state and pen proximity are only sampled here, the read above means
+        // their changes are noticed with the next button event or idle poll
+        let current_leds = host_leds.read();
+        if current_leds.iter().ne(leds.iter()) {
+            audio.play(Cue::LockToggle);
+            speech.say(&speech_feedback::describe_locks(&leds, &current_leds).join(", "));
+            leds = current_leds;
         }
+        layout_runtime.set_leds(leds.iter().copied());
+        layout_runtime.set_pen_proximity(pen.poll());
+        render(&mut layout_runtime, &mut outputs, &mut gamepad);
+
+        // The panic chord and the lock see the physical keys, the rest the resolved
+        // chords. Nothing else sees the keys while the input is locked.
+        for ev in xppen_events.drain() {
+            debug!("Input {:?}", ev.change);
+            let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+            panic_chord.process(&ev, t);
+            if let Some(lock) = input_lock.as_mut() {
+                lock.process(&ev, t);
+                if lock.is_locked() {
+                    continue;
+                }
+            }
+            chords.process(ev, t);
+        }
+        // The keyboards only press and release, they have no long press
+        for keyboard in &keyboards {
+            while let Some(KeyEvent { change: ev, t }) = keyboard.poll() {
+                debug!("Keyboard input {:?}", ev);
+                if input_lock.as_ref().is_some_and(|lock| lock.is_locked()) {
+                    continue;
+                }
+                chords.process(ev, t);
+            }
+        }
+        if input_lock.as_mut().is_some_and(|lock| lock.tick(t)) {
+            let summary = if input_lock.as_ref().is_some_and(|lock| lock.is_locked()) {
+                // The chord keys were pressed in the layout, nothing may stay held
+                layout_runtime.release_all();
+                render(&mut layout_runtime, &mut outputs, &mut gamepad);
+                "Input locked"
+            } else {
+                "Input unlocked"
+            };
+            info!("{}", summary);
+            audio.play(Cue::LockToggle);
+            speech.say(summary);
+            if let Some(Err(e)) = notifications.as_mut().map(|n| n.show(summary, &[])) {
+                warn!("Cannot show the lock notification: {}", e);
+            }
+        }
+        chords.tick(t);
 
         // Emit virtual keys
-        while let Some(ev) = xppen_events.next() {
-            println!("Input: {:?}", ev);
-            layout_runtime.process_keyevent(ev, time::Instant::now());
-            layout_runtime.render(|k, s| {
-                println!("Output > {:?} pressed {}", k, s);
-                kbd.emit_key(k, s);
-                sleep(Duration::from_millis(2));
-            });
+        for (ev, t) in chords.by_ref() {
+            let _span = debug_span!("input", event = ?ev).entered();
+            let gesture = gestures.process(&ev, t);
+            if let Some((wheel, device)) = dial.as_mut() {
+                if let Some(value) = wheel.turn(&ev) {
+                    if let Err(e) = device.emit(value) {
+                        warn!("Cannot emit the dial position {}: {}", value, e);
+                    }
+                    continue;
+                }
+            }
+            let Some(ev) = scanner.as_mut().map_or(Some(ev), |s| s.process(ev, t)) else {
+                continue;
+            };
+            let Some(ev) = morse.as_mut().map_or(Some(ev), |m| m.process(ev, t)) else {
+                continue;
+            };
+            let Some(ev) = divider.as_mut().map_or(Some(ev), |d| d.process(ev, t)) else {
+                continue;
+            };
+            let ev = accelerator.process(ev, t);
+
+            if let (
+                Some(stats),
+                KeyStateChange::Pressed(k) | KeyStateChange::Click(k) | KeyStateChange::Turn(k, _),
+            ) = (stats.as_mut(), ev)
+            {
+                stats.record(k, layout_runtime.resolve(k));
+                unsaved_presses += 1;
+                if unsaved_presses >= STATS_SAVE_PRESSES {
+                    unsaved_presses = 0;
+                    if let Err(e) = stats.save(&stats_path) {
+                        warn!("Cannot save statistics to {}: {}", stats_path.display(), e);
+                    }
+                }
+            }
+
+            layout_runtime.process_keyevent(ev, t);
+            render(&mut layout_runtime, &mut outputs, &mut gamepad);
+
+            if let Some(gesture) = gesture {
+                debug!("Gesture {:?}", gesture);
+                layout_runtime.process_keyevent(gesture, t);
+                render(&mut layout_runtime, &mut outputs, &mut gamepad);
+            }
+        }
+
+        if panic_chord.tick(t) {
+            warn!("Panic chord, releasing all keys and resetting the layers.");
+            layout_runtime.release_all();
+            render(&mut layout_runtime, &mut outputs, &mut gamepad);
+            audio.play(Cue::Error);
+        }
+
+        // Macro recording is started and stopped by the Mrec action
+        if let Some((_, r)) = recorder.as_mut() {
+            r.poll();
+        }
+        match (layout_runtime.recording(), recorder.take()) {
+            (Some(name), None) => {
+                info!("Recording macro {}", name);
+                recorder = Some((name.to_string(), MacroRecorder::start()));
+            }
+            (None, Some((name, r))) => {
+                let m = r.finish(&name);
+                info!("Recorded macro {} with {} steps", name, m.steps.len());
+                // Saving the layout reloads it together with the new macro
+                if let Err(e) = m.save_to_layout(&layout_path) {
+                    error!("Cannot save the macro to {}: {}", layout_path.display(), e);
+                    audio.play(Cue::Error);
+                }
+                layout_runtime.store_macro(m);
+            }
+            (_, r) => recorder = r,
+        }
+
+        // The Rollback action reverts the configuration to its previous backup
+        if layout_runtime.take_rollback() {
+            rollback_config(&layout_path);
+            match load_macros(&macro_path, &source) {
+                Ok(macros) => layout_runtime.set_macros(macros),
+                Err(e) => {
+                    warn!("Cannot load macros from {}: {}", macro_path.display(), e);
+                    audio.play(Cue::Error);
+                }
+            }
+        }
+
+        // The Passthrough action leaves the keypad to the system until pressed again
+        if layout_runtime.is_bypassed() != xppen.is_released() {
+            let bypassed = layout_runtime.is_bypassed();
+            xppen_events.reset();
+            if !bypassed {
+                xppen.restart(open_device(cli, &source));
+            } else if let Err(e) = xppen.release() {
+                warn!("Cannot restore the standard keyboard mode: {}", e);
+            }
+            let summary = if bypassed { "Layout bypassed" } else { "Layout resumed" };
+            info!("{}", summary);
+            speech.say(summary);
+            if let Some(Err(e)) = notifications.as_mut().map(|n| n.show(summary, &[])) {
+                warn!("Cannot show the passthrough notification: {}", e);
+            }
+        }
+
+        // Scripts bound to the keys by the Cmd action
+        for cmd in layout_runtime.take_commands() {
+            if cli.dry_run {
+                info!("Dry run command {}", cmd);
+            } else if let Err(e) = shell_command::spawn(&cmd) {
+                warn!("Cannot run {}: {}", cmd, e);
+                audio.play(Cue::Error);
+            } else {
+                debug!("Running {}", cmd);
+            }
+        }
+
+        match morse.as_mut().and_then(|m| m.tick(t)) {
+            Some(Ok(keys)) => {
+                layout_runtime.tap(keys);
+                render(&mut layout_runtime, &mut outputs, &mut gamepad);
+            }
+            Some(Err(code)) => {
+                warn!("Unknown Morse code {}", code);
+                audio.play(Cue::Error);
+            }
+            None => {}
+        }
+
+        if let Some(scanner) = scanner.as_mut() {
+            scanner.tick(t);
+            if scanner.highlighted() != highlighted {
+                highlighted = scanner.highlighted();
+                if let Some(label) = highlighted.and_then(|k| geometry.label(k)) {
+                    speech.say(label);
+                }
+            }
+        }
+
+        let current_layers = layout_runtime.get_active_layers();
+        if let Some(cue) = Cue::for_layers(&active_layers, &current_layers) {
+            audio.play(cue);
+            speech.say(
+                &speech_feedback::describe_layers(&active_layers, &current_layers, |l| {
+                    layout_runtime.get_layer_name(l)
+                })
+                .join(", "),
+            );
+        }
+        if let (Some(n), true) = (notifications.as_mut(), settings.layer_notifications) {
+            if current_layers != active_layers {
+                // The topmost layer decides what the keys do
+                let top = current_layers.last().copied().unwrap_or_default();
+                let body = if settings.cheat_sheet {
+                    cheat_sheet(&layout, top, &geometry)
+                } else {
+                    vec![]
+                };
+                if let Err(e) = n.show(&format!("Layer {}", layer_title(&layout, top)), &body) {
+                    warn!("Cannot show the layer notification: {}", e);
+                }
+            }
+        }
+        if let Some(Err(e)) = control.as_ref().map(|c| c.set_active_layers(&current_layers)) {
+            warn!("Cannot publish the active layers: {}", e);
         }
+        active_layers = current_layers;
     }
 }
@@ -0,0 +1,82 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Proximity of the tablet pens connected to the host
+///
+/// Every pen tablet is read in its own thread, the changes are only
+/// collected when `poll` is called. The tablets are not grabbed, the pen
+/// keeps working for the applications.
+pub struct PenProximity {
+    changes: Receiver<bool>,
+    near: bool,
+}
+
+impl PenProximity {
+    pub fn open() -> Self {
+        let (tx, rx) = mpsc::channel();
+        let near = watch_pens(tx);
+        Self { changes: rx, near }
+    }
+
+    /// Is any pen in proximity? Collects the changes since the last call.
+    pub fn poll(&mut self) -> bool {
+        while let Ok(near) = self.changes.try_recv() {
+            self.near = near;
+        }
+        self.near
+    }
+}
+
+/// Send the proximity changes of every pen tablet to `tx`, returns whether
+/// a pen is near already
+#[cfg(target_os = "linux")]
+fn watch_pens(tx: Sender<bool>) -> bool {
+    use std::thread;
+
+    use evdev::{InputEventKind, Key};
+    use tracing::info;
+
+    /// Tools of a tablet pen, any of them in proximity means the pen is near
+    const PEN_TOOLS: [Key; 2] = [Key::BTN_TOOL_PEN, Key::BTN_TOOL_RUBBER];
+
+    let mut near = false;
+    for (path, mut device) in evdev::enumerate() {
+        let is_pen = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(Key::BTN_TOOL_PEN));
+        if !is_pen {
+            continue;
+        }
+
+        info!(
+            "Watching pen proximity on {} {:?}",
+            path.display(),
+            device.name()
+        );
+        if let Ok(state) = device.get_key_state() {
+            near |= PEN_TOOLS.iter().any(|k| state.contains(*k));
+        }
+
+        let tx = tx.clone();
+        thread::spawn(move || loop {
+            let Ok(events) = device.fetch_events() else {
+                return;
+            };
+            for ev in events {
+                if let InputEventKind::Key(k) = ev.kind() {
+                    if PEN_TOOLS.contains(&k) && tx.send(ev.value() != 0).is_err() {
+                        // Nobody is interested anymore
+                        return;
+                    }
+                }
+            }
+        });
+    }
+    near
+}
+
+/// The tablets are only readable through evdev, elsewhere the pen is
+/// never near
+#[cfg(not(target_os = "linux"))]
+fn watch_pens(_tx: Sender<bool>) -> bool {
+    false
+}
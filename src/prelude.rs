@@ -0,0 +1,52 @@
+//! The public API of the layout engine
+//!
+//! Everything needed to embed the engine is re-exported here, the module
+//! layout behind it is an implementation detail and may change. The device
+//! and host integration (`xppen_hid`, `virtual_keyboard`, `host_leds`, ...)
+//! of the driver binary is only public with the `driver` feature.
+//!
+//! ```no_run
+//! use xppen_ack05::prelude::*;
+//!
+//! let layout = load_layout(&default_layout_path()).unwrap();
+//! let mut switcher = LayerSwitcher::new(&layout);
+//! switcher.start();
+//! switcher.process_keyevent(KeyStateChange::Click(KeyCoords(0, 0, 0)), std::time::Instant::now());
+//! switcher.render(|key, pressed| println!("{:?} {}", key, pressed));
+//! ```
+
+pub use crate::clock::{Clock, ManualClock, SystemClock};
+pub use crate::evdev_input::KeyboardSource;
+pub use crate::kbd_events::acceleration::RotaryAccelerator;
+pub use crate::kbd_events::chords::{Chord, ChordResolver};
+pub use crate::kbd_events::dial::{WheelDial, DIAL_POSITIONS};
+pub use crate::kbd_events::divider::RotaryDivider;
+pub use crate::kbd_events::gestures::{GestureDetector, RotaryGesture};
+pub use crate::kbd_events::lock::InputLock;
+pub use crate::kbd_events::morse::MorseDecoder;
+pub use crate::kbd_events::panic::PanicChord;
+pub use crate::kbd_events::scanning::SwitchScanner;
+pub use crate::kbd_events::{ChangeDetector, HasState, KeyEvent, KeyStateChange};
+pub use crate::keycodes::{AbsoluteAxisType, KeyCode, LedType, RelativeAxisType, UnknownCode};
+pub use crate::layout::builder::{LayerBuilder, LayoutBuilder};
+pub use crate::layout::geometry::{BlockGeometry, Geometry};
+pub use crate::layout::keys::{parse_key, KeyGroup, UnknownKey, G, S};
+pub use crate::layout::layer::Layer;
+pub use crate::layout::serialization::{
+    builtin_layout, default_layout_path, load_layout, parse_chords, parse_fallback_map,
+    parse_geometry, parse_keyboards, parse_layout, parse_macros, parse_report_map, parse_settings,
+    profile_layout_path, DEFAULT_PROFILE,
+};
+pub use crate::layout::switcher::{LayerChange, LayerChangeReason, LayerSwitcher};
+pub use crate::layout::types::{
+    ActivationDebounce, EventCount, KeyCoords, KeyRepeat, Keymap, KeymapEvent, LayerCondition,
+    LayerId, LayerStatus, LayoutSettings, LeaderSequence, LongPressRace, TapHold,
+};
+pub use crate::layout::validation::{validate, LayoutError};
+pub use crate::macros::recorder::MacroRecorder;
+pub use crate::macros::{Macro, MacroLibrary, MacroRepeat, MacroStep};
+pub use crate::virtual_gamepad::GamepadEvent;
+pub use crate::xppen_hid::report_map::{KeyboardReportMap, ReportMap};
+pub use crate::xppen_hid::XpPenError;
+/// The error of the layout parsers, eg. `parse_layout`
+pub use toml::de::Error as ParseError;
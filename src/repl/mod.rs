@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use enumset::EnumSet;
+
+use crate::clock::ManualClock;
+use crate::kbd_events::{ChangeDetector, KeyEvent, KeyStateChange};
+use crate::keycodes::KeyCode;
+use crate::layout::geometry::Geometry;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeyCoords;
+use crate::xppen_hid::XpPenButtons;
+
+/// The virtual clock advances in steps like the main loop polls
+const WAIT_STEP: Duration = Duration::from_millis(10);
+
+const HELP: &str = "\
+press <button>    press and keep holding the button
+release <button>  release the button
+click <button>    press and release the button
+wait <ms>         let the time pass
+layers            list the active layers
+held              list the keys held on the virtual keyboard
+reset             release everything and restart the layout
+help              show this help
+
+Buttons are b01-b10, cw, ccw or their geometry labels, eg. top-left.";
+
+/// Interactive exploration of a layout
+///
+/// The commands drive the same change detection and layer switching as the
+/// physical device does, but on a virtual clock, so holds and timeouts are
+/// reproducible. Every step reports the detected events and the emitted keys.
+pub struct Repl {
+    layout: LayerSwitcher,
+    detector: ChangeDetector<XpPenButtons>,
+    geometry: Geometry,
+    /// Buttons physically held
+    buttons: EnumSet<XpPenButtons>,
+    /// Keys held on the virtual keyboard
+    held: Vec<KeyCode>,
+    /// The virtual clock shared with the detector and the layout
+    clock: ManualClock,
+}
+
+impl Repl {
+    pub fn new(mut layout: LayerSwitcher, geometry: Geometry) -> Self {
+        let clock = ManualClock::new();
+        let mut detector = ChangeDetector::new();
+        detector.set_long_press_tiers(layout.get_long_press_tiers());
+        detector.set_clock(clock.clone());
+        layout.set_clock(clock.clone());
+
+        let mut repl = Self {
+            layout,
+            detector,
+            geometry,
+            buttons: EnumSet::empty(),
+            held: Vec::new(),
+            clock,
+        };
+        repl.layout.start();
+        repl
+    }
+
+    /// Execute one command line and describe what happened
+    pub fn execute(&mut self, line: &str) -> Result<Vec<String>, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(vec![]);
+        };
+        let arg = words.collect::<Vec<&str>>().join(" ");
+
+        match command {
+            "press" => {
+                let button = self.button(&arg)?;
+                Ok(self.set_buttons(self.buttons | button))
+            }
+            "release" => {
+                let button = self.button(&arg)?;
+                Ok(self.set_buttons(self.buttons - button))
+            }
+            "click" => {
+                let button = self.button(&arg)?;
+                let mut out = self.set_buttons(self.buttons | button);
+                self.clock.advance(WAIT_STEP);
+                out.extend(self.set_buttons(self.buttons - button));
+                Ok(out)
+            }
+            "wait" => {
+                let ms: u64 = arg
+                    .parse()
+                    .map_err(|_| format!("Not a number of ms: {}", arg))?;
+                Ok(self.wait(Duration::from_millis(ms)))
+            }
+            "layers" => Ok(vec![format!(
+                "layers {:?}",
+                self.layout.get_active_layers()
+            )]),
+            "held" => Ok(vec![format!("held {:?}", self.held)]),
+            "reset" => {
+                // The releases have to be rendered before the restart drops them
+                self.layout.release_all();
+                let out = self.render(vec![]);
+                self.layout.start();
+                self.detector.reset();
+                self.buttons = EnumSet::empty();
+                Ok(self.render(out))
+            }
+            "help" => Ok(HELP.lines().map(str::to_string).collect()),
+            _ => Err(format!("Unknown command {}, try help", command)),
+        }
+    }
+
+    /// Find the button by its name or its geometry label
+    fn button(&self, name: &str) -> Result<XpPenButtons, String> {
+        let coords = match name.to_lowercase().as_str() {
+            "cw" => Some(XpPenButtons::XpRoCW.into()),
+            "ccw" => Some(XpPenButtons::XpRoCCW.into()),
+            n => n
+                .strip_prefix('b')
+                .and_then(|idx| idx.parse::<u8>().ok())
+                .filter(|idx| (1..=10).contains(idx))
+                .map(|idx| KeyCoords(0, 0, idx - 1))
+                .or_else(|| self.geometry.find(name)),
+        };
+
+        EnumSet::<XpPenButtons>::all()
+            .iter()
+            .find(|b| coords == Some((*b).into()))
+            .ok_or_else(|| format!("Unknown button {}", name))
+    }
+
+    fn set_buttons(&mut self, buttons: EnumSet<XpPenButtons>) -> Vec<String> {
+        self.buttons = buttons;
+        self.detector.analyze_now(buttons);
+
+        // The rotary encoder only pulses
+        self.buttons -= XpPenButtons::XpRoCW | XpPenButtons::XpRoCCW;
+        self.process()
+    }
+
+    fn wait(&mut self, duration: Duration) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut left = duration;
+        while !left.is_zero() {
+            let step = left.min(WAIT_STEP);
+            self.clock.advance(step);
+            left -= step;
+            self.detector.tick_now();
+            out.extend(self.process());
+        }
+
+        // Long presses repeat with every tick, report them once with a count
+        let mut collapsed: Vec<(String, usize)> = Vec::new();
+        for line in out {
+            match collapsed.last_mut() {
+                Some((last, count)) if *last == line => *count += 1,
+                _ => collapsed.push((line, 1)),
+            }
+        }
+        collapsed
+            .into_iter()
+            .map(|(line, count)| match count {
+                1 => line,
+                _ => format!("{} x{}", line, count),
+            })
+            .collect()
+    }
+
+    fn process(&mut self) -> Vec<String> {
+        let mut out = Vec::new();
+        self.layout.tick_now();
+        for ev in self.detector.drain() {
+            let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+            match ev {
+                // The held time grows with every tick, without it the repeats collapse
+                KeyStateChange::LongPress(k, _) => out.push(format!("event LongPress({:?})", k)),
+                ev => out.push(format!("event {:?}", ev)),
+            }
+            self.layout.process_keyevent(ev, t);
+        }
+        self.render(out)
+    }
+
+    fn render(&mut self, mut out: Vec<String>) -> Vec<String> {
+        let held = &mut self.held;
+        self.layout.render(|k, pressed| {
+            held.retain(|h| *h != k);
+            if pressed {
+                held.push(k);
+            }
+            out.push(format!("key {}{:?}", if pressed { '+' } else { '-' }, k));
+        });
+        self.layout
+            .render_pointer(|axis, delta| out.push(format!("pointer {:?} {:+}", axis, delta)));
+        out
+    }
+}
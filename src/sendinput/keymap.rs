@@ -0,0 +1,97 @@
+use crate::keycodes::KeyCode;
+
+/// How a key is typed through SendInput
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WinKey {
+    /// A set 1 scancode, the extended ones are prefixed by E0
+    Scan(u16, bool),
+    /// A virtual key code, for the keys without a stable scancode
+    Virtual(u16),
+}
+
+/// Keys typed with an E0 prefixed scancode, the navigation block and
+/// the right side modifiers
+const EXTENDED: [(KeyCode, u16); 19] = [
+    (KeyCode::KEY_KPENTER, 0x1c),
+    (KeyCode::KEY_RIGHTCTRL, 0x1d),
+    (KeyCode::KEY_KPSLASH, 0x35),
+    (KeyCode::KEY_SYSRQ, 0x37),
+    (KeyCode::KEY_RIGHTALT, 0x38),
+    (KeyCode::KEY_HOME, 0x47),
+    (KeyCode::KEY_UP, 0x48),
+    (KeyCode::KEY_PAGEUP, 0x49),
+    (KeyCode::KEY_LEFT, 0x4b),
+    (KeyCode::KEY_RIGHT, 0x4d),
+    (KeyCode::KEY_END, 0x4f),
+    (KeyCode::KEY_DOWN, 0x50),
+    (KeyCode::KEY_PAGEDOWN, 0x51),
+    (KeyCode::KEY_INSERT, 0x52),
+    (KeyCode::KEY_DELETE, 0x53),
+    (KeyCode::KEY_LEFTMETA, 0x5b),
+    (KeyCode::KEY_RIGHTMETA, 0x5c),
+    (KeyCode::KEY_COMPOSE, 0x5d),
+    (KeyCode::KEY_HANGEUL, 0x72),
+];
+
+/// The keys of the international layouts outside the regular block
+const INTERNATIONAL: [(KeyCode, u16); 7] = [
+    (KeyCode::KEY_KPEQUAL, 0x59),
+    (KeyCode::KEY_KATAKANAHIRAGANA, 0x70),
+    (KeyCode::KEY_RO, 0x73),
+    (KeyCode::KEY_HENKAN, 0x79),
+    (KeyCode::KEY_MUHENKAN, 0x7b),
+    (KeyCode::KEY_YEN, 0x7d),
+    (KeyCode::KEY_KPCOMMA, 0x7e),
+];
+
+/// Media and browser keys, Windows maps their virtual key codes to the
+/// actions no matter the keyboard
+const VIRTUAL: [(KeyCode, u16); 21] = [
+    (KeyCode::KEY_PAUSE, 0x13),
+    (KeyCode::KEY_HELP, 0x2f),
+    (KeyCode::KEY_SLEEP, 0x5f),
+    (KeyCode::KEY_BACK, 0xa6),
+    (KeyCode::KEY_FORWARD, 0xa7),
+    (KeyCode::KEY_REFRESH, 0xa8),
+    (KeyCode::KEY_STOP, 0xa9),
+    (KeyCode::KEY_SEARCH, 0xaa),
+    (KeyCode::KEY_BOOKMARKS, 0xab),
+    (KeyCode::KEY_HOMEPAGE, 0xac),
+    (KeyCode::KEY_MUTE, 0xad),
+    (KeyCode::KEY_VOLUMEDOWN, 0xae),
+    (KeyCode::KEY_VOLUMEUP, 0xaf),
+    (KeyCode::KEY_NEXTSONG, 0xb0),
+    (KeyCode::KEY_PREVIOUSSONG, 0xb1),
+    (KeyCode::KEY_STOPCD, 0xb2),
+    (KeyCode::KEY_PLAYPAUSE, 0xb3),
+    (KeyCode::KEY_MAIL, 0xb4),
+    (KeyCode::KEY_MEDIA, 0xb5),
+    (KeyCode::KEY_COMPUTER, 0xb6),
+    (KeyCode::KEY_CALC, 0xb7),
+];
+
+/// VK_F13, the following function keys are numbered in order
+const VK_F13: u16 = 0x7c;
+
+/// The Windows equivalent of `key`, None when it has none
+///
+/// The keycodes of the regular keyboard block (KEY_ESC to KEY_KPDOT,
+/// KEY_102ND, KEY_F11 and KEY_F12) are the set 1 scancodes already.
+pub fn win_key(key: KeyCode) -> Option<WinKey> {
+    let code = key.code();
+    if (KeyCode::KEY_ESC.code()..=KeyCode::KEY_KPDOT.code()).contains(&code)
+        || (KeyCode::KEY_102ND.code()..=KeyCode::KEY_F12.code()).contains(&code)
+    {
+        return Some(WinKey::Scan(code, false));
+    }
+    if (KeyCode::KEY_F13.code()..=KeyCode::KEY_F24.code()).contains(&code) {
+        return Some(WinKey::Virtual(VK_F13 + code - KeyCode::KEY_F13.code()));
+    }
+
+    let find =
+        |table: &[(KeyCode, u16)]| table.iter().find(|(k, _)| *k == key).map(|(_, code)| *code);
+    find(&EXTENDED)
+        .map(|scan| WinKey::Scan(scan, true))
+        .or_else(|| find(&INTERNATIONAL).map(|scan| WinKey::Scan(scan, false)))
+        .or_else(|| find(&VIRTUAL).map(WinKey::Virtual))
+}
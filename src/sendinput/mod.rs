@@ -0,0 +1,182 @@
+pub mod keymap;
+
+use std::io;
+use std::mem;
+
+use crate::keycodes::{KeyCode, RelativeAxisType};
+use keymap::{win_key, WinKey};
+
+const INPUT_MOUSE: u32 = 0;
+const INPUT_KEYBOARD: u32 = 1;
+
+const KEYEVENTF_EXTENDEDKEY: u32 = 0x0001;
+const KEYEVENTF_KEYUP: u32 = 0x0002;
+const KEYEVENTF_SCANCODE: u32 = 0x0008;
+
+const MOUSEEVENTF_MOVE: u32 = 0x0001;
+const MOUSEEVENTF_LEFTDOWN: u32 = 0x0002;
+const MOUSEEVENTF_LEFTUP: u32 = 0x0004;
+const MOUSEEVENTF_RIGHTDOWN: u32 = 0x0008;
+const MOUSEEVENTF_RIGHTUP: u32 = 0x0010;
+const MOUSEEVENTF_MIDDLEDOWN: u32 = 0x0020;
+const MOUSEEVENTF_MIDDLEUP: u32 = 0x0040;
+const MOUSEEVENTF_XDOWN: u32 = 0x0080;
+const MOUSEEVENTF_XUP: u32 = 0x0100;
+const MOUSEEVENTF_WHEEL: u32 = 0x0800;
+const MOUSEEVENTF_HWHEEL: u32 = 0x1000;
+
+const XBUTTON1: u32 = 0x0001;
+const XBUTTON2: u32 = 0x0002;
+
+/// One detent of a wheel, REL_WHEEL_HI_RES uses the same unit
+const WHEEL_DELTA: i32 = 120;
+
+/// struct MOUSEINPUT
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MouseInput {
+    dx: i32,
+    dy: i32,
+    mouse_data: u32,
+    flags: u32,
+    time: u32,
+    extra_info: usize,
+}
+
+/// struct KEYBDINPUT
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct KeybdInput {
+    vk: u16,
+    scan: u16,
+    flags: u32,
+    time: u32,
+    extra_info: usize,
+}
+
+/// The union of struct INPUT, HARDWAREINPUT is never sent and smaller
+/// than the others
+#[repr(C)]
+#[derive(Clone, Copy)]
+union InputData {
+    mouse: MouseInput,
+    keyboard: KeybdInput,
+}
+
+/// struct INPUT, a keyboard or a mouse event for SendInput
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Input {
+    kind: u32,
+    data: InputData,
+}
+
+#[link(name = "user32")]
+extern "system" {
+    fn SendInput(count: u32, inputs: *const Input, size: i32) -> u32;
+}
+
+/// The keyboard event typing `key`, None for the keys Windows does not have
+pub fn key_input(key: KeyCode, down: bool) -> Option<Input> {
+    let up = if down { 0 } else { KEYEVENTF_KEYUP };
+    let (vk, scan, flags) = match win_key(key)? {
+        WinKey::Scan(scan, false) => (0, scan, KEYEVENTF_SCANCODE | up),
+        WinKey::Scan(scan, true) => (0, scan, KEYEVENTF_SCANCODE | KEYEVENTF_EXTENDEDKEY | up),
+        WinKey::Virtual(vk) => (vk, 0, up),
+    };
+    Some(Input {
+        kind: INPUT_KEYBOARD,
+        data: InputData {
+            keyboard: KeybdInput {
+                vk,
+                scan,
+                flags,
+                time: 0,
+                extra_info: 0,
+            },
+        },
+    })
+}
+
+/// The mouse event pressing or releasing `button`, None for the buttons
+/// a Windows mouse does not have
+pub fn button_input(button: KeyCode, down: bool) -> Option<Input> {
+    let (flags, mouse_data) = match (button, down) {
+        (KeyCode::BTN_LEFT, true) => (MOUSEEVENTF_LEFTDOWN, 0),
+        (KeyCode::BTN_LEFT, false) => (MOUSEEVENTF_LEFTUP, 0),
+        (KeyCode::BTN_RIGHT, true) => (MOUSEEVENTF_RIGHTDOWN, 0),
+        (KeyCode::BTN_RIGHT, false) => (MOUSEEVENTF_RIGHTUP, 0),
+        (KeyCode::BTN_MIDDLE, true) => (MOUSEEVENTF_MIDDLEDOWN, 0),
+        (KeyCode::BTN_MIDDLE, false) => (MOUSEEVENTF_MIDDLEUP, 0),
+        (KeyCode::BTN_SIDE | KeyCode::BTN_BACK, true) => (MOUSEEVENTF_XDOWN, XBUTTON1),
+        (KeyCode::BTN_SIDE | KeyCode::BTN_BACK, false) => (MOUSEEVENTF_XUP, XBUTTON1),
+        (KeyCode::BTN_EXTRA | KeyCode::BTN_FORWARD, true) => (MOUSEEVENTF_XDOWN, XBUTTON2),
+        (KeyCode::BTN_EXTRA | KeyCode::BTN_FORWARD, false) => (MOUSEEVENTF_XUP, XBUTTON2),
+        _ => return None,
+    };
+    Some(mouse_input(0, 0, mouse_data, flags))
+}
+
+/// The mouse event moving the pointer or turning a wheel, None for the axes
+/// a Windows mouse does not have
+pub fn rel_input(axis: RelativeAxisType, delta: i32) -> Option<Input> {
+    let input = match axis {
+        RelativeAxisType::REL_X => mouse_input(delta, 0, 0, MOUSEEVENTF_MOVE),
+        RelativeAxisType::REL_Y => mouse_input(0, delta, 0, MOUSEEVENTF_MOVE),
+        // The wheel data is signed, up and right are positive like in evdev
+        RelativeAxisType::REL_WHEEL => {
+            mouse_input(0, 0, (delta * WHEEL_DELTA) as u32, MOUSEEVENTF_WHEEL)
+        }
+        RelativeAxisType::REL_HWHEEL => {
+            mouse_input(0, 0, (delta * WHEEL_DELTA) as u32, MOUSEEVENTF_HWHEEL)
+        }
+        RelativeAxisType::REL_WHEEL_HI_RES => mouse_input(0, 0, delta as u32, MOUSEEVENTF_WHEEL),
+        RelativeAxisType::REL_HWHEEL_HI_RES => mouse_input(0, 0, delta as u32, MOUSEEVENTF_HWHEEL),
+        _ => return None,
+    };
+    Some(input)
+}
+
+fn mouse_input(dx: i32, dy: i32, mouse_data: u32, flags: u32) -> Input {
+    Input {
+        kind: INPUT_MOUSE,
+        data: InputData {
+            mouse: MouseInput {
+                dx,
+                dy,
+                mouse_data,
+                flags,
+                time: 0,
+                extra_info: 0,
+            },
+        },
+    }
+}
+
+/// Inject the events as one uninterrupted sequence, like a single frame
+/// of a uinput device
+pub fn send(inputs: &[Input]) -> io::Result<()> {
+    if inputs.is_empty() {
+        return Ok(());
+    }
+    // SAFETY: the inputs are valid INPUT structs and the count matches
+    let sent = unsafe {
+        SendInput(
+            inputs.len() as u32,
+            inputs.as_ptr(),
+            mem::size_of::<Input>() as i32,
+        )
+    };
+    if sent == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if (sent as usize) < inputs.len() {
+        // Another desktop, eg. the secure one of the UAC prompts, or an
+        // elevated application in the foreground blocks the injection
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "The input was blocked",
+        ));
+    }
+    Ok(())
+}
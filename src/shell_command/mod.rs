@@ -0,0 +1,51 @@
+use std::ffi::OsString;
+use std::io;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// The environment passed on to the commands, everything a desktop tool
+/// needs to reach the session. The rest (eg. NOTIFY_SOCKET or RUST_LOG of
+/// the driver) stays private.
+const PASSED_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LC_ALL",
+    "DISPLAY",
+    "XAUTHORITY",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+    "XDG_SESSION_TYPE",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_CONFIG_HOME",
+    "XDG_DATA_HOME",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "SWAYSOCK",
+    "HYPRLAND_INSTANCE_SIGNATURE",
+];
+
+/// The variables of `vars` the commands may see
+pub(crate) fn sanitized_env<I>(vars: I) -> Vec<(OsString, OsString)>
+where
+    I: IntoIterator<Item = (OsString, OsString)>,
+{
+    vars.into_iter()
+        .filter(|(name, _)| PASSED_ENV.iter().any(|passed| name == passed))
+        .collect()
+}
+
+/// Run `command` using the shell without waiting for it. The command gets
+/// no input and a sanitized environment, it is reaped in the background.
+pub fn spawn(command: &str) -> io::Result<()> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .env_clear()
+        .envs(sanitized_env(std::env::vars_os()))
+        .stdin(Stdio::null())
+        .spawn()?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
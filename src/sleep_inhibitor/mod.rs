@@ -0,0 +1,177 @@
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use std::sync::mpsc;
+#[cfg(target_os = "linux")]
+use std::thread;
+
+#[cfg(target_os = "linux")]
+use tracing::warn;
+#[cfg(target_os = "linux")]
+use zbus::blocking::{Connection, Proxy};
+#[cfg(target_os = "linux")]
+use zbus::zvariant::OwnedFd;
+
+/// How long is the system suspend delayed at most, logind enforces
+/// its own InhibitDelayMaxSec limit as well
+#[cfg(target_os = "linux")]
+const MAX_SUSPEND_DELAY: Duration = Duration::from_secs(1);
+
+/// The time the system has to be asleep for to notice it by the clocks,
+/// the main loop checks them much more often
+const SUSPEND_GAP: Duration = Duration::from_secs(1);
+
+pub enum SleepEvent {
+    /// The system is going to sleep. Release all held keys and
+    /// confirm using the sender, the suspend is delayed until then.
+    Suspending(Sender<()>),
+    /// The system woke up, re-initialize the device
+    Resumed,
+}
+
+/// Delay inhibitor lock on systemd-logind sleep. It gives the driver
+/// a chance to release all virtual keys before the system suspends,
+/// otherwise modifiers held at suspend time are stuck after resume.
+pub struct SleepInhibitor {
+    events: Receiver<SleepEvent>,
+}
+
+impl SleepInhibitor {
+    #[cfg(target_os = "linux")]
+    pub fn start() -> zbus::Result<Self> {
+        let connection = Connection::system()?;
+        let (tx, rx) = mpsc::channel();
+
+        // Subscribe before the lock is taken so no signal is missed
+        let proxy = logind_proxy(&connection)?;
+        let signals = proxy.receive_signal("PrepareForSleep")?;
+        let lock = take_lock(&proxy)?;
+
+        thread::spawn(move || {
+            let mut lock = Some(lock);
+
+            for msg in signals {
+                let Ok(going_to_sleep) = msg.body().deserialize::<bool>() else {
+                    continue;
+                };
+
+                if going_to_sleep {
+                    let (ready_tx, ready_rx) = mpsc::channel();
+                    if tx.send(SleepEvent::Suspending(ready_tx)).is_err() {
+                        return;
+                    }
+                    let _ = ready_rx.recv_timeout(MAX_SUSPEND_DELAY);
+
+                    // Let the system sleep
+                    lock = None;
+                } else {
+                    if lock.is_none() {
+                        lock = take_lock(&proxy)
+                            .map_err(|e| warn!("Cannot take the sleep inhibitor: {}", e))
+                            .ok();
+                    }
+                    if tx.send(SleepEvent::Resumed).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { events: rx })
+    }
+
+    /// Only logind delays the suspend, elsewhere the held keys are
+    /// released when the resume is noticed
+    #[cfg(not(target_os = "linux"))]
+    pub fn start() -> zbus::Result<Self> {
+        Err(zbus::Error::Unsupported)
+    }
+
+    /// Get the pending sleep event if there is one
+    pub fn poll(&self) -> Option<SleepEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+/// Notices a resume from a system suspend, also without logind
+///
+/// CLOCK_BOOTTIME keeps running while the system is asleep, the monotonic
+/// clock behind `Instant` stops. When the boot time moved on further than
+/// the monotonic time since the last check, the system slept in between.
+#[derive(Debug, Default)]
+pub struct ResumeDetector {
+    /// The boot time ahead of the monotonic time at the last check
+    offset: Option<Duration>,
+}
+
+impl ResumeDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Was the system asleep since the last call?
+    #[cfg(target_os = "linux")]
+    pub fn check(&mut self) -> bool {
+        self.observe(clock(libc::CLOCK_BOOTTIME), clock(libc::CLOCK_MONOTONIC))
+    }
+
+    /// The macOS CLOCK_MONOTONIC keeps running while asleep like the Linux
+    /// boot time, CLOCK_UPTIME_RAW behind `Instant` stops
+    #[cfg(target_os = "macos")]
+    pub fn check(&mut self) -> bool {
+        self.observe(clock(libc::CLOCK_MONOTONIC), clock(libc::CLOCK_UPTIME_RAW))
+    }
+
+    /// Without a clock running through the sleep a suspend is never noticed
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn check(&mut self) -> bool {
+        false
+    }
+
+    /// Compare the readings of the boot time and the monotonic clock
+    /// to the previous ones
+    pub fn observe(&mut self, boottime: Duration, monotonic: Duration) -> bool {
+        let offset = boottime.saturating_sub(monotonic);
+        let slept = self
+            .offset
+            .is_some_and(|last| offset.saturating_sub(last) > SUSPEND_GAP);
+        self.offset = Some(offset);
+        slept
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: the timespec is a valid place for the result
+    unsafe { libc::clock_gettime(id, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+#[cfg(target_os = "linux")]
+fn logind_proxy(connection: &Connection) -> zbus::Result<Proxy<'static>> {
+    Proxy::new(
+        connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )
+}
+
+/// The lock is held for as long as the file descriptor is open
+#[cfg(target_os = "linux")]
+fn take_lock(proxy: &Proxy) -> zbus::Result<OwnedFd> {
+    proxy.call(
+        "Inhibit",
+        &(
+            "sleep",
+            "XP-Pen ACK05 driver",
+            "Release held keys before sleep",
+            "delay",
+        ),
+    )
+}
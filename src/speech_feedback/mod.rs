@@ -0,0 +1,84 @@
+use std::collections::BTreeSet;
+use std::process::Command;
+use std::thread;
+
+use tracing::warn;
+
+use crate::keycodes::LedType;
+use crate::layout::types::LayerId;
+
+/// Application name reported to speech-dispatcher
+const APPLICATION_NAME: &str = "xppen-ack05";
+
+/// Lock indicators worth announcing and their spoken names
+const LOCKS: [(LedType, &str); 3] = [
+    (LedType::LED_CAPSL, "Caps Lock"),
+    (LedType::LED_NUML, "Num Lock"),
+    (LedType::LED_SCROLLL, "Scroll Lock"),
+];
+
+/// Spoken announcements of layer and lock changes using speech-dispatcher
+///
+/// Announcements are only made when the crate is built with the `speech`
+/// feature. The `spd-say` client is used, so there is nothing to link
+/// against and a missing speech-dispatcher only disables the feedback.
+pub struct SpeechFeedback {
+    enabled: bool,
+}
+
+impl SpeechFeedback {
+    pub fn open() -> Self {
+        Self {
+            enabled: cfg!(feature = "speech"),
+        }
+    }
+
+    /// Announce `text`, this does not block. A newer announcement
+    /// interrupts the one being spoken, only the current state matters.
+    pub fn say(&self, text: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut cmd = Command::new("spd-say");
+        cmd.args(["--application-name", APPLICATION_NAME, "--cancel", text]);
+        thread::spawn(move || {
+            if let Err(e) = cmd.status() {
+                warn!("Speech feedback not available: {}", e);
+            }
+        });
+    }
+}
+
+/// Describe a change of the active layers, the layers are called by `name`
+/// and by their number when they have no name
+pub fn describe_layers<'a, F>(before: &[LayerId], after: &[LayerId], name: F) -> Vec<String>
+where
+    F: Fn(LayerId) -> Option<&'a str>,
+{
+    let spoken = |l: LayerId| name(l).map_or_else(|| format!("layer {}", l), str::to_string);
+    let mut texts = Vec::new();
+    for l in before.iter().filter(|l| !after.contains(l)) {
+        texts.push(format!("{} off", spoken(*l)));
+    }
+    for l in after.iter().filter(|l| !before.contains(l)) {
+        texts.push(format!("{} on", spoken(*l)));
+    }
+    texts
+}
+
+/// Describe a change of the lock indicators
+pub fn describe_locks(
+    before: &BTreeSet<LedType>,
+    after: &BTreeSet<LedType>,
+) -> Vec<String> {
+    let mut texts = Vec::new();
+    for (led, name) in LOCKS {
+        match (before.contains(&led), after.contains(&led)) {
+            (false, true) => texts.push(format!("{} on", name)),
+            (true, false) => texts.push(format!("{} off", name)),
+            _ => {}
+        }
+    }
+    texts
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::layout::geometry::Geometry;
+use crate::layout::types::{KeyCoords, KeymapEvent};
+
+/// Name of the file the statistics are stored in
+const STATS_FILE: &str = "stats.toml";
+
+/// Size of one key in the heatmap
+const CELL_WIDTH: usize = 120;
+const CELL_HEIGHT: usize = 60;
+
+/// Local usage statistics, they never leave the machine
+///
+/// Presses are counted per physical position and per resolved action, so
+/// the bindings that are never used can be found and the layers rebalanced.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageStats {
+    /// Presses per position, keyed by "block,row,column"
+    #[serde(default)]
+    pub buttons: BTreeMap<String, u64>,
+    /// Presses per resolved action
+    #[serde(default)]
+    pub actions: BTreeMap<String, u64>,
+}
+
+fn position_key(coords: KeyCoords) -> String {
+    format!("{},{},{}", coords.0, coords.1, coords.2)
+}
+
+impl UsageStats {
+    /// Count a press of `coords` that resolved to `action`
+    pub fn record(&mut self, coords: KeyCoords, action: Option<&KeymapEvent>) {
+        *self.buttons.entry(position_key(coords)).or_default() += 1;
+        if let Some(action) = action {
+            *self.actions.entry(format!("{:?}", action)).or_default() += 1;
+        }
+    }
+
+    pub fn presses(&self, coords: KeyCoords) -> u64 {
+        self.buttons
+            .get(&position_key(coords))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Render the press counts over the device geometry as an SVG image,
+    /// one row of keys per geometry row
+    pub fn heatmap(&self, geometry: &Geometry) -> String {
+        let max = geometry
+            .positions()
+            .map(|(coords, _)| self.presses(coords))
+            .max()
+            .unwrap_or_default()
+            .max(1);
+
+        let rows = geometry.blocks.iter().flat_map(|block| block.rows.iter());
+        let columns = rows.clone().map(|row| row.len()).max().unwrap_or_default();
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="12">"#,
+            columns * CELL_WIDTH,
+            rows.count() * CELL_HEIGHT
+        );
+
+        let mut y = 0;
+        for (b_idx, block) in geometry.blocks.iter().enumerate() {
+            for (r_idx, row) in block.rows.iter().enumerate() {
+                for (c_idx, label) in row.iter().enumerate() {
+                    let presses = self.presses(KeyCoords(b_idx as u8, r_idx as u8, c_idx as u8));
+                    // White for unused keys, deep red for the most used one
+                    let heat = 255 - (presses * 200 / max) as u8;
+                    let x = c_idx * CELL_WIDTH;
+                    let _ = writeln!(
+                        svg,
+                        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="rgb(255,{},{})" stroke="black"/>"#,
+                        x, y, CELL_WIDTH, CELL_HEIGHT, heat, heat
+                    );
+                    let _ = writeln!(
+                        svg,
+                        r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                        x + CELL_WIDTH / 2,
+                        y + CELL_HEIGHT / 2 - 4,
+                        escape(label)
+                    );
+                    let _ = writeln!(
+                        svg,
+                        r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                        x + CELL_WIDTH / 2,
+                        y + CELL_HEIGHT / 2 + 14,
+                        presses
+                    );
+                }
+                y += CELL_HEIGHT;
+            }
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Load the statistics, a missing file means nothing was counted yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        toml::from_str(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let source =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, source)
+    }
+
+    /// The default location of the statistics, `$XDG_DATA_HOME/xppen-ack05/stats.toml`
+    pub fn default_path() -> PathBuf {
+        let data = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+            .unwrap_or_default();
+        data.join("xppen-ack05").join(STATS_FILE)
+    }
+}
+
+/// Labels are user provided, keep them from breaking the markup
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
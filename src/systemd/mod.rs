@@ -0,0 +1,144 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(target_os = "linux")]
+use std::ffi::OsString;
+#[cfg(target_os = "linux")]
+use std::io::Write;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixStream};
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+/// The socket journald reads the output streams of services from
+#[cfg(target_os = "linux")]
+const JOURNAL_STREAM_SOCKET: &str = "/run/systemd/journal/stdout";
+
+/// Set by the signal handler when the service is asked to stop
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Tell the service manager about a state change, eg. `READY=1`, using the
+/// sd_notify protocol. Returns false when not started by systemd as
+/// a Type=notify service.
+#[cfg(target_os = "linux")]
+pub fn notify(state: &str) -> io::Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_socket(&socket, state).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// There is no systemd to notify elsewhere
+#[cfg(not(target_os = "linux"))]
+pub fn notify(_state: &str) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Send the `state` to the notification `socket`. A leading @ means
+/// a socket in the abstract namespace.
+#[cfg(target_os = "linux")]
+pub(crate) fn notify_socket(socket: &OsString, state: &str) -> io::Result<()> {
+    let addr = match socket.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(Path::new(socket))?,
+    };
+    let sender = UnixDatagram::unbound()?;
+    sender.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+/// Send the standard output and error to the journal, the way
+/// sd_journal_stream_fd does. Every line becomes a journal entry
+/// tagged with `identifier`.
+#[cfg(target_os = "linux")]
+pub fn log_to_journal(identifier: &str) -> io::Result<()> {
+    let mut stream = UnixStream::connect(JOURNAL_STREAM_SOCKET)?;
+    stream.shutdown(std::net::Shutdown::Read)?;
+
+    // Identifier, unit, priority (info), level prefix, forwarding to
+    // syslog, kmsg and console
+    write!(stream, "{}\n\n6\n1\n0\n0\n0\n", identifier)?;
+
+    for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: both descriptors are valid, dup2 only replaces the target
+        if unsafe { libc::dup2(stream.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// The journal only exists with systemd, the output stays where it is
+#[cfg(not(target_os = "linux"))]
+pub fn log_to_journal(_identifier: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "The journal is only available on Linux",
+    ))
+}
+
+#[cfg(unix)]
+extern "C" fn request_stop(_signal: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Note SIGTERM and SIGINT instead of dying on them, so the held keys can
+/// be released before the exit. The signal interrupts a blocking read.
+#[cfg(unix)]
+pub fn handle_stop_signals() -> io::Result<()> {
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: the handler only stores to an atomic, the action is fully
+        // initialized and SA_RESTART is left out on purpose
+        let result = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = request_stop as extern "C" fn(libc::c_int) as usize;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut())
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn SetConsoleCtrlHandler(
+        handler: Option<unsafe extern "system" fn(u32) -> i32>,
+        add: i32,
+    ) -> i32;
+}
+
+/// Note every console close request, eg. Ctrl+C
+#[cfg(windows)]
+unsafe extern "system" fn request_stop(_event: u32) -> i32 {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+    // Handled, the process is not ended right away. Windows still ends it
+    // a few seconds after closing the console window, enough to release
+    // the held keys.
+    1
+}
+
+/// Note Ctrl+C and the other console events instead of dying on them,
+/// so the held keys can be released before the exit
+#[cfg(windows)]
+pub fn handle_stop_signals() -> io::Result<()> {
+    // SAFETY: the handler only stores to an atomic
+    if unsafe { SetConsoleCtrlHandler(Some(request_stop), 1) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Was the service asked to stop since handle_stop_signals?
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
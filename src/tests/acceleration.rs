@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange::{self, Click, Turn};
+use crate::kbd_events::acceleration::RotaryAccelerator;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::parse_settings;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeyCoords;
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, DEFAULT_LAYER_CONFIG};
+
+const CW: KeyCoords = KeyCoords(1, 0, 1);
+const CCW: KeyCoords = KeyCoords(1, 0, 0);
+
+fn accelerator() -> RotaryAccelerator {
+    RotaryAccelerator::new(CW, CCW, [
+        (Duration::from_millis(80), 2),
+        (Duration::from_millis(30), 4),
+    ])
+}
+
+fn tick(accelerator: &mut RotaryAccelerator, k: KeyCoords, t: &mut TestTime, ms: u64) -> KeyStateChange<KeyCoords> {
+    accelerator.process(Click(k), t.advance_ms(ms))
+}
+
+#[test]
+fn test_rotary_acceleration() {
+    let mut accelerator = accelerator();
+    let mut t = TestTime::start();
+
+    assert_eq!(tick(&mut accelerator, CW, &mut t, 0), Click(CW));
+    assert_eq!(tick(&mut accelerator, CW, &mut t, 200), Click(CW));
+    assert_eq!(tick(&mut accelerator, CW, &mut t, 60), Turn(CW, 2));
+    assert_eq!(tick(&mut accelerator, CW, &mut t, 20), Turn(CW, 4));
+    assert_eq!(tick(&mut accelerator, CW, &mut t, 30), Turn(CW, 4));
+
+    // A reversal starts slow again
+    assert_eq!(tick(&mut accelerator, CCW, &mut t, 10), Click(CCW));
+    assert_eq!(tick(&mut accelerator, CCW, &mut t, 10), Turn(CCW, 4));
+
+    // The other keys pass unchanged
+    let press = KeyStateChange::Pressed(KeyCoords(0, 0, 0));
+    assert_eq!(accelerator.process(press, t.advance_ms(10)), press);
+}
+
+#[test]
+fn test_rotary_turn_steps() {
+    let layout_vec = vec![
+        Layer{
+            keymap: vec![vec![], vec![vec![G().k(KeyCode::KEY_MINUS).p(), G().k(KeyCode::KEY_EQUAL).p()]]],
+            ..DEFAULT_LAYER_CONFIG
+        }
+    ];
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // Every step clicks the key
+    layout.process_keyevent(Turn(CW, 3), t);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_EQUAL, true), (KeyCode::KEY_EQUAL, false),
+        (KeyCode::KEY_EQUAL, true), (KeyCode::KEY_EQUAL, false),
+        (KeyCode::KEY_EQUAL, true), (KeyCode::KEY_EQUAL, false),
+    ]);
+}
+
+#[test]
+fn test_parse_rotary_acceleration() {
+    assert_eq!(parse_settings("").unwrap().rotary_acceleration, vec![]);
+    let settings = parse_settings("[settings]\nrotary_acceleration = [{ within_ms = 40, steps = 3 }]").unwrap();
+    assert_eq!(settings.rotary_acceleration, vec![(Duration::from_millis(40), 3)]);
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use enumset::EnumSet;
+
+use crate::async_frontend::AsyncFrontend;
+use crate::button_device::{ButtonDevice, ReadResult};
+use crate::file_watcher::FileWatcher;
+
+use super::backup::scratch_dir;
+use super::button_device::{MockButton, MockDevice};
+use super::passthrough::{Keypad, ModalDevice};
+
+#[test]
+fn test_async_frontend_reports() {
+    let mut device = MockDevice::open().unwrap();
+    device.reports.extend([EnumSet::only(MockButton::Play), EnumSet::empty()]);
+    let mut frontend = AsyncFrontend::start(device);
+    let wait = Duration::from_secs(1);
+
+    assert!(matches!(frontend.read_timeout(wait), Ok((ReadResult::Keys(keys), _)) if keys == MockButton::Play));
+    assert!(matches!(frontend.read_timeout(wait), Ok((ReadResult::Keys(keys), _)) if keys.is_empty()));
+    assert!(matches!(frontend.read_timeout(Duration::from_millis(10)), Ok((ReadResult::Timeout, _))));
+
+    assert_eq!(frontend.with(|device| device.reports.push_back(EnumSet::only(MockButton::Next))), Some(()));
+    assert!(matches!(frontend.read_timeout(wait), Ok((ReadResult::Keys(keys), _)) if keys == MockButton::Next));
+}
+
+#[test]
+fn test_async_frontend_release() {
+    let keypad = Arc::new(Keypad::default());
+    let mut frontend = AsyncFrontend::start(ModalDevice::open_shared(&keypad));
+
+    // The device is restored and closed, the frontend only waits
+    frontend.release().unwrap();
+    assert!(frontend.is_released());
+    assert!(!keypad.switched.load(Ordering::SeqCst));
+    assert!(!keypad.open.load(Ordering::SeqCst));
+    assert!(matches!(frontend.read_timeout(Duration::from_millis(10)), Ok((ReadResult::Timeout, _))));
+
+    frontend.restart(ModalDevice::open_shared(&keypad));
+    assert!(!frontend.is_released());
+    assert!(keypad.switched.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_async_frontend_wake_up() {
+    let mut frontend = AsyncFrontend::start(MockDevice::open().unwrap());
+    let wait = Duration::from_secs(10);
+
+    // A wake up before the wait is kept
+    let mut wake = frontend.waker();
+    wake();
+    let start = Instant::now();
+    assert!(matches!(frontend.read_timeout(wait), Ok((ReadResult::Timeout, _))));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // A change of the watched file wakes the loop up, the watcher reads it then
+    let dir = scratch_dir("async_frontend");
+    let path = dir.join("layout.toml");
+    let mut watcher = FileWatcher::open(&path).unwrap();
+    frontend.watch(Some(&watcher));
+    fs::write(&path, "[[layers]]").unwrap();
+    let start = Instant::now();
+    assert!(matches!(frontend.read_timeout(wait), Ok((ReadResult::Timeout, _))));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(watcher.changed());
+}
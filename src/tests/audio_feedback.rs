@@ -0,0 +1,10 @@
+use crate::audio_feedback::Cue;
+
+#[test]
+fn test_layer_change_cue() {
+    assert_eq!(Cue::for_layers(&[0], &[0]), None);
+    assert_eq!(Cue::for_layers(&[0], &[0, 2]), Some(Cue::LayerEnter));
+    assert_eq!(Cue::for_layers(&[0, 2], &[0]), Some(Cue::LayerExit));
+    // Moving to another layer is an enter
+    assert_eq!(Cue::for_layers(&[0, 2], &[0, 3]), Some(Cue::LayerEnter));
+}
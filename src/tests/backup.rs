@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::backup;
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::Rollback;
+
+use super::testtime::TestTime;
+use super::{TestDevice, DEFAULT_LAYER_CONFIG};
+
+/// A fresh directory for the test files
+pub(super) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("xppen-ack05-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_backup_rollback() {
+    let dir = scratch_dir("rollback");
+    let path = dir.join("macros.toml");
+
+    // Nothing to back up or restore yet
+    assert_eq!(backup::rollback(&path).unwrap(), None);
+    backup::write(&path, "first").unwrap();
+    assert_eq!(backup::backups(&path).unwrap().len(), 0);
+
+    backup::write(&path, "second").unwrap();
+    backup::write(&path, "broken").unwrap();
+    assert_eq!(backup::backups(&path).unwrap().len(), 2);
+
+    // Every rollback steps one version back
+    assert!(backup::rollback(&path).unwrap().is_some());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+    assert!(backup::rollback(&path).unwrap().is_some());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+    assert_eq!(backup::rollback(&path).unwrap(), None);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "first");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_backup_pruning() {
+    let dir = scratch_dir("pruning");
+    let path = dir.join("macros.toml");
+
+    for i in 0..15 {
+        backup::write(&path, &format!("version {}", i)).unwrap();
+    }
+
+    // Only the newest backups are kept, other files are left alone
+    fs::write(dir.join("backups").join("other.toml.1"), "other").unwrap();
+    let backups = backup::backups(&path).unwrap();
+    assert_eq!(backups.len(), 10);
+    assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "version 13");
+    assert_eq!(fs::read_to_string(&backups[9]).unwrap(), "version 4");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_backup_known_good() {
+    let dir = scratch_dir("known-good");
+    let path = dir.join("layout.toml");
+
+    fs::write(&path, "good").unwrap();
+    assert!(backup::known_good(&path).unwrap().is_some());
+    // The same content is kept only once
+    assert_eq!(backup::known_good(&path).unwrap(), None);
+    assert_eq!(backup::backups(&path).unwrap().len(), 1);
+
+    // A broken edit goes back to the known good version, the copy
+    // of the content being rolled back from is skipped
+    fs::write(&path, "broken").unwrap();
+    assert!(backup::rollback(&path).unwrap().is_some());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "good");
+    assert!(backup::known_good(&path).unwrap().is_some());
+    assert_eq!(backup::rollback(&path).unwrap(), None);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "good");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_rollback_action() {
+    let layout_vec = vec![Layer {
+        keymap: vec![vec![vec![Rollback]]],
+        ..DEFAULT_LAYER_CONFIG
+    }];
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    assert!(!layout.take_rollback());
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert!(layout.take_rollback());
+    assert!(!layout.take_rollback());
+}
@@ -0,0 +1,74 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::builder::{LayerBuilder, LayoutBuilder};
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Inh, Lhold, No, Pass};
+use crate::layout::types::{KeyCoords, LayerStatus};
+use crate::layout::validation::LayoutError;
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, TestDevice};
+
+#[test]
+fn test_layout_builder() {
+    let layout_vec = LayoutBuilder::new()
+        .layer(LayerBuilder::new()
+            .name("base")
+            .key(TestDevice::B01, Lhold(1))
+            .key(TestDevice::B02, G().k(KeyCode::KEY_A).p())
+            .key(TestDevice::B04, G().k(KeyCode::KEY_B).p()))
+        .layer(LayerBuilder::new()
+            .name("shift")
+            .inherits("base")
+            .on_active([KeyCode::KEY_LEFTSHIFT])
+            .default_action(Inh)
+            .key(TestDevice::B02, G().k(KeyCode::KEY_C).p()))
+        .build()
+        .unwrap();
+
+    // The gaps are filled with the default action
+    assert_eq!(layout_vec[0].keymap, vec![vec![vec![Lhold(1), G().k(KeyCode::KEY_A).p()],
+                                               vec![Pass, G().k(KeyCode::KEY_B).p()]]]);
+    assert!(layout_vec[0].status_on_reset == LayerStatus::LayerActive);
+    assert!(layout_vec[1].status_on_reset == LayerStatus::LayerPassthrough);
+    assert_eq!(layout_vec[1].inherit, Some(0));
+
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_LEFTSHIFT, true),
+        (KeyCode::KEY_C, true), (KeyCode::KEY_C, false),
+        (KeyCode::KEY_B, true), (KeyCode::KEY_B, false),
+        (KeyCode::KEY_LEFTSHIFT, false),
+    ]);
+}
+
+#[test]
+fn test_layer_builder_rebinds_key() {
+    let layout_vec = LayoutBuilder::new()
+        .layer(LayerBuilder::new()
+            .key(KeyCoords(0, 0, 2), G().k(KeyCode::KEY_A).p())
+            .key(KeyCoords(0, 0, 2), No))
+        .build()
+        .unwrap();
+
+    assert_eq!(layout_vec[0].keymap, vec![vec![vec![Pass, Pass, No]]]);
+}
+
+#[test]
+fn test_layout_builder_unknown_layer() {
+    let layout = LayoutBuilder::new()
+        .layer(LayerBuilder::new().name("base"))
+        .layer(LayerBuilder::new().inherits("bsae"));
+
+    assert_eq!(layout.layer_id("base"), Some(0));
+    assert_eq!(layout.build().err(), Some(LayoutError::UnknownLayerName { layer: 1, name: "bsae".to_string() }));
+}
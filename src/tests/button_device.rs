@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use enumset::{EnumSet, EnumSetType};
+
+use crate::button_device::reader::DeviceReader;
+use crate::button_device::{read_into, ButtonDevice, ReadResult};
+use crate::kbd_events::{ChangeDetector, HasState, KeyEvent};
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::keys::G;
+use crate::layout::types::KeyCoords;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, DEFAULT_LAYER_CONFIG};
+
+#[derive(EnumSetType, Debug, Hash)]
+pub(super) enum MockButton {
+    Play,
+    Next,
+}
+
+impl HasState for MockButton {
+    // Next is a wheel
+    fn has_state(self) -> bool {
+        self == MockButton::Play
+    }
+}
+
+impl From<MockButton> for KeyCoords {
+    fn from(button: MockButton) -> Self {
+        KeyCoords(0, 0, button as u8)
+    }
+}
+
+/// A remote that replays scripted reports
+pub(super) struct MockDevice {
+    pub(super) reports: VecDeque<EnumSet<MockButton>>,
+}
+
+impl ButtonDevice for MockDevice {
+    type Button = MockButton;
+    type Error = io::Error;
+
+    fn open() -> io::Result<Self> {
+        Ok(Self{ reports: VecDeque::new() })
+    }
+
+    fn read_timeout(&mut self, timeout: i32) -> io::Result<ReadResult<MockButton>> {
+        let Some(report) = self.reports.pop_front() else {
+            // Like a real device, waits for nothing
+            thread::sleep(Duration::from_millis(timeout.max(0) as u64));
+            return Ok(ReadResult::Timeout);
+        };
+        Ok(ReadResult::Keys(report))
+    }
+}
+
+#[test]
+fn test_mock_device() {
+    let layout_vec = vec![Layer{
+        keymap: vec![vec![vec![G().k(KeyCode::KEY_PLAYPAUSE).p(), G().k(KeyCode::KEY_NEXTSONG).p()]]],
+        ..DEFAULT_LAYER_CONFIG
+    }];
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    let mut device = MockDevice::open().unwrap();
+    assert_eq!(device.buttons(), MockButton::Play | MockButton::Next);
+    device.reports.extend([
+        EnumSet::only(MockButton::Play),
+        EnumSet::empty(),
+        EnumSet::only(MockButton::Next),
+    ]);
+
+    let mut detector = ChangeDetector::new();
+    let mut events = Vec::new();
+    for _ in 0..4 {
+        read_into(&mut device, &mut detector, 10, t.advance_ms(10)).unwrap();
+        for ev in detector.drain() {
+            let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+            events.push(format!("{:?}", ev));
+            layout.process_keyevent(ev, t);
+        }
+    }
+
+    assert_eq!(events, vec![
+        "Pressed(KeyCoords(0, 0, 0))", "Released(KeyCoords(0, 0, 0))", "Click(KeyCoords(0, 0, 1))"
+    ]);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_PLAYPAUSE, true), (KeyCode::KEY_PLAYPAUSE, false),
+        (KeyCode::KEY_NEXTSONG, true), (KeyCode::KEY_NEXTSONG, false),
+    ]);
+}
+
+#[test]
+fn test_device_reader() {
+    let mut device = MockDevice::open().unwrap();
+    device.reports.extend([EnumSet::only(MockButton::Play), EnumSet::empty()]);
+    let reader = DeviceReader::start(device);
+    let wait = Duration::from_secs(1);
+
+    // The reports arrive in order, the timeouts of the device are skipped
+    assert!(matches!(reader.read_timeout(wait), Ok((ReadResult::Keys(keys), _)) if keys == MockButton::Play));
+    assert!(matches!(reader.read_timeout(wait), Ok((ReadResult::Keys(keys), _)) if keys.is_empty()));
+    assert!(matches!(reader.read_timeout(Duration::from_millis(10)), Ok((ReadResult::Timeout, _))));
+
+    // The device is used in the reader thread
+    assert_eq!(reader.with(|device| device.reports.push_back(EnumSet::only(MockButton::Next))), Some(()));
+    assert!(matches!(reader.read_timeout(wait), Ok((ReadResult::Keys(keys), _)) if keys == MockButton::Next));
+}
+
+#[test]
+fn test_device_reader_waker() {
+    let mut reader = DeviceReader::start(MockDevice::open().unwrap());
+    let mut wake = reader.waker();
+
+    // A wake up before the wait ends it right away as a timeout
+    wake();
+    let start = Instant::now();
+    assert!(matches!(reader.read_timeout(Duration::from_secs(5)), Ok((ReadResult::Timeout, _))));
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    // The waker follows the restarted reader
+    reader.restart(MockDevice::open().unwrap());
+    wake();
+    let start = Instant::now();
+    assert!(matches!(reader.read_timeout(Duration::from_secs(5)), Ok((ReadResult::Timeout, _))));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_device_reader_timestamps() {
+    let mut device = MockDevice::open().unwrap();
+    device.reports.extend([EnumSet::only(MockButton::Play), EnumSet::empty()]);
+    let reader = DeviceReader::start(device);
+    let wait = Duration::from_secs(1);
+
+    // The reports wait in the queue, they keep the time they were read at
+    thread::sleep(Duration::from_millis(100));
+    let taken = Instant::now();
+    let Ok((ReadResult::Keys(_), pressed)) = reader.read_timeout(wait) else {
+        panic!("No report");
+    };
+    let Ok((ReadResult::Keys(_), released)) = reader.read_timeout(wait) else {
+        panic!("No report");
+    };
+    assert!(pressed <= released);
+    assert!(taken - released >= Duration::from_millis(50));
+}
+
+#[test]
+fn test_device_reader_stopped() {
+    let reader = DeviceReader::start(MockDevice::open().unwrap());
+
+    // The thread died without a report, the main loop reconnects on the error
+    assert_eq!(reader.with(|_| panic!("Device driver bug")), None::<()>);
+    assert!(reader.read_timeout(Duration::from_secs(1)).is_err());
+}
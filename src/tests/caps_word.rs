@@ -0,0 +1,66 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::CapsWord;
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// B01 starts a caps word, B02 types A, B03 types minus and B04 types space
+fn caps_word_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ CapsWord,                   G().k(KeyCode::KEY_A).p() ],
+        vec![ G().k(KeyCode::KEY_MINUS).p(),  G().k(KeyCode::KEY_SPACE).p() ],
+    ])
+}
+
+#[test]
+fn test_caps_word() {
+    let layout_vec = caps_word_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_LEFTSHIFT, true),
+        (KeyCode::KEY_A, true), (KeyCode::KEY_A, false),
+        (KeyCode::KEY_MINUS, true), (KeyCode::KEY_MINUS, false),
+        (KeyCode::KEY_A, true), (KeyCode::KEY_A, false),
+    ]);
+
+    // The space ends the word before it is sent
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_LEFTSHIFT, false),
+        (KeyCode::KEY_SPACE, true), (KeyCode::KEY_SPACE, false),
+        (KeyCode::KEY_A, true), (KeyCode::KEY_A, false),
+    ]);
+}
+
+#[test]
+fn test_caps_word_cancel() {
+    let layout_vec = caps_word_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // Pressed again
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_LEFTSHIFT, false)]);
+
+    // Released with everything else
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.release_all();
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_LEFTSHIFT, false)]);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+}
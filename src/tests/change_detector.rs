@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use enumset::EnumSet;
+
+use crate::kbd_events::{ChangeDetector, KeyStateChange};
+use crate::xppen_hid::XpPenButtons::{self, XpB01, XpB02, XpRoCCW, XpRoCW};
+
+use super::testtime::TestTime;
+
+fn drain(detector: &mut ChangeDetector<XpPenButtons>) -> Vec<String> {
+    let mut events: Vec<String> = detector.drain().map(|ev| format!("{:?}", ev.change)).collect();
+    events.sort();
+    events
+}
+
+fn names(events: &[KeyStateChange<XpPenButtons>]) -> Vec<String> {
+    let mut names: Vec<String> = events.iter().map(|ev| format!("{:?}", ev)).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_chatter_debounce() {
+    let mut detector = ChangeDetector::new();
+    detector.set_debounce(Duration::from_millis(30));
+    let mut t = TestTime::start();
+
+    detector.analyze(EnumSet::only(XpB01), t.now());
+    detector.analyze(EnumSet::empty(), t.advance_ms(100));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Pressed(XpB01), KeyStateChange::Released(XpB01)]));
+
+    // Bounce within the debounce window is ignored, including its release
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(10));
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(10));
+    detector.analyze(EnumSet::empty(), t.advance_ms(5));
+    assert_eq!(drain(&mut detector), names(&[]));
+
+    // A real press after the window
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(100));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Pressed(XpB01)]));
+}
+
+#[test]
+fn test_chatter_debounce_per_key() {
+    let mut detector = ChangeDetector::new();
+    detector.set_key_debounce(XpB02, Duration::from_millis(30));
+    let mut t = TestTime::start();
+
+    detector.analyze(XpB01 | XpB02, t.now());
+    detector.analyze(EnumSet::empty(), t.advance_ms(100));
+    drain(&mut detector);
+
+    detector.analyze(XpB01 | XpB02, t.advance_ms(10));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Pressed(XpB01)]));
+}
+
+#[test]
+fn test_duplicate_reports() {
+    let mut detector = ChangeDetector::new();
+    detector.set_duplicate_window(Duration::from_millis(10));
+    let mut t = TestTime::start();
+
+    detector.analyze(EnumSet::only(XpRoCW), t.now());
+    detector.analyze(EnumSet::only(XpRoCW), t.advance_ms(2));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Click(XpRoCW)]));
+
+    // The next detent
+    detector.analyze(EnumSet::only(XpRoCW), t.advance_ms(50));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Click(XpRoCW)]));
+
+    // Duplicates of stateful keys are not dropped, they drive long press detection
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(50));
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(2));
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(300));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Pressed(XpB01), KeyStateChange::LongPress(XpB01, Duration::from_millis(302))]));
+}
+
+#[test]
+fn test_fast_spin_is_not_duplicate() {
+    let mut detector = ChangeDetector::new();
+    let mut t = TestTime::start();
+
+    // Two real detents of a fast spin, the detection is off by default
+    detector.analyze(EnumSet::only(XpRoCW), t.now());
+    detector.analyze(EnumSet::only(XpRoCW), t.advance_ms(5));
+    let events: Vec<KeyStateChange<XpPenButtons>> = detector.drain().map(|ev| ev.change).collect();
+    assert_eq!(events, vec![KeyStateChange::Click(XpRoCW), KeyStateChange::Click(XpRoCW)]);
+}
+
+#[test]
+fn test_event_timestamps() {
+    let mut detector = ChangeDetector::new();
+    let mut t = TestTime::start();
+
+    let t_press = t.now();
+    detector.analyze(EnumSet::only(XpB01), t_press);
+    let t_release = t.advance_ms(100);
+    detector.analyze(EnumSet::empty(), t_release);
+
+    // Events keep the time of the report, not of the processing
+    t.advance_ms(500);
+    assert_eq!(detector.next().map(|ev| ev.t), Some(t_press));
+    assert_eq!(detector.next().map(|ev| ev.t), Some(t_release));
+    assert!(detector.next().is_none());
+}
+
+#[test]
+fn test_drain_takes_all_events() {
+    let mut detector = ChangeDetector::new();
+    let mut t = TestTime::start();
+
+    detector.analyze(EnumSet::only(XpB01), t.now());
+    detector.analyze(EnumSet::empty(), t.advance_ms(100));
+    detector.analyze(EnumSet::only(XpB02), t.advance_ms(100));
+
+    // The events not consumed by the loop are dropped as well
+    let first = detector.drain().next().map(|ev| ev.change);
+    assert_eq!(first, Some(KeyStateChange::Pressed(XpB01)));
+    assert!(detector.next().is_none());
+
+    detector.analyze(EnumSet::empty(), t.advance_ms(100));
+    let events: Vec<KeyStateChange<XpPenButtons>> = detector.by_ref().map(|ev| ev.change).collect();
+    assert_eq!(events, vec![KeyStateChange::Released(XpB02)]);
+}
+
+#[test]
+fn test_bounce_keys() {
+    let mut detector = ChangeDetector::new();
+    detector.set_bounce_keys(Duration::from_millis(300));
+    let mut t = TestTime::start();
+
+    detector.analyze(EnumSet::only(XpB01), t.now());
+    detector.analyze(EnumSet::empty(), t.advance_ms(100));
+    drain(&mut detector);
+
+    // Second press within the bounce window since the first press is ignored
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(100));
+    detector.analyze(EnumSet::empty(), t.advance_ms(50));
+    assert_eq!(drain(&mut detector), names(&[]));
+
+    // Other keys are not affected
+    detector.analyze(EnumSet::only(XpB02), t.advance_ms(10));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Pressed(XpB02)]));
+    detector.analyze(EnumSet::empty(), t.advance_ms(10));
+    drain(&mut detector);
+
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(100));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Pressed(XpB01)]));
+}
+
+#[test]
+fn test_reversal_filter() {
+    let mut detector = ChangeDetector::new();
+    detector.set_reversal_filter(XpRoCW, XpRoCCW, Duration::from_millis(40));
+    let mut t = TestTime::start();
+
+    detector.analyze(EnumSet::only(XpRoCW), t.now());
+    detector.analyze(EnumSet::empty(), t.advance_ms(5));
+    detector.analyze(EnumSet::only(XpRoCW), t.advance_ms(20));
+    detector.analyze(EnumSet::empty(), t.advance_ms(5));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Click(XpRoCW), KeyStateChange::Click(XpRoCW)]));
+
+    // Spurious reversal right after a tick is ignored, the spin goes on
+    detector.analyze(EnumSet::only(XpRoCCW), t.advance_ms(10));
+    detector.analyze(EnumSet::empty(), t.advance_ms(5));
+    detector.analyze(EnumSet::only(XpRoCW), t.advance_ms(20));
+    detector.analyze(EnumSet::empty(), t.advance_ms(5));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Click(XpRoCW)]));
+
+    // A real change of direction
+    detector.analyze(EnumSet::only(XpRoCCW), t.advance_ms(100));
+    detector.analyze(EnumSet::empty(), t.advance_ms(5));
+    detector.analyze(EnumSet::only(XpRoCCW), t.advance_ms(20));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Click(XpRoCCW), KeyStateChange::Click(XpRoCCW)]));
+}
+
+#[test]
+fn test_slow_keys() {
+    let mut detector = ChangeDetector::new();
+    detector.set_slow_keys(Duration::from_millis(100));
+    let mut t = TestTime::start();
+
+    // Too short press is ignored completely
+    detector.analyze(EnumSet::only(XpB01), t.now());
+    assert!(detector.has_short_pressed());
+    detector.tick(t.advance_ms(50));
+    detector.analyze(EnumSet::empty(), t.advance_ms(20));
+    assert_eq!(drain(&mut detector), names(&[]));
+    assert!(!detector.has_pressed());
+
+    // Held long enough, the press is reported once accepted
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(100));
+    detector.tick(t.advance_ms(50));
+    assert_eq!(drain(&mut detector), names(&[]));
+    detector.tick(t.advance_ms(60));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Pressed(XpB01)]));
+
+    detector.analyze(EnumSet::empty(), t.advance_ms(20));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Released(XpB01)]));
+
+    // Stateless keys are not delayed
+    detector.analyze(EnumSet::only(XpRoCW), t.advance_ms(100));
+    assert_eq!(drain(&mut detector), names(&[KeyStateChange::Click(XpRoCW)]));
+}
@@ -0,0 +1,106 @@
+use std::time::{Duration, Instant};
+
+use crate::kbd_events::chords::{Chord, ChordResolver};
+use crate::kbd_events::KeyStateChange::{self, Click, LongPress, Pressed, Released};
+use crate::layout::serialization::{parse_chords, parse_geometry};
+use crate::layout::types::KeyCoords;
+
+use super::testtime::TestTime;
+use super::TestDevice;
+
+const CHORD: KeyCoords = KeyCoords(2, 0, 0);
+
+fn resolver() -> ChordResolver {
+    ChordResolver::new(vec![Chord {
+        keys: vec![TestDevice::B01, TestDevice::B02],
+        coords: CHORD,
+    }])
+}
+
+fn resolved(chords: &mut ChordResolver) -> Vec<KeyStateChange<KeyCoords>> {
+    chords.map(|(ev, _)| ev).collect()
+}
+
+fn feed(chords: &mut ChordResolver, events: &[KeyStateChange<KeyCoords>], t: Instant) {
+    for ev in events {
+        chords.process(*ev, t);
+    }
+}
+
+#[test]
+fn test_chord() {
+    let mut chords = resolver();
+    let mut t = TestTime::start();
+
+    chords.process(Pressed(TestDevice::B02), t.now());
+    assert!(chords.is_pending());
+    assert_eq!(resolved(&mut chords), vec![]);
+    chords.process(Pressed(TestDevice::B01), t.advance_ms(20));
+    assert!(!chords.is_pending());
+    assert_eq!(resolved(&mut chords), vec![Pressed(CHORD)]);
+
+    // The long press of the chord, the first key stands for it
+    let held = Duration::from_millis(500);
+    feed(&mut chords, &[LongPress(TestDevice::B02, held), LongPress(TestDevice::B01, held)], t.advance_ms(500));
+    assert_eq!(resolved(&mut chords), vec![LongPress(CHORD, held)]);
+
+    // The first released key releases the chord, the other one is swallowed
+    chords.process(Released(TestDevice::B01), t.advance_ms(100));
+    assert_eq!(resolved(&mut chords), vec![Released(CHORD)]);
+    chords.process(Released(TestDevice::B02), t.advance_ms(100));
+    assert_eq!(resolved(&mut chords), vec![]);
+
+    // The keys work on their own again
+    feed(&mut chords, &[Pressed(TestDevice::B01), Released(TestDevice::B01)], t.advance_ms(100));
+    assert_eq!(resolved(&mut chords), vec![Pressed(TestDevice::B01), Released(TestDevice::B01)]);
+}
+
+#[test]
+fn test_chord_not_formed() {
+    let mut chords = resolver();
+    let mut t = TestTime::start();
+
+    // The window passes, the press is let through late with its own time
+    let t0 = t.now();
+    chords.process(Pressed(TestDevice::B01), t0);
+    chords.tick(t.advance_ms(20));
+    assert!(chords.is_pending());
+    chords.tick(t.advance_ms(40));
+    assert_eq!(chords.next(), Some((Pressed(TestDevice::B01), t0)));
+    chords.process(Pressed(TestDevice::B02), t.advance_ms(10));
+    feed(&mut chords, &[Released(TestDevice::B02), Released(TestDevice::B01)], t.advance_ms(10));
+    assert_eq!(resolved(&mut chords), vec![
+        Pressed(TestDevice::B02), Released(TestDevice::B02), Released(TestDevice::B01),
+    ]);
+
+    // Other keys pass right away and let the held back key through first
+    chords.process(Pressed(TestDevice::B01), t.advance_ms(100));
+    feed(&mut chords, &[Click(TestDevice::B03), Pressed(TestDevice::B04)], t.advance_ms(10));
+    assert_eq!(resolved(&mut chords), vec![
+        Pressed(TestDevice::B01), Click(TestDevice::B03), Pressed(TestDevice::B04),
+    ]);
+}
+
+#[test]
+fn test_parse_chords() {
+    let source = r#"
+        [[chords]]
+        keys = [[0, 0, 0], [0, 0, 1]]
+
+        [[chords]]
+        keys = [[0, 0, 2], [0, 0, 9]]
+        name = "save"
+    "#;
+    let chords = parse_chords(source).unwrap();
+    assert_eq!(chords[1], Chord {
+        keys: vec![KeyCoords(0, 0, 2), KeyCoords(0, 0, 9)],
+        coords: KeyCoords(2, 0, 1),
+    });
+
+    // The chords are positions of the geometry
+    let geometry = parse_geometry(source).unwrap();
+    assert_eq!(geometry.label(KeyCoords(2, 0, 0)), Some("top-left+top-middle"));
+    assert_eq!(geometry.label(KeyCoords(2, 0, 1)), Some("save"));
+
+    assert!(parse_chords("[[chords]]\nkeys = [[0, 0, 0]]").is_err());
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use enumset::EnumSet;
+
+use crate::clock::{Clock, ManualClock};
+use crate::kbd_events::{ChangeDetector, KeyEvent, KeyStateChange};
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Klong, Lactivate};
+use crate::layout::types::{KeyCoords, LayerStatus};
+use crate::layout::keys::G;
+use crate::xppen_hid::XpPenButtons::{self, XpB01};
+
+use super::{assert_emitted_keys, TestDevice, DEFAULT_LAYER_CONFIG};
+
+fn process(detector: &mut ChangeDetector<XpPenButtons>, layout: &mut LayerSwitcher) {
+    for ev in detector.drain() {
+        let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+        layout.process_keyevent(ev, t);
+    }
+    layout.tick_now();
+}
+
+#[test]
+fn test_clock_long_press() {
+    let layout_vec = vec![Layer{
+        keymap: vec![vec![vec![Klong(G().k(KeyCode::KEY_X), G().k(KeyCode::KEY_Y))]]],
+        ..DEFAULT_LAYER_CONFIG
+    }];
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+
+    // The detector and the layout see the same time, nobody passes it around
+    let clock = ManualClock::new();
+    let mut detector = ChangeDetector::new();
+    detector.set_clock(clock.clone());
+    layout.set_clock(clock.clone());
+
+    detector.analyze_now(EnumSet::only(XpB01));
+    process(&mut detector, &mut layout);
+    clock.advance_ms(100);
+    detector.tick_now();
+    process(&mut detector, &mut layout);
+    assert_emitted_keys(&mut layout, vec![]);
+
+    clock.advance_ms(150);
+    detector.tick_now();
+    process(&mut detector, &mut layout);
+    detector.analyze_now(EnumSet::empty());
+    process(&mut detector, &mut layout);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_Y, true), (KeyCode::KEY_Y, false)]);
+}
+
+#[test]
+fn test_clock_layer_timeout() {
+    let layout_vec = vec![
+        Layer{
+            keymap: vec![vec![vec![Lactivate(1)]]],
+            ..DEFAULT_LAYER_CONFIG
+        },
+        Layer{
+            status_on_reset: LayerStatus::LayerPassthrough,
+            timeout: Some(Duration::from_millis(500)),
+            ..DEFAULT_LAYER_CONFIG
+        },
+    ];
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let clock = ManualClock::new();
+    layout.set_clock(clock.clone());
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), clock.now());
+    assert_eq!(layout.next_timer_in(), Some(Duration::from_millis(500)));
+
+    clock.advance_ms(200);
+    layout.tick_now();
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_eq!(layout.next_timer_in(), Some(Duration::from_millis(300)));
+
+    // An overdue timer is due right away
+    clock.advance_ms(400);
+    assert_eq!(layout.next_timer_in(), Some(Duration::ZERO));
+    layout.tick_now();
+    assert_eq!(layout.get_active_layers(), vec![0]);
+    assert_eq!(layout.next_timer_in(), None);
+}
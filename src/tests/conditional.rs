@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::parse_layout;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{If, IfHeld, Inh, Klong, Ltoggle, No};
+use crate::layout::types::KeyCoords;
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, test_layer, test_layout, TestDevice, DEFAULT_LAYER_CONFIG};
+
+const B_LONG: KeyCoords = KeyCoords(0, 0, 2);
+
+// B01 toggles the layer 1, B02 types A in it and B outside of it,
+// the third key is a short X / long Y in it and Z outside of it
+fn conditional_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![
+            Ltoggle(1),
+            If(1, Box::new(G().k(KeyCode::KEY_A).p()), Box::new(G().k(KeyCode::KEY_B).p())),
+            If(1, Box::new(Klong(G().k(KeyCode::KEY_X), G().k(KeyCode::KEY_Y))), Box::new(G().k(KeyCode::KEY_Z).p())),
+        ]]).name("base"),
+        test_layer(vec![vec![Inh, Inh, Inh]]).inherits("base"),
+    ])
+}
+
+#[test]
+fn test_if_layer_active() {
+    let layout_vec = conditional_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+
+    // A held branch is resolved like a key of its own
+    layout.process_keyevent(KeyStateChange::Pressed(B_LONG), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::LongPress(B_LONG, Duration::from_millis(300)), t.advance_ms(300));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_Y, true), (KeyCode::KEY_Y, false)]);
+    layout.process_keyevent(KeyStateChange::Released(B_LONG), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![]);
+}
+
+#[test]
+fn test_if_branch_kept_until_release() {
+    let layout_vec = conditional_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(B_LONG), t.advance_ms(50));
+
+    // Leaving the layer does not change the branch of the held key
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    assert_eq!(layout.get_active_layers(), vec![0]);
+    layout.process_keyevent(KeyStateChange::Released(B_LONG), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_X, true), (KeyCode::KEY_X, false)]);
+
+    layout.process_keyevent(KeyStateChange::Click(B_LONG), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_Z, true), (KeyCode::KEY_Z, false)]);
+}
+
+#[test]
+fn test_parse_if() {
+    let layers = parse_layout(r#"
+        [[layers]]
+        keymap = [[[{ If = ["tools", "a", { Klong = ["x", "y"] }] }]]]
+
+        [[layers]]
+        name = "tools"
+        keymap = [[["Inh"]]]
+    "#).unwrap();
+
+    assert_eq!(layers[0].keymap[0][0][0],
+               If(1, Box::new(G().k(KeyCode::KEY_A).p()), Box::new(Klong(G().k(KeyCode::KEY_X), G().k(KeyCode::KEY_Y)))));
+}
+
+// B01 only modifies the wheel, which zooms while it is held and scrolls otherwise
+#[test]
+fn test_if_held() {
+    let wheel = KeyCoords(1, 0, 1);
+    let layout_vec = vec![Layer{
+        keymap: vec![
+            vec![vec![No]],
+            vec![vec![
+                Inh,
+                IfHeld(TestDevice::B01, Box::new(G().k(KeyCode::KEY_EQUAL).p()), Box::new(G().k(KeyCode::KEY_DOWN).p())),
+            ]],
+        ],
+        ..DEFAULT_LAYER_CONFIG
+    }];
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(wheel), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_DOWN, true), (KeyCode::KEY_DOWN, false)]);
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(wheel), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(200)), t.advance_ms(200));
+    layout.process_keyevent(KeyStateChange::Click(wheel), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_EQUAL, true), (KeyCode::KEY_EQUAL, false),
+        (KeyCode::KEY_EQUAL, true), (KeyCode::KEY_EQUAL, false),
+    ]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(wheel), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_DOWN, true), (KeyCode::KEY_DOWN, false)]);
+}
+
+#[test]
+fn test_parse_if_held() {
+    let layers = parse_layout(r#"
+        [[layers]]
+        keymap = [[["No"]], [["Inh", { IfHeld = [[0, 0, 0], "ctrl+=", "KEY_DOWN"] }]]]
+    "#).unwrap();
+
+    assert_eq!(layers[0].keymap[1][0][1],
+               IfHeld(TestDevice::B01,
+                      Box::new(G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_EQUAL).p()),
+                      Box::new(G().k(KeyCode::KEY_DOWN).p())));
+}
@@ -0,0 +1,117 @@
+use crate::keycodes::{KeyCode, LedType};
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::{KeymapEvent, LayerCondition};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, test_layer, test_layout, TestDevice};
+
+// Dual layout, the second layer is tied to Num Lock
+fn led_layered_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![ G().k(KeyCode::KEY_A).p(), G().k(KeyCode::KEY_B).p() ]]),
+        test_layer(vec![vec![ G().k(KeyCode::KEY_1).p(), G().k(KeyCode::KEY_2).p() ]])
+            .condition(LayerCondition::LedOn(LedType::LED_NUML))
+            .on_active([KeyCode::KEY_LEFTSHIFT]),
+    ])
+}
+
+#[test]
+fn test_led_conditioned_layer() {
+    let layout_vec = led_layered_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    assert_emitted_keys(&mut layout, vec![]);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+
+    layout.set_leds([LedType::LED_NUML, LedType::LED_CAPSL]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    // No change in LED state, no change in layers
+    layout.set_leds([LedType::LED_NUML, LedType::LED_CAPSL]);
+    assert_emitted_keys(&mut layout, vec![]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_2, true), (KeyCode::KEY_2, false)]);
+
+    layout.set_leds([LedType::LED_CAPSL]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+}
+
+#[test]
+fn test_led_state_query() {
+    // B01 types a dash, or an underscore while Caps Lock is on
+    let layout_vec = test_layout(vec![
+        test_layer(vec![vec![KeymapEvent::If(
+            1,
+            Box::new(G().k(KeyCode::KEY_LEFTSHIFT).k(KeyCode::KEY_MINUS).p()),
+            Box::new(G().k(KeyCode::KEY_MINUS).p()),
+        )]]),
+        test_layer(vec![vec![KeymapEvent::Pass]])
+            .condition(LayerCondition::LedOn(LedType::LED_CAPSL)),
+    ]);
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // Unknown until the first update
+    assert!(!layout.is_led_on(LedType::LED_CAPSL));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_MINUS, true), (KeyCode::KEY_MINUS, false)]);
+
+    layout.set_leds([LedType::LED_CAPSL]);
+    assert!(layout.is_led_on(LedType::LED_CAPSL));
+    assert!(!layout.is_led_on(LedType::LED_NUML));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_MINUS, true),
+        (KeyCode::KEY_MINUS, false), (KeyCode::KEY_LEFTSHIFT, false),
+    ]);
+
+    // The LED state is forgotten until the next update
+    layout.release_all();
+    assert!(!layout.is_led_on(LedType::LED_CAPSL));
+}
+
+// Dual layout, the brush layer is only active while the pen is near
+fn pen_layered_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![ G().k(KeyCode::KEY_S).p() ]]),
+        test_layer(vec![vec![ G().k(KeyCode::KEY_B).p() ]])
+            .condition(LayerCondition::PenNear),
+    ])
+}
+
+#[test]
+fn test_pen_conditioned_layer() {
+    let layout_vec = pen_layered_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_S, true), (KeyCode::KEY_S, false)]);
+
+    layout.set_pen_proximity(true);
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+
+    layout.set_pen_proximity(false);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_S, true), (KeyCode::KEY_S, false)]);
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Cooldown, Kg};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// Single layout, B01 deletes with a 500 ms cooldown, B02 types B freely
+fn cooldown_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Cooldown(Box::new(Kg(G().k(KeyCode::KEY_DELETE))), Duration::from_millis(500)),
+              G().k(KeyCode::KEY_B).p() ],
+    ])
+}
+
+#[test]
+fn test_cooldown() {
+    let layout_vec = cooldown_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_DELETE, true), (KeyCode::KEY_DELETE, false)]);
+
+    // Mashing the button does nothing until the cooldown passes
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // Other keys are not affected
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+
+    // The cooldown counts from the last accepted press
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(200));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_DELETE, true), (KeyCode::KEY_DELETE, false)]);
+}
@@ -0,0 +1,33 @@
+use zbus::blocking::{Connection, Proxy};
+
+use crate::dbus_control::{ControlRequest, DbusControl, BUS_NAME};
+
+#[test]
+#[ignore = "needs a D-Bus session bus"]
+fn test_dbus_control() {
+    let control = DbusControl::start().unwrap();
+    control.set_layout("default", vec!["base".to_string(), "".to_string(), "shift".to_string()]);
+    control.set_active_layers(&[0, 2]).unwrap();
+
+    let connection = Connection::session().unwrap();
+    let proxy = Proxy::new(&connection, BUS_NAME, "/org/kymars/Ack05", BUS_NAME).unwrap();
+
+    // Unnamed layers are known by their index
+    let layers: Vec<String> = proxy.get_property("Layers").unwrap();
+    assert_eq!(layers, vec!["base", "1", "shift"]);
+    let active: Vec<String> = proxy.get_property("ActiveLayers").unwrap();
+    assert_eq!(active, vec!["base", "shift"]);
+
+    let _: () = proxy.call("ActivateLayer", &("1",)).unwrap();
+    let _: () = proxy.call("DeactivateLayer", &("shift",)).unwrap();
+    let _: () = proxy.call("Reload", &()).unwrap();
+    let _: () = proxy.call("Passthrough", &(true,)).unwrap();
+    assert!(proxy.call::<_, _, ()>("ActivateLayer", &("missing",)).is_err());
+    assert!(proxy.call::<_, _, ()>("SwitchProfile", &("../layout",)).is_err());
+
+    assert_eq!(control.poll(), Some(ControlRequest::ActivateLayer(1)));
+    assert_eq!(control.poll(), Some(ControlRequest::DeactivateLayer(2)));
+    assert_eq!(control.poll(), Some(ControlRequest::Reload));
+    assert_eq!(control.poll(), Some(ControlRequest::Passthrough(true)));
+    assert_eq!(control.poll(), None);
+}
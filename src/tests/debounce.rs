@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::ActivationDebounce;
+use crate::layout::types::KeymapEvent::{Khl, Ldeactivate};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, test_layer, test_layout, TestDevice};
+
+// Dual layout, hold B01 to activate the second layer, press B01 again to leave it
+fn debounced_layout(debounce: ActivationDebounce) -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![ Khl(G().k(KeyCode::KEY_0), 1), G().k(KeyCode::KEY_B).p() ]]),
+        test_layer(vec![vec![ Ldeactivate(1), G().k(KeyCode::KEY_E).p() ]])
+            .activation_debounce(debounce),
+    ])
+}
+
+#[test]
+fn test_activation_debounce_trigger_key() {
+    let layout_vec = debounced_layout(ActivationDebounce::TriggerKey(Duration::from_millis(300)));
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(250)), t.advance_ms(250));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![]);
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    // Fumbled second press of the hold key is ignored
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    // Other keys work normally
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_E, false)]);
+
+    // The window is over
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+}
+
+#[test]
+fn test_activation_debounce_all_keys() {
+    let layout_vec = debounced_layout(ActivationDebounce::AllKeys(Duration::from_millis(300)));
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(250)), t.advance_ms(250));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(200));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_E, false)]);
+}
@@ -0,0 +1,43 @@
+use crate::desktop_notifications::{cheat_sheet, layer_title};
+use crate::layout::geometry::Geometry;
+use crate::layout::serialization::{parse_layout, parse_settings};
+
+const LAYOUT: &str = r#"
+[settings]
+layer_notifications = true
+
+[[layers]]
+name = "base"
+keymap = [[[{ Kg = "ctrl+z" }, { Khl = ["b", 1] }, "No"]]]
+
+[[layers]]
+keymap = [[[{ Kg = "h i" }, "Inh", { Ltoggle = 0 }]]]
+"#;
+
+#[test]
+fn test_notification_settings() {
+    let settings = parse_settings(LAYOUT).unwrap();
+    assert!(settings.layer_notifications);
+    assert!(!settings.cheat_sheet);
+    assert!(!parse_settings("").unwrap().layer_notifications);
+}
+
+#[test]
+fn test_cheat_sheet() {
+    let layers = parse_layout(LAYOUT).unwrap();
+    let geometry = Geometry::ack05();
+
+    assert_eq!(layer_title(&layers, 0), "base");
+    assert_eq!(layer_title(&layers, 1), "1");
+
+    // The keys that do nothing are left out
+    assert_eq!(
+        cheat_sheet(&layers, 0, &geometry),
+        vec!["top-left: ctrl+z", "top-middle: b / layer 1"]
+    );
+    assert_eq!(
+        cheat_sheet(&layers, 1, &geometry),
+        vec!["top-left: h i", "top-right: layer base"]
+    );
+    assert!(cheat_sheet(&layers, 2, &geometry).is_empty());
+}
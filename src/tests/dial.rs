@@ -0,0 +1,34 @@
+use crate::kbd_events::KeyStateChange;
+use crate::kbd_events::dial::{WheelDial, DIAL_POSITIONS};
+use crate::layout::types::KeyCoords;
+
+const CW: KeyCoords = KeyCoords(1, 0, 1);
+const CCW: KeyCoords = KeyCoords(1, 0, 0);
+
+#[test]
+fn test_dial_accumulates() {
+    let mut dial = WheelDial::new(CW, CCW);
+
+    assert_eq!(dial.turn(&KeyStateChange::Click(CW)), Some(1));
+    assert_eq!(dial.turn(&KeyStateChange::Click(CW)), Some(2));
+    assert_eq!(dial.turn(&KeyStateChange::Click(CCW)), Some(1));
+
+    // Other keys do not move the dial
+    assert_eq!(dial.turn(&KeyStateChange::Click(KeyCoords(0, 0, 0))), None);
+    assert_eq!(dial.turn(&KeyStateChange::Pressed(CW)), None);
+    assert_eq!(dial.value(), 1);
+
+    // A full turn wraps around
+    assert_eq!(dial.turn(&KeyStateChange::Click(CCW)), Some(0));
+    assert_eq!(dial.turn(&KeyStateChange::Click(CCW)), Some(DIAL_POSITIONS - 1));
+}
+
+#[test]
+fn test_dial_resolution() {
+    let mut dial = WheelDial::new(CW, CCW);
+    dial.set_resolution(4, 10);
+
+    assert_eq!(dial.turn(&KeyStateChange::Click(CW)), Some(4));
+    assert_eq!(dial.turn(&KeyStateChange::Click(CW)), Some(8));
+    assert_eq!(dial.turn(&KeyStateChange::Click(CW)), Some(2));
+}
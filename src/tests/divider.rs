@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use crate::kbd_events::KeyStateChange::{self, Click};
+use crate::kbd_events::divider::{RotaryDivider, DIVIDER_IDLE_TIMEOUT};
+use crate::layout::serialization::parse_settings;
+use crate::layout::types::KeyCoords;
+
+use super::testtime::TestTime;
+
+const CW: KeyCoords = KeyCoords(1, 0, 1);
+const CCW: KeyCoords = KeyCoords(1, 0, 0);
+
+fn tick(divider: &mut RotaryDivider, k: KeyCoords, t: &mut TestTime, ms: u64) -> Option<KeyStateChange<KeyCoords>> {
+    divider.process(Click(k), t.advance_ms(ms))
+}
+
+#[test]
+fn test_rotary_divider() {
+    let mut divider = RotaryDivider::new(CW, CCW, 3, Duration::from_millis(500));
+    let mut t = TestTime::start();
+
+    assert_eq!(tick(&mut divider, CW, &mut t, 0), None);
+    assert_eq!(tick(&mut divider, CW, &mut t, 50), None);
+    assert_eq!(tick(&mut divider, CW, &mut t, 50), Some(Click(CW)));
+    assert_eq!(tick(&mut divider, CW, &mut t, 50), None);
+
+    // The direction change starts counting anew
+    assert_eq!(tick(&mut divider, CCW, &mut t, 50), None);
+    assert_eq!(tick(&mut divider, CCW, &mut t, 50), None);
+    assert_eq!(tick(&mut divider, CCW, &mut t, 50), Some(Click(CCW)));
+
+    // So does a pause
+    assert_eq!(tick(&mut divider, CW, &mut t, 50), None);
+    assert_eq!(tick(&mut divider, CW, &mut t, 50), None);
+    assert_eq!(tick(&mut divider, CW, &mut t, 600), None);
+    assert_eq!(tick(&mut divider, CW, &mut t, 50), None);
+    assert_eq!(tick(&mut divider, CW, &mut t, 50), Some(Click(CW)));
+
+    // The other keys pass unchanged
+    let press = KeyStateChange::Pressed(KeyCoords(0, 0, 0));
+    assert_eq!(divider.process(press, t.advance_ms(10)), Some(press));
+}
+
+#[test]
+fn test_parse_rotary_divider() {
+    assert_eq!(parse_settings("").unwrap().rotary_divider, None);
+    let settings = parse_settings("[settings]\nrotary_divider = { detents = 3 }").unwrap();
+    assert_eq!(settings.rotary_divider, Some((3, DIVIDER_IDLE_TIMEOUT)));
+    let settings = parse_settings("[settings]\nrotary_divider = { detents = 2, idle_ms = 800 }").unwrap();
+    assert_eq!(settings.rotary_divider, Some((2, Duration::from_millis(800))));
+}
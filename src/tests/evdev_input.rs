@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use crate::keycodes::KeyCode;
+
+use crate::evdev_input::KeyboardSource;
+use crate::kbd_events::KeyStateChange;
+use crate::layout::serialization::{parse_geometry, parse_keyboards, parse_layout};
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeyCoords;
+use crate::layout::validation::validate;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, TestDevice};
+
+const LAYOUT: &str = r#"
+[[keyboards]]
+path = "/dev/input/by-id/usb-Keyboard-event-kbd"
+grab = true
+
+[[layers]]
+keymap = [[[{ Lhold = "nav" }]], [[]], [["No", "KEY_ESC", "KEY_1"]]]
+
+[[layers]]
+name = "nav"
+keymap = [[["Pass"]], [[]], [["No", "Pass", "KEY_F1"]]]
+"#;
+
+#[test]
+fn test_parse_keyboards() {
+    assert_eq!(parse_keyboards("").unwrap(), vec![]);
+
+    let keyboards = parse_keyboards(LAYOUT).unwrap();
+    assert_eq!(keyboards, vec![KeyboardSource {
+        path: PathBuf::from("/dev/input/by-id/usb-Keyboard-event-kbd"),
+        grab: true,
+        block: 2,
+    }]);
+
+    // The keys are columns of the only row, the codes above are not keys
+    assert_eq!(keyboards[0].coords(KeyCode::KEY_ESC), Some(KeyCoords(2, 0, 1)));
+    assert_eq!(keyboards[0].coords(KeyCode::BTN_LEFT), None);
+
+    // The keyboards come after the chords
+    let source = r#"
+        [[chords]]
+        keys = [[0, 0, 0], [0, 0, 1]]
+
+        [[keyboards]]
+        path = "/dev/input/event3"
+
+        [[keyboards]]
+        path = "/dev/input/event4"
+        name = "numpad"
+    "#;
+    let blocks: Vec<u8> = parse_keyboards(source).unwrap().iter().map(|k| k.block).collect();
+    assert_eq!(blocks, vec![3, 4]);
+    assert!(parse_keyboards("[[keyboards]]\ngrab = true").is_err());
+
+    let geometry = parse_geometry(source).unwrap();
+    assert_eq!(geometry.blocks[3].name, "event3");
+    assert_eq!(geometry.blocks[4].name, "numpad");
+    assert_eq!(geometry.label(KeyCoords(4, 0, 30)), Some("KEY_A"));
+}
+
+#[test]
+fn test_keyboard_layers() {
+    // The keymap of the keyboard block is a known position
+    let layers = parse_layout(LAYOUT).unwrap();
+    assert_eq!(validate(&layers, &parse_geometry(LAYOUT).unwrap()), Ok(()));
+
+    let keyboard = &parse_keyboards(LAYOUT).unwrap()[0];
+    let key_1 = keyboard.coords(KeyCode::KEY_1).unwrap();
+    let mut layout = LayerSwitcher::new(&layers);
+    layout.start();
+    let t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(key_1), t);
+    layout.process_keyevent(KeyStateChange::Released(key_1), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_1, true), (KeyCode::KEY_1, false)]);
+
+    // A keypad button is the layer modifier of the keyboard
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(key_1), t);
+    layout.process_keyevent(KeyStateChange::Released(key_1), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_F1, true), (KeyCode::KEY_F1, false)]);
+}
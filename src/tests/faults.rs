@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use enumset::EnumSet;
+
+use crate::button_device::recover;
+use crate::kbd_events::{ChangeDetector, KeyEvent};
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::keys::G;
+use crate::layout::types::KeyCoords;
+use crate::xppen_hid::faults::{Fault, FaultInjector, FaultyRead};
+use crate::xppen_hid::report_map::ReportMap;
+use crate::xppen_hid::{XpPenButtons, XpPenResult};
+use crate::xppen_hid::XpPenButtons::{XpB01, XpB02};
+
+use super::report;
+use super::testtime::TestTime;
+use super::single_layer;
+
+fn faults_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_B).p() ],
+    ])
+}
+
+/// The reports of the given button states, 20 ms apart
+fn reports(states: &[&[XpPenButtons]]) -> impl Iterator<Item=(Vec<u8>, Instant)> {
+    let mut t = TestTime::start();
+    let states: Vec<Vec<u8>> = states.iter().map(|s| report(s).to_vec()).collect();
+    states.into_iter().map(move |r| (r, t.advance_ms(20)))
+}
+
+/// Feed the reads to the engine the way the main loop does and collect
+/// the emitted keys. A disconnect is recovered from like in the main loop.
+fn drive<I>(layout: &mut LayerSwitcher, reads: I) -> Vec<(KeyCode, bool)>
+where
+    I: Iterator<Item=(FaultyRead, Instant)>
+{
+    let mut detector = ChangeDetector::<XpPenButtons>::new();
+    let mut emitted = Vec::new();
+
+    for (read, t) in reads {
+        match read {
+            FaultyRead::Report(buf) => match ReportMap::ack05().parse(&buf) {
+                XpPenResult::Keys(keys) => {
+                    detector.analyze(keys, t);
+                }
+                _ => detector.tick(t),
+            },
+            FaultyRead::Disconnected => recover(layout, &mut detector),
+        }
+        layout.tick(t);
+
+        for ev in detector.drain() {
+            let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+            layout.process_keyevent(ev, t);
+        }
+        layout.render(|k, v| emitted.push((k, v)));
+    }
+
+    emitted
+}
+
+#[test]
+fn test_fault_bad_reports() {
+    let layout_vec = faults_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+
+    // Garbage while B01 is held must neither release it nor press anything else
+    let mut reads = FaultInjector::new(reports(&[&[XpB01], &[XpB01], &[XpB01], &[XpB01], &[]]));
+    reads.inject(Fault::ShortRead);
+    reads.inject(Fault::Malformed);
+    reads.inject(Fault::Malformed);
+
+    assert_eq!(drive(&mut layout, reads), vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_LEFTSHIFT, false)]);
+}
+
+#[test]
+fn test_fault_disconnect() {
+    let layout_vec = faults_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+
+    let mut reads = FaultInjector::new(reports(&[&[XpB01], &[XpB01, XpB02], &[XpB01], &[XpB01], &[XpB01], &[]]));
+    reads.inject(Fault::Gap(Duration::ZERO));
+    reads.inject(Fault::Gap(Duration::from_secs(10)));
+    reads.inject(Fault::Disconnect(2));
+
+    // Nothing stays stuck while the device is gone, the key still held
+    // after the reconnect is pressed again
+    assert_eq!(drive(&mut layout, reads), vec![
+        (KeyCode::KEY_LEFTSHIFT, true),
+        (KeyCode::KEY_B, true),
+        (KeyCode::KEY_B, false),
+        (KeyCode::KEY_LEFTSHIFT, false),
+        (KeyCode::KEY_LEFTSHIFT, true),
+        (KeyCode::KEY_LEFTSHIFT, false),
+    ]);
+}
+
+#[test]
+fn test_recover() {
+    let layout_vec = faults_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut detector = ChangeDetector::<XpPenButtons>::new();
+    let mut t = TestTime::start();
+    let mut emitted = Vec::new();
+
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(20));
+    for ev in detector.drain() {
+        let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+        layout.process_keyevent(ev, t);
+    }
+    layout.render(|k, v| emitted.push((k, v)));
+
+    // The held key is released and pressed again by the next report
+    recover(&mut layout, &mut detector);
+    layout.render(|k, v| emitted.push((k, v)));
+    detector.analyze(EnumSet::only(XpB01), t.advance_ms(20));
+    for ev in detector.drain() {
+        let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+        layout.process_keyevent(ev, t);
+    }
+    layout.render(|k, v| emitted.push((k, v)));
+
+    assert_eq!(emitted, vec![
+        (KeyCode::KEY_LEFTSHIFT, true),
+        (KeyCode::KEY_LEFTSHIFT, false),
+        (KeyCode::KEY_LEFTSHIFT, true),
+    ]);
+}
+
+#[test]
+fn test_fault_seeded() {
+    let layout_vec = faults_layout();
+
+    // Random presses of both buttons, finished by a release of everything
+    let states: [&[XpPenButtons]; 4] = [&[], &[XpB01], &[XpB02], &[XpB01, XpB02]];
+    let mut sequence: Vec<&[XpPenButtons]> = (0..300usize).map(|i| states[(i * 7 + i / 5) % 4]).collect();
+    sequence.push(&[]);
+
+    let run = |seed| {
+        let mut layout = LayerSwitcher::new(&layout_vec);
+        layout.start();
+        let reads = FaultInjector::seeded(reports(&sequence), seed, 20);
+        let mut emitted = drive(&mut layout, reads);
+
+        // The last report could have been lost, release it all like the panic chord
+        layout.release_all();
+        layout.render(|k, v| emitted.push((k, v)));
+        emitted
+    };
+
+    // The same seed injects the same faults, the keys released together
+    // can still come out in a different order
+    let faulty = |seed| -> Vec<FaultyRead> {
+        FaultInjector::seeded(reports(&sequence), seed, 20).map(|(read, _)| read).collect()
+    };
+    assert_eq!(faulty(42), faulty(42));
+    assert_ne!(faulty(42), faulty(43));
+
+    for seed in [1, 42, 1992] {
+        let emitted = run(seed);
+
+        // Every release matches a press and nothing stays held
+        let mut held = HashSet::new();
+        for (k, pressed) in emitted {
+            if pressed {
+                assert!(held.insert(k), "Seed {}: {:?} pressed twice", seed, k);
+            } else {
+                assert!(held.remove(&k), "Seed {}: {:?} released while up", seed, k);
+            }
+        }
+        assert!(held.is_empty(), "Seed {}: {:?} stuck", seed, held);
+    }
+}
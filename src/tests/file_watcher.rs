@@ -0,0 +1,28 @@
+use std::fs;
+
+use crate::file_watcher::FileWatcher;
+
+use super::backup::scratch_dir;
+
+#[test]
+fn test_file_watcher() {
+    let dir = scratch_dir("watcher");
+    let path = dir.join("layout.toml");
+
+    // The file does not exist yet
+    let mut watcher = FileWatcher::open(&path).unwrap();
+    assert!(!watcher.changed());
+
+    fs::write(&path, "[[layers]]").unwrap();
+    assert!(watcher.changed());
+    assert!(!watcher.changed());
+
+    // Other files in the directory are not interesting
+    fs::write(dir.join("macros.toml"), "").unwrap();
+    assert!(!watcher.changed());
+
+    // Editors save by renaming a new file over the old one
+    fs::write(dir.join("layout.toml.swp"), "[[layers]]").unwrap();
+    fs::rename(dir.join("layout.toml.swp"), &path).unwrap();
+    assert!(watcher.changed());
+}
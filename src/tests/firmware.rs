@@ -0,0 +1,27 @@
+use crate::xppen_hid::firmware::{FirmwareVersion, Quirks, ACK05_MODE_SWITCH, FIRMWARE_REPORT_LEN};
+use crate::xppen_hid::report_map::ReportMap;
+
+#[test]
+fn test_firmware_version() {
+    assert_eq!(FirmwareVersion(0x0100).to_string(), "1.00");
+    assert_eq!(FirmwareVersion(0x0212).to_string(), "2.12");
+}
+
+#[test]
+fn test_firmware_report() {
+    let mut reply = [0u8; FIRMWARE_REPORT_LEN];
+    reply[..3].copy_from_slice(&[0x02, 0x01, 0x04]);
+    assert_eq!(FirmwareVersion::from_report(&reply), Some(FirmwareVersion(0x0104)));
+
+    // Another report, an empty reply and a short one have no version
+    assert_eq!(FirmwareVersion::from_report(&[0x03, 0x01, 0x04]), None);
+    assert_eq!(FirmwareVersion::from_report(&[0x02, 0x00, 0x00]), None);
+    assert_eq!(FirmwareVersion::from_report(&[0x02]), None);
+}
+
+#[test]
+fn test_firmware_quirks() {
+    let ack05 = Quirks::ack05();
+    assert_eq!(ack05.mode_switch, Some(ACK05_MODE_SWITCH));
+    assert_eq!(ack05.report_map, ReportMap::ack05());
+}
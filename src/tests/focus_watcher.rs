@@ -0,0 +1,119 @@
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use crate::focus_watcher::{
+    matches, parse_active_window, parse_hyprctl_class, parse_wm_class, BackendConfig, FocusBackend,
+    FocusConfig, FocusWatcher,
+};
+
+const CONFIG: &str = r#"
+default_profile = "office"
+
+[focus]
+backend = "command"
+command = "focused-app"
+poll_ms = 5
+
+[[rules]]
+app = "krita"
+profile = "krita"
+
+[[rules]]
+app = "org.blender.*"
+profile = "blender"
+
+[[rules]]
+app = "*gimp*"
+profile = "gimp"
+"#;
+
+#[test]
+fn test_matches() {
+    assert!(matches("krita", "Krita"));
+    assert!(!matches("krita", "krita-helper"));
+    assert!(matches("org.*", "org.kde.krita"));
+    assert!(matches("*krita", "org.kde.krita"));
+    assert!(matches("org.*.krita", "org.kde.krita"));
+    assert!(!matches("org.*.krita", "org.krita"));
+    assert!(matches("*", "anything"));
+    assert!(!matches("a*a", "a"));
+}
+
+#[test]
+fn test_parse_backend_output() {
+    assert_eq!(
+        parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007\n"),
+        Some("0x3a00007".to_string())
+    );
+    assert_eq!(parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0\n"), None);
+    assert_eq!(
+        parse_wm_class("WM_CLASS(STRING) = \"krita\", \"Krita\"\n"),
+        Some("Krita".to_string())
+    );
+    assert_eq!(parse_wm_class("WM_CLASS:  not found.\n"), None);
+    assert_eq!(
+        parse_hyprctl_class("Window 55d5 -> ~:\n\tmapped: 1\n\tclass: org.kde.krita\n\ttitle: Krita\n"),
+        Some("org.kde.krita".to_string())
+    );
+}
+
+#[test]
+fn test_focus_config() {
+    let config = FocusConfig::parse(CONFIG).unwrap();
+    assert_eq!(
+        config.focus.backend,
+        BackendConfig::Command {
+            command: "focused-app".to_string()
+        }
+    );
+    assert_eq!(config.profile_for("krita"), "krita");
+    assert_eq!(config.profile_for("org.blender.Blender"), "blender");
+    assert_eq!(config.profile_for("gimp-2.10"), "gimp");
+    assert_eq!(config.profile_for("firefox"), "office");
+
+    let config = FocusConfig::parse("[focus]\nbackend = \"x11\"\n").unwrap();
+    assert_eq!(config.focus.backend, BackendConfig::X11);
+    assert_eq!(config.profile_for("firefox"), "default");
+}
+
+/// Reports the apps sent by the test, keeps the last one focused
+struct MockBackend {
+    apps: Receiver<Option<String>>,
+    focused: Option<String>,
+}
+
+impl FocusBackend for MockBackend {
+    fn focused_app(&mut self) -> Option<String> {
+        if let Ok(app) = self.apps.try_recv() {
+            self.focused = app;
+        }
+        self.focused.clone()
+    }
+}
+
+fn wait_for_profile(watcher: &FocusWatcher) -> Option<String> {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) {
+        if let Some(profile) = watcher.poll() {
+            return Some(profile);
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    None
+}
+
+#[test]
+fn test_focus_watcher() {
+    let (tx, rx) = mpsc::channel();
+    let backend = MockBackend { apps: rx, focused: None };
+    let watcher = FocusWatcher::with_backend(Box::new(backend), FocusConfig::parse(CONFIG).unwrap());
+
+    tx.send(Some("krita".to_string())).unwrap();
+    assert_eq!(wait_for_profile(&watcher), Some("krita".to_string()));
+
+    // Losing the focus keeps the profile, another app of the same profile changes nothing
+    tx.send(None).unwrap();
+    tx.send(Some("Krita".to_string())).unwrap();
+    tx.send(Some("firefox".to_string())).unwrap();
+    assert_eq!(wait_for_profile(&watcher), Some("office".to_string()));
+}
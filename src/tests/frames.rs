@@ -0,0 +1,80 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Kg, Output};
+use crate::layout::keys::{G, S};
+
+use super::testtime::TestTime;
+use super::{single_layer, TestDevice};
+
+// B01 types ctrl+1 without the shift held on the host, B02 types "a b",
+// B03 sends play/pause through a media device
+fn frames_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_1).m(KeyCode::KEY_LEFTSHIFT).p(),
+              S().k(KeyCode::KEY_A).k(KeyCode::KEY_B).p() ],
+        vec![ Output(Box::new(Kg(G().k(KeyCode::KEY_PLAYPAUSE))), "Consumer Control".to_string()) ],
+    ])
+}
+
+/// The rendered frames with their output devices
+type Frames = Vec<(Option<String>, Vec<(KeyCode, bool)>)>;
+
+fn frames(layout: &mut LayerSwitcher) -> Frames {
+    let mut frames = Vec::new();
+    layout.render_frames(|output, keys| frames.push((output.map(str::to_string), keys.to_vec())));
+    frames
+}
+
+#[test]
+fn test_keygroup_frames() {
+    let layout_vec = frames_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // The mask is lifted in the same frame the keys are pressed in
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    assert_eq!(frames(&mut layout), vec![
+        (None, vec![(KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_LEFTCTRL, true), (KeyCode::KEY_1, true)]),
+    ]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
+    assert_eq!(frames(&mut layout), vec![
+        (None, vec![(KeyCode::KEY_1, false), (KeyCode::KEY_LEFTCTRL, false), (KeyCode::KEY_LEFTSHIFT, true)]),
+    ]);
+}
+
+#[test]
+fn test_frame_per_key_event() {
+    let layout_vec = frames_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // A key is never pressed and released within a single frame
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_eq!(frames(&mut layout), vec![
+        (None, vec![(KeyCode::KEY_A, true)]),
+        (None, vec![(KeyCode::KEY_A, false), (KeyCode::KEY_B, true)]),
+        (None, vec![(KeyCode::KEY_B, false)]),
+    ]);
+}
+
+#[test]
+fn test_frame_per_output() {
+    let layout_vec = frames_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B03), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    let consumer = Some("Consumer Control".to_string());
+    assert_eq!(frames(&mut layout), vec![
+        (consumer, vec![(KeyCode::KEY_PLAYPAUSE, true)]),
+        (None, vec![(KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_LEFTCTRL, true), (KeyCode::KEY_1, true)]),
+    ]);
+}
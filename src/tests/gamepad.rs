@@ -0,0 +1,81 @@
+use crate::keycodes::{AbsoluteAxisType, KeyCode};
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Gaxis, Gbtn, Gnudge};
+use crate::virtual_gamepad::{GamepadEvent, GAMEPAD_AXIS_MAX};
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// Single layout, B01 is the A button, B02 pushes the stick left,
+// B03 and B04 steer the X axis like the wheel would
+fn gamepad_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Gbtn(KeyCode::BTN_SOUTH), Gaxis(AbsoluteAxisType::ABS_X, -GAMEPAD_AXIS_MAX) ],
+        vec![ Gnudge(AbsoluteAxisType::ABS_RX, 20000), Gnudge(AbsoluteAxisType::ABS_RX, -20000) ],
+    ])
+}
+
+fn assert_gamepad_events(layout: &mut LayerSwitcher, expected: Vec<GamepadEvent>) {
+    let mut events = Vec::new();
+    layout.render_gamepad(|ev| events.push(ev));
+    assert_eq!(events, expected);
+}
+
+#[test]
+fn test_gamepad_button_and_axis() {
+    let layout_vec = gamepad_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    let (buttons, axes) = layout.get_used_gamepad();
+    assert!(buttons.contains(&KeyCode::BTN_SOUTH));
+    assert_eq!(axes, vec![AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_RX]);
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(10));
+    assert_gamepad_events(&mut layout, vec![
+        GamepadEvent::Button(KeyCode::BTN_SOUTH, true),
+        GamepadEvent::Axis(AbsoluteAxisType::ABS_X, -GAMEPAD_AXIS_MAX),
+    ]);
+
+    // Nothing goes to the keyboard
+    assert_emitted_keys(&mut layout, vec![]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(10));
+    assert_gamepad_events(&mut layout, vec![
+        GamepadEvent::Button(KeyCode::BTN_SOUTH, false),
+        GamepadEvent::Axis(AbsoluteAxisType::ABS_X, 0),
+    ]);
+}
+
+#[test]
+fn test_gamepad_nudge() {
+    let layout_vec = gamepad_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // The axis stays where it was moved to and is clamped to its range
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
+    assert_gamepad_events(&mut layout, vec![
+        GamepadEvent::Axis(AbsoluteAxisType::ABS_RX, 20000),
+        GamepadEvent::Axis(AbsoluteAxisType::ABS_RX, GAMEPAD_AXIS_MAX),
+        GamepadEvent::Axis(AbsoluteAxisType::ABS_RX, GAMEPAD_AXIS_MAX - 20000),
+    ]);
+
+    // Everything returns to the center
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.release_all();
+    assert_gamepad_events(&mut layout, vec![
+        GamepadEvent::Button(KeyCode::BTN_SOUTH, true),
+        GamepadEvent::Button(KeyCode::BTN_SOUTH, false),
+        GamepadEvent::Axis(AbsoluteAxisType::ABS_RX, 0),
+    ]);
+}
@@ -0,0 +1,51 @@
+use crate::layout::geometry::Geometry;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::{builtin_layout, parse_geometry};
+use crate::layout::types::{KeyCoords, KeymapEvent};
+
+use super::DEFAULT_LAYER_CONFIG;
+
+#[test]
+fn test_default_geometry() {
+    let geometry = parse_geometry("").unwrap();
+    assert_eq!(geometry, Geometry::ack05());
+
+    assert_eq!(geometry.label(KeyCoords(0, 0, 0)), Some("top-left"));
+    assert_eq!(geometry.label(KeyCoords(0, 0, 10)), None);
+    assert_eq!(geometry.label(KeyCoords(1, 0, 1)), Some("wheel CW"));
+    assert_eq!(geometry.find("wheel CCW"), Some(KeyCoords(1, 0, 0)));
+    assert_eq!(geometry.positions().count(), 15);
+    assert_eq!(geometry.label(KeyCoords(1, 1, 2)), Some("wheel wiggle"));
+
+    assert!(!geometry.is_stateless(KeyCoords(0, 0, 0)));
+    assert!(geometry.is_stateless(KeyCoords(1, 0, 0)));
+}
+
+#[test]
+fn test_hold_on_stateless_block() {
+    let geometry = Geometry::ack05();
+    let layers = builtin_layout();
+    assert_eq!(geometry.misplaced_hold_actions(&layers), vec![]);
+
+    let layers = vec![Layer {
+        keymap: vec![
+            vec![vec![KeymapEvent::Lhold(1)]],
+            vec![vec![KeymapEvent::Pass, KeymapEvent::Lhold(1)]],
+        ],
+        ..DEFAULT_LAYER_CONFIG
+    }];
+    assert_eq!(geometry.misplaced_hold_actions(&layers), vec![(0, KeyCoords(1, 0, 1))]);
+}
+
+#[test]
+fn test_custom_geometry() {
+    let geometry = parse_geometry(r#"
+        [[geometry.blocks]]
+        name = "pad"
+        rows = [["a", "b"], ["c"]]
+    "#).unwrap();
+
+    assert!(geometry.contains(KeyCoords(0, 1, 0)));
+    assert!(!geometry.contains(KeyCoords(0, 1, 1)));
+    assert_eq!(geometry.find("b"), Some(KeyCoords(0, 0, 1)));
+}
@@ -0,0 +1,77 @@
+use crate::kbd_events::KeyStateChange;
+use crate::kbd_events::gestures::GestureDetector;
+use crate::layout::types::KeyCoords;
+use crate::xppen_hid::report_map::ReportMap;
+use crate::xppen_hid::XpPenButtons::{XpRoCCW, XpRoCW};
+use crate::xppen_hid::{XpPenResult, XP_ROTARY_GESTURES};
+
+use super::testtime::TestTime;
+
+const CW: KeyCoords = KeyCoords(1, 0, 1);
+const CCW: KeyCoords = KeyCoords(1, 0, 0);
+const SPIN_CW: KeyCoords = KeyCoords(1, 1, 0);
+const SPIN_CCW: KeyCoords = KeyCoords(1, 1, 1);
+const WIGGLE: KeyCoords = KeyCoords(1, 1, 2);
+
+fn tick(detector: &mut GestureDetector, k: KeyCoords, t: &mut TestTime, ms: u64) -> Option<KeyCoords> {
+    match detector.process(&KeyStateChange::Click(k), t.advance_ms(ms)) {
+        Some(KeyStateChange::Click(g)) => Some(g),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_spin_gesture() {
+    let mut detector = GestureDetector::new(CW, CCW, [SPIN_CW, SPIN_CCW, WIGGLE]);
+    let mut t = TestTime::start();
+
+    assert_eq!(tick(&mut detector, CW, &mut t, 0), None);
+    assert_eq!(tick(&mut detector, CW, &mut t, 50), None);
+    assert_eq!(tick(&mut detector, CW, &mut t, 50), None);
+    assert_eq!(tick(&mut detector, CW, &mut t, 50), Some(SPIN_CW));
+
+    // Too slow
+    assert_eq!(tick(&mut detector, CCW, &mut t, 500), None);
+    assert_eq!(tick(&mut detector, CCW, &mut t, 200), None);
+    assert_eq!(tick(&mut detector, CCW, &mut t, 200), None);
+    assert_eq!(tick(&mut detector, CCW, &mut t, 200), None);
+
+    assert_eq!(tick(&mut detector, CCW, &mut t, 50), None);
+    assert_eq!(tick(&mut detector, CCW, &mut t, 20), Some(SPIN_CCW));
+}
+
+#[test]
+fn test_wiggle_gesture() {
+    let mut detector = GestureDetector::new(CW, CCW, [SPIN_CW, SPIN_CCW, WIGGLE]);
+    let mut t = TestTime::start();
+
+    assert_eq!(tick(&mut detector, CW, &mut t, 0), None);
+    assert_eq!(tick(&mut detector, CCW, &mut t, 100), Some(WIGGLE));
+
+    // A slow direction change is not a wiggle
+    assert_eq!(tick(&mut detector, CW, &mut t, 1000), None);
+    assert_eq!(tick(&mut detector, CCW, &mut t, 1000), None);
+
+    // Other events are ignored
+    assert!(detector.process(&KeyStateChange::Pressed(KeyCoords(0, 0, 1)), t.advance_ms(1)).is_none());
+}
+
+// The first wheel bit of the ACK05 report is a counter-clockwise pulse,
+// CCW=1.0.0 of the README, and clockwise pulses spin clockwise
+#[test]
+fn test_ack05_rotary_direction() {
+    let XpPenResult::Keys(keys) = ReportMap::ack05().parse(&[0x02, 240, 0, 0, 0, 0, 0, 0x01]) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpRoCCW);
+    let (cw, ccw): (KeyCoords, KeyCoords) = (XpRoCW.into(), XpRoCCW.into());
+    assert_eq!((cw, ccw), (CW, CCW));
+    assert_eq!(XP_ROTARY_GESTURES, [SPIN_CW, SPIN_CCW, WIGGLE]);
+
+    let mut detector = GestureDetector::new(cw, ccw, XP_ROTARY_GESTURES);
+    let mut t = TestTime::start();
+    assert_eq!(tick(&mut detector, cw, &mut t, 0), None);
+    assert_eq!(tick(&mut detector, cw, &mut t, 50), None);
+    assert_eq!(tick(&mut detector, cw, &mut t, 50), None);
+    assert_eq!(tick(&mut detector, cw, &mut t, 50), Some(SPIN_CW));
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::parse_layout;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{HoldThreshold, Klong};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// Single layout, B01 decides after 500 ms, B02 uses the layout threshold
+fn hold_threshold_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ HoldThreshold(Box::new(Klong(G().k(KeyCode::KEY_0), G().k(KeyCode::KEY_1))), Duration::from_millis(500)),
+              Klong(G().k(KeyCode::KEY_A), G().k(KeyCode::KEY_B)) ],
+    ])
+}
+
+#[test]
+fn test_hold_threshold() {
+    let layout_vec = hold_threshold_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // Longer than the layout threshold is still a tap of B01
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(300)), t.advance_ms(300));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_0, true), (KeyCode::KEY_0, false)]);
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(600)), t.advance_ms(600));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_1, true), (KeyCode::KEY_1, false)]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // The other key keeps the threshold of the layout
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B02, Duration::from_millis(300)), t.advance_ms(300));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+}
+
+#[test]
+fn test_hold_threshold_tiers() {
+    let layout_vec = hold_threshold_layout();
+    let layout = LayerSwitcher::new(&layout_vec);
+    assert!(layout.get_long_press_tiers().contains(&Duration::from_millis(500)));
+}
+
+#[test]
+fn test_parse_hold_threshold() {
+    let layers = parse_layout(r#"
+        [[layers]]
+        keymap = [[[{ HoldThreshold = [{ Klong = ["0", "1"] }, 500] }]]]
+    "#).unwrap();
+
+    assert_eq!(layers[0].keymap[0][0][0],
+               HoldThreshold(Box::new(Klong(G().k(KeyCode::KEY_0), G().k(KeyCode::KEY_1))), Duration::from_millis(500)));
+    assert_eq!(layers[0].get_hold_threshold(TestDevice::B01), Some(Duration::from_millis(500)));
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::keys::G;
+use crate::layout::types::KeyRepeat;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+fn repeat_layout() -> Vec<Layer> {
+    single_layer(vec![vec![G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_Z).p()]])
+}
+
+#[test]
+fn test_key_repeat() {
+    let layout_vec = repeat_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_key_repeat(Some(KeyRepeat{
+        delay: Duration::from_millis(500),
+        period: Duration::from_millis(100),
+    }));
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTCTRL, true), (KeyCode::KEY_Z, true)]);
+    assert_eq!(layout.next_timer(), Some(t.now() + Duration::from_millis(500)));
+
+    layout.tick(t.advance_ms(400));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // Only the last key repeats, it is pressed again without a release
+    // and the modifier stays down
+    layout.tick(t.advance_ms(100));
+    layout.tick(t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_Z, true), (KeyCode::KEY_Z, true)]);
+
+    // Missed repeats are not sent in a burst
+    layout.tick(t.advance_ms(250));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_Z, true)]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(10));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_Z, false), (KeyCode::KEY_LEFTCTRL, false)]);
+    assert_eq!(layout.next_timer(), None);
+}
+
+#[test]
+fn test_no_key_repeat_by_default() {
+    let layout_vec = repeat_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.tick(t.advance_ms(2000));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTCTRL, true), (KeyCode::KEY_Z, true)]);
+}
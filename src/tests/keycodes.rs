@@ -0,0 +1,58 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::keycodes::{AbsoluteAxisType, KeyCode, LedType, RelativeAxisType, UnknownCode};
+
+#[derive(Debug, Deserialize)]
+struct Codes {
+    key: KeyCode,
+    rel: RelativeAxisType,
+    abs: AbsoluteAxisType,
+    led: LedType,
+}
+
+#[test]
+fn test_keycode_names() {
+    assert_eq!(KeyCode::from_str("KEY_A"), Ok(KeyCode::KEY_A));
+    assert_eq!(KeyCode::from_str("key_a"), Err(UnknownCode("key_a".to_string())));
+    assert_eq!(KeyCode::KEY_A.code(), 30);
+    assert_eq!(KeyCode::BTN_SOUTH.name(), Some("BTN_SOUTH"));
+    assert_eq!(format!("{:?}", KeyCode::KEY_VOLUMEUP), "KEY_VOLUMEUP");
+    assert_eq!(format!("{:?}", KeyCode::new(0x2ff)), "unknown key: 767");
+
+    // The layouts name the codes in any case
+    let codes: Codes = toml::from_str(
+        "key = \"key_b\"\nrel = \"REL_WHEEL\"\nabs = \"abs_hat0x\"\nled = \"LED_CAPSL\"",
+    )
+    .unwrap();
+    assert_eq!(codes.key, KeyCode::KEY_B);
+    assert_eq!(codes.rel, RelativeAxisType::REL_WHEEL);
+    assert_eq!(codes.abs, AbsoluteAxisType::ABS_HAT0X);
+    assert_eq!(codes.led, LedType::LED_CAPSL);
+    assert!(toml::from_str::<Codes>("key = \"KEY_NOPE\"\nrel = \"REL_X\"\nabs = \"ABS_X\"\nled = \"LED_NUML\"").is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_keycode_evdev() {
+    // The codes are numbered and named like the ones of the evdev crate
+    for code in 0..0x300 {
+        let key = KeyCode::new(code);
+        assert_eq!(evdev::Key::from(key), evdev::Key::new(code));
+        assert_eq!(format!("{:?}", key), format!("{:?}", evdev::Key::new(code)));
+    }
+    for code in 0..0x40 {
+        assert_eq!(
+            format!("{:?}", AbsoluteAxisType(code)),
+            format!("{:?}", evdev::AbsoluteAxisType(code))
+        );
+    }
+    for code in 0..0x10 {
+        assert_eq!(
+            format!("{:?}", RelativeAxisType(code)),
+            format!("{:?}", evdev::RelativeAxisType(code))
+        );
+        assert_eq!(format!("{:?}", LedType(code)), format!("{:?}", evdev::LedType(code)));
+    }
+}
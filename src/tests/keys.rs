@@ -0,0 +1,52 @@
+use crate::keycodes::KeyCode;
+
+use crate::layout::keys::{parse_key, KeyGroup, UnknownKey, G, S};
+use crate::layout::serialization::parse_layout;
+use crate::layout::types::KeymapEvent::Klong;
+
+#[test]
+fn test_parse_key() {
+    assert_eq!(parse_key("KEY_F12"), Ok(KeyCode::KEY_F12));
+    assert_eq!(parse_key("f12"), Ok(KeyCode::KEY_F12));
+    assert_eq!(parse_key("leftalt"), Ok(KeyCode::KEY_LEFTALT));
+    assert_eq!(parse_key("Ctrl"), Ok(KeyCode::KEY_LEFTCTRL));
+    assert_eq!(parse_key("a"), Ok(KeyCode::KEY_A));
+    assert_eq!(parse_key("-"), Ok(KeyCode::KEY_MINUS));
+    assert_eq!(parse_key("btn_left"), Ok(KeyCode::BTN_LEFT));
+    assert_eq!(parse_key("hyper"), Err(UnknownKey("hyper".to_string())));
+}
+
+#[test]
+fn test_key_group_from_str() {
+    assert_eq!("ctrl+shift+a".parse(), Ok(G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_LEFTSHIFT).k(KeyCode::KEY_A)));
+    assert_eq!("KEY_F12".parse(), Ok(G().k(KeyCode::KEY_F12)));
+    assert_eq!("h i".parse(), Ok(S().k(KeyCode::KEY_H).k(KeyCode::KEY_I)));
+
+    assert!("".parse::<KeyGroup>().is_err());
+    assert!("ctrl+".parse::<KeyGroup>().is_err());
+    assert!("ctrl+c ctrl+v".parse::<KeyGroup>().is_err());
+}
+
+#[test]
+fn test_key_group_display() {
+    for spelled in ["ctrl+shift+a", "f12", "h i", "alt+-"] {
+        assert_eq!(spelled.parse::<KeyGroup>().unwrap().to_string(), spelled);
+    }
+}
+
+#[test]
+fn test_key_names_in_layout() {
+    let layers = parse_layout(r#"
+        [[layers]]
+        on_active_keys = ["shift"]
+        keymap = [[["ctrl+z", "o k", ["leftalt", "tab"], { Klong = ["f12", "del"] }]]]
+    "#).unwrap();
+
+    assert_eq!(layers[0].on_active_keys, vec![KeyCode::KEY_LEFTSHIFT]);
+    assert_eq!(layers[0].keymap[0][0], vec![
+        G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_Z).p(),
+        S().k(KeyCode::KEY_O).k(KeyCode::KEY_K).p(),
+        G().k(KeyCode::KEY_LEFTALT).k(KeyCode::KEY_TAB).p(),
+        Klong(G().k(KeyCode::KEY_F12), G().k(KeyCode::KEY_DELETE)),
+    ]);
+}
@@ -0,0 +1,55 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::{LayerChangeReason, LayerSwitcher};
+use crate::layout::types::KeymapEvent::{Inh, Lactivate, Lhold};
+
+use super::testtime::TestTime;
+use super::{test_layer, test_layout, TestDevice};
+
+// B01 holds the tools layer, B02 activates it until it times out
+fn hooks_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![Lhold(1), Lactivate(1)]]).name("base"),
+        test_layer(vec![vec![Inh, Inh]])
+            .name("tools")
+            .inherits("base")
+            .timeout(Duration::from_millis(500)),
+    ])
+}
+
+#[test]
+fn test_layer_change_hook() {
+    let layout_vec = hooks_layout();
+    let (tx, rx) = mpsc::channel();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.on_layer_change(move |change| {
+        tx.send((change.layer, change.name.to_string(), change.active, change.reason)).unwrap();
+    });
+    layout.start();
+    let mut t = TestTime::start();
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![(0, "base".to_string(), true, LayerChangeReason::Host)]);
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(100));
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+        (1, "tools".to_string(), true, LayerChangeReason::Key(TestDevice::B01)),
+        (1, "tools".to_string(), false, LayerChangeReason::Key(TestDevice::B01)),
+    ]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(100));
+    layout.tick(t.advance_ms(600));
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+        (1, "tools".to_string(), true, LayerChangeReason::Key(TestDevice::B02)),
+        (1, "tools".to_string(), false, LayerChangeReason::Timeout),
+    ]);
+
+    // Activating an active layer is not a change
+    layout.activate_layer(1);
+    layout.activate_layer(1);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![
+        (1, "tools".to_string(), true, LayerChangeReason::Host),
+    ]);
+}
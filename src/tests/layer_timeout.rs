@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::parse_settings;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Lactivate, Pass};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, test_layer, test_layout, TestDevice};
+
+// B01 activates a shifted layer that times out after 500 ms into a layer
+// typing C on B02. The base layer types A on B02.
+fn timeout_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![Lactivate(1), G().k(KeyCode::KEY_A).p()]]),
+        test_layer(vec![vec![Pass, Pass]])
+            .on_active([KeyCode::KEY_LEFTSHIFT])
+            .timeout(Duration::from_millis(500))
+            .on_timeout("next"),
+        test_layer(vec![vec![Pass, G().k(KeyCode::KEY_C).p()]]).name("next"),
+    ])
+}
+
+#[test]
+fn test_layer_timeout() {
+    let layout_vec = timeout_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_eq!(layout.next_timer(), Some(t.advance_ms(500)));
+
+    layout.tick(t.now() - Duration::from_millis(100));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    // The timeout switches to the next layer, that one has no timeout
+    layout.tick(t.now());
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
+    assert_eq!(layout.get_active_layers(), vec![0, 2]);
+    assert_eq!(layout.next_timer(), None);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1000));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_C, true), (KeyCode::KEY_C, false)]);
+}
+
+#[test]
+fn test_layer_timeout_without_tick() {
+    let mut layout_vec = timeout_layout();
+    layout_vec[1].on_timeout_layer = None;
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
+
+    // The elapsed timeout is noticed by the next key event
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(600));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+}
+
+#[test]
+fn test_idle_return() {
+    let mut layout_vec = timeout_layout();
+    layout_vec[1].timeout = None;
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_idle_timeout(Some(Duration::from_secs(10)));
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
+
+    // Every key event restarts the idle timeout
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(5000));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+    assert_eq!(layout.next_timer(), Some(t.now() + Duration::from_secs(10)));
+
+    layout.tick(t.advance_ms(9000));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    layout.tick(t.advance_ms(1000));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+    assert_eq!(layout.next_timer(), None);
+}
+
+#[test]
+fn test_idle_return_with_held_key() {
+    let mut layout_vec = timeout_layout();
+    layout_vec[1].timeout = None;
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_idle_timeout(Some(Duration::from_secs(10)));
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_A, true)]);
+
+    // A key held all the time is not idle
+    layout.tick(t.advance_ms(20000));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_eq!(layout.next_timer(), None);
+}
+
+#[test]
+fn test_parse_idle_timeout() {
+    assert_eq!(parse_settings("").unwrap().idle_timeout, None);
+    let settings = parse_settings("[settings]\nidle_timeout_ms = 60000").unwrap();
+    assert_eq!(settings.idle_timeout, Some(Duration::from_secs(60)));
+}
@@ -0,0 +1,54 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Inh, Lhold, Ltoggle};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, test_layer, test_layout, TestDevice};
+
+// B01 toggles a layer typing C on B02, B03 holds it. The base layer types A on B02.
+fn toggle_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![Ltoggle(1), G().k(KeyCode::KEY_A).p(), Lhold(1)]]).name("base"),
+        test_layer(vec![vec![Inh, G().k(KeyCode::KEY_C).p(), Inh]]).inherits("base"),
+    ])
+}
+
+#[test]
+fn test_layer_toggle() {
+    let layout_vec = toggle_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    // The layer stays latched
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_C, true), (KeyCode::KEY_C, false), (KeyCode::KEY_C, true), (KeyCode::KEY_C, false)
+    ]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    assert_eq!(layout.get_active_layers(), vec![0]);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+}
+
+#[test]
+fn test_layer_toggle_latches_held_layer() {
+    let layout_vec = toggle_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B03), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B03), t.advance_ms(50));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::builtin_layout;
+use crate::layout::types::KeymapEvent::{Cooldown, Klong, Leader, Lhold};
+use crate::layout::types::{KeyCoords, KeymapEvent, LayerStatus, LeaderSequence};
+use crate::layout::keys::{G, S};
+
+#[derive(Serialize, Deserialize)]
+struct Dump {
+    layers: Vec<Layer>,
+}
+
+#[test]
+fn test_layout_round_trip() {
+    let layers = builtin_layout();
+    let dump = toml::to_string(&Dump { layers: layers.clone() }).unwrap();
+    let loaded: Dump = toml::from_str(&dump).unwrap();
+
+    assert_eq!(loaded.layers.len(), layers.len());
+    for (loaded, layer) in loaded.layers.iter().zip(&layers) {
+        assert_eq!(loaded.keymap, layer.keymap);
+        assert_eq!(loaded.on_active_keys, layer.on_active_keys);
+        assert!(loaded.status_on_reset == layer.status_on_reset);
+    }
+}
+
+#[test]
+fn test_keymap_event_round_trip() {
+    let events = vec![
+        G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_Z).m(KeyCode::KEY_LEFTSHIFT).p(),
+        S().k(KeyCode::KEY_H).k(KeyCode::KEY_I).p(),
+        Klong(G().k(KeyCode::KEY_F12), G().k(KeyCode::KEY_DELETE)),
+        Cooldown(Box::new(Lhold(2)), Duration::from_millis(500)),
+        Leader(vec![LeaderSequence { keys: vec![KeyCoords(0, 0, 1)], action: KeymapEvent::Rollback }],
+               Duration::from_secs(1)),
+    ];
+
+    #[derive(Serialize, Deserialize)]
+    struct Events {
+        events: Vec<KeymapEvent>,
+    }
+    let dump = toml::to_string(&Events { events: events.clone() }).unwrap();
+    assert!(dump.contains("KEY_LEFTCTRL"));
+    assert_eq!(toml::from_str::<Events>(&dump).unwrap().events, events);
+}
+
+#[test]
+fn test_runtime_status_not_serialized() {
+    #[derive(Serialize)]
+    struct Status {
+        status: LayerStatus,
+    }
+    assert!(toml::to_string(&Status { status: LayerStatus::LayerActive }).is_ok());
+    let held = LayerStatus::LayerHoldAndTapKey(KeyCoords(0, 0, 1), std::time::Instant::now(), 1);
+    assert!(toml::to_string(&Status { status: held }).is_err());
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::parse_layout;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::{KeyCoords, KeymapEvent, LeaderSequence};
+use crate::layout::types::KeymapEvent::{Leader, Lhold};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, test_layer, test_layout, TestDevice};
+
+fn sequence(keys: &[KeyCoords], action: KeymapEvent) -> LeaderSequence {
+    LeaderSequence { keys: keys.to_vec(), action }
+}
+
+// B01 is the leader: B02 types A, B03 B02 types B and B03 B03 holds layer 1
+fn leader_layout() -> Vec<Layer> {
+    let leader = Leader(vec![
+        sequence(&[TestDevice::B02], G().k(KeyCode::KEY_A).p()),
+        sequence(&[TestDevice::B03, TestDevice::B02], G().k(KeyCode::KEY_B).p()),
+        sequence(&[TestDevice::B03, TestDevice::B03], Lhold(1)),
+    ], Duration::from_millis(1000));
+
+    test_layout(vec![
+        test_layer(vec![
+            vec![ leader,                     G().k(KeyCode::KEY_X).p() ],
+            vec![ G().k(KeyCode::KEY_Y).p(),      G().k(KeyCode::KEY_Z).p() ],
+        ]),
+        test_layer(vec![
+            vec![ G().k(KeyCode::KEY_1).p(),      G().k(KeyCode::KEY_2).p() ],
+        ]),
+    ])
+}
+
+#[test]
+fn test_leader_single_key() {
+    let layout_vec = leader_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // The action follows the key of the sequence, held while it is held
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true)]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, false)]);
+
+    // The leader is over, the key has its own binding again
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_X, true), (KeyCode::KEY_X, false)]);
+}
+
+#[test]
+fn test_leader_two_keys() {
+    let layout_vec = leader_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+
+    // A layer held by the last key of the sequence
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B03), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_1, true), (KeyCode::KEY_1, false)]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B03), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+}
+
+#[test]
+fn test_leader_abort() {
+    let layout_vec = leader_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // An unknown sequence is swallowed
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_Z, true), (KeyCode::KEY_Z, false)]);
+
+    // An incomplete sequence times out
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(100));
+    assert_eq!(layout.next_timer(), Some(t.now() + Duration::from_millis(1000)));
+    layout.tick(t.advance_ms(1000));
+    assert_eq!(layout.next_timer(), None);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_X, true), (KeyCode::KEY_X, false)]);
+}
+
+#[test]
+fn test_parse_leader() {
+    let layout_vec = parse_layout(r#"
+        [[layers]]
+        keymap = [[[{ Leader = [[
+            { keys = [[0, 0, 1]], action = "KEY_A" },
+            { keys = [[0, 1, 0], [0, 0, 1]], action = { Cmd = "grim" } },
+        ], 800] }]]]
+    "#).unwrap();
+    assert_eq!(layout_vec[0].keymap[0][0][0], Leader(vec![
+        sequence(&[TestDevice::B02], G().k(KeyCode::KEY_A).p()),
+        sequence(&[TestDevice::B03, TestDevice::B02], KeymapEvent::Cmd("grim".to_string())),
+    ], Duration::from_millis(800)));
+
+    // The longer sequence could never be typed
+    assert!(parse_layout(r#"
+        [[layers]]
+        keymap = [[[{ Leader = [[
+            { keys = [[0, 0, 1]], action = "KEY_A" },
+            { keys = [[0, 0, 1], [0, 0, 1]], action = "KEY_B" },
+        ], 800] }]]]
+    "#).is_err());
+}
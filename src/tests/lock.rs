@@ -0,0 +1,49 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::kbd_events::lock::{InputLock, LOCK_HOLD};
+use crate::kbd_events::KeyStateChange;
+use crate::layout::serialization::parse_settings;
+use crate::layout::types::KeyCoords;
+
+use super::testtime::TestTime;
+use super::TestDevice;
+
+fn hold_chord(lock: &mut InputLock, t: &mut TestTime, ms: u64) -> bool {
+    lock.process(&KeyStateChange::Pressed(TestDevice::B01), t.now());
+    lock.process(&KeyStateChange::Pressed(TestDevice::B04), t.advance_ms(10));
+    let changed = lock.tick(t.advance_ms(ms));
+    lock.process(&KeyStateChange::Released(TestDevice::B01), t.advance_ms(10));
+    lock.process(&KeyStateChange::Released(TestDevice::B04), t.advance_ms(10));
+    changed
+}
+
+#[test]
+fn test_input_lock() {
+    let mut lock = InputLock::new(vec![TestDevice::B01, TestDevice::B04], Duration::from_millis(1000));
+    let (tx, rx) = mpsc::channel();
+    lock.on_lock_change(move |locked| tx.send(locked).unwrap());
+    let mut t = TestTime::start();
+
+    // A short press of the chord does nothing
+    assert!(!hold_chord(&mut lock, &mut t, 500));
+    assert!(!lock.is_locked());
+
+    assert!(hold_chord(&mut lock, &mut t, 1000));
+    assert!(lock.is_locked());
+
+    // The chord has to be released and held again to unlock
+    assert!(!lock.tick(t.advance_ms(2000)));
+    assert!(lock.is_locked());
+    assert!(hold_chord(&mut lock, &mut t, 1000));
+    assert!(!lock.is_locked());
+
+    assert_eq!(rx.try_iter().collect::<Vec<bool>>(), vec![true, false]);
+}
+
+#[test]
+fn test_parse_input_lock() {
+    assert_eq!(parse_settings("").unwrap().input_lock, None);
+    let settings = parse_settings("[settings]\ninput_lock = { keys = [[0, 0, 0], [0, 0, 9]] }").unwrap();
+    assert_eq!(settings.input_lock, Some((vec![KeyCoords(0, 0, 0), KeyCoords(0, 0, 9)], LOCK_HOLD)));
+}
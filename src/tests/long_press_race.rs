@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::LongPressRace;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, hold_and_tap_layered_layout, short_long_press_layout, TestDevice};
+
+#[test]
+fn test_long_press_race_hold_wins() {
+    let layout_vec = short_long_press_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // The release arrived before the LongPress event
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(300));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_1, true), (KeyCode::KEY_1, false)]);
+}
+
+#[test]
+fn test_long_press_race_tap_wins() {
+    let layout_vec = short_long_press_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_long_press_race(LongPressRace::TapWins);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // The release arrived before the LongPress event
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(300));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_0, true), (KeyCode::KEY_0, false)]);
+}
+
+#[test]
+fn test_long_press_race_tap_wins_layer() {
+    let layout_vec = hold_and_tap_layered_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_long_press_race(LongPressRace::TapWins);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // Released late, but without a LongPress
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(300));
+    assert_eq!(layout.get_active_layers(), vec![0, 2]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_2, true), (KeyCode::KEY_2, false)]);
+
+    // LongPress received, hold it was
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(10));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(250)), t.advance_ms(250));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(10));
+    assert_eq!(layout.get_active_layers(), vec![0]);
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use enumset::EnumSet;
+
+use crate::kbd_events::{ChangeDetector, KeyStateChange};
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::Ktiers;
+use crate::layout::keys::G;
+use crate::xppen_hid::XpPenButtons::XpB01;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// Single layout, tap = save, long = save as, very long = export
+fn tiers_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Ktiers(G().k(KeyCode::KEY_S), vec![
+            (Duration::from_millis(200), G().k(KeyCode::KEY_LEFTSHIFT).k(KeyCode::KEY_S)),
+            (Duration::from_millis(1000), G().k(KeyCode::KEY_E)),
+        ]) ],
+    ])
+}
+
+#[test]
+fn test_long_press_tiers() {
+    let layout_vec = tiers_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    assert_eq!(layout.get_long_press_tiers().len(), 3);
+
+    // Tap
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_S, true), (KeyCode::KEY_S, false)]);
+
+    // Long
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(300)), t.advance_ms(300));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(200));
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_S, true),
+        (KeyCode::KEY_S, false), (KeyCode::KEY_LEFTSHIFT, false)]);
+
+    // Very long, clicked as soon as the tier is reached
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(300)), t.advance_ms(300));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(1100)), t.advance_ms(800));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_E, false)]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(200));
+    assert_emitted_keys(&mut layout, vec![]);
+}
+
+#[test]
+fn test_long_press_tiers_detector() {
+    let mut detector = ChangeDetector::new();
+    detector.set_long_press_tiers([Duration::from_millis(1000), Duration::from_millis(200)]);
+    let mut t = TestTime::start();
+
+    detector.analyze(EnumSet::only(XpB01), t.now());
+    assert!(detector.has_short_pressed());
+
+    detector.tick(t.advance_ms(300));
+    assert!(detector.has_short_pressed());
+
+    detector.tick(t.advance_ms(800));
+    assert!(!detector.has_short_pressed());
+    assert!(detector.has_pressed());
+}
@@ -0,0 +1,140 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use evdev::{Device, InputEventKind};
+
+use crate::kbd_events::{ChangeDetector, KeyEvent};
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::types::KeyCoords;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::keys::G;
+use crate::virtual_keyboard::VirtualKeyboard;
+use crate::xppen_hid::report_map::ReportMap;
+use crate::xppen_hid::{XpPenButtons, XpPenResult};
+use crate::xppen_hid::XpPenButtons::XpB01;
+
+use super::testtime::TestTime;
+use super::{report, single_layer};
+
+/// How long to wait for the virtual device and its events to show up
+const LOOPBACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn loopback_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_B).p() ],
+    ])
+}
+
+/// Find the evdev node of our own virtual device and forward its key events
+fn observe(name: &str) -> mpsc::Receiver<(KeyCode, i32)> {
+    let start = Instant::now();
+    let mut device = loop {
+        let found = evdev::enumerate()
+            .map(|(_, d)| d)
+            .find(|d: &Device| d.name() == Some(name));
+        if let Some(d) = found {
+            break d;
+        }
+        assert!(start.elapsed() < LOOPBACK_TIMEOUT, "Virtual device {} did not appear", name);
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    let (tx, rx) = mpsc::channel();
+    // The thread ends once the virtual device is destroyed
+    thread::spawn(move || loop {
+        let Ok(events) = device.fetch_events() else {
+            return;
+        };
+        for ev in events {
+            if let InputEventKind::Key(k) = ev.kind() {
+                if tx.send((k.into(), ev.value())).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uinput and read access to /dev/input"]
+fn test_loopback() {
+    let layout_vec = loopback_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+
+    let mut kbd = VirtualKeyboard::output("Loopback test", layout.get_used_keys()).unwrap();
+    let observed = observe("XP-Pen ACK05 driver Loopback test");
+
+    // Hold B01 and tap B02 within it
+    let mut detector = ChangeDetector::<XpPenButtons>::new();
+    let mut t = TestTime::start();
+    let reports = [
+        report(&[XpB01]),
+        report(&[XpB01, XpPenButtons::XpB02]),
+        report(&[XpB01]),
+        report(&[]),
+    ];
+
+    let mut rendered = Vec::new();
+    for buf in reports {
+        let XpPenResult::Keys(keys) = ReportMap::ack05().parse(&buf) else {
+            panic!("Report not recognized");
+        };
+        detector.analyze(keys, t.advance_ms(50));
+        for ev in detector.drain() {
+            let KeyEvent { change: ev, t }: KeyEvent<KeyCoords> = ev.map(Into::into);
+            layout.process_keyevent(ev, t);
+            layout.render(|k, v| {
+                kbd.emit_key(k, v).unwrap();
+                rendered.push((k, v as i32));
+            });
+        }
+    }
+
+    let expected = vec![
+        (KeyCode::KEY_LEFTSHIFT, 1),
+        (KeyCode::KEY_B, 1),
+        (KeyCode::KEY_B, 0),
+        (KeyCode::KEY_LEFTSHIFT, 0),
+    ];
+    assert_eq!(rendered, expected);
+
+    let mut received = Vec::new();
+    while received.len() < expected.len() {
+        match observed.recv_timeout(LOOPBACK_TIMEOUT) {
+            Ok(ev) => received.push(ev),
+            Err(_) => break,
+        }
+    }
+    assert_eq!(received, expected);
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uinput and read access to /dev/input"]
+fn test_release_on_drop() {
+    let keys = [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_A];
+    let mut kbd = VirtualKeyboard::output("Drop test", keys).unwrap();
+    let observed = observe("XP-Pen ACK05 driver Drop test");
+
+    // The process goes away in the middle of a hold, eg. it panicked
+    kbd.emit_keys(&[(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_A, true)]).unwrap();
+    drop(kbd);
+
+    let expected = vec![
+        (KeyCode::KEY_LEFTSHIFT, 1),
+        (KeyCode::KEY_A, 1),
+        (KeyCode::KEY_A, 0),
+        (KeyCode::KEY_LEFTSHIFT, 0),
+    ];
+    let mut received = Vec::new();
+    while received.len() < expected.len() {
+        match observed.recv_timeout(LOOPBACK_TIMEOUT) {
+            Ok(ev) => received.push(ev),
+            Err(_) => break,
+        }
+    }
+    assert_eq!(received, expected);
+}
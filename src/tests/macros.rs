@@ -0,0 +1,266 @@
+use std::fs;
+
+use std::collections::BTreeSet;
+
+use crate::kbd_events::KeyStateChange;
+use crate::keycodes::KeyCode;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Mcancel, Mplay, Mrec};
+use crate::layout::serialization::{parse_layout, parse_macros};
+use crate::layout::types::{KeyCoords, LayerStatus};
+use crate::macros::{Macro, MacroLibrary, MacroRepeat, MacroStep};
+
+use super::backup::scratch_dir;
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice, DEFAULT_LAYER_CONFIG};
+
+// Single layout, B01 plays the macro, B02 records it, B03 cancels it
+fn macro_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Mplay("hello".to_string()), Mrec("hello".to_string()) ],
+        vec![ Mcancel ],
+    ])
+}
+
+fn step(key: KeyCode, pressed: bool) -> MacroStep {
+    MacroStep::Key { key, pressed, delay_ms: 0 }
+}
+
+fn delayed(key: KeyCode, pressed: bool, delay_ms: u64) -> MacroStep {
+    MacroStep::Key { key, pressed, delay_ms }
+}
+
+#[test]
+fn test_macro_play() {
+    let layout_vec = macro_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // Unknown macro does nothing
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // Keys left pressed by the macro are released
+    layout.store_macro(Macro::new("hello", vec![
+        step(KeyCode::KEY_LEFTSHIFT, true), step(KeyCode::KEY_H, true), step(KeyCode::KEY_H, false),
+    ]));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_H, true), (KeyCode::KEY_H, false), (KeyCode::KEY_LEFTSHIFT, false),
+    ]);
+}
+
+#[test]
+fn test_macro_record_toggle() {
+    let layout_vec = macro_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    assert_eq!(layout.recording(), None);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_eq!(layout.recording(), Some("hello"));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_eq!(layout.recording(), None);
+
+    // Anything can be recorded, so the whole keyboard is registered
+    assert!(layout.get_used_keys().contains(&KeyCode::KEY_Z));
+}
+
+#[test]
+fn test_macro_library_file() {
+    let mut library = MacroLibrary::default();
+    library.insert(Macro::new("a", vec![step(KeyCode::KEY_A, true)]));
+    library.insert(Macro::new("a", vec![step(KeyCode::KEY_B, true), step(KeyCode::KEY_B, false)]));
+    assert_eq!(library.macros.len(), 1);
+
+    let source = toml::to_string(&library).unwrap();
+    assert!(source.contains("KEY_B"));
+    assert_eq!(toml::from_str::<MacroLibrary>(&source).unwrap(), library);
+}
+
+#[test]
+fn test_macro_speed_and_repeat() {
+    let layout_vec = macro_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    let mut m = Macro::new("hello", vec![delayed(KeyCode::KEY_H, true, 0), delayed(KeyCode::KEY_H, false, 100)]);
+    m.speed = 2.0;
+    m.repeat = MacroRepeat::Times(2);
+    layout.store_macro(m);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, true)]);
+
+    // Twice as fast, the release is due after 50 ms
+    layout.tick(t.advance_ms(40));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.tick(t.advance_ms(10));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, false), (KeyCode::KEY_H, true)]);
+    layout.tick(t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, false)]);
+    assert!(layout.next_macro_step().is_none());
+}
+
+#[test]
+fn test_macro_while_held() {
+    let layout_vec = macro_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    let mut m = Macro::new("hello", vec![delayed(KeyCode::KEY_H, true, 100), delayed(KeyCode::KEY_H, false, 0)]);
+    m.repeat = MacroRepeat::WhileHeld;
+    layout.store_macro(m);
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    for _ in 0..3 {
+        layout.tick(t.advance_ms(100));
+        assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, true), (KeyCode::KEY_H, false)]);
+    }
+
+    // The running iteration is finished after the release
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    layout.tick(t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, true), (KeyCode::KEY_H, false)]);
+    layout.tick(t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+    assert!(layout.next_macro_step().is_none());
+}
+
+#[test]
+fn test_macro_cancel() {
+    let layout_vec = macro_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.store_macro(Macro::new("hello", vec![
+        step(KeyCode::KEY_LEFTSHIFT, true), step(KeyCode::KEY_H, true), delayed(KeyCode::KEY_H, false, 1000),
+    ]));
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_H, true)]);
+
+    // Cancel releases the held keys immediately
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, false), (KeyCode::KEY_LEFTSHIFT, false)]);
+    layout.tick(t.advance_ms(1000));
+    assert_emitted_keys(&mut layout, vec![]);
+}
+
+#[test]
+fn test_macro_library_references() {
+    let layout_vec = vec![Layer {
+        keymap: vec![vec![vec![Mplay("hello".to_string()), Mplay("shared".to_string()), Mplay("typo".to_string())]]],
+        ..DEFAULT_LAYER_CONFIG
+    }, Layer {
+        keymap: vec![vec![vec![Mrec("hello".to_string())]]],
+        ..DEFAULT_LAYER_CONFIG
+    }];
+
+    let mut library = MacroLibrary::default();
+    library.insert(Macro::new("shared", vec![step(KeyCode::KEY_A, true)]));
+
+    // The layout section overrides the shared macro
+    library.merge(parse_macros(r#"
+        [[macros]]
+        name = "shared"
+        steps = [{ key = "KEY_B", pressed = true }, { key = "KEY_B", pressed = false, delay_ms = 10 }]
+    "#).unwrap());
+    assert_eq!(library.get("shared").unwrap().steps.len(), 2);
+
+    // "hello" is recorded by the second layer
+    assert_eq!(library.missing_macros(&layout_vec), vec![(0, KeyCoords(0, 0, 2), "typo".to_string())]);
+}
+
+#[test]
+fn test_macro_step_language() {
+    let layout_vec = vec![Layer {
+        keymap: vec![vec![vec![Mplay("steps".to_string())]]],
+        ..DEFAULT_LAYER_CONFIG
+    }, Layer {
+        status_on_reset: LayerStatus::LayerPassthrough,
+        ..DEFAULT_LAYER_CONFIG
+    }];
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    let library = parse_macros(r#"
+        [[macros]]
+        name = "steps"
+        steps = [
+            { wait_key = "KEY_LEFTSHIFT", pressed = false },
+            { if_layer = 1, then = [{ key = "KEY_A", pressed = true }], else = [{ key = "KEY_B", pressed = true }] },
+            { delay_ms = 100 },
+            { layer = 1, active = true },
+            { if_layer = 1, then = [{ key = "KEY_C", pressed = true }] },
+        ]
+    "#).unwrap();
+    layout.set_macros(library);
+    assert!(layout.get_used_keys().contains(&KeyCode::KEY_C));
+
+    // Wait for the user to release the modifier on the real keyboard
+    let mut shift = BTreeSet::new();
+    shift.insert(KeyCode::KEY_LEFTSHIFT);
+    layout.set_host_keys(&shift);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.tick(t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    layout.set_host_keys(&BTreeSet::new());
+    layout.tick(t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true)]);
+
+    // The layer is switched by the macro after the delay, keys still held are released at the end
+    layout.tick(t.advance_ms(100));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_C, true), (KeyCode::KEY_C, false), (KeyCode::KEY_B, false)]);
+    assert!(layout.next_macro_step().is_none());
+}
+
+#[test]
+fn test_macro_release_all() {
+    let layout_vec = macro_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    layout.store_macro(Macro::new("hello", vec![step(KeyCode::KEY_H, true), delayed(KeyCode::KEY_H, false, 1000)]));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, true)]);
+
+    // The panic chord releases the keys held by a running macro too
+    layout.release_all();
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_H, false)]);
+    assert!(layout.next_macro_step().is_none());
+}
+
+#[test]
+fn test_macro_saved_to_layout() {
+    let path = scratch_dir("macro-layout").join("layout.toml");
+    fs::write(&path, r#"
+# Painting
+[[layers]]
+keymap = [[[{ Mrec = "hello" }, { Mplay = "hello" }]]]
+"#).unwrap();
+
+    Macro::new("hello", vec![step(KeyCode::KEY_H, true)]).save_to_layout(&path).unwrap();
+    Macro::new("bye", vec![step(KeyCode::KEY_B, true)]).save_to_layout(&path).unwrap();
+    // A new recording replaces the macro of the same name
+    Macro::new("hello", vec![step(KeyCode::KEY_H, true), delayed(KeyCode::KEY_H, false, 50)]).save_to_layout(&path).unwrap();
+
+    let source = fs::read_to_string(&path).unwrap();
+    assert!(source.contains("# Painting"));
+    assert_eq!(parse_layout(&source).unwrap().len(), 1);
+    let library = parse_macros(&source).unwrap();
+    assert_eq!(library.macros.len(), 2);
+    assert_eq!(library.get("hello").unwrap().steps, vec![step(KeyCode::KEY_H, true), delayed(KeyCode::KEY_H, false, 50)]);
+    assert_eq!(library.get("bye").unwrap().steps, vec![step(KeyCode::KEY_B, true)]);
+}
@@ -0,0 +1,69 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::keys::parse_key;
+use crate::layout::serialization::{parse_layout, parse_settings};
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeyCoords;
+use crate::virtual_keyboard::scancodes::hid_scancode;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, TestDevice};
+
+const WHEEL_CCW: KeyCoords = KeyCoords(1, 0, 0);
+const WHEEL_CW: KeyCoords = KeyCoords(1, 0, 1);
+
+#[test]
+fn test_volume_knob() {
+    let layout_vec = parse_layout(r#"
+        [[layers]]
+        keymap = [
+            [["play", "mute", "KEY_BRIGHTNESSUP"]],
+            [["voldown", "volup"]],
+        ]
+    "#).unwrap();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // The media keys are registered to the OS, assert_emitted_keys checks that
+    let used = layout.get_used_keys();
+    for k in [KeyCode::KEY_PLAYPAUSE, KeyCode::KEY_MUTE, KeyCode::KEY_VOLUMEUP, KeyCode::KEY_VOLUMEDOWN] {
+        assert!(used.contains(&k), "{:?} is not registered", k);
+    }
+
+    layout.process_keyevent(KeyStateChange::Click(WHEEL_CW), t);
+    layout.process_keyevent(KeyStateChange::Click(WHEEL_CW), t);
+    layout.process_keyevent(KeyStateChange::Click(WHEEL_CCW), t);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_VOLUMEUP, true), (KeyCode::KEY_VOLUMEUP, false),
+        (KeyCode::KEY_VOLUMEUP, true), (KeyCode::KEY_VOLUMEUP, false),
+        (KeyCode::KEY_VOLUMEDOWN, true), (KeyCode::KEY_VOLUMEDOWN, false),
+    ]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(KeyCoords(0, 0, 2)), t);
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_PLAYPAUSE, true), (KeyCode::KEY_PLAYPAUSE, false),
+        (KeyCode::KEY_BRIGHTNESSUP, true), (KeyCode::KEY_BRIGHTNESSUP, false),
+    ]);
+}
+
+#[test]
+fn test_media_key_names() {
+    assert_eq!(parse_key("volup"), Ok(KeyCode::KEY_VOLUMEUP));
+    assert_eq!(parse_key("volumedown"), Ok(KeyCode::KEY_VOLUMEDOWN));
+    assert_eq!(parse_key("prev"), Ok(KeyCode::KEY_PREVIOUSSONG));
+    assert_eq!(parse_key("KEY_BRIGHTNESSDOWN"), Ok(KeyCode::KEY_BRIGHTNESSDOWN));
+}
+
+#[test]
+fn test_consumer_scancodes() {
+    assert_eq!(hid_scancode(KeyCode::KEY_VOLUMEUP), Some(0xc00e9));
+    assert_eq!(hid_scancode(KeyCode::KEY_MUTE), Some(0xc00e2));
+    assert_eq!(hid_scancode(KeyCode::KEY_BRIGHTNESSDOWN), Some(0xc0070));
+    assert_eq!(hid_scancode(KeyCode::KEY_HOMEPAGE), Some(0xc0223));
+
+    assert!(!parse_settings("").unwrap().scancodes);
+    assert!(parse_settings("[settings]\nscancodes = true").unwrap().scancodes);
+}
@@ -1,11 +1,14 @@
-use evdev::Key;
+use std::time::Duration;
 
 use crate::kbd_events::KeyStateChange;
+use crate::keycodes::KeyCode;
+use crate::layout::builder::{LayerBuilder, LayoutBuilder};
 use crate::layout::layer::Layer;
-use crate::layout::types::KeyCoords;
+use crate::layout::types::{KeyCoords, KeymapEvent};
 use crate::layout::switcher::LayerSwitcher;
 use crate::layout::types::KeymapEvent::{Kg, No, Lhold, Inh, Ltap, Lactivate, Pass, LhtK, LhtL, Klong, Khl, Khtl, Ldeactivate};
 use crate::layout::keys::{G, S};
+use crate::xppen_hid::XpPenButtons;
 
 use self::testtime::TestTime;
 
@@ -20,18 +23,51 @@ impl TestDevice {
 }
 
 const DEFAULT_LAYER_CONFIG: Layer = Layer{
+    name: String::new(),
     status_on_reset: crate::layout::types::LayerStatus::LayerActive,
     inherit: None,
     on_active_keys: vec![],
     disable_active_on_press: false,
     on_timeout_layer: None,
     timeout: None,
+    condition: None,
+    activation_debounce: None,
+    output: None,
     keymap: vec![],
     default_action: crate::layout::types::KeymapEvent::Pass,
 };
 
+/// A layout of a single active layer, the rows of keys of the first block
+fn single_layer(rows: Vec<Vec<KeymapEvent>>) -> Vec<Layer> {
+    vec![Layer{
+        keymap: vec![rows],
+        ..DEFAULT_LAYER_CONFIG
+    }]
+}
+
+/// A layer of the rows of keys of the first block, for the layouts
+/// of more layers
+fn test_layer(rows: Vec<Vec<KeymapEvent>>) -> LayerBuilder {
+    let mut layer = LayerBuilder::new();
+    for (r, row) in rows.into_iter().enumerate() {
+        for (c, ev) in row.into_iter().enumerate() {
+            layer = layer.key(KeyCoords(0, r as u8, c as u8), ev);
+        }
+    }
+    layer
+}
+
+/// The layout of the test layers, the first one is active and the others
+/// are passthrough unless configured
+fn test_layout(layers: Vec<LayerBuilder>) -> Vec<Layer> {
+    layers.into_iter()
+        .fold(LayoutBuilder::new(), LayoutBuilder::layer)
+        .build()
+        .expect("The test layout refers to an unknown layer")
+}
+
 #[track_caller]
-fn assert_emitted_keys(layout: &mut LayerSwitcher, keys: Vec<(Key, bool)>) {
+fn assert_emitted_keys(layout: &mut LayerSwitcher, keys: Vec<(KeyCode, bool)>) {
     let mut received = Vec::new();
 
     // Compute all registered keys. This is done every time instead of once,
@@ -57,12 +93,25 @@ fn assert_emitted_keys(layout: &mut LayerSwitcher, keys: Vec<(Key, bool)>) {
     assert_eq!(idx, keys.len(), "Expected {} key presses. Got only {}.", keys.len(), idx);
 }
 
+/// Raw report of the one bit per key protocol
+fn report(buttons: &[XpPenButtons]) -> [u8; 8] {
+    let mut buf = [0x02, 240, 0, 0, 0, 0, 0, 0];
+    for b in buttons {
+        match *b as u8 {
+            idx @ 0..=7 => buf[2] |= 1 << idx,
+            idx @ 8..=9 => buf[3] |= 1 << (idx - 8),
+            idx => buf[7] |= 1 << (idx - 10),
+        }
+    }
+    buf
+}
+
 // Single layer, basic key press and release test
 fn basic_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ G().k(Key::KEY_LEFTALT).p(),   G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), No,           ],
+            vec![ G().k(KeyCode::KEY_LEFTALT).p(),   G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), No,           ],
         ],
     ];
 
@@ -77,6 +126,75 @@ fn basic_layout() -> Vec<Layer> {
 }
 
 mod testtime;
+mod geometry;
+mod conditions;
+mod debounce;
+mod change_detector;
+mod long_press_race;
+mod long_press_tiers;
+mod release_all;
+mod gestures;
+mod audio_feedback;
+mod speech_feedback;
+mod scanning;
+mod morse;
+mod multiplier;
+mod macros;
+mod panic;
+mod cooldown;
+mod passthrough;
+mod turbo;
+mod gamepad;
+mod dial;
+mod outputs;
+mod scancodes;
+#[cfg(target_os = "linux")]
+mod loopback;
+mod faults;
+mod report_map;
+mod watchdog;
+mod repl;
+mod stats;
+mod backup;
+mod serialization;
+mod keys;
+mod file_watcher;
+mod validation;
+mod layer_timeout;
+mod oneshot;
+mod layer_toggle;
+mod pointer;
+mod key_repeat;
+mod button_device;
+#[cfg(target_os = "linux")]
+mod systemd;
+mod dbus_control;
+mod focus_watcher;
+mod desktop_notifications;
+mod shell_command;
+mod leader;
+mod tap_dance;
+mod chords;
+mod media_keys;
+mod caps_word;
+mod hold_threshold;
+mod tap_hold;
+mod conditional;
+mod layer_hooks;
+mod builder;
+mod layout_serde;
+mod shared_layout;
+mod frames;
+mod clock;
+mod acceleration;
+mod divider;
+mod lock;
+mod resume;
+mod firmware;
+mod evdev_input;
+mod keycodes;
+#[cfg(all(feature = "tokio", target_os = "linux"))]
+mod async_frontend;
 
 #[test]
 fn test_basic_layout() {
@@ -89,17 +207,17 @@ fn test_basic_layout() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTALT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTALT, true)]);
 
     // Test that long press will not break the key flow
-    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01), t.advance_ms(500));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(500)), t.advance_ms(500));
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTALT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTALT, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t.advance_ms(10));
     assert_emitted_keys(&mut layout, vec![]);
@@ -110,22 +228,22 @@ fn test_basic_layout() {
 fn basic_layered_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ Lhold(1),              G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), No,           ],
+            vec![ Lhold(1),              G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ G().k(Key::KEY_0).p(), Pass,          ],
-            vec![ Inh          , G().k(Key::KEY_E).p(), ],
+            vec![ G().k(KeyCode::KEY_0).p(), Pass,          ],
+            vec![ Inh          , G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
     let keymap_inh = vec![ // blocks
         vec![ // rows
-            vec![ G().k(Key::KEY_1).p(), G().k(Key::KEY_9).p(), ],
-            vec![ G().k(Key::KEY_2).p(), G().k(Key::KEY_3).p(), ],
+            vec![ G().k(KeyCode::KEY_1).p(), G().k(KeyCode::KEY_9).p(), ],
+            vec![ G().k(KeyCode::KEY_2).p(), G().k(KeyCode::KEY_3).p(), ],
         ],
     ];
 
@@ -137,7 +255,7 @@ fn basic_layered_layout() -> Vec<Layer> {
     let shift_layer = Layer{
         status_on_reset: crate::layout::types::LayerStatus::LayerPassthrough,
         inherit: Some(2),
-        on_active_keys: vec![Key::KEY_LEFTSHIFT],
+        on_active_keys: vec![KeyCode::KEY_LEFTSHIFT],
         keymap: keymap_shift,
         ..DEFAULT_LAYER_CONFIG
     };
@@ -164,23 +282,23 @@ fn test_basic_layered_layout() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     // Test that long press will not break the layer switch flow
-    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01), t.advance_ms(500));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(500)), t.advance_ms(500));
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true), (Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_E, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_2, true), (Key::KEY_2, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_2, true), (KeyCode::KEY_2, false)]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
     assert_emitted_keys(&mut layout, vec![]);
@@ -196,19 +314,19 @@ fn test_basic_layered_layout_cross_release() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true),]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true),]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
     assert_emitted_keys(&mut layout, vec![]);
@@ -218,15 +336,15 @@ fn test_basic_layered_layout_cross_release() {
 fn tap_layered_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ Ltap(1),               G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), No,           ],
+            vec![ Ltap(1),               G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
             vec![ No,                    Inh,           ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), G().k(Key::KEY_E).p(), ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
@@ -237,7 +355,7 @@ fn tap_layered_layout() -> Vec<Layer> {
 
     let shift_layer = Layer{
         status_on_reset: crate::layout::types::LayerStatus::LayerPassthrough,
-        on_active_keys: vec![Key::KEY_LEFTSHIFT],
+        on_active_keys: vec![KeyCode::KEY_LEFTSHIFT],
         keymap: keymap_shift,
         ..DEFAULT_LAYER_CONFIG
     };
@@ -257,12 +375,12 @@ fn test_tap_layered_layout() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_LEFTSHIFT, false), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_B, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
@@ -283,12 +401,12 @@ fn test_tap_layered_hold() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false) ]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false) ]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -298,7 +416,7 @@ fn test_tap_layered_hold() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true), (Key::KEY_LEFTSHIFT, false), (Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_E, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
     assert_emitted_keys(&mut layout, vec![]);
@@ -314,12 +432,12 @@ fn test_tap_layered_hold_crossed() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true) ]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true) ]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -329,17 +447,17 @@ fn test_tap_layered_hold_crossed() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, false) ]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, false) ]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true), (Key::KEY_LEFTSHIFT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_LEFTSHIFT, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, false)]);
 }
 
 #[test]
@@ -352,12 +470,12 @@ fn test_tap_layered_hold_dual_crossed() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true) ]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true) ]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -367,17 +485,17 @@ fn test_tap_layered_hold_dual_crossed() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true), (Key::KEY_LEFTSHIFT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_LEFTSHIFT, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, false) ]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, false) ]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, false)]);
 }
 
 #[test]
@@ -390,12 +508,12 @@ fn test_tap_layered_hold_dual_crossed_lifo() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true) ]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true) ]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -405,17 +523,17 @@ fn test_tap_layered_hold_dual_crossed_lifo() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true), (Key::KEY_LEFTSHIFT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_LEFTSHIFT, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, false) ]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, false) ]);
 }
 
 // Dual layout, basic test simulating Shift behavior (hold to stay in the second layer),
@@ -423,15 +541,15 @@ fn test_tap_layered_hold_dual_crossed_lifo() {
 fn layered_layout_with_masked_key() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ Lhold(1),              G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), No,           ],
+            vec![ Lhold(1),              G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ G().k(Key::KEY_0).p(),         Inh,           ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), S().m(Key::KEY_LEFTSHIFT).k(Key::KEY_E).p(), ],
+            vec![ G().k(KeyCode::KEY_0).p(),         Inh,           ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), S().m(KeyCode::KEY_LEFTSHIFT).k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
@@ -442,7 +560,7 @@ fn layered_layout_with_masked_key() -> Vec<Layer> {
 
     let shift_layer = Layer{
         status_on_reset: crate::layout::types::LayerStatus::LayerPassthrough,
-        on_active_keys: vec![Key::KEY_LEFTSHIFT],
+        on_active_keys: vec![KeyCode::KEY_LEFTSHIFT],
         keymap: keymap_shift,
         ..DEFAULT_LAYER_CONFIG
     };
@@ -462,17 +580,17 @@ fn test_layered_layout_w_masked_key() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     // This temporarily masks the Shift key
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, false), (Key::KEY_E, true), (Key::KEY_E, false), (Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_E, true), (KeyCode::KEY_E, false), (KeyCode::KEY_LEFTSHIFT, true)]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
     assert_emitted_keys(&mut layout, vec![]);
@@ -484,15 +602,15 @@ fn test_layered_layout_w_masked_key() {
 fn layered_layout_with_mask() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ Lhold(1),              G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), No,           ],
+            vec![ Lhold(1),              G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ G().k(Key::KEY_0).p(),         Inh,           ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), G().k(Key::KEY_E).p(), ],
+            vec![ G().k(KeyCode::KEY_0).p(),         Inh,           ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
@@ -503,7 +621,7 @@ fn layered_layout_with_mask() -> Vec<Layer> {
 
     let shift_layer = Layer{
         status_on_reset: crate::layout::types::LayerStatus::LayerPassthrough,
-        on_active_keys: vec![Key::KEY_LEFTSHIFT],
+        on_active_keys: vec![KeyCode::KEY_LEFTSHIFT],
         disable_active_on_press: true,
         keymap: keymap_shift,
         ..DEFAULT_LAYER_CONFIG
@@ -525,20 +643,20 @@ fn test_layered_layout_w_mask() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     // This temporarily masks the Shift key
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, false), (Key::KEY_E, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_E, true)]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, false), (Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, false), (KeyCode::KEY_LEFTSHIFT, true)]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
     assert_emitted_keys(&mut layout, vec![]);
@@ -554,20 +672,20 @@ fn test_layered_layout_w_mask_crossed() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(1));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     // This temporarily masks the Shift key
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTSHIFT, false), (Key::KEY_E, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_E, true)]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, false)]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
     assert_emitted_keys(&mut layout, vec![]);
@@ -577,22 +695,22 @@ fn test_layered_layout_w_mask_crossed() {
 fn hold_and_tap_layered_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ LhtL(1, 2),            G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), No,           ],
+            vec![ LhtL(1, 2),            G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ No,                    G().k(Key::KEY_T).p(),           ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), G().k(Key::KEY_E).p(), ],
+            vec![ No,                    G().k(KeyCode::KEY_T).p(),           ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
     let keymap_tap = vec![ // blocks
         vec![ // rows
-            vec![ No,            G().k(Key::KEY_3).p(), ],
-            vec![ G().k(Key::KEY_1).p(), G().k(Key::KEY_2).p(), ],
+            vec![ No,            G().k(KeyCode::KEY_3).p(), ],
+            vec![ G().k(KeyCode::KEY_1).p(), G().k(KeyCode::KEY_2).p(), ],
         ],
     ];
 
@@ -633,7 +751,7 @@ fn test_hold_and_tap_layered_layout() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_T, true), (Key::KEY_T, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -644,7 +762,7 @@ fn test_hold_and_tap_layered_layout() {
     assert_eq!(layout.get_active_layers(), vec![0, 2]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_2, true), (Key::KEY_2, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_2, true), (KeyCode::KEY_2, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
@@ -667,7 +785,7 @@ fn test_hold_and_tap_layered_layout_long_press() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_T, true), (Key::KEY_T, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -685,15 +803,15 @@ fn test_hold_and_tap_layered_layout_long_press() {
 fn hold_and_tap_key_layered_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ LhtK(1, G().k(Key::KEY_0)),   G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), No,           ],
+            vec![ LhtK(1, G().k(KeyCode::KEY_0)),   G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ No,                    G().k(Key::KEY_T).p(), ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), G().k(Key::KEY_E).p(), ],
+            vec![ No,                    G().k(KeyCode::KEY_T).p(), ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
@@ -705,7 +823,7 @@ fn hold_and_tap_key_layered_layout() -> Vec<Layer> {
     let shift_layer = Layer{
         status_on_reset: crate::layout::types::LayerStatus::LayerPassthrough,
         keymap: keymap_shift,
-        on_active_keys: vec![Key::KEY_4],
+        on_active_keys: vec![KeyCode::KEY_4],
         ..DEFAULT_LAYER_CONFIG
     };
 
@@ -724,18 +842,18 @@ fn test_hold_and_tap_key_layered_layout() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_4, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_4, true)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_T, true), (Key::KEY_T, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     // Time was short enough for tap key
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(190));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_4, false), (Key::KEY_0, true), (Key::KEY_0, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_4, false), (KeyCode::KEY_0, true), (KeyCode::KEY_0, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
@@ -753,18 +871,18 @@ fn test_hold_and_tap_key_layered_layout_long_press() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_4, true)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_4, true)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_T, true), (Key::KEY_T, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     // Time was too long for a tap key
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(220));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_4, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_4, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
@@ -776,15 +894,15 @@ fn test_hold_and_tap_key_layered_layout_long_press() {
 fn hold_and_tap_keygroup_layered_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ LhtK(1, G().k(Key::KEY_LEFTALT).k(Key::KEY_0)),   G().k(Key::KEY_B).p(), ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(),                          No,           ],
+            vec![ LhtK(1, G().k(KeyCode::KEY_LEFTALT).k(KeyCode::KEY_0)),   G().k(KeyCode::KEY_B).p(), ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(),                          No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ No,                    G().k(Key::KEY_T).p(), ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), G().k(Key::KEY_E).p(), ],
+            vec![ No,                    G().k(KeyCode::KEY_T).p(), ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
@@ -819,13 +937,13 @@ fn test_hold_and_tap_keygroup_layered_layout() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_T, true), (Key::KEY_T, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     // Time was short enough for tap key
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(190));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_LEFTALT, true), (Key::KEY_0, true), (Key::KEY_0, false), (Key::KEY_LEFTALT, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTALT, true), (KeyCode::KEY_0, true), (KeyCode::KEY_0, false), (KeyCode::KEY_LEFTALT, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
@@ -848,7 +966,7 @@ fn test_hold_and_tap_keygroup_layered_layout_long_press() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_T, true), (Key::KEY_T, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -866,8 +984,8 @@ fn test_hold_and_tap_keygroup_layered_layout_long_press() {
 fn short_long_press_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ Klong(G().k(Key::KEY_0), G().k(Key::KEY_1)),   G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(),           No,           ],
+            vec![ Klong(G().k(KeyCode::KEY_0), G().k(KeyCode::KEY_1)),   G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(),           No,           ],
         ],
     ];
 
@@ -894,7 +1012,7 @@ fn test_short_long_press_layout() {
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(200));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_0, true), (Key::KEY_0, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_0, true), (KeyCode::KEY_0, false)]);
 
     layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(100));
     assert_emitted_keys(&mut layout, vec![]);
@@ -903,14 +1021,14 @@ fn test_short_long_press_layout() {
     // on the state analyzer sending a LongPress event.
 
     // First long press is not long enough to be detected as long
-    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(100)), t.advance_ms(100));
     assert_emitted_keys(&mut layout, vec![]);
 
-    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01), t.advance_ms(500));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_1, true), (Key::KEY_1, false)]);
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(600)), t.advance_ms(500));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_1, true), (KeyCode::KEY_1, false)]);
 
     // LongPress might arrive multiple times, additional events should do nothing
-    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01), t.advance_ms(500));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(1100)), t.advance_ms(500));
     assert_emitted_keys(&mut layout, vec![]);
 
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(200));
@@ -921,15 +1039,15 @@ fn test_short_long_press_layout() {
 fn short_key_long_layer_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ Khl(G().k(Key::KEY_0), 1),   G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(),                          No,           ],
+            vec![ Khl(G().k(KeyCode::KEY_0), 1),   G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(),                          No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ No,             G().k(Key::KEY_T).p(), ],
-            vec![ Ldeactivate(1), G().k(Key::KEY_E).p(), ],
+            vec![ No,             G().k(KeyCode::KEY_T).p(), ],
+            vec![ Ldeactivate(1), G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
@@ -964,13 +1082,13 @@ fn test_short_key_long_layer_layout() {
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     // Time was short enough for tap key
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(190));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_0, true), (Key::KEY_0, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_0, true), (KeyCode::KEY_0, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
@@ -993,12 +1111,12 @@ fn test_short_key_long_layer_layout_long_press() {
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     // Time was too long for a tap key
-    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01), t.advance_ms(220));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(220)), t.advance_ms(220));
     assert_emitted_keys(&mut layout, vec![]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
@@ -1009,7 +1127,7 @@ fn test_short_key_long_layer_layout_long_press() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true), (Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_E, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
@@ -1023,15 +1141,15 @@ fn test_short_key_long_layer_layout_long_press() {
 fn short_key_long_tap_layer_layout() -> Vec<Layer> {
     let keymap_default = vec![ // blocks
         vec![ // rows
-            vec![ Khtl(G().k(Key::KEY_0), 1),   G().k(Key::KEY_B).p() ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(),                          No,           ],
+            vec![ Khtl(G().k(KeyCode::KEY_0), 1),   G().k(KeyCode::KEY_B).p() ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(),                          No,           ],
         ],
     ];
 
     let keymap_shift = vec![ // blocks
         vec![ // rows
-            vec![ No,                    G().k(Key::KEY_T).p(), ],
-            vec![ G().k(Key::KEY_LEFTSHIFT).p(), G().k(Key::KEY_E).p(), ],
+            vec![ No,                    G().k(KeyCode::KEY_T).p(), ],
+            vec![ G().k(KeyCode::KEY_LEFTSHIFT).p(), G().k(KeyCode::KEY_E).p(), ],
         ],
     ];
 
@@ -1066,13 +1184,13 @@ fn test_short_key_long_tap_layer_layout() {
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     // Time was short enough for tap key
     layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(190));
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_0, true), (Key::KEY_0, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_0, true), (KeyCode::KEY_0, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
@@ -1095,12 +1213,12 @@ fn test_short_key_long_tap_layer_layout_long_press() {
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_B, true), (Key::KEY_B, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
     // Time was too long for a tap key
-    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01), t.advance_ms(220));
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(220)), t.advance_ms(220));
     assert_emitted_keys(&mut layout, vec![]);
 
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
@@ -1111,7 +1229,7 @@ fn test_short_key_long_tap_layer_layout_long_press() {
     assert_eq!(layout.get_active_layers(), vec![0, 1]);
 
     layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t);
-    assert_emitted_keys(&mut layout, vec![(Key::KEY_E, true), (Key::KEY_E, false)]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_E, true), (KeyCode::KEY_E, false)]);
 
     assert_eq!(layout.get_active_layers(), vec![0]);
 
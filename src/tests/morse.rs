@@ -0,0 +1,52 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::morse::MorseDecoder;
+use crate::kbd_events::KeyStateChange;
+use crate::layout::keys::G;
+use crate::layout::types::KeyCoords;
+
+use super::testtime::TestTime;
+use super::TestDevice;
+
+fn key(decoder: &mut MorseDecoder, t: &mut TestTime, gap_ms: u64, held_ms: u64) {
+    assert!(decoder.process(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(gap_ms)).is_none());
+    assert!(decoder.tick(t.advance_ms(held_ms)).is_none());
+    assert!(decoder.process(KeyStateChange::Released(TestDevice::B01), t.now()).is_none());
+}
+
+#[test]
+fn test_morse_letter() {
+    let mut decoder = MorseDecoder::new(TestDevice::B01);
+    let mut t = TestTime::start();
+
+    // .-. = R
+    key(&mut decoder, &mut t, 0, 100);
+    key(&mut decoder, &mut t, 100, 400);
+    key(&mut decoder, &mut t, 100, 100);
+    assert!(decoder.is_composing());
+    assert!(decoder.tick(t.advance_ms(300)).is_none());
+    assert_eq!(decoder.tick(t.advance_ms(400)), Some(Ok(&G().k(KeyCode::KEY_R))));
+    assert!(!decoder.is_composing());
+
+    // Other keys are not touched
+    let ev = decoder.process(KeyStateChange::Pressed(TestDevice::B02), t.now());
+    assert!(matches!(ev, Some(KeyStateChange::Pressed(KeyCoords(0, 0, 1)))));
+}
+
+#[test]
+fn test_morse_custom_code() {
+    let mut decoder = MorseDecoder::new(TestDevice::B01);
+    decoder.set_code("-.-.-", G().m(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_S));
+    let mut t = TestTime::start();
+
+    for held in [400, 100, 400, 100, 400] {
+        key(&mut decoder, &mut t, 100, held);
+    }
+    assert_eq!(decoder.tick(t.advance_ms(1000)), Some(Ok(&G().m(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_S))));
+
+    // ...... is not in the table
+    for _ in 0..6 {
+        key(&mut decoder, &mut t, 100, 100);
+    }
+    assert_eq!(decoder.tick(t.advance_ms(1000)), Some(Err("......".to_string())));
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::Kmul;
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// Single layout, B01 zooms in three steps, B02 zooms out twice with a pause
+fn multiplier_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Kmul(G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_EQUAL), 3, Duration::ZERO),
+              Kmul(G().k(KeyCode::KEY_MINUS), 2, Duration::from_millis(20)) ],
+    ])
+}
+
+#[test]
+fn test_multiplier() {
+    let layout_vec = multiplier_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    // All clicks are sent on press, nothing on release
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
+    let click = [(KeyCode::KEY_LEFTCTRL, true), (KeyCode::KEY_EQUAL, true), (KeyCode::KEY_EQUAL, false), (KeyCode::KEY_LEFTCTRL, false)];
+    assert_emitted_keys(&mut layout, click.repeat(3));
+}
+
+#[test]
+fn test_multiplier_delay() {
+    let layout_vec = multiplier_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // Stateless keys (rotary encoder) work too, the following clicks
+    // wait on the timer instead of blocking the rendering
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.now());
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_MINUS, true), (KeyCode::KEY_MINUS, false)]);
+    assert_eq!(layout.next_timer(), Some(t.now() + Duration::from_millis(20)));
+
+    layout.tick(t.advance_ms(10));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.tick(t.advance_ms(10));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_MINUS, true), (KeyCode::KEY_MINUS, false)]);
+    assert_eq!(layout.next_timer(), None);
+}
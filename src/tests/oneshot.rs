@@ -0,0 +1,66 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Klong, Oneshot};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// B01 is a sticky shift, B02 types A, B03 is a sticky ctrl, B04 types B or C on a long press
+fn oneshot_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Oneshot(G().k(KeyCode::KEY_LEFTSHIFT)), G().k(KeyCode::KEY_A).p() ],
+        vec![ Oneshot(G().k(KeyCode::KEY_LEFTCTRL)), Klong(G().k(KeyCode::KEY_B), G().k(KeyCode::KEY_C)) ],
+    ])
+}
+
+#[test]
+fn test_oneshot() {
+    let layout_vec = oneshot_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
+
+    // The shift stays down while the next key is held
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(500));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true)]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, false), (KeyCode::KEY_LEFTSHIFT, false)]);
+
+    // Only one key is modified
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+}
+
+#[test]
+fn test_oneshot_stacking_and_cancel() {
+    let layout_vec = oneshot_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_LEFTCTRL, true)]);
+
+    // A click resolved on release consumes both modifiers
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B04), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_B, true), (KeyCode::KEY_B, false), (KeyCode::KEY_LEFTCTRL, false), (KeyCode::KEY_LEFTSHIFT, false)
+    ]);
+
+    // The second press cancels the sticky modifier
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_LEFTSHIFT, false)]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(50));
+    layout.release_all();
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTCTRL, true), (KeyCode::KEY_LEFTCTRL, false)]);
+}
@@ -0,0 +1,60 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Inh, Kg, Lhold, Output};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{test_layer, test_layout, TestDevice};
+
+// Dual layout, B02 sends play/pause through a media device, holding B01
+// activates a layer that is routed to a different device altogether
+fn routed_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![
+            vec![ Lhold(1),
+                  Output(Box::new(Kg(G().k(KeyCode::KEY_PLAYPAUSE))), "Consumer Control".to_string()) ],
+            vec![ G().k(KeyCode::KEY_A).p() ],
+        ]).name("base"),
+        test_layer(vec![
+            vec![ Inh, Inh ],
+            vec![ G().k(KeyCode::KEY_B).p() ],
+        ]).inherits("base").output("App"),
+    ])
+}
+
+fn routed_keys(layout: &mut LayerSwitcher) -> Vec<(Option<String>, KeyCode, bool)> {
+    let mut keys = Vec::new();
+    layout.render_routed(|output, k, pressed| keys.push((output.map(str::to_string), k, pressed)));
+    keys
+}
+
+#[test]
+fn test_output_routing() {
+    let layout_vec = routed_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    assert_eq!(layout.get_outputs(), vec!["Consumer Control", "App"]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t);
+    let media = Some("Consumer Control".to_string());
+    assert_eq!(routed_keys(&mut layout), vec![
+        (media.clone(), KeyCode::KEY_PLAYPAUSE, true), (media.clone(), KeyCode::KEY_PLAYPAUSE, false),
+        (None, KeyCode::KEY_A, true), (None, KeyCode::KEY_A, false),
+    ]);
+
+    // The layer routes its keys, the binding output wins for the inherited key
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    let app = Some("App".to_string());
+    assert_eq!(routed_keys(&mut layout), vec![
+        (app.clone(), KeyCode::KEY_B, true), (app.clone(), KeyCode::KEY_B, false),
+        (media.clone(), KeyCode::KEY_PLAYPAUSE, true), (media.clone(), KeyCode::KEY_PLAYPAUSE, false),
+    ]);
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use crate::kbd_events::panic::PanicChord;
+use crate::kbd_events::KeyStateChange;
+
+use super::testtime::TestTime;
+use super::TestDevice;
+
+#[test]
+fn test_panic_chord() {
+    let mut chord = PanicChord::new(vec![TestDevice::B01, TestDevice::B04], Duration::from_millis(1000));
+    let mut t = TestTime::start();
+
+    // A single key is not enough
+    chord.process(&KeyStateChange::Pressed(TestDevice::B01), t.now());
+    assert!(!chord.is_pending());
+    assert!(!chord.tick(t.advance_ms(2000)));
+
+    // The hold time counts from the moment the chord is complete
+    chord.process(&KeyStateChange::Pressed(TestDevice::B04), t.now());
+    assert!(chord.is_pending());
+    assert!(!chord.tick(t.advance_ms(900)));
+    assert!(chord.tick(t.advance_ms(100)));
+
+    // Reported only once
+    assert!(!chord.tick(t.advance_ms(100)));
+    assert!(!chord.is_pending());
+
+    // Releasing any key breaks the chord
+    chord.process(&KeyStateChange::Released(TestDevice::B04), t.now());
+    chord.process(&KeyStateChange::Pressed(TestDevice::B04), t.advance_ms(100));
+    chord.process(&KeyStateChange::Released(TestDevice::B01), t.advance_ms(500));
+    assert!(!chord.tick(t.advance_ms(1000)));
+}
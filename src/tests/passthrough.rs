@@ -0,0 +1,126 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::button_device::reader::DeviceReader;
+use crate::button_device::{ButtonDevice, ReadResult};
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::Passthrough;
+use crate::layout::keys::G;
+
+use super::button_device::MockButton;
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+/// The state of a mock keypad, the test keeps it when the device moves
+/// to the reader
+#[derive(Default)]
+pub(super) struct Keypad {
+    /// In the key bit mode instead of the standard keyboard one
+    pub(super) switched: AtomicBool,
+    pub(super) open: AtomicBool,
+}
+
+/// A keypad switched to the key bit mode when opened, like the ACK05
+pub(super) struct ModalDevice(Arc<Keypad>);
+
+impl ModalDevice {
+    pub(super) fn open_shared(keypad: &Arc<Keypad>) -> Self {
+        keypad.open.store(true, Ordering::SeqCst);
+        keypad.switched.store(true, Ordering::SeqCst);
+        Self(keypad.clone())
+    }
+}
+
+impl Drop for ModalDevice {
+    fn drop(&mut self) {
+        self.0.open.store(false, Ordering::SeqCst);
+    }
+}
+
+impl ButtonDevice for ModalDevice {
+    type Button = MockButton;
+    type Error = io::Error;
+
+    fn open() -> io::Result<Self> {
+        Ok(Self::open_shared(&Arc::default()))
+    }
+
+    fn read_timeout(&mut self, timeout: i32) -> io::Result<ReadResult<MockButton>> {
+        thread::sleep(Duration::from_millis(timeout.max(0) as u64));
+        Ok(ReadResult::Timeout)
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        self.0.switched.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+// Single layout, B01 bypasses the layout, B02 types B
+fn passthrough_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Passthrough, G().k(KeyCode::KEY_B).p() ],
+    ])
+}
+
+#[test]
+fn test_passthrough() {
+    let layout_vec = passthrough_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // The key held by the layout is released by the bypass
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    assert!(layout.is_bypassed());
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+
+    // The other keys do nothing meanwhile
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // Pressed again, the layout is back
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    assert!(!layout.is_bypassed());
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+}
+
+#[test]
+fn test_passthrough_device() {
+    let layout_vec = passthrough_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+    let keypad = Arc::new(Keypad::default());
+    let mut reader = DeviceReader::start(ModalDevice::open_shared(&keypad));
+    assert!(keypad.switched.load(Ordering::SeqCst));
+
+    // The bypass restores the standard keyboard mode and closes the keypad
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(50));
+    assert!(layout.is_bypassed() && !reader.is_released());
+    reader.release().unwrap();
+    assert!(reader.is_released());
+    assert!(!keypad.switched.load(Ordering::SeqCst));
+    assert!(!keypad.open.load(Ordering::SeqCst));
+
+    // Nothing is read meanwhile, it is not an error
+    assert!(matches!(reader.read_timeout(Duration::from_millis(10)), Ok((ReadResult::Timeout, _))));
+    assert_eq!(reader.with(|_| ()), None);
+
+    // Turned off by another input, the keypad is opened and switched again
+    layout.set_bypassed(false);
+    reader.restart(ModalDevice::open_shared(&keypad));
+    assert!(!reader.is_released());
+    assert!(keypad.switched.load(Ordering::SeqCst));
+    assert!(keypad.open.load(Ordering::SeqCst));
+}
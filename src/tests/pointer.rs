@@ -0,0 +1,75 @@
+use crate::keycodes::{KeyCode, RelativeAxisType};
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Pbtn, Pmove, Pscroll};
+use crate::virtual_pointer::is_pointer_button;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+fn pointer_layout() -> Vec<Layer> {
+    single_layer(vec![vec![Pbtn(KeyCode::BTN_LEFT), Pmove(10, 0)], vec![Pmove(0, -5)]])
+}
+
+fn emitted_moves(layout: &mut LayerSwitcher) -> Vec<(RelativeAxisType, i32)> {
+    let mut moves = Vec::new();
+    layout.render_pointer(|axis, delta| moves.push((axis, delta)));
+    moves
+}
+
+#[test]
+fn test_pointer() {
+    let layout_vec = pointer_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    assert!(layout.get_used_keys().contains(&KeyCode::BTN_LEFT));
+    assert_eq!(layout.get_used_pointer_axes(), vec![RelativeAxisType::REL_X, RelativeAxisType::REL_Y]);
+
+    // Drag: the button stays down while the pointer moves
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::BTN_LEFT, true)]);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(50));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(50));
+    assert_eq!(emitted_moves(&mut layout), vec![
+        (RelativeAxisType::REL_X, 10), (RelativeAxisType::REL_Y, -5)
+    ]);
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::BTN_LEFT, false)]);
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t.advance_ms(50));
+    layout.release_all();
+    assert_emitted_keys(&mut layout, vec![(KeyCode::BTN_LEFT, true), (KeyCode::BTN_LEFT, false)]);
+}
+
+#[test]
+fn test_scroll() {
+    let layout_vec = single_layer(vec![vec![Pscroll(1, 0), Pscroll(-1, 0)], vec![Pscroll(0, 2)]]);
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    assert_eq!(layout.get_used_pointer_axes(), vec![RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_HWHEEL]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t);
+    assert_eq!(emitted_moves(&mut layout), vec![
+        (RelativeAxisType::REL_WHEEL, 1), (RelativeAxisType::REL_WHEEL, -1), (RelativeAxisType::REL_HWHEEL, 2)
+    ]);
+    assert_emitted_keys(&mut layout, vec![]);
+}
+
+#[test]
+fn test_pointer_buttons() {
+    assert!(is_pointer_button(KeyCode::BTN_LEFT));
+    assert!(is_pointer_button(KeyCode::BTN_MIDDLE));
+    assert!(is_pointer_button(KeyCode::BTN_TASK));
+    assert!(!is_pointer_button(KeyCode::BTN_SOUTH));
+    assert!(!is_pointer_button(KeyCode::BTN_0));
+    assert!(!is_pointer_button(KeyCode::KEY_A));
+}
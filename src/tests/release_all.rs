@@ -0,0 +1,29 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::switcher::LayerSwitcher;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, basic_layered_layout, TestDevice};
+
+#[test]
+fn test_release_all() {
+    let layout_vec = basic_layered_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true), (KeyCode::KEY_B, true)]);
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+
+    layout.release_all();
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, false), (KeyCode::KEY_LEFTSHIFT, false)]);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+
+    // The physical releases arriving later do nothing
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, vec![]);
+}
@@ -0,0 +1,35 @@
+use crate::layout::geometry::Geometry;
+use crate::layout::switcher::LayerSwitcher;
+use crate::repl::Repl;
+
+use super::basic_layered_layout;
+
+#[test]
+fn test_repl() {
+    let layout_vec = basic_layered_layout();
+    let mut repl = Repl::new(LayerSwitcher::new(&layout_vec), Geometry::ack05());
+
+    // b01 holds the shift layer, b02 types B or 9 through the inherited layer
+    assert_eq!(repl.execute("click b02").unwrap(), vec![
+        "event Pressed(KeyCoords(0, 0, 1))",
+        "key +KEY_B",
+        "event Released(KeyCoords(0, 0, 1))",
+        "key -KEY_B",
+    ]);
+
+    assert_eq!(repl.execute("press top-left").unwrap(), vec![
+        "event Pressed(KeyCoords(0, 0, 0))",
+        "key +KEY_LEFTSHIFT",
+    ]);
+    assert_eq!(repl.execute("layers").unwrap(), vec!["layers [0, 1]"]);
+    assert_eq!(repl.execute("wait 1000").unwrap(), vec!["event LongPress(KeyCoords(0, 0, 0)) x80"]);
+    assert_eq!(repl.execute("held").unwrap(), vec!["held [KEY_LEFTSHIFT]"]);
+
+    assert_eq!(repl.execute("reset").unwrap(), vec!["key -KEY_LEFTSHIFT"]);
+    assert_eq!(repl.execute("held").unwrap(), vec!["held []"]);
+    assert_eq!(repl.execute("").unwrap(), Vec::<String>::new());
+
+    assert!(repl.execute("press b11").is_err());
+    assert!(repl.execute("wait soon").is_err());
+    assert!(repl.execute("jump").is_err());
+}
@@ -0,0 +1,127 @@
+use crate::layout::serialization::{parse_fallback_map, parse_report_map};
+use crate::xppen_hid::report_map::{
+    describe_report, set_bits, KeyboardReportMap, ReportBit, ReportMap, ScanCode, WheelField,
+};
+use crate::xppen_hid::XpPenButtons::{XpB01, XpB02, XpB03, XpB05, XpB08, XpB10, XpRoCCW, XpRoCW};
+use crate::xppen_hid::XpPenResult;
+
+use super::report;
+
+#[test]
+fn test_ack05_report() {
+    assert_eq!(parse_report_map("").unwrap(), None);
+
+    let XpPenResult::Keys(keys) = ReportMap::ack05().parse(&report(&[XpB01, XpB10, XpRoCW])) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpB01 | XpB10 | XpRoCW);
+
+    // Reports of the HID scan code protocol and truncated reports are skipped
+    assert!(matches!(ReportMap::ack05().parse(&[0x02, 0, 4, 0, 0, 0, 0, 0]), XpPenResult::TryAgain));
+    assert!(matches!(ReportMap::ack05().parse(&[0x02, 240, 1]), XpPenResult::TryAgain));
+}
+
+#[test]
+fn test_custom_report_map() {
+    let map = parse_report_map(r#"
+        [report]
+        id_byte = 0
+        id = 5
+        buttons = [{ byte = 1, bit = 7 }, { byte = 1, bit = 0 }, { byte = 2, bit = 3 }]
+        wheel = { delta_byte = 3 }
+    "#).unwrap().unwrap();
+
+    assert_eq!(map.buttons[2], ReportBit::new(2, 3));
+    assert_eq!(map.wheel, Some(WheelField::Delta { delta_byte: 3 }));
+
+    let XpPenResult::Keys(keys) = map.parse(&[5, 0x81, 0x00, 0x00]) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpB01 | XpB02);
+
+    // The wheel delta is signed
+    let XpPenResult::Keys(keys) = map.parse(&[5, 0x00, 0x08, 0xfe]) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpB03 | XpRoCCW);
+
+    assert!(matches!(map.parse(&[6, 0x81, 0x00, 0x00]), XpPenResult::TryAgain));
+    assert!(matches!(map.parse(&[5, 0x81, 0x00]), XpPenResult::TryAgain));
+}
+
+#[test]
+fn test_describe_report() {
+    assert_eq!(set_bits(&[0x02, 0x00, 0x81]), vec![ReportBit::new(0, 1), ReportBit::new(2, 0), ReportBit::new(2, 7)]);
+    assert_eq!(set_bits(&[0x00, 0x00]), vec![]);
+
+    assert_eq!(describe_report(&[0x02, 0xf0, 0x01]), "02 f0 01 | 0.1 1.4 1.5 1.6 1.7 2.0");
+    assert_eq!(describe_report(&[0x00]), "00 | ");
+}
+
+#[test]
+fn test_fallback_map() {
+    assert_eq!(parse_fallback_map("").unwrap(), KeyboardReportMap::ack05());
+    assert!(parse_fallback_map("[fallback]").unwrap().is_empty());
+
+    let map = parse_fallback_map(r#"
+        [fallback]
+        id = 2
+        buttons = [{ usage = 5 }, { modifiers = 1, usage = 29 }, { modifiers = 2, usage = 0 }]
+        cw = { modifiers = 1, usage = 46 }
+        ccw = { modifiers = 1, usage = 45 }
+    "#).unwrap();
+    assert_eq!(map.buttons[1], ScanCode::new(1, 29));
+    assert!(!map.is_empty());
+
+    // B -> button 0, the release is a report without keys
+    let XpPenResult::Keys(keys) = map.parse(&[2, 0, 0, 5, 0, 0, 0, 0, 0]) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpB01);
+    let XpPenResult::Keys(keys) = map.parse(&[2, 0, 0, 0, 0, 0, 0, 0, 0]) else {
+        panic!("Report not recognized");
+    };
+    assert!(keys.is_empty());
+
+    // The modifiers have to match, Ctrl+Z is not Z
+    let XpPenResult::Keys(keys) = map.parse(&[2, 1, 0, 29, 0, 0, 0, 0, 0]) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpB02);
+    let XpPenResult::Keys(keys) = map.parse(&[2, 0, 0, 29, 0, 0, 0, 0, 0]) else {
+        panic!("Report not recognized");
+    };
+    assert!(keys.is_empty());
+
+    // A modifier alone, the wheel
+    let XpPenResult::Keys(keys) = map.parse(&[2, 2, 0, 0, 0, 0, 0, 0, 0]) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpB03);
+    let XpPenResult::Keys(keys) = map.parse(&[2, 1, 0, 45, 0, 0, 0, 0, 0]) else {
+        panic!("Report not recognized");
+    };
+    assert_eq!(keys, XpRoCCW);
+
+    // Other reports and truncated ones are skipped
+    assert!(matches!(map.parse(&[1, 0, 0, 5, 0, 0, 0, 0, 0]), XpPenResult::TryAgain));
+    assert!(matches!(map.parse(&[2, 0]), XpPenResult::TryAgain));
+
+    // Keyboards without report ids
+    let map = KeyboardReportMap { id: None, buttons: vec![ScanCode::new(0, 5)], cw: None, ccw: None };
+    assert!(matches!(map.parse(&[0, 0, 5, 0, 0, 0, 0, 0]), XpPenResult::Keys(keys) if keys == XpB01));
+}
+
+#[test]
+fn test_fallback_map_ack05() {
+    let map = KeyboardReportMap::default();
+    assert!(!map.is_empty());
+
+    // E -> button 1, Ctrl+Shift+Z -> button 7, Ctrl alone -> button 4
+    assert!(matches!(map.parse(&[2, 0, 0, 8, 0, 0, 0, 0, 0]), XpPenResult::Keys(keys) if keys == XpB02));
+    assert!(matches!(map.parse(&[2, 3, 0, 29, 0, 0, 0, 0, 0]), XpPenResult::Keys(keys) if keys == XpB08));
+    assert!(matches!(map.parse(&[2, 1, 0, 0, 0, 0, 0, 0, 0]), XpPenResult::Keys(keys) if keys == XpB05));
+
+    // The zoom of the wheel
+    assert!(matches!(map.parse(&[2, 1, 0, 46, 0, 0, 0, 0, 0]), XpPenResult::Keys(keys) if keys == XpRoCW));
+}
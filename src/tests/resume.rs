@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use crate::kbd_events::chords::{Chord, ChordResolver};
+use crate::kbd_events::panic::PanicChord;
+use crate::kbd_events::KeyStateChange::{Pressed, Released};
+use crate::layout::types::KeyCoords;
+use crate::sleep_inhibitor::ResumeDetector;
+
+use super::testtime::TestTime;
+use super::TestDevice;
+
+fn secs(s: f64) -> Duration {
+    Duration::from_secs_f64(s)
+}
+
+#[test]
+fn test_resume_detector() {
+    let mut detector = ResumeDetector::new();
+
+    // The first check has nothing to compare to
+    assert!(!detector.observe(secs(100.0), secs(90.0)));
+
+    // Both clocks run while awake
+    assert!(!detector.observe(secs(100.5), secs(90.5)));
+    assert!(!detector.observe(secs(160.5), secs(150.5)));
+
+    // Only the boot time runs while asleep
+    assert!(detector.observe(secs(3760.5), secs(151.0)));
+    assert!(!detector.observe(secs(3761.0), secs(151.5)));
+
+    // A short hiccup is no suspend
+    assert!(!detector.observe(secs(3761.7), secs(151.7)));
+
+    // The real clocks agree while awake
+    let mut detector = ResumeDetector::new();
+    assert!(!detector.check());
+    assert!(!detector.check());
+}
+
+#[test]
+fn test_reset_after_resume() {
+    let mut t = TestTime::start();
+
+    // The chord held before the suspend is never released
+    let mut panic = PanicChord::new(vec![TestDevice::B01, TestDevice::B04], Duration::from_millis(1000));
+    panic.process(&Pressed(TestDevice::B01), t.now());
+    panic.process(&Pressed(TestDevice::B04), t.now());
+    assert!(panic.is_pending());
+    panic.reset();
+    assert!(!panic.is_pending());
+    assert!(!panic.tick(t.advance_ms(2000)));
+
+    // Pressing one key again does not complete the forgotten chord
+    panic.process(&Pressed(TestDevice::B04), t.now());
+    assert!(!panic.tick(t.advance_ms(2000)));
+
+    let chord = KeyCoords(2, 0, 0);
+    let mut chords = ChordResolver::new(vec![Chord { keys: vec![TestDevice::B01, TestDevice::B02], coords: chord }]);
+    chords.process(Pressed(TestDevice::B01), t.now());
+    assert!(chords.is_pending());
+    chords.reset();
+    assert!(!chords.is_pending());
+    chords.tick(t.advance_ms(100));
+    assert_eq!(chords.next(), None);
+
+    // The chord is formed anew
+    chords.process(Pressed(TestDevice::B01), t.now());
+    chords.process(Pressed(TestDevice::B02), t.advance_ms(10));
+    chords.process(Released(TestDevice::B01), t.advance_ms(10));
+    let events: Vec<_> = chords.by_ref().map(|(ev, _)| ev).collect();
+    assert_eq!(events, vec![Pressed(chord), Released(chord)]);
+}
@@ -0,0 +1,19 @@
+use crate::keycodes::KeyCode;
+
+use crate::virtual_keyboard::scancodes::hid_scancode;
+
+#[test]
+fn test_hid_scancodes() {
+    assert_eq!(hid_scancode(KeyCode::KEY_A), Some(0x70004));
+    assert_eq!(hid_scancode(KeyCode::KEY_1), Some(0x7001e));
+    assert_eq!(hid_scancode(KeyCode::KEY_ENTER), Some(0x70028));
+    assert_eq!(hid_scancode(KeyCode::KEY_BACKSLASH), Some(0x70031));
+    assert_eq!(hid_scancode(KeyCode::KEY_DELETE), Some(0x7004c));
+    assert_eq!(hid_scancode(KeyCode::KEY_F12), Some(0x70045));
+    assert_eq!(hid_scancode(KeyCode::KEY_LEFTCTRL), Some(0x700e0));
+    assert_eq!(hid_scancode(KeyCode::KEY_RIGHTMETA), Some(0x700e7));
+    assert_eq!(hid_scancode(KeyCode::KEY_PLAYPAUSE), Some(0xc00cd));
+
+    // Not a keyboard key
+    assert_eq!(hid_scancode(KeyCode::BTN_SOUTH), None);
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use crate::kbd_events::scanning::SwitchScanner;
+use crate::kbd_events::KeyStateChange;
+use crate::layout::types::KeyCoords;
+
+use super::testtime::TestTime;
+use super::TestDevice;
+
+const SWITCH: KeyCoords = KeyCoords(0, 0, 9);
+
+#[test]
+fn test_scanning_cycle() {
+    let mut scanner = SwitchScanner::new(SWITCH, vec![TestDevice::B01, TestDevice::B02, TestDevice::B03]);
+    scanner.set_interval(Duration::from_millis(100));
+    let mut t = TestTime::start();
+
+    assert_eq!(scanner.tick(t.now()), Some(TestDevice::B01));
+    assert_eq!(scanner.tick(t.advance_ms(50)), None);
+    assert_eq!(scanner.tick(t.advance_ms(60)), Some(TestDevice::B02));
+    // Missed ticks are skipped and the scan wraps around
+    assert_eq!(scanner.tick(t.advance_ms(200)), Some(TestDevice::B01));
+}
+
+#[test]
+fn test_scanning_select() {
+    let mut scanner = SwitchScanner::new(SWITCH, vec![TestDevice::B01, TestDevice::B02]);
+    scanner.set_interval(Duration::from_millis(100));
+    let mut t = TestTime::start();
+
+    scanner.tick(t.now());
+    scanner.tick(t.advance_ms(110));
+    assert_eq!(scanner.highlighted(), Some(TestDevice::B02));
+
+    // Other keys pass through
+    let ev = scanner.process(KeyStateChange::Pressed(TestDevice::B04), t.now());
+    assert!(matches!(ev, Some(KeyStateChange::Pressed(TestDevice::B04))));
+
+    // The switch presses the highlighted target, the highlight does not move while held
+    let ev = scanner.process(KeyStateChange::Pressed(SWITCH), t.advance_ms(10));
+    assert!(matches!(ev, Some(KeyStateChange::Pressed(TestDevice::B02))));
+    assert_eq!(scanner.tick(t.advance_ms(500)), None);
+    let held = Duration::from_millis(500);
+    let ev = scanner.process(KeyStateChange::LongPress(SWITCH, held), t.now());
+    assert_eq!(ev, Some(KeyStateChange::LongPress(TestDevice::B02, held)));
+
+    // Releasing the switch releases the target and restarts the scan
+    let ev = scanner.process(KeyStateChange::Released(SWITCH), t.advance_ms(10));
+    assert!(matches!(ev, Some(KeyStateChange::Released(TestDevice::B02))));
+    assert_eq!(scanner.highlighted(), Some(TestDevice::B01));
+}
@@ -0,0 +1,287 @@
+use std::time::Duration;
+
+use crate::keycodes::{AbsoluteAxisType, KeyCode, LedType};
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::keys::{G, S};
+use crate::layout::serialization::{builtin_layout, parse_layout, parse_settings};
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Cooldown, Kg, Kmul, Lhold, LhtK, LhtL, Lmove, No, Pass};
+use crate::layout::types::{
+    ActivationDebounce, DialOutput, KeyCoords, LayerCondition, LayerStatus, LongPressRace,
+};
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, TestDevice};
+
+const LAYOUT: &str = r#"
+[[layers]]
+keymap = [
+    [["No", { Lhold = 1 }, ["KEY_LEFTCTRL", "KEY_Z"]],
+     [{ LhtK = [1, "KEY_B"] }, { Kmul = ["KEY_EQUAL", 3, 20] }]],
+]
+
+[[layers]]
+on_active_keys = ["KEY_LEFTSHIFT"]
+disable_active_on_press = true
+timeout_ms = 1500
+condition = { led_on = "LED_NUML" }
+activation_debounce = { trigger_key_ms = 150 }
+default_action = "No"
+keymap = [
+    [["Pass", "Pass", { keys = ["KEY_1"], mask = ["KEY_LEFTSHIFT"], sequential = true }],
+     [{ Cooldown = ["KEY_DELETE", 500] }]],
+]
+"#;
+
+#[test]
+fn test_parse_layout() {
+    let layers = parse_layout(LAYOUT).unwrap();
+    assert_eq!(layers.len(), 2);
+
+    assert!(matches!(layers[0].status_on_reset, LayerStatus::LayerActive));
+    assert_eq!(layers[0].keymap, vec![vec![
+        vec![No, Lhold(1), G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_Z).p()],
+        vec![LhtK(1, G().k(KeyCode::KEY_B)), Kmul(G().k(KeyCode::KEY_EQUAL), 3, Duration::from_millis(20))],
+    ]]);
+    assert_eq!(layers[0].default_action, Pass);
+
+    assert!(matches!(layers[1].status_on_reset, LayerStatus::LayerPassthrough));
+    assert_eq!(layers[1].on_active_keys, vec![KeyCode::KEY_LEFTSHIFT]);
+    assert!(layers[1].disable_active_on_press);
+    assert_eq!(layers[1].timeout, Some(Duration::from_millis(1500)));
+    assert_eq!(layers[1].condition, Some(LayerCondition::LedOn(LedType::LED_NUML)));
+    assert_eq!(layers[1].activation_debounce, Some(ActivationDebounce::TriggerKey(Duration::from_millis(150))));
+    assert_eq!(layers[1].default_action, No);
+    assert_eq!(layers[1].keymap[0][0][2], Kg(S().k(KeyCode::KEY_1).m(KeyCode::KEY_LEFTSHIFT)));
+    assert_eq!(layers[1].keymap[0][1][0], Cooldown(Box::new(G().k(KeyCode::KEY_DELETE).p()), Duration::from_millis(500)));
+}
+
+#[test]
+fn test_parsed_layout_runs() {
+    let layers = parse_layout(LAYOUT).unwrap();
+    let mut layout = LayerSwitcher::new(&layers);
+    let mut t = TestTime::start();
+    layout.start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(10));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, true)]);
+
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B03), t.advance_ms(200));
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_LEFTSHIFT, false), (KeyCode::KEY_DELETE, true), (KeyCode::KEY_DELETE, false), (KeyCode::KEY_LEFTSHIFT, true)
+    ]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(10));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_LEFTSHIFT, false)]);
+}
+
+#[test]
+fn test_layout_fallback_and_errors() {
+    // No layers in the file, the built-in layout is used
+    assert_eq!(parse_layout("").unwrap().len(), builtin_layout().len());
+
+    assert!(parse_layout(r#"
+        [[layers]]
+        keymap = [[["KEY_NOT_A_KEY"]]]
+    "#).is_err());
+
+    assert!(parse_layout(r#"
+        [[layers]]
+        colour = "red"
+        keymap = []
+    "#).is_err());
+}
+
+#[test]
+fn test_named_layers() {
+    let layers = parse_layout(r#"
+        [[layers]]
+        name = "base"
+        keymap = [[[{ Lhold = "shift" }, { LhtL = ["shift", 0] }]]]
+
+        [[layers]]
+        name = "shift"
+        inherit = "base"
+        on_timeout_layer = "base"
+        keymap = [[["Inh", { Cooldown = [{ Lmove = "base" }, 100] }]]]
+    "#).unwrap();
+
+    assert_eq!(layers[0].keymap[0][0], vec![Lhold(1), LhtL(1, 0)]);
+    assert_eq!(layers[1].inherit, Some(0));
+    assert_eq!(layers[1].on_timeout_layer, Some(0));
+    assert_eq!(layers[1].keymap[0][0][1], Cooldown(Box::new(Lmove(0)), Duration::from_millis(100)));
+
+    let layout = LayerSwitcher::new(&layers);
+    assert_eq!(layout.get_layer_id("shift"), Some(1));
+    assert_eq!(layout.get_layer_id("tools"), None);
+    assert_eq!(layout.get_layer_name(0), Some("base"));
+
+    // Unknown and ambiguous names are errors
+    assert!(parse_layout(r#"
+        [[layers]]
+        keymap = [[[{ Lhold = "tools" }]]]
+    "#).is_err());
+    assert!(parse_layout(r#"
+        [[layers]]
+        name = "base"
+        keymap = []
+
+        [[layers]]
+        name = "base"
+        keymap = []
+    "#).is_err());
+}
+
+#[test]
+fn test_device_settings() {
+    let settings = parse_settings("").unwrap();
+    assert_eq!(settings.device_path, None);
+    assert_eq!(settings.device_serial, None);
+
+    let settings = parse_settings(r#"
+[settings]
+device_path = "/dev/hidraw3"
+device_serial = "0123456789"
+"#).unwrap();
+    assert_eq!(settings.device_path.as_deref(), Some("/dev/hidraw3"));
+    assert_eq!(settings.device_serial.as_deref(), Some("0123456789"));
+}
+
+#[test]
+fn test_debounce_settings() {
+    let settings = parse_settings("").unwrap();
+    assert_eq!(settings.debounce, None);
+    assert!(settings.key_debounce.is_empty());
+
+    let settings = parse_settings(r#"
+[settings]
+debounce_ms = 20
+key_debounce = [{ key = [0, 0, 3], ms = 40 }]
+"#).unwrap();
+    assert_eq!(settings.debounce, Some(Duration::from_millis(20)));
+    assert_eq!(settings.key_debounce, vec![(KeyCoords(0, 0, 3), Duration::from_millis(40))]);
+}
+
+#[test]
+fn test_long_press_race_setting() {
+    assert_eq!(parse_settings("").unwrap().long_press_race, None);
+    let settings = parse_settings("[settings]\nlong_press_race = \"tap_wins\"").unwrap();
+    assert_eq!(settings.long_press_race, Some(LongPressRace::TapWins));
+    assert!(parse_settings("[settings]\nlong_press_race = \"nobody\"").is_err());
+}
+
+#[test]
+fn test_rotary_gestures_setting() {
+    assert_eq!(parse_settings("").unwrap().rotary_gestures, None);
+    let settings = parse_settings("[settings]\nrotary_gestures = { spin_ticks = 6 }").unwrap();
+    assert_eq!(settings.rotary_gestures, Some((6, 2, Duration::from_millis(300))));
+    assert!(parse_settings("[settings]\nrotary_gestures = { spins = 6 }").is_err());
+}
+
+#[test]
+fn test_accessibility_filter_settings() {
+    let settings = parse_settings("").unwrap();
+    assert_eq!((settings.bounce_keys, settings.slow_keys), (None, None));
+    let settings = parse_settings("[settings]\nbounce_keys_ms = 300\nslow_keys_ms = 150").unwrap();
+    assert_eq!(settings.bounce_keys, Some(Duration::from_millis(300)));
+    assert_eq!(settings.slow_keys, Some(Duration::from_millis(150)));
+}
+
+#[test]
+fn test_scanning_setting() {
+    assert_eq!(parse_settings("").unwrap().scanning, None);
+    let settings = parse_settings("[settings]\nscanning = { switch = [0, 0, 9], targets = [[0, 0, 0], [0, 0, 1]] }").unwrap();
+    assert_eq!(settings.scanning, Some((KeyCoords(0, 0, 9), vec![KeyCoords(0, 0, 0), KeyCoords(0, 0, 1)], Duration::from_millis(1500))));
+}
+
+#[test]
+fn test_morse_setting() {
+    assert_eq!(parse_settings("").unwrap().morse, None);
+    let settings = parse_settings(r#"
+        [settings]
+        morse = { key = [0, 0, 9], dash_ms = 300, codes = [{ code = "-.-.-", keys = ["KEY_LEFTCTRL", "KEY_S"] }] }
+    "#).unwrap();
+    let morse = settings.morse.unwrap();
+    assert_eq!(morse.key, KeyCoords(0, 0, 9));
+    assert_eq!((morse.dash, morse.letter_gap), (Duration::from_millis(300), Duration::from_millis(600)));
+    assert_eq!(morse.codes, vec![("-.-.-".to_string(), G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_S))]);
+}
+
+#[test]
+fn test_panic_chord_setting() {
+    assert_eq!(parse_settings("").unwrap().panic_chord, None);
+    let settings = parse_settings("[settings]\npanic_chord = { keys = [[0, 0, 4], [0, 0, 5]] }").unwrap();
+    assert_eq!(settings.panic_chord, Some((vec![KeyCoords(0, 0, 4), KeyCoords(0, 0, 5)], Duration::from_secs(2))));
+    let settings = parse_settings("[settings]\npanic_chord = { keys = [], hold_ms = 500 }").unwrap();
+    assert_eq!(settings.panic_chord, Some((vec![], Duration::from_millis(500))));
+}
+
+#[test]
+fn test_rotary_reversal_setting() {
+    assert_eq!(parse_settings("").unwrap().rotary_reversal, None);
+    let settings = parse_settings("[settings]\nrotary_reversal_ms = 0").unwrap();
+    assert_eq!(settings.rotary_reversal, Some(Duration::ZERO));
+}
+
+#[test]
+fn test_dial_setting() {
+    assert_eq!(parse_settings("").unwrap().dial, None);
+    let settings = parse_settings("[settings]\ndial = {}").unwrap();
+    assert_eq!(settings.dial, Some(DialOutput { axis: AbsoluteAxisType::ABS_WHEEL, step: 1, positions: 72 }));
+    let settings = parse_settings("[settings]\ndial = { axis = \"ABS_MISC\", step = 3, positions = 36 }").unwrap();
+    assert_eq!(settings.dial, Some(DialOutput { axis: AbsoluteAxisType::ABS_MISC, step: 3, positions: 36 }));
+    assert!(parse_settings("[settings]\ndial = { axis = \"ABS_X\" }").is_err());
+}
+
+#[test]
+fn test_kernel_repeat_setting() {
+    assert_eq!(parse_settings("").unwrap().kernel_repeat, None);
+    let settings = parse_settings("[settings]\nkernel_repeat = { delay_ms = 500, period_ms = 33 }").unwrap();
+    let repeat = settings.kernel_repeat.unwrap();
+    assert_eq!((repeat.delay, repeat.period), (Duration::from_millis(500), Duration::from_millis(33)));
+}
+
+#[test]
+fn test_watchdog_setting() {
+    assert_eq!(parse_settings("").unwrap().watchdog_try_again, None);
+    let settings = parse_settings("[settings]\nwatchdog_try_again = 0").unwrap();
+    assert_eq!(settings.watchdog_try_again, Some(0));
+}
+
+#[test]
+fn test_duplicate_window_setting() {
+    assert_eq!(parse_settings("").unwrap().duplicate_window, None);
+    let settings = parse_settings("[settings]\nduplicate_window_ms = 0").unwrap();
+    assert_eq!(settings.duplicate_window, Some(Duration::ZERO));
+}
+
+#[test]
+fn test_hold_threshold_setting() {
+    let source = r#"
+[settings]
+hold_threshold_ms = 500
+
+[[layers]]
+keymap = [[[{ LhtK = [1, "KEY_B"] }]]]
+
+[[layers]]
+keymap = [[["Pass"]]]
+"#;
+    assert_eq!(parse_settings("").unwrap().hold_threshold, None);
+    let settings = parse_settings(source).unwrap();
+    assert_eq!(settings.hold_threshold, Some(Duration::from_millis(500)));
+
+    let layers = parse_layout(source).unwrap();
+    let mut layout = LayerSwitcher::new(&layers);
+    layout.set_hold_threshold(settings.hold_threshold.unwrap());
+    layout.start();
+    let mut t = TestTime::start();
+    assert_eq!(layout.get_long_press_tiers(), vec![Duration::from_millis(500)]);
+
+    // A hold with the default threshold is still a tap
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(300)), t.advance_ms(300));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+use std::thread;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::{Inh, Lhold};
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, test_layer, test_layout, TestDevice};
+
+// B01 holds a layer typing C on B02, the base layer types A on B02
+fn shared_layout() -> Vec<Layer> {
+    test_layout(vec![
+        test_layer(vec![vec![Lhold(1), G().k(KeyCode::KEY_A).p()]]).name("base"),
+        test_layer(vec![vec![Inh, G().k(KeyCode::KEY_C).p()]]).inherits("base"),
+    ])
+}
+
+#[test]
+fn test_switcher_in_worker_thread() {
+    let layers = Arc::new(shared_layout());
+    let mut layout = LayerSwitcher::from_shared(Arc::clone(&layers));
+    layout.on_layer_change(|_| {});
+    layout.start();
+
+    // The switcher owns its state, it can be handed over to a worker
+    let worker = thread::spawn(move || {
+        let mut t = TestTime::start();
+        layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+        t.advance_ms(10);
+        layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+        layout
+    });
+
+    let mut layout = worker.join().unwrap();
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_C, true), (KeyCode::KEY_C, false)]);
+    assert_eq!(Arc::strong_count(&layers), 2);
+}
+
+#[test]
+fn test_switcher_copies_layers() {
+    let mut layout_vec = shared_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+
+    // The switcher keeps its own copy, the caller may change the original
+    layout_vec.truncate(1);
+
+    let t = TestTime::start();
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_C, true), (KeyCode::KEY_C, false)]);
+}
@@ -0,0 +1,41 @@
+use std::ffi::OsString;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::serialization::parse_layout;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::Cmd;
+use crate::shell_command::sanitized_env;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, TestDevice};
+
+#[test]
+fn test_cmd_action() {
+    let layout_vec = parse_layout(r#"
+        [[layers]]
+        keymap = [[[{ Cmd = "grim shot.png" }, { Cmd = "notify-send hi" }]]]
+    "#).unwrap();
+    assert_eq!(layout_vec[0].keymap[0][0][0], Cmd("grim shot.png".to_string()));
+
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+
+    assert!(layout.take_commands().is_empty());
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, vec![]);
+    assert_eq!(layout.take_commands(), vec!["grim shot.png", "notify-send hi"]);
+
+    // The release does not run it again
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t);
+    assert!(layout.take_commands().is_empty());
+}
+
+#[test]
+fn test_sanitized_env() {
+    let vars = [("PATH", "/usr/bin"), ("NOTIFY_SOCKET", "/run/notify"), ("WAYLAND_DISPLAY", "wayland-1")]
+        .map(|(name, value)| (OsString::from(name), OsString::from(value)));
+    let names: Vec<OsString> = sanitized_env(vars).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["PATH", "WAYLAND_DISPLAY"]);
+}
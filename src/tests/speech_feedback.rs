@@ -0,0 +1,17 @@
+use std::collections::BTreeSet;
+
+use crate::keycodes::LedType;
+use crate::speech_feedback::{describe_layers, describe_locks};
+
+#[test]
+fn test_describe_changes() {
+    let name = |l| (l == 3).then_some("tools");
+    assert_eq!(describe_layers(&[0, 2], &[0, 3], name), vec!["layer 2 off", "tools on"]);
+    assert!(describe_layers(&[0], &[0], name).is_empty());
+
+    let mut before = BTreeSet::new();
+    before.insert(LedType::LED_NUML);
+    let mut after = BTreeSet::new();
+    after.insert(LedType::LED_CAPSL);
+    assert_eq!(describe_locks(&before, &after), vec!["Caps Lock on", "Num Lock off"]);
+}
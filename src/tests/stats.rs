@@ -0,0 +1,60 @@
+use crate::kbd_events::KeyStateChange;
+use crate::layout::geometry::Geometry;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeyCoords;
+use crate::stats::UsageStats;
+
+use super::testtime::TestTime;
+use super::{basic_layered_layout, TestDevice};
+
+#[test]
+fn test_usage_stats() {
+    let layout_vec = basic_layered_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let t = TestTime::start();
+    let mut stats = UsageStats::default();
+
+    // The action is resolved with the layer state at the time of the press
+    stats.record(TestDevice::B03, layout.resolve(TestDevice::B03));
+    stats.record(TestDevice::B01, layout.resolve(TestDevice::B01));
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    stats.record(TestDevice::B03, layout.resolve(TestDevice::B03));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t);
+    stats.record(TestDevice::B03, layout.resolve(TestDevice::B03));
+
+    assert_eq!(stats.presses(TestDevice::B03), 3);
+    assert_eq!(stats.presses(TestDevice::B01), 1);
+    assert_eq!(stats.presses(TestDevice::B02), 0);
+    assert_eq!(stats.actions.len(), 3);
+    assert_eq!(stats.actions.values().sum::<u64>(), 4);
+
+    // The shift layer inherits a different key for B03
+    let shift_action = format!("{:?}", layout.resolve(TestDevice::B03).unwrap());
+    assert!(shift_action.contains("KEY_LEFTSHIFT"));
+    assert_eq!(stats.actions[&shift_action], 2);
+
+    let source = toml::to_string(&stats).unwrap();
+    assert_eq!(toml::from_str::<UsageStats>(&source).unwrap(), stats);
+}
+
+#[test]
+fn test_usage_heatmap() {
+    let mut stats = UsageStats::default();
+    for _ in 0..4 {
+        stats.record(KeyCoords(0, 0, 1), None);
+    }
+    stats.record(KeyCoords(1, 0, 0), None);
+
+    let svg = stats.heatmap(&Geometry::ack05());
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.ends_with("</svg>\n"));
+    assert_eq!(svg.matches("<rect").count(), 15);
+
+    // The most used key is the hottest, unused keys stay white
+    assert!(svg.contains(r#"fill="rgb(255,55,55)""#));
+    assert!(svg.contains(r#"fill="rgb(255,205,205)""#));
+    assert!(svg.contains(r#"fill="rgb(255,255,255)""#));
+    assert!(svg.contains(">top-middle</text>"));
+    assert!(svg.contains(">4</text>"));
+}
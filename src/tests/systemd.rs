@@ -0,0 +1,32 @@
+use std::ffi::OsString;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+use crate::systemd::notify_socket;
+
+#[test]
+fn test_notify() {
+    let path = std::env::temp_dir().join(format!("xppen-ack05-notify-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let receiver = UnixDatagram::bind(&path).unwrap();
+
+    notify_socket(&OsString::from(&path), "READY=1").unwrap();
+
+    let mut buf = [0u8; 64];
+    let len = receiver.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"READY=1");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_notify_abstract() {
+    let name = format!("xppen-ack05-notify-{}", std::process::id());
+    let addr = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+    let receiver = UnixDatagram::bind_addr(&addr).unwrap();
+
+    notify_socket(&OsString::from(format!("@{}", name)), "STOPPING=1").unwrap();
+
+    let mut buf = [0u8; 64];
+    let len = receiver.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"STOPPING=1");
+}
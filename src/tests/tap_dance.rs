@@ -0,0 +1,87 @@
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::parse_layout;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::TapDance;
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// B01 dances A, B, C for one, two and three taps, B02 types X
+fn tap_dance_layout() -> Vec<Layer> {
+    let dance = TapDance(vec![G().k(KeyCode::KEY_A), G().k(KeyCode::KEY_B), G().k(KeyCode::KEY_C)]);
+
+    single_layer(vec![
+        vec![ dance, G().k(KeyCode::KEY_X).p() ],
+    ])
+}
+
+#[test]
+fn test_tap_dance() {
+    let layout_vec = tap_dance_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // A single tap waits for the window to pass
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(50));
+    assert_emitted_keys(&mut layout, vec![]);
+    assert_eq!(layout.next_timer(), Some(t.now() + layout.get_hold_threshold()));
+    layout.tick(t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.tick(t.advance_ms(1000));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+
+    // Two taps
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.tick(t.advance_ms(1000));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, true), (KeyCode::KEY_B, false)]);
+    assert_eq!(layout.next_timer(), None);
+
+    // The last tap does not wait
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_C, true), (KeyCode::KEY_C, false)]);
+}
+
+#[test]
+fn test_tap_dance_interrupted() {
+    let layout_vec = tap_dance_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // Another key ends the dance before its own press
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![
+        (KeyCode::KEY_B, true), (KeyCode::KEY_B, false),
+        (KeyCode::KEY_X, true), (KeyCode::KEY_X, false),
+    ]);
+
+    // A tap after the window starts a new dance even without a tick
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(100));
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B01), t.advance_ms(1000));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+    layout.tick(t.advance_ms(1000));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_A, true), (KeyCode::KEY_A, false)]);
+}
+
+#[test]
+fn test_parse_tap_dance() {
+    let layout_vec = parse_layout(r#"
+        [[layers]]
+        keymap = [[[{ TapDance = ["KEY_A", ["KEY_LEFTCTRL", "KEY_B"]] }]]]
+    "#).unwrap();
+    assert_eq!(layout_vec[0].keymap[0][0][0], TapDance(vec![
+        G().k(KeyCode::KEY_A),
+        G().k(KeyCode::KEY_LEFTCTRL).k(KeyCode::KEY_B),
+    ]));
+}
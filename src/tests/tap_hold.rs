@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::serialization::parse_settings;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::TapHold;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, hold_and_tap_key_layered_layout, short_key_long_layer_layout, TestDevice};
+
+#[test]
+fn test_hold_on_other_press() {
+    let layout_vec = short_key_long_layer_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_tap_hold(TapHold::HoldOnOtherPress);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // The other key is pressed in the held layer right away
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(20));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true)]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(20));
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, false)]);
+}
+
+#[test]
+fn test_hold_on_other_press_layer_tap() {
+    let layout_vec = hold_and_tap_key_layered_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_tap_hold(TapHold::HoldOnOtherPress);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Click(TestDevice::B02), t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_4, true), (KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
+
+    // Released quickly, but it was a hold already
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_4, false)]);
+    assert_eq!(layout.get_active_layers(), vec![0]);
+}
+
+#[test]
+fn test_permissive_hold() {
+    let layout_vec = short_key_long_layer_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_tap_hold(TapHold::PermissiveHold);
+    layout.start();
+    let mut t = TestTime::start();
+
+    // The other key waits for the decision
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(20));
+    assert_eq!(layout.get_active_layers(), vec![0]);
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // Its full tap makes the held key a hold
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(20));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true), (KeyCode::KEY_T, false)]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![]);
+}
+
+#[test]
+fn test_permissive_hold_rolled_tap() {
+    let layout_vec = short_key_long_layer_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_tap_hold(TapHold::PermissiveHold);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // The held key was released first, both were taps in the base layer
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(20));
+    assert_eq!(layout.get_active_layers(), vec![0]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_0, true), (KeyCode::KEY_0, false), (KeyCode::KEY_B, true)]);
+
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B02), t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_B, false)]);
+}
+
+#[test]
+fn test_permissive_hold_long_press() {
+    let layout_vec = short_key_long_layer_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.set_tap_hold(TapHold::PermissiveHold);
+    layout.start();
+    let mut t = TestTime::start();
+
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t.advance_ms(20));
+    assert_emitted_keys(&mut layout, vec![]);
+
+    // The hold threshold decides as well
+    layout.process_keyevent(KeyStateChange::LongPress(TestDevice::B01, Duration::from_millis(200)), t.advance_ms(200));
+    assert_eq!(layout.get_active_layers(), vec![0, 1]);
+    assert_emitted_keys(&mut layout, vec![(KeyCode::KEY_T, true)]);
+}
+
+#[test]
+fn test_parse_tap_hold() {
+    assert_eq!(parse_settings("").unwrap().tap_hold, TapHold::Timeout);
+    let settings = parse_settings("[settings]\ntap_hold = \"permissive_hold\"").unwrap();
+    assert_eq!(settings.tap_hold, TapHold::PermissiveHold);
+    let settings = parse_settings("[settings]\ntap_hold = \"hold_on_other_press\"").unwrap();
+    assert_eq!(settings.tap_hold, TapHold::HoldOnOtherPress);
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use crate::keycodes::KeyCode;
+
+use crate::kbd_events::KeyStateChange;
+use crate::layout::layer::Layer;
+use crate::layout::switcher::LayerSwitcher;
+use crate::layout::types::KeymapEvent::Kturbo;
+use crate::layout::keys::G;
+
+use super::testtime::TestTime;
+use super::{assert_emitted_keys, single_layer, TestDevice};
+
+// Single layout, B01 scrubs frames at full speed, B02 ramps up over 300 ms
+fn turbo_layout() -> Vec<Layer> {
+    single_layer(vec![
+        vec![ Kturbo(G().k(KeyCode::KEY_RIGHT), Duration::from_millis(50), Duration::ZERO),
+              Kturbo(G().k(KeyCode::KEY_LEFT), Duration::from_millis(50), Duration::from_millis(300)) ],
+    ])
+}
+
+#[test]
+fn test_turbo() {
+    let layout_vec = turbo_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+    let click = [(KeyCode::KEY_RIGHT, true), (KeyCode::KEY_RIGHT, false)];
+
+    // The first click is sent right away
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B01), t);
+    assert_emitted_keys(&mut layout, click.to_vec());
+    assert_eq!(layout.next_timer(), Some(t.now() + Duration::from_millis(50)));
+
+    layout.tick(t.advance_ms(30));
+    assert_emitted_keys(&mut layout, vec![]);
+    layout.tick(t.advance_ms(20));
+    assert_emitted_keys(&mut layout, click.to_vec());
+    layout.tick(t.advance_ms(50));
+    assert_emitted_keys(&mut layout, click.to_vec());
+
+    // Release stops the clicking
+    layout.process_keyevent(KeyStateChange::Released(TestDevice::B01), t.advance_ms(10));
+    assert_emitted_keys(&mut layout, vec![]);
+    assert_eq!(layout.next_timer(), None);
+    layout.tick(t.advance_ms(100));
+    assert_emitted_keys(&mut layout, vec![]);
+}
+
+#[test]
+fn test_turbo_ramp_up() {
+    let layout_vec = turbo_layout();
+    let mut layout = LayerSwitcher::new(&layout_vec);
+    layout.start();
+    let mut t = TestTime::start();
+    let start = t.now();
+    let click = [(KeyCode::KEY_LEFT, true), (KeyCode::KEY_LEFT, false)];
+
+    // Starts four times slower
+    layout.process_keyevent(KeyStateChange::Pressed(TestDevice::B02), t);
+    assert_emitted_keys(&mut layout, click.to_vec());
+    assert_eq!(layout.next_timer(), Some(start + Duration::from_millis(200)));
+
+    // Held for 200 ms, half the slowdown is gone
+    layout.tick(t.advance_ms(200));
+    assert_emitted_keys(&mut layout, click.to_vec());
+    assert_eq!(layout.next_timer(), Some(start + Duration::from_millis(300)));
+
+    // Full speed after the ramp-up
+    layout.tick(t.advance_ms(100));
+    assert_emitted_keys(&mut layout, click.to_vec());
+    assert_eq!(layout.next_timer(), Some(start + Duration::from_millis(350)));
+}
@@ -0,0 +1,50 @@
+use crate::layout::geometry::Geometry;
+use crate::layout::layer::Layer;
+use crate::layout::serialization::builtin_layout;
+use crate::layout::types::KeyCoords;
+use crate::layout::types::KeymapEvent::{Cooldown, Lhold, LhtL, Pass};
+use crate::layout::validation::{validate, LayoutError};
+
+use super::DEFAULT_LAYER_CONFIG;
+
+#[test]
+fn test_builtin_layout_is_valid() {
+    assert_eq!(validate(&builtin_layout(), &Geometry::ack05()), Ok(()));
+}
+
+#[test]
+fn test_layout_errors() {
+    let layers = vec![
+        Layer {
+            keymap: vec![vec![vec![
+                Pass,
+                LhtL(1, 5),
+                Cooldown(Box::new(Lhold(7)), std::time::Duration::from_millis(100)),
+            ]]],
+            ..DEFAULT_LAYER_CONFIG
+        },
+        Layer {
+            inherit: Some(2),
+            keymap: vec![vec![], vec![vec![Pass, Lhold(0)]], vec![vec![Pass]]],
+            ..DEFAULT_LAYER_CONFIG
+        },
+        Layer {
+            inherit: Some(1),
+            on_timeout_layer: Some(3),
+            ..DEFAULT_LAYER_CONFIG
+        },
+    ];
+
+    let errors = validate(&layers, &Geometry::ack05()).unwrap_err();
+    assert_eq!(errors, vec![
+        LayoutError::UnknownLayer { layer: 0, coords: Some(KeyCoords(0, 0, 1)), target: 5 },
+        LayoutError::UnknownLayer { layer: 0, coords: Some(KeyCoords(0, 0, 2)), target: 7 },
+        LayoutError::UnknownPosition { layer: 1, coords: KeyCoords(2, 0, 0) },
+        LayoutError::UnknownLayer { layer: 2, coords: None, target: 3 },
+        LayoutError::HoldOnStateless { layer: 1, coords: KeyCoords(1, 0, 1) },
+        LayoutError::InheritCycle(vec![1, 2, 1]),
+    ]);
+
+    assert_eq!(errors[0].to_string(), "Layer 0 block 0 row 0 column 1: refers to the unknown layer 5");
+    assert_eq!(errors[5].to_string(), "Layers 1 -> 2 -> 1 inherit from each other in a loop");
+}
@@ -0,0 +1,45 @@
+use enumset::EnumSet;
+
+use crate::xppen_hid::watchdog::Watchdog;
+use crate::xppen_hid::XpPenButtons::XpB01;
+use crate::xppen_hid::XpPenResult;
+
+#[test]
+fn test_watchdog_try_again() {
+    let mut watchdog = Watchdog::new();
+    watchdog.set_limit(3);
+
+    // A valid report in between starts the count over
+    assert!(!watchdog.check(&XpPenResult::TryAgain));
+    assert!(!watchdog.check(&XpPenResult::TryAgain));
+    assert!(!watchdog.check(&XpPenResult::Keys(EnumSet::empty())));
+    assert!(!watchdog.check(&XpPenResult::TryAgain));
+    assert!(!watchdog.check(&XpPenResult::Timeout));
+    assert!(!watchdog.check(&XpPenResult::TryAgain));
+    assert!(watchdog.check(&XpPenResult::TryAgain));
+
+    // Fires once, then starts over
+    assert!(!watchdog.check(&XpPenResult::TryAgain));
+}
+
+#[test]
+fn test_watchdog_silence() {
+    let mut watchdog = Watchdog::new();
+    watchdog.set_limit(3);
+
+    // The keypad is quiet while a key is held, that is no fault
+    assert!(!watchdog.check(&XpPenResult::Keys(EnumSet::only(XpB01))));
+    for _ in 0..10 {
+        assert!(!watchdog.check(&XpPenResult::Timeout));
+    }
+}
+
+#[test]
+fn test_watchdog_disabled() {
+    let mut watchdog = Watchdog::new();
+    watchdog.set_limit(0);
+
+    for _ in 0..100 {
+        assert!(!watchdog.check(&XpPenResult::TryAgain));
+    }
+}
@@ -0,0 +1,9 @@
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+pub use uinput::VirtualDial;
+
+#[cfg(not(target_os = "linux"))]
+mod unsupported;
+#[cfg(not(target_os = "linux"))]
+pub use unsupported::VirtualDial;
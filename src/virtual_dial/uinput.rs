@@ -0,0 +1,67 @@
+use std::io;
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AbsInfo, AttributeSet, EventType, InputEvent, Key, UinputAbsSetup};
+use tracing::info;
+
+use crate::keycodes::AbsoluteAxisType;
+
+/// Reported in ABS_MISC while the dial is in use, tablet pads do the same
+const PAD_DEVICE_ID: i32 = 15;
+
+/// A uinput tablet pad exposing the wheel as an absolute axis
+///
+/// Drawing tablet pads report their touch rings as ABS_WHEEL, libinput
+/// recognizes the device as a pad thanks to the BTN_0 button and the
+/// X/Y axes, even though those are never used.
+pub struct VirtualDial {
+    dev: VirtualDevice,
+    axis: AbsoluteAxisType,
+}
+
+impl VirtualDial {
+    /// Register a dial reporting `axis` (ABS_WHEEL or ABS_MISC) in the range 0..positions
+    pub fn new(axis: AbsoluteAxisType, positions: i32) -> io::Result<Self> {
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_0);
+
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name("XP-Pen ACK05 dial")
+            .with_keys(&keys)?;
+        let mut axes = vec![
+            (AbsoluteAxisType::ABS_X, AbsInfo::new(0, 0, 1, 0, 0, 0)),
+            (AbsoluteAxisType::ABS_Y, AbsInfo::new(0, 0, 1, 0, 0, 0)),
+            (axis, AbsInfo::new(0, 0, positions - 1, 0, 0, 0)),
+        ];
+        if axis != AbsoluteAxisType::ABS_MISC {
+            axes.push((
+                AbsoluteAxisType::ABS_MISC,
+                AbsInfo::new(0, 0, PAD_DEVICE_ID, 0, 0, 0),
+            ));
+        }
+        for (axis, info) in axes {
+            builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis.into(), info))?;
+        }
+        let mut dev = builder.build()?;
+
+        for path in dev.enumerate_dev_nodes_blocking()? {
+            let path = path?;
+            info!("Dial available as {}", path.display());
+        }
+
+        Ok(Self { dev, axis })
+    }
+
+    /// Report a new dial position
+    pub fn emit(&mut self, value: i32) -> io::Result<()> {
+        let mut events = vec![InputEvent::new(EventType::ABSOLUTE, self.axis.0, value)];
+        if self.axis != AbsoluteAxisType::ABS_MISC {
+            events.push(InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_MISC.0,
+                PAD_DEVICE_ID,
+            ));
+        }
+        self.dev.emit(&events)
+    }
+}
@@ -0,0 +1,21 @@
+use std::convert::Infallible;
+use std::io;
+
+use crate::keycodes::AbsoluteAxisType;
+
+/// The dial needs uinput, the layouts using it still load elsewhere
+/// but their wheel does not turn anything
+pub struct VirtualDial(Infallible);
+
+impl VirtualDial {
+    pub fn new(_axis: AbsoluteAxisType, _positions: i32) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The virtual dial is only available on Linux",
+        ))
+    }
+
+    pub fn emit(&mut self, _value: i32) -> io::Result<()> {
+        match self.0 {}
+    }
+}
@@ -0,0 +1,23 @@
+use crate::keycodes::{AbsoluteAxisType, KeyCode};
+
+/// Axis values range from -GAMEPAD_AXIS_MAX to GAMEPAD_AXIS_MAX, 0 is the center
+pub const GAMEPAD_AXIS_MAX: i32 = 32767;
+
+/// An event for the virtual gamepad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GamepadEvent {
+    /// Gamepad button (BTN_SOUTH, BTN_TL, ...) pressed or released
+    Button(KeyCode, bool),
+    /// New absolute position of an axis
+    Axis(AbsoluteAxisType, i32),
+}
+
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+pub use uinput::VirtualGamepad;
+
+#[cfg(not(target_os = "linux"))]
+mod unsupported;
+#[cfg(not(target_os = "linux"))]
+pub use unsupported::VirtualGamepad;
@@ -0,0 +1,80 @@
+use std::io;
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AbsInfo, AttributeSet, EventType, InputEvent, Key, UinputAbsSetup};
+use tracing::info;
+
+use super::{GamepadEvent, GAMEPAD_AXIS_MAX};
+use crate::keycodes::{AbsoluteAxisType, KeyCode};
+
+/// A uinput gamepad for emulators and games that ignore keyboards
+///
+/// It is registered next to the virtual keyboard and only carries
+/// the buttons and axes the layout actually uses.
+pub struct VirtualGamepad {
+    pad: VirtualDevice,
+    /// Buttons currently held down
+    held: Vec<KeyCode>,
+}
+
+impl VirtualGamepad {
+    pub fn new<B, A>(buttons: B, axes: A) -> io::Result<Self>
+    where
+        B: IntoIterator<Item = KeyCode>,
+        A: IntoIterator<Item = AbsoluteAxisType>,
+    {
+        let mut keys = AttributeSet::<Key>::new();
+        for k in buttons {
+            keys.insert(k.into());
+        }
+        // Games recognize a gamepad by its primary button
+        keys.insert(Key::BTN_SOUTH);
+
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name("XP-Pen ACK05 gamepad")
+            .with_keys(&keys)?;
+        for axis in axes {
+            let info = AbsInfo::new(0, -GAMEPAD_AXIS_MAX, GAMEPAD_AXIS_MAX, 0, 0, 0);
+            builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis.into(), info))?;
+        }
+        let mut pad = builder.build()?;
+
+        for path in pad.enumerate_dev_nodes_blocking()? {
+            let path = path?;
+            info!("Gamepad available as {}", path.display());
+        }
+
+        Ok(Self {
+            pad,
+            held: Vec::new(),
+        })
+    }
+
+    pub fn emit(&mut self, ev: GamepadEvent) -> io::Result<()> {
+        let ev = match ev {
+            GamepadEvent::Button(k, down) => {
+                self.held.retain(|held| *held != k);
+                if down {
+                    self.held.push(k);
+                }
+                InputEvent::new(EventType::KEY, k.code(), down as i32)
+            }
+            GamepadEvent::Axis(axis, value) => InputEvent::new(EventType::ABSOLUTE, axis.0, value),
+        };
+        self.pad.emit(&[ev])
+    }
+}
+
+impl Drop for VirtualGamepad {
+    // Make sure no button stays pressed in the game when the driver
+    // exits or panics mid-hold
+    fn drop(&mut self) {
+        let events: Vec<InputEvent> = self
+            .held
+            .iter()
+            .rev()
+            .map(|k| InputEvent::new(EventType::KEY, k.code(), 0))
+            .collect();
+        let _ = self.pad.emit(&events);
+    }
+}
@@ -0,0 +1,26 @@
+use std::convert::Infallible;
+use std::io;
+
+use super::GamepadEvent;
+use crate::keycodes::{AbsoluteAxisType, KeyCode};
+
+/// The gamepad needs uinput, the layouts using it still load elsewhere
+/// but their gamepad actions do nothing
+pub struct VirtualGamepad(Infallible);
+
+impl VirtualGamepad {
+    pub fn new<B, A>(_buttons: B, _axes: A) -> io::Result<Self>
+    where
+        B: IntoIterator<Item = KeyCode>,
+        A: IntoIterator<Item = AbsoluteAxisType>,
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "The virtual gamepad is only available on Linux",
+        ))
+    }
+
+    pub fn emit(&mut self, _ev: GamepadEvent) -> io::Result<()> {
+        match self.0 {}
+    }
+}
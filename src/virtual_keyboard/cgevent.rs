@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+use std::io;
+
+use tracing::{info, warn};
+
+use crate::cgevent::{self, keymap::mac_key};
+use crate::keycodes::{KeyCode, RelativeAxisType};
+use crate::layout::types::KeyRepeat;
+
+/// Types the keys by posting CGEvents
+///
+/// Like on Windows there are no virtual devices, the keys are posted to
+/// the HID event tap of the desktop. All the outputs of the layout end up
+/// there, the keys a Mac keyboard does not have are only reported.
+pub struct VirtualKeyboard {
+    /// Keys that were checked for a macOS equivalent already
+    keys: BTreeSet<KeyCode>,
+    /// Keys currently held down, in the order they were pressed
+    held: Vec<KeyCode>,
+}
+
+impl VirtualKeyboard {
+    pub fn new<I>(keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        Self::named("The virtual keyboard", keyset)
+    }
+
+    /// Another output of the layout, typed into the same desktop
+    pub fn output<I>(output: &str, keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        Self::named(&format!("The output {}", output), keyset)
+    }
+
+    fn named<I>(name: &str, keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        let mut kbd = Self {
+            keys: BTreeSet::new(),
+            held: Vec::new(),
+        };
+        kbd.ensure_keys(keyset)?;
+        if !cgevent::trusted() {
+            warn!(
+                "{} needs the Accessibility permission, the keys are dropped until it is granted",
+                name
+            );
+        }
+        info!("{} types through CGEvent", name);
+        Ok(kbd)
+    }
+
+    /// macOS does not repeat the posted keys, the layout engine has to
+    /// repeat them instead
+    pub fn set_repeat(&mut self, repeat: Option<KeyRepeat>) -> io::Result<()> {
+        if repeat.is_some() {
+            warn!("The kernel repeat is only available on Linux, the keys are not repeated");
+        }
+        Ok(())
+    }
+
+    /// The keys are always typed by their virtual key codes
+    pub fn set_scancodes(&mut self, _enabled: bool) {}
+
+    /// Report the `keyset` keys macOS has no equivalent for, they are never
+    /// typed. Nothing has to be registered, returns false.
+    pub fn ensure_keys<I>(&mut self, keyset: I) -> io::Result<bool>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        let missing: Vec<KeyCode> = keyset
+            .into_iter()
+            .filter(|k| self.keys.insert(*k) && mac_key(*k).is_none())
+            .collect();
+        if !missing.is_empty() {
+            warn!("The keys {:?} cannot be typed on macOS", missing);
+        }
+        Ok(false)
+    }
+
+    /// The pointer moves without registering the axes
+    pub fn ensure_axes<I>(&mut self, _axes: I) -> io::Result<bool>
+    where
+        I: IntoIterator<Item = RelativeAxisType>,
+    {
+        Ok(false)
+    }
+
+    /// There is no device to recreate, the held keys are still pressed
+    pub fn recover(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Release all keys still held down
+    pub fn release_all(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for key in self.held.drain(..).rev() {
+            if let Some(code) = mac_key(key) {
+                // Release the rest even when one fails
+                result = result.and(cgevent::post_key(code, false, false));
+            }
+        }
+        result
+    }
+
+    /// Release the held keys. The same happens on drop, this only reports
+    /// the errors.
+    pub fn close(mut self) -> io::Result<()> {
+        self.release_all()
+    }
+
+    /// Send a key event. The held keys are tracked even when the posting
+    /// fails, so the release is not lost.
+    pub fn emit_key(&mut self, key: KeyCode, down: bool) -> io::Result<()> {
+        self.emit_keys(&[(key, down)])
+    }
+
+    /// Post the key events in order. A press of a key that is held already
+    /// is typed as an autorepeat.
+    pub fn emit_keys(&mut self, keys: &[(KeyCode, bool)]) -> io::Result<()> {
+        self.ensure_keys(keys.iter().map(|(k, _)| *k))?;
+
+        for (key, down) in keys.iter().copied() {
+            let repeat = down && self.held.contains(&key);
+            if !repeat {
+                self.held.retain(|k| *k != key);
+                if down {
+                    self.held.push(key);
+                }
+            }
+            if let Some(code) = mac_key(key) {
+                cgevent::post_key(code, down, repeat)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the pointer along a relative axis
+    pub fn emit_rel(&mut self, axis: RelativeAxisType, delta: i32) -> io::Result<()> {
+        cgevent::post_rel(axis, delta)
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    // Make sure no key stays pressed in the desktop, also after a panic
+    // mid-hold
+    fn drop(&mut self) {
+        let _ = self.release_all();
+    }
+}
@@ -0,0 +1,253 @@
+use std::io;
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, EventType, InputEvent, Key, MiscType};
+use tracing::info;
+
+use super::scancodes::hid_scancode;
+use super::uinput::UinputKeyboard;
+use crate::host_leds::VIRTUAL_KEYBOARD_NAME;
+use crate::keycodes::{KeyCode, RelativeAxisType};
+use crate::layout::types::KeyRepeat;
+
+/// The uinput device behind the virtual keyboard
+enum Device {
+    /// Created by the evdev builder
+    Evdev(VirtualDevice),
+    /// Created through the uinput ioctls, only to register the kernel repeat
+    Uinput(UinputKeyboard),
+}
+
+impl Device {
+    /// Post a batch of events terminated by a SYN_REPORT
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        match self {
+            Device::Evdev(dev) => dev.emit(events),
+            Device::Uinput(dev) => dev.emit(events),
+        }
+    }
+}
+
+pub struct VirtualKeyboard {
+    kbd: Device,
+    /// Device name and keyset, to be able to recreate the device
+    name: String,
+    keys: AttributeSet<Key>,
+    /// Relative pointer axes, empty unless the layout moves the pointer
+    axes: AttributeSet<evdev::RelativeAxisType>,
+    /// Keys currently held down, in the order they were pressed
+    held: Vec<KeyCode>,
+    /// Send MSC_SCAN before each key event like a real USB keyboard
+    scancodes: bool,
+    /// Kernel repeat of the held keys, None disables it
+    repeat: Option<KeyRepeat>,
+}
+
+impl VirtualKeyboard {
+    pub fn new<I>(keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item=KeyCode>
+    {
+        Self::named(VIRTUAL_KEYBOARD_NAME, keyset)
+    }
+
+    /// Create an additional output device, eg. "XP-Pen ACK05 driver Consumer Control"
+    pub fn output<I>(output: &str, keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item=KeyCode>
+    {
+        Self::named(&format!("{} {}", VIRTUAL_KEYBOARD_NAME, output), keyset)
+    }
+
+    fn named<I>(name: &str, keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item=KeyCode>
+    {
+        let mut keys = AttributeSet::<Key>::new();
+        for k in keyset {
+            keys.insert(k.into());
+        }
+
+        Ok(Self {
+            kbd: Self::build(name, &keys, &AttributeSet::new(), None)?,
+            name: name.to_string(),
+            keys,
+            axes: AttributeSet::new(),
+            held: Vec::new(),
+            scancodes: false,
+            repeat: None,
+        })
+    }
+
+    fn build(
+        name: &str,
+        keys: &AttributeSet<Key>,
+        axes: &AttributeSet<evdev::RelativeAxisType>,
+        repeat: Option<KeyRepeat>,
+    ) -> io::Result<Device> {
+        let kbd = match repeat {
+            // The evdev builder cannot register EV_REP
+            Some(repeat) => Device::Uinput(UinputKeyboard::create(name, keys, axes, repeat)?),
+            None => {
+                let mut misc = AttributeSet::<MiscType>::new();
+                misc.insert(MiscType::MSC_SCAN);
+                let mut builder = VirtualDeviceBuilder::new()?
+                    .name(name)
+                    .with_keys(keys)?
+                    .with_msc(&misc)?;
+                if axes.iter().next().is_some() {
+                    builder = builder.with_relative_axes(axes)?;
+                }
+                Device::Evdev(builder.build()?)
+            }
+        };
+        info!("Available as {}", name);
+        Ok(kbd)
+    }
+
+    /// Let the kernel repeat the held keys (EV_REP) with the given delay
+    /// and period, None disables the repeat. This is an alternative to
+    /// repeating in the layout engine, macro style devices want neither.
+    /// The device is recreated, the held keys stay pressed.
+    pub fn set_repeat(&mut self, repeat: Option<KeyRepeat>) -> io::Result<()> {
+        if self.repeat == repeat {
+            return Ok(());
+        }
+
+        self.repeat = repeat;
+        self.recover()
+    }
+
+    /// Send the HID scancode (MSC_SCAN) before each key event. Some remapping
+    /// tools and games rely on it. Keys without a known scancode are sent as
+    /// plain key events.
+    pub fn set_scancodes(&mut self, enabled: bool) {
+        self.scancodes = enabled;
+    }
+
+    /// Make sure all `keyset` keys can be emitted, eg. after the layout was
+    /// reloaded. The keys are registered by recreating the device, the held
+    /// keys stay pressed. Returns whether the device had to be recreated.
+    pub fn ensure_keys<I>(&mut self, keyset: I) -> io::Result<bool>
+    where
+        I: IntoIterator<Item=KeyCode>
+    {
+        let missing: Vec<KeyCode> = keyset.into_iter()
+            .filter(|k| !self.keys.contains((*k).into()))
+            .collect();
+        if missing.is_empty() {
+            return Ok(false);
+        }
+
+        info!("Registering new keys {:?}", missing);
+        for k in missing {
+            self.keys.insert(k.into());
+        }
+        self.recover()?;
+        Ok(true)
+    }
+
+    /// Make sure the pointer can be moved along all `axes` (REL_X, REL_Y, ...),
+    /// the same way `ensure_keys` registers the keys. The mouse buttons
+    /// are registered as keys.
+    pub fn ensure_axes<I>(&mut self, axes: I) -> io::Result<bool>
+    where
+        I: IntoIterator<Item=RelativeAxisType>
+    {
+        let missing: Vec<RelativeAxisType> = axes.into_iter()
+            .filter(|a| !self.axes.contains((*a).into()))
+            .collect();
+        if missing.is_empty() {
+            return Ok(false);
+        }
+
+        info!("Registering new pointer axes {:?}", missing);
+        for a in missing {
+            self.axes.insert(a.into());
+        }
+        self.recover()?;
+        Ok(true)
+    }
+
+    /// Recreate the uinput device after an emission error, eg. when the uinput
+    /// module was reloaded. The keys that were held are pressed again.
+    pub fn recover(&mut self) -> io::Result<()> {
+        self.kbd = Self::build(&self.name, &self.keys, &self.axes, self.repeat)?;
+
+        let events: Vec<InputEvent> = self.held.iter()
+            .map(|k| InputEvent::new(EventType::KEY, k.code(), 1))
+            .collect();
+        if !events.is_empty() {
+            self.kbd.emit(&events)?;
+        }
+        Ok(())
+    }
+
+    /// Release all keys still held down, the device stays usable. The events
+    /// are always followed by a SYN, even when no key was held.
+    pub fn release_all(&mut self) -> io::Result<()> {
+        let events: Vec<InputEvent> = self.held.iter().rev()
+            .map(|k| InputEvent::new(EventType::KEY, k.code(), 0))
+            .collect();
+        self.held.clear();
+        self.kbd.emit(&events)
+    }
+
+    /// Release the held keys and destroy the uinput device. The same happens
+    /// on drop, this only reports the errors.
+    pub fn close(mut self) -> io::Result<()> {
+        self.release_all()
+    }
+
+    /// Send a key event. The held keys are tracked even when the emission
+    /// fails, so `recover` can restore the intended state.
+    pub fn emit_key(&mut self, key: KeyCode, down: bool) -> io::Result<()> {
+        self.emit_keys(&[(key, down)])
+    }
+
+    /// Send the key events as a single frame terminated by one SYN_REPORT,
+    /// eg. a modifier together with the key it modifies. A key should not
+    /// appear twice in a frame, the applications may merge its events.
+    /// A press of a key that is held already is sent as an autorepeat.
+    pub fn emit_keys(&mut self, keys: &[(KeyCode, bool)]) -> io::Result<()> {
+        // The kernel silently drops keys the device did not register
+        self.ensure_keys(keys.iter().map(|(k, _)| *k))?;
+
+        let type_ = EventType::KEY;
+        let mut events = Vec::with_capacity(2 * keys.len());
+        for (key, down) in keys.iter().copied() {
+            if let Some(scancode) = hid_scancode(key).filter(|_| self.scancodes) {
+                events.push(InputEvent::new(EventType::MISC, MiscType::MSC_SCAN.0, scancode as i32));
+            }
+
+            // Like a real keyboard, the repeats of a held key have the value 2
+            let value = if down && self.held.contains(&key) {
+                2
+            } else {
+                self.held.retain(|k| *k != key);
+                if down {
+                    self.held.push(key);
+                }
+                down as i32
+            };
+            events.push(InputEvent::new(type_, key.code(), value));
+        }
+        self.kbd.emit(&events)
+    }
+
+    /// Move the pointer along a relative axis
+    pub fn emit_rel(&mut self, axis: RelativeAxisType, delta: i32) -> io::Result<()> {
+        // The kernel silently drops axes the device did not register
+        self.ensure_axes([axis])?;
+        self.kbd.emit(&[InputEvent::new(EventType::RELATIVE, axis.0, delta)])
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    // The uinput node is destroyed when the device is closed, just make sure
+    // no key stays pressed in the system. This covers a panic mid-hold too,
+    // the devices are dropped while the stack unwinds.
+    fn drop(&mut self) {
+        let _ = self.release_all();
+    }
+}
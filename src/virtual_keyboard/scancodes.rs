@@ -0,0 +1,70 @@
+use crate::keycodes::KeyCode;
+
+/// Linux keycodes of the USB HID keyboard usages (page 0x07), indexed by the
+/// usage. This is the table the kernel uses to decode real keyboards, 0 means
+/// the usage has no keycode.
+#[rustfmt::skip]
+const HID_KEYBOARD: [u8; 0xa0] = [
+      0,  0,  0,  0, 30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38,
+     50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45, 21, 44,  2,  3,
+      4,  5,  6,  7,  8,  9, 10, 11, 28,  1, 14, 15, 57, 12, 13, 26,
+     27, 43, 43, 39, 40, 41, 51, 52, 53, 58, 59, 60, 61, 62, 63, 64,
+     65, 66, 67, 68, 87, 88, 99, 70,119,110,102,104,111,107,109,106,
+    105,108,103, 69, 98, 55, 74, 78, 96, 79, 80, 81, 75, 76, 77, 71,
+     72, 73, 82, 83, 86,127,116,117,183,184,185,186,187,188,189,190,
+    191,192,193,194,134,138,130,132,128,129,131,137,133,135,136,113,
+    115,114,  0,  0,  0,121,  0, 89, 93,124, 92, 94, 95,  0,  0,  0,
+    122,123, 90, 91, 85,  0,  0,  0,  0,  0,  0,  0,111,  0,  0,  0,
+];
+
+/// Modifiers start at usage 0xe0
+const HID_MODIFIERS: [KeyCode; 8] = [
+    KeyCode::KEY_LEFTCTRL, KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_LEFTALT, KeyCode::KEY_LEFTMETA,
+    KeyCode::KEY_RIGHTCTRL, KeyCode::KEY_RIGHTSHIFT, KeyCode::KEY_RIGHTALT, KeyCode::KEY_RIGHTMETA,
+];
+
+/// Media keys of the consumer page (0x0c), sent by "Consumer Control" devices
+const HID_CONSUMER: [(KeyCode, u32); 24] = [
+    (KeyCode::KEY_BRIGHTNESSUP, 0x6f),
+    (KeyCode::KEY_BRIGHTNESSDOWN, 0x70),
+    (KeyCode::KEY_PLAY, 0xb0),
+    (KeyCode::KEY_PAUSECD, 0xb1),
+    (KeyCode::KEY_RECORD, 0xb2),
+    (KeyCode::KEY_FASTFORWARD, 0xb3),
+    (KeyCode::KEY_REWIND, 0xb4),
+    (KeyCode::KEY_NEXTSONG, 0xb5),
+    (KeyCode::KEY_PREVIOUSSONG, 0xb6),
+    (KeyCode::KEY_STOPCD, 0xb7),
+    (KeyCode::KEY_EJECTCD, 0xb8),
+    (KeyCode::KEY_PLAYPAUSE, 0xcd),
+    (KeyCode::KEY_MUTE, 0xe2),
+    (KeyCode::KEY_VOLUMEUP, 0xe9),
+    (KeyCode::KEY_VOLUMEDOWN, 0xea),
+    (KeyCode::KEY_MEDIA, 0x183),
+    (KeyCode::KEY_MAIL, 0x18a),
+    (KeyCode::KEY_CALC, 0x192),
+    (KeyCode::KEY_FILE, 0x194),
+    (KeyCode::KEY_WWW, 0x196),
+    (KeyCode::KEY_SEARCH, 0x221),
+    (KeyCode::KEY_HOMEPAGE, 0x223),
+    (KeyCode::KEY_BACK, 0x224),
+    (KeyCode::KEY_FORWARD, 0x225),
+];
+
+/// The MSC_SCAN value a USB keyboard reports together with `key`,
+/// the HID usage page in the upper 16 bits and the usage in the lower ones.
+/// Media keys use the consumer page even when the keyboard page has them
+/// too (mute and volume), the way real keyboards send them.
+pub fn hid_scancode(key: KeyCode) -> Option<u32> {
+    if let Some((_, usage)) = HID_CONSUMER.iter().find(|(k, _)| *k == key) {
+        return Some(0xc0000 | usage);
+    }
+
+    if let Some(usage) = HID_KEYBOARD.iter().position(|code| *code != 0 && u16::from(*code) == key.code()) {
+        return Some(0x70000 | usage as u32);
+    }
+
+    HID_MODIFIERS.iter()
+        .position(|k| *k == key)
+        .map(|idx| 0x700e0 + idx as u32)
+}
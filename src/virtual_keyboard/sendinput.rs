@@ -0,0 +1,146 @@
+use std::collections::BTreeSet;
+use std::io;
+
+use tracing::{info, warn};
+
+use crate::keycodes::{KeyCode, RelativeAxisType};
+use crate::layout::types::KeyRepeat;
+use crate::sendinput::{self, keymap::win_key};
+
+/// Types the keys through SendInput
+///
+/// Windows has no virtual devices to register, the keys are injected into
+/// the input of the desktop. All the outputs of the layout end up there,
+/// the keys a Windows keyboard does not have are only reported.
+pub struct VirtualKeyboard {
+    /// Keys that were checked for a Windows equivalent already
+    keys: BTreeSet<KeyCode>,
+    /// Keys currently held down, in the order they were pressed
+    held: Vec<KeyCode>,
+}
+
+impl VirtualKeyboard {
+    pub fn new<I>(keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        Self::named("The virtual keyboard", keyset)
+    }
+
+    /// Another output of the layout, typed into the same desktop
+    pub fn output<I>(output: &str, keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        Self::named(&format!("The output {}", output), keyset)
+    }
+
+    fn named<I>(name: &str, keyset: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        let mut kbd = Self {
+            keys: BTreeSet::new(),
+            held: Vec::new(),
+        };
+        kbd.ensure_keys(keyset)?;
+        info!("{} types through SendInput", name);
+        Ok(kbd)
+    }
+
+    /// Windows does not repeat the injected keys, the layout engine
+    /// has to repeat them instead
+    pub fn set_repeat(&mut self, repeat: Option<KeyRepeat>) -> io::Result<()> {
+        if repeat.is_some() {
+            warn!("The kernel repeat is only available on Linux, the keys are not repeated");
+        }
+        Ok(())
+    }
+
+    /// The keys are always typed by their scancodes
+    pub fn set_scancodes(&mut self, _enabled: bool) {}
+
+    /// Report the `keyset` keys Windows has no equivalent for, they are
+    /// never typed. Nothing has to be registered, returns false.
+    pub fn ensure_keys<I>(&mut self, keyset: I) -> io::Result<bool>
+    where
+        I: IntoIterator<Item = KeyCode>,
+    {
+        let missing: Vec<KeyCode> = keyset
+            .into_iter()
+            .filter(|k| self.keys.insert(*k) && win_key(*k).is_none())
+            .collect();
+        if !missing.is_empty() {
+            warn!("The keys {:?} cannot be typed on Windows", missing);
+        }
+        Ok(false)
+    }
+
+    /// The pointer moves without registering the axes
+    pub fn ensure_axes<I>(&mut self, _axes: I) -> io::Result<bool>
+    where
+        I: IntoIterator<Item = RelativeAxisType>,
+    {
+        Ok(false)
+    }
+
+    /// There is no device to recreate, the held keys are still pressed
+    pub fn recover(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Release all keys still held down
+    pub fn release_all(&mut self) -> io::Result<()> {
+        let inputs: Vec<_> = self
+            .held
+            .iter()
+            .rev()
+            .filter_map(|k| sendinput::key_input(*k, false))
+            .collect();
+        self.held.clear();
+        sendinput::send(&inputs)
+    }
+
+    /// Release the held keys. The same happens on drop, this only reports
+    /// the errors.
+    pub fn close(mut self) -> io::Result<()> {
+        self.release_all()
+    }
+
+    /// Send a key event. The held keys are tracked even when the injection
+    /// fails, so the release is not lost.
+    pub fn emit_key(&mut self, key: KeyCode, down: bool) -> io::Result<()> {
+        self.emit_keys(&[(key, down)])
+    }
+
+    /// Inject the key events in one go, no other input gets in between.
+    /// A press of a key that is held already is typed as an autorepeat.
+    pub fn emit_keys(&mut self, keys: &[(KeyCode, bool)]) -> io::Result<()> {
+        self.ensure_keys(keys.iter().map(|(k, _)| *k))?;
+
+        let mut inputs = Vec::with_capacity(keys.len());
+        for (key, down) in keys.iter().copied() {
+            if !(down && self.held.contains(&key)) {
+                self.held.retain(|k| *k != key);
+                if down {
+                    self.held.push(key);
+                }
+            }
+            inputs.extend(sendinput::key_input(key, down));
+        }
+        sendinput::send(&inputs)
+    }
+
+    /// Move the pointer along a relative axis
+    pub fn emit_rel(&mut self, axis: RelativeAxisType, delta: i32) -> io::Result<()> {
+        sendinput::rel_input(axis, delta).map_or(Ok(()), |input| sendinput::send(&[input]))
+    }
+}
+
+impl Drop for VirtualKeyboard {
+    // Make sure no key stays pressed in the desktop, also after a panic
+    // mid-hold
+    fn drop(&mut self) {
+        let _ = self.release_all();
+    }
+}
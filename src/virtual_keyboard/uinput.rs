@@ -0,0 +1,123 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+
+use evdev::{AttributeSet, EventType, InputEvent, Key, MiscType, RelativeAxisType};
+
+use crate::layout::types::KeyRepeat;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+const UINPUT_MAX_NAME_SIZE: usize = 80;
+
+// The uinput ioctls, see linux/uinput.h
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_DEV_SETUP: libc::c_ulong = 0x405c5503;
+const UI_SET_EVBIT: libc::c_ulong = 0x40045564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x40045565;
+const UI_SET_RELBIT: libc::c_ulong = 0x40045566;
+const UI_SET_MSCBIT: libc::c_ulong = 0x40045568;
+
+const REP_DELAY: u16 = 0x00;
+const REP_PERIOD: u16 = 0x01;
+
+#[repr(C)]
+struct UinputSetup {
+    id: libc::input_id,
+    name: [u8; UINPUT_MAX_NAME_SIZE],
+    ff_effects_max: u32,
+}
+
+/// A uinput keyboard with the kernel key repeat, created directly through
+/// the uinput ioctls
+///
+/// The evdev builder has no way to register EV_REP, which is needed
+/// for the kernel to repeat the held keys. The keyboards without the
+/// repeat are created by the builder.
+pub struct UinputKeyboard {
+    file: File,
+}
+
+impl UinputKeyboard {
+    pub fn create(
+        name: &str,
+        keys: &AttributeSet<Key>,
+        axes: &AttributeSet<RelativeAxisType>,
+        repeat: KeyRepeat,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(UINPUT_PATH)?;
+        let fd = file.as_raw_fd();
+
+        let ioctl = |request: libc::c_ulong, arg: libc::c_ulong| -> io::Result<()> {
+            // SAFETY: the uinput set bit ioctls take an integer argument
+            if unsafe { libc::ioctl(fd, request, arg) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        };
+
+        ioctl(UI_SET_EVBIT, EventType::KEY.0 as libc::c_ulong)?;
+        for k in keys.iter() {
+            ioctl(UI_SET_KEYBIT, k.code() as libc::c_ulong)?;
+        }
+        if axes.iter().next().is_some() {
+            ioctl(UI_SET_EVBIT, EventType::RELATIVE.0 as libc::c_ulong)?;
+            for axis in axes.iter() {
+                ioctl(UI_SET_RELBIT, axis.0 as libc::c_ulong)?;
+            }
+        }
+        ioctl(UI_SET_EVBIT, EventType::MISC.0 as libc::c_ulong)?;
+        ioctl(UI_SET_MSCBIT, MiscType::MSC_SCAN.0 as libc::c_ulong)?;
+        ioctl(UI_SET_EVBIT, EventType::REPEAT.0 as libc::c_ulong)?;
+
+        let mut setup = UinputSetup {
+            id: libc::input_id {
+                bustype: 0x03, // BUS_USB
+                vendor: 0x1234,
+                product: 0x5678,
+                version: 0x111,
+            },
+            name: [0; UINPUT_MAX_NAME_SIZE],
+            ff_effects_max: 0,
+        };
+        // Keep the terminating zero
+        let len = name.len().min(UINPUT_MAX_NAME_SIZE - 1);
+        setup.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+
+        // SAFETY: UI_DEV_SETUP reads a struct uinput_setup, which UinputSetup mirrors
+        if unsafe { libc::ioctl(fd, UI_DEV_SETUP, &setup as *const UinputSetup) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        ioctl(UI_DEV_CREATE, 0)?;
+
+        let mut kbd = Self { file };
+        // The kernel picks its defaults on creation, override them
+        kbd.emit(&[
+            InputEvent::new(EventType::REPEAT, REP_DELAY, repeat.delay.as_millis() as i32),
+            InputEvent::new(EventType::REPEAT, REP_PERIOD, repeat.period.as_millis() as i32),
+        ])?;
+        Ok(kbd)
+    }
+
+    /// Post a batch of events terminated by a SYN_REPORT
+    pub fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+        let raw: Vec<libc::input_event> = events.iter().chain([&syn])
+            .map(|ev| *ev.as_ref())
+            .collect();
+
+        // SAFETY: input_event is a plain C struct, the kernel reads it as bytes
+        let bytes = unsafe {
+            std::slice::from_raw_parts(raw.as_ptr() as *const u8, std::mem::size_of_val(raw.as_slice()))
+        };
+        self.file.write_all(bytes)
+    }
+}
+
+impl Drop for UinputKeyboard {
+    fn drop(&mut self) {
+        // SAFETY: UI_DEV_DESTROY takes no argument. Closing the file
+        // destroys the device as well, this just makes it immediate.
+        unsafe { libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY) };
+    }
+}
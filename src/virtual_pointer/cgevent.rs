@@ -0,0 +1,56 @@
+use std::io;
+
+use tracing::info;
+
+use crate::cgevent;
+use crate::keycodes::{KeyCode, RelativeAxisType};
+
+/// Clicks the mouse buttons and moves the pointer by posting CGEvents
+///
+/// Like the keyboard it has no device of its own, the events go to the
+/// same pointer the real mice move.
+pub struct VirtualPointer {
+    /// Buttons currently held down
+    held: Vec<KeyCode>,
+}
+
+impl VirtualPointer {
+    pub fn new<B, A>(_buttons: B, _axes: A) -> io::Result<Self>
+    where
+        B: IntoIterator<Item = KeyCode>,
+        A: IntoIterator<Item = RelativeAxisType>,
+    {
+        info!("The pointer moves through CGEvent");
+        Ok(Self { held: Vec::new() })
+    }
+
+    /// Press or release the mouse buttons in order
+    pub fn emit_buttons(&mut self, buttons: &[(KeyCode, bool)]) -> io::Result<()> {
+        for (k, down) in buttons.iter().copied() {
+            self.held.retain(|held| *held != k);
+            if down {
+                self.held.push(k);
+            }
+            if let Some(button) = cgevent::mouse_button(k) {
+                cgevent::post_button(button, down)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the pointer or turn a wheel along a relative axis
+    pub fn emit_rel(&mut self, axis: RelativeAxisType, delta: i32) -> io::Result<()> {
+        cgevent::post_rel(axis, delta)
+    }
+}
+
+impl Drop for VirtualPointer {
+    // Make sure no button stays pressed in the desktop
+    fn drop(&mut self) {
+        for k in self.held.drain(..).rev() {
+            if let Some(button) = cgevent::mouse_button(k) {
+                let _ = cgevent::post_button(button, false);
+            }
+        }
+    }
+}
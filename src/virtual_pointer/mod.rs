@@ -0,0 +1,22 @@
+use crate::keycodes::KeyCode;
+
+/// Is the key a mouse button (BTN_LEFT to BTN_TASK)? Those are sent
+/// through the pointer device, not the keyboard.
+pub fn is_pointer_button(key: KeyCode) -> bool {
+    (KeyCode::BTN_LEFT.code()..=KeyCode::BTN_TASK.code()).contains(&key.code())
+}
+
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+pub use uinput::VirtualPointer;
+
+#[cfg(windows)]
+mod sendinput;
+#[cfg(windows)]
+pub use sendinput::VirtualPointer;
+
+#[cfg(target_os = "macos")]
+mod cgevent;
+#[cfg(target_os = "macos")]
+pub use cgevent::VirtualPointer;
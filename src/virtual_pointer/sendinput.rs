@@ -0,0 +1,59 @@
+use std::io;
+
+use tracing::info;
+
+use crate::keycodes::{KeyCode, RelativeAxisType};
+use crate::sendinput;
+
+/// Clicks the mouse buttons and moves the pointer through SendInput
+///
+/// Like the keyboard it has no device of its own, the events go to the
+/// same pointer the real mice move.
+pub struct VirtualPointer {
+    /// Buttons currently held down
+    held: Vec<KeyCode>,
+}
+
+impl VirtualPointer {
+    pub fn new<B, A>(_buttons: B, _axes: A) -> io::Result<Self>
+    where
+        B: IntoIterator<Item = KeyCode>,
+        A: IntoIterator<Item = RelativeAxisType>,
+    {
+        info!("The pointer moves through SendInput");
+        Ok(Self { held: Vec::new() })
+    }
+
+    /// Press or release the mouse buttons in one go
+    pub fn emit_buttons(&mut self, buttons: &[(KeyCode, bool)]) -> io::Result<()> {
+        let inputs: Vec<_> = buttons
+            .iter()
+            .filter_map(|(k, down)| {
+                self.held.retain(|held| held != k);
+                if *down {
+                    self.held.push(*k);
+                }
+                sendinput::button_input(*k, *down)
+            })
+            .collect();
+        sendinput::send(&inputs)
+    }
+
+    /// Move the pointer or turn a wheel along a relative axis
+    pub fn emit_rel(&mut self, axis: RelativeAxisType, delta: i32) -> io::Result<()> {
+        sendinput::rel_input(axis, delta).map_or(Ok(()), |input| sendinput::send(&[input]))
+    }
+}
+
+impl Drop for VirtualPointer {
+    // Make sure no button stays pressed in the desktop
+    fn drop(&mut self) {
+        let inputs: Vec<_> = self
+            .held
+            .iter()
+            .rev()
+            .filter_map(|k| sendinput::button_input(*k, false))
+            .collect();
+        let _ = sendinput::send(&inputs);
+    }
+}
@@ -0,0 +1,89 @@
+use std::io;
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, EventType, InputEvent, Key};
+use tracing::info;
+
+use crate::keycodes::{KeyCode, RelativeAxisType};
+
+/// A uinput mouse for the pointer and wheel actions
+///
+/// Some toolkits ignore relative events coming from keyboard-class
+/// devices, so the buttons and axes get a device of their own. It is
+/// only registered when the layout uses them.
+pub struct VirtualPointer {
+    dev: VirtualDevice,
+    /// Buttons currently held down
+    held: Vec<KeyCode>,
+}
+
+impl VirtualPointer {
+    pub fn new<B, A>(buttons: B, axes: A) -> io::Result<Self>
+    where
+        B: IntoIterator<Item = KeyCode>,
+        A: IntoIterator<Item = RelativeAxisType>,
+    {
+        // libinput recognizes a mouse by its left button and the X/Y axes
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_LEFT);
+        for k in buttons {
+            keys.insert(k.into());
+        }
+        let mut rel = AttributeSet::<evdev::RelativeAxisType>::new();
+        rel.insert(evdev::RelativeAxisType::REL_X);
+        rel.insert(evdev::RelativeAxisType::REL_Y);
+        for axis in axes {
+            rel.insert(axis.into());
+        }
+
+        let mut dev = VirtualDeviceBuilder::new()?
+            .name("XP-Pen ACK05 pointer")
+            .with_keys(&keys)?
+            .with_relative_axes(&rel)?
+            .build()?;
+
+        for path in dev.enumerate_dev_nodes_blocking()? {
+            let path = path?;
+            info!("Pointer available as {}", path.display());
+        }
+
+        Ok(Self {
+            dev,
+            held: Vec::new(),
+        })
+    }
+
+    /// Press or release the mouse buttons in a single frame
+    pub fn emit_buttons(&mut self, buttons: &[(KeyCode, bool)]) -> io::Result<()> {
+        let events: Vec<InputEvent> = buttons
+            .iter()
+            .map(|(k, down)| {
+                self.held.retain(|held| held != k);
+                if *down {
+                    self.held.push(*k);
+                }
+                InputEvent::new(EventType::KEY, k.code(), *down as i32)
+            })
+            .collect();
+        self.dev.emit(&events)
+    }
+
+    /// Move the pointer or turn a wheel along a relative axis
+    pub fn emit_rel(&mut self, axis: RelativeAxisType, delta: i32) -> io::Result<()> {
+        self.dev
+            .emit(&[InputEvent::new(EventType::RELATIVE, axis.0, delta)])
+    }
+}
+
+impl Drop for VirtualPointer {
+    // Make sure no button stays pressed in the system
+    fn drop(&mut self) {
+        let events: Vec<InputEvent> = self
+            .held
+            .iter()
+            .rev()
+            .map(|k| InputEvent::new(EventType::KEY, k.code(), 0))
+            .collect();
+        let _ = self.dev.emit(&events);
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The longest timing gap injected at random
+const MAX_RANDOM_GAP: Duration = Duration::from_secs(5);
+
+/// The most reports lost by a random disconnect
+const MAX_RANDOM_OUTAGE: usize = 3;
+
+/// Device errors that can be injected into the report stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// The report is cut short
+    ShortRead,
+    /// The report header and content are garbage
+    Malformed,
+    /// The device disappears for the given number of reports, they are lost
+    Disconnect(usize),
+    /// The report and all that follow arrive late by the given time
+    Gap(Duration),
+}
+
+/// A read from the faulty device
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaultyRead {
+    Report(Vec<u8>),
+    Disconnected,
+}
+
+/// Wraps a stream of timestamped raw reports and injects device errors
+///
+/// Faults are either injected on demand for the next report, or at random
+/// with a fixed seed, so a failing run can be replayed exactly.
+pub struct FaultInjector<I> {
+    reports: I,
+    /// Faults requested for the next reports
+    queued: VecDeque<Fault>,
+    /// xorshift state, 0 disables the random faults
+    rng: u64,
+    /// Chance of a random fault per report
+    percent: u64,
+    /// Sum of the injected gaps, all later reports are delayed by it
+    delay: Duration,
+    /// Reports still lost to a disconnect
+    outage: usize,
+}
+
+impl<I> FaultInjector<I>
+where
+    I: Iterator<Item = (Vec<u8>, Instant)>,
+{
+    /// Pass the reports through, faults are only injected on demand
+    pub fn new(reports: I) -> Self {
+        Self {
+            reports,
+            queued: VecDeque::new(),
+            rng: 0,
+            percent: 0,
+            delay: Duration::ZERO,
+            outage: 0,
+        }
+    }
+
+    /// Inject a random fault into `percent` % of the reports
+    pub fn seeded(reports: I, seed: u64, percent: u8) -> Self {
+        Self {
+            // xorshift gets stuck on zero
+            rng: seed.max(1),
+            percent: percent.min(100) as u64,
+            ..Self::new(reports)
+        }
+    }
+
+    /// Apply the fault to the next report
+    pub fn inject(&mut self, fault: Fault) {
+        self.queued.push_back(fault);
+    }
+
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn random_fault(&mut self) -> Option<Fault> {
+        if self.rng == 0 || self.random() % 100 >= self.percent {
+            return None;
+        }
+
+        let fault = match self.random() % 4 {
+            0 => Fault::ShortRead,
+            1 => Fault::Malformed,
+            2 => Fault::Disconnect(1 + self.random() as usize % MAX_RANDOM_OUTAGE),
+            _ => Fault::Gap(MAX_RANDOM_GAP.mul_f64((self.random() % 1000) as f64 / 1000.0)),
+        };
+        Some(fault)
+    }
+}
+
+impl<I> Iterator for FaultInjector<I>
+where
+    I: Iterator<Item = (Vec<u8>, Instant)>,
+{
+    type Item = (FaultyRead, Instant);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut report, t) = self.reports.next()?;
+
+        if self.outage > 0 {
+            self.outage -= 1;
+            return Some((FaultyRead::Disconnected, t + self.delay));
+        }
+
+        let fault = self.queued.pop_front().or_else(|| self.random_fault());
+        match fault {
+            Some(Fault::ShortRead) => {
+                let len = self.random() as usize % report.len().max(1);
+                report.truncate(len);
+            }
+            Some(Fault::Malformed) => {
+                for b in report.iter_mut() {
+                    *b = self.random() as u8;
+                }
+                // Make sure it does not pass as a valid report by accident
+                if let Some(id) = report.get_mut(1) {
+                    *id = !240;
+                }
+            }
+            Some(Fault::Disconnect(lost)) => {
+                self.outage = lost.saturating_sub(1);
+                return Some((FaultyRead::Disconnected, t + self.delay));
+            }
+            Some(Fault::Gap(gap)) => self.delay += gap,
+            None => {}
+        }
+
+        Some((FaultyRead::Report(report), t + self.delay))
+    }
+}
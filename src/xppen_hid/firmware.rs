@@ -0,0 +1,58 @@
+use std::fmt;
+
+use super::report_map::ReportMap;
+
+/// The packet switching the ACK05 to one bit per key, sniffed from the
+/// communication of the official application
+pub const ACK05_MODE_SWITCH: [u8; 10] =
+    [0x02, 0xb0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+/// The vendor feature report asked for the firmware version, it shares
+/// the report id with the mode switch packet
+pub const FIRMWARE_REPORT_ID: u8 = 0x02;
+
+/// The length of the firmware report including its id
+pub const FIRMWARE_REPORT_LEN: usize = 10;
+
+/// The firmware revision of the keypad in BCD, eg. 0x0102 = 1.02
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FirmwareVersion(pub u16);
+
+impl FirmwareVersion {
+    /// The version in the reply to the firmware feature report, the major
+    /// and the minor BCD byte follow the report id. None when the reply
+    /// belongs to another report or carries no version.
+    pub fn from_report(reply: &[u8]) -> Option<Self> {
+        match reply {
+            [FIRMWARE_REPORT_ID, major, minor, ..] if (*major, *minor) != (0, 0) => {
+                Some(Self(u16::from_be_bytes([*major, *minor])))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:x}.{:02x}", self.0 >> 8, self.0 & 0xff)
+    }
+}
+
+/// The protocol details of a firmware revision
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quirks {
+    /// The packet switching to one bit per key, None when there is none
+    pub mode_switch: Option<[u8; 10]>,
+    /// The format of the reports in that mode
+    pub report_map: ReportMap,
+}
+
+impl Quirks {
+    /// The firmware the driver was written for
+    pub fn ack05() -> Self {
+        Self {
+            mode_switch: Some(ACK05_MODE_SWITCH),
+            report_map: ReportMap::ack05(),
+        }
+    }
+}
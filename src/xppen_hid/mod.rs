@@ -1,15 +1,49 @@
-use enumset::{EnumSet, EnumSetType};
-use hidapi::{self, BusType, HidApi, HidDevice, HidResult};
+#[cfg(test)]
+pub mod faults;
+pub mod firmware;
+pub mod report_map;
+#[cfg(target_os = "linux")]
+mod usbfs;
+pub mod watchdog;
 
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::time::Duration;
+
+use enumset::EnumSetType;
+use hidapi::{self, BusType, DeviceInfo, HidApi, HidDevice, HidError};
+use tracing::{debug, info, trace, warn};
+
+use crate::button_device::{ButtonDevice, ReadResult};
 use crate::kbd_events::HasState;
 use crate::layout::types::KeyCoords;
+use firmware::{FirmwareVersion, Quirks, FIRMWARE_REPORT_ID, FIRMWARE_REPORT_LEN};
+use report_map::{KeyboardReportMap, ReportMap};
+#[cfg(target_os = "linux")]
+use usbfs::reset_usb;
 
 const PID: u16 = 0x0202;
 const VID: u16 = 0x28bd;
 
+/// Logical positions of the rotary encoder gestures: spin CW, spin CCW, wiggle
+pub const XP_ROTARY_GESTURES: [KeyCoords; 3] =
+    [KeyCoords(1, 1, 0), KeyCoords(1, 1, 1), KeyCoords(1, 1, 2)];
+
+/// Opposite rotary encoder ticks arriving within this window are spurious
+pub const XP_ROTARY_REVERSAL_FILTER: Duration = Duration::from_millis(30);
+
 // XP-Pen ACK05
 pub struct XpPenAck05 {
     device: HidDevice,
+    map: ReportMap,
+    /// The protocol details of the firmware
+    quirks: Quirks,
+    /// The keys of the standard keyboard mode
+    fallback: KeyboardReportMap,
+    /// The device could not be switched to the key bit mode,
+    /// the fallback decodes the reports
+    degraded: bool,
 }
 
 #[derive(EnumSetType, Debug, Hash)]
@@ -24,13 +58,18 @@ pub enum XpPenButtons {
     XpB08,
     XpB09,
     XpB10,
-    XpRoCW,
     XpRoCCW,
+    XpRoCW,
 }
 
 impl Into<KeyCoords> for XpPenButtons {
+    // Buttons are in block 0, the stateless rotary encoder has its own block 1
     fn into(self) -> KeyCoords {
-        return KeyCoords(0, 0, self as u8);
+        match self {
+            XpPenButtons::XpRoCCW => KeyCoords(1, 0, 0),
+            XpPenButtons::XpRoCW => KeyCoords(1, 0, 1),
+            _ => KeyCoords(0, 0, self as u8),
+        }
     }
 }
 
@@ -42,14 +81,91 @@ impl HasState for XpPenButtons {
     }
 }
 
-fn open_keyboard(api: &HidApi) -> Option<HidDevice> {
+/// Why the keypad cannot be used
+#[derive(Debug)]
+pub enum XpPenError {
+    /// No ACK05 is connected
+    NotFound,
+    /// The keypad is connected, but its hidraw node is not accessible,
+    /// the udev rule is most likely missing
+    PermissionDenied(String),
+    /// The HID communication failed, eg. the keypad was unplugged
+    Io(HidError),
+    /// The keypad is connected in a way the driver cannot configure
+    UnsupportedBus(BusType),
+    /// The firmware has no known key bit mode
+    NoModeSwitch,
+}
+
+impl fmt::Display for XpPenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XpPenError::NotFound => write!(f, "No XP-Pen ACK05 found"),
+            XpPenError::PermissionDenied(path) => write!(
+                f,
+                "No permission to open {}, is the udev rule installed?",
+                path
+            ),
+            XpPenError::Io(e) => write!(f, "HID communication failed: {}", e),
+            XpPenError::UnsupportedBus(bus) => {
+                write!(f, "Connection over {:?} is not supported", bus)
+            }
+            XpPenError::NoModeSwitch => write!(f, "The firmware has no known key bit mode"),
+        }
+    }
+}
+
+impl std::error::Error for XpPenError {}
+
+impl From<HidError> for XpPenError {
+    fn from(e: HidError) -> Self {
+        XpPenError::Io(e)
+    }
+}
+
+impl From<io::Error> for XpPenError {
+    fn from(error: io::Error) -> Self {
+        XpPenError::Io(HidError::IoError { error })
+    }
+}
+
+/// Which of the connected keypads to drive, all the given fields have to match.
+/// The default selects the first one found.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceSelector {
+    /// The hidraw node, eg. /dev/hidraw3. It can change with the enumeration order.
+    pub path: Option<String>,
+    /// The serial number as shown by `list-devices`, it stays with the unit
+    pub serial: Option<String>,
+}
+
+impl DeviceSelector {
+    fn matches(&self, device: &DeviceInfo) -> bool {
+        self.path
+            .as_ref()
+            .is_none_or(|path| device.path().to_bytes() == path.as_bytes())
+            && self
+                .serial
+                .as_ref()
+                .is_none_or(|serial| device.serial_number() == Some(serial.as_str()))
+    }
+}
+
+/// Is the `device` the vendor interface of an ACK05 picked by the `selector`?
+fn is_keyboard(device: &DeviceInfo, selector: &DeviceSelector) -> bool {
+    device.vendor_id() == VID
+        && device.product_id() == PID
+        && device.usage_page() == 0xff0a
+        && device.usage() == 0x1
+        && selector.matches(device)
+}
+
+/// Open the first ACK05 picked by the `selector`
+fn open_keyboard(api: &HidApi, selector: &DeviceSelector) -> Result<HidDevice, XpPenError> {
+    let mut error = XpPenError::NotFound;
     for device in api.device_list() {
-        if device.vendor_id() == VID
-            && device.product_id() == PID
-            && device.usage_page() == 0xff0a
-            && device.usage() == 0x1
-        {
-            println!(
+        if is_keyboard(device, selector) {
+            info!(
                 "SELECTING {:?} {:?} {:?} {:?} interface: {} usage: {:04x} ({:04x})",
                 device.path(),
                 device.manufacturer_string(),
@@ -59,31 +175,85 @@ fn open_keyboard(api: &HidApi) -> Option<HidDevice> {
                 device.usage(),
                 device.usage_page()
             );
-            if let HidResult::Ok(hid) = device.open_device(api) {
-                return Some(hid);
+            match device.open_device(api) {
+                Ok(hid) => return Ok(hid),
+                Err(hid_error) => {
+                    // hidapi does not tell why, ask the node itself
+                    let path = device.path().to_string_lossy().into_owned();
+                    let access = OpenOptions::new().read(true).write(true).open(&path);
+                    error = match access {
+                        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                            XpPenError::PermissionDenied(path)
+                        }
+                        _ => XpPenError::Io(hid_error),
+                    };
+                }
             }
         }
     }
 
-    println!("No device found.");
-    None
+    Err(error)
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum XpPenResult {
-    Timeout,
-    TryAgain,
-    Keys(EnumSet<XpPenButtons>),
+/// Only usbfs can reset the keypad to its standard keyboard mode
+#[cfg(not(target_os = "linux"))]
+fn reset_usb(_hidraw: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Resetting the keypad is only available on Linux",
+    ))
 }
 
-impl XpPenAck05 {
-    pub fn new() -> Self {
-        let api = hidapi::HidApi::new().unwrap();
+/// Return the ACK05 picked by the `selector` to its standard keyboard mode
+/// without opening it, eg. after an earlier run left it in the key bit mode.
+pub fn restore_standard_mode(selector: &DeviceSelector) -> Result<(), XpPenError> {
+    let api = hidapi::HidApi::new()?;
+    let device = api
+        .device_list()
+        .find(|device| is_keyboard(device, selector))
+        .ok_or(XpPenError::NotFound)?;
+    if !matches!(device.bus_type(), BusType::Usb) {
+        // Only the USB connection is ever switched
+        return Ok(());
+    }
+    reset_usb(&device.path().to_string_lossy())?;
+    Ok(())
+}
 
-        // Print out information about all connected devices
-        for device in api.device_list() {
-            println!(
-                "0x{:04x}:0x{:04x} 0x{:04x}:0x{:04x} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}",
+/// Ask the keypad for its firmware version. The release number of the USB
+/// device descriptor is used when it does not answer the feature report.
+fn read_firmware(device: &HidDevice) -> Option<FirmwareVersion> {
+    let mut buf = [0u8; FIRMWARE_REPORT_LEN];
+    buf[0] = FIRMWARE_REPORT_ID;
+    match device.get_feature_report(&mut buf) {
+        Ok(len) => {
+            info!("Firmware report: {:02x?}", &buf[..len]);
+            if let Some(version) = FirmwareVersion::from_report(&buf[..len]) {
+                return Some(version);
+            }
+            debug!("The firmware report has no version, using the release number.");
+        }
+        Err(e) => debug!(
+            "Cannot read the firmware report: {}, using the release number.",
+            e
+        ),
+    }
+    device
+        .get_device_info()
+        .ok()
+        .map(|info| FirmwareVersion(info.release_number()))
+}
+
+pub type XpPenResult = ReadResult<XpPenButtons>;
+
+/// Describe all connected HID devices, one per line
+pub fn list_devices() -> Result<Vec<String>, XpPenError> {
+    let api = hidapi::HidApi::new()?;
+    let devices = api
+        .device_list()
+        .map(|device| {
+            format!(
+                "0x{:04x}:0x{:04x} 0x{:04x}:0x{:04x} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}{}",
                 device.vendor_id(),
                 device.product_id(),
                 device.usage(),
@@ -95,95 +265,153 @@ impl XpPenAck05 {
                 device.interface_number(),
                 device.bus_type(),
                 device.release_number(),
-                device.path()
-            );
-        }
+                device.path(),
+                if device.vendor_id() == VID && device.product_id() == PID {
+                    " (ACK05)"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect();
+    Ok(devices)
+}
+
+impl XpPenAck05 {
+    /// Open the ACK05 picked by the `selector`, eg. by its hidraw path
+    /// or serial number
+    pub fn open_selected(selector: &DeviceSelector) -> Result<Self, XpPenError> {
+        let api = hidapi::HidApi::new()?;
 
         // Connect to device using its VID and PID
-        let device = open_keyboard(&api).unwrap();
-        println!("Device: {:?}", device);
-
-        // Initialize XP-Pen ACK05
-        // This was sniffed from the USB communication between the official application
-        // and the device. It switches the protocol to represent each key with one bit
-        // instead of sending HID scan codes.
-        let bus = device
+        let device = open_keyboard(&api, selector)?;
+        debug!("Device: {:?}", device);
+
+        match read_firmware(&device) {
+            Some(version) => info!("Firmware {}", version),
+            None => warn!("Unknown firmware version, assuming the usual ACK05 protocol."),
+        }
+        let quirks = Quirks::ack05();
+
+        let mut xppen = Self {
+            device,
+            map: quirks.report_map.clone(),
+            quirks,
+            fallback: KeyboardReportMap::default(),
+            degraded: false,
+        };
+        if let Err(e) = xppen.configure() {
+            warn!("{}, decoding the standard keyboard reports instead.", e);
+        }
+        Ok(xppen)
+    }
+
+    pub fn set_blocking(&self) {
+        let _ = self.device.set_blocking_mode(true);
+    }
+
+    /// Decode the reports using `map` instead of the bit layout of the
+    /// firmware, None returns to the firmware one
+    pub fn set_report_map(&mut self, map: Option<ReportMap>) {
+        self.map = map.unwrap_or_else(|| self.quirks.report_map.clone());
+    }
+
+    /// Decode the standard keyboard reports using `map` when the device
+    /// cannot be switched to the key bit mode
+    pub fn set_fallback_map(&mut self, map: KeyboardReportMap) {
+        self.fallback = map;
+    }
+
+    /// Are the standard keyboard reports decoded instead of the key bits?
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Decode a raw report the way the current mode needs
+    pub fn parse(&self, report: &[u8]) -> XpPenResult {
+        if self.degraded {
+            self.fallback.parse(report)
+        } else {
+            self.map.parse(report)
+        }
+    }
+
+    /// Read the next report undecoded, wait at most `timeout` ms (-1 = forever).
+    /// Returns the length of the report, 0 when none arrived in time.
+    pub fn read_raw(&mut self, buf: &mut [u8], timeout: i32) -> Result<usize, XpPenError> {
+        let res = self.device.read_timeout(buf, timeout)?;
+        trace!("Read: {:?}", &buf[..res]);
+        Ok(res)
+    }
+
+    /// Send the sniffed mode switch packet, only possible over USB
+    fn switch_mode(&mut self) -> Result<(), XpPenError> {
+        let bus = self
+            .device
             .get_device_info()
             .map_or(BusType::Usb, |info| info.bus_type());
         if let BusType::Usb = bus {
-            println!("Configuring USB HID key bit mode.");
-            let buf = [0x02, 0xb0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
-            let res = device.write(&buf).unwrap();
-            println!("Wrote: {:?} byte(s)", res);
+            info!("Configuring USB HID key bit mode.");
+            let buf = self.quirks.mode_switch.ok_or(XpPenError::NoModeSwitch)?;
+            let res = self.device.write(&buf)?;
+            debug!("Wrote: {:?} byte(s)", res);
         } else if let BusType::Bluetooth = bus {
-            println!("Configuring Bluetooth HID key bit mode.");
-            panic!("Bluetooth connection is currently not supported!.");
+            info!("Configuring Bluetooth HID key bit mode.");
+            return Err(XpPenError::UnsupportedBus(bus));
             //let buf = [0x02, 0xb0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
             //let res = device.write(&buf).unwrap();
             //println!("Wrote: {:?} byte(s)", res);
         }
 
-        Self { device }
+        Ok(())
     }
 
-    pub fn set_blocking(&self) {
-        let _ = self.device.set_blocking_mode(true);
+    pub fn read(&mut self, block: bool) -> Result<XpPenResult, XpPenError> {
+        self.read_timeout(if block { -1 } else { 25 })
     }
+}
 
-    pub fn read(&self, block: bool) -> XpPenResult {
-        let mut buf = [0u8; 32];
+impl ButtonDevice for XpPenAck05 {
+    type Button = XpPenButtons;
+    type Error = XpPenError;
 
-        let timeout = if block { -1 } else { 25 };
+    fn open() -> Result<Self, XpPenError> {
+        Self::open_selected(&DeviceSelector::default())
+    }
 
-        let res = self.device.read_timeout(&mut buf[..], timeout).unwrap();
-        //println!("Read: {:?}", &buf[..res]);
-        if res == 0 {
-            return XpPenResult::Timeout;
-        }
+    /// Initialize XP-Pen ACK05
+    /// The packet of the firmware was sniffed from the USB communication between
+    /// the official application and the device. It switches the protocol to represent
+    /// each key with one bit instead of sending HID scan codes.
+    /// The device forgets the mode when it loses power, eg. during system suspend.
+    /// When the switch fails the reports are decoded by the fallback map.
+    fn configure(&mut self) -> Result<(), XpPenError> {
+        let result = self.switch_mode();
+        self.degraded = result.is_err();
+        result
+    }
 
-        if buf[1] != 240 {
-            return XpPenResult::TryAgain;
+    /// No packet switching back to the standard keyboard mode is known,
+    /// the USB device is reset instead. The handle is stale afterwards.
+    fn restore(&mut self) -> Result<(), XpPenError> {
+        if self.degraded {
+            // The keypad was never switched
+            return Ok(());
         }
+        let info = self.device.get_device_info()?;
+        reset_usb(&info.path().to_string_lossy())?;
+        Ok(())
+    }
 
-        let mut state = EnumSet::empty();
+    /// Read the next report, wait at most `timeout` ms (-1 = forever)
+    fn read_timeout(&mut self, timeout: i32) -> Result<XpPenResult, XpPenError> {
+        let mut buf = [0u8; 32];
 
-        if buf[2] & 0x01 > 0 {
-            state |= XpPenButtons::XpB01;
-        }
-        if buf[2] & 0x02 > 0 {
-            state |= XpPenButtons::XpB02;
-        }
-        if buf[2] & 0x04 > 0 {
-            state |= XpPenButtons::XpB03;
-        }
-        if buf[2] & 0x08 > 0 {
-            state |= XpPenButtons::XpB04;
-        }
-        if buf[2] & 0x10 > 0 {
-            state |= XpPenButtons::XpB05;
-        }
-        if buf[2] & 0x20 > 0 {
-            state |= XpPenButtons::XpB06;
-        }
-        if buf[2] & 0x40 > 0 {
-            state |= XpPenButtons::XpB07;
-        }
-        if buf[2] & 0x80 > 0 {
-            state |= XpPenButtons::XpB08;
-        }
-        if buf[3] & 0x01 > 0 {
-            state |= XpPenButtons::XpB09;
-        }
-        if buf[3] & 0x02 > 0 {
-            state |= XpPenButtons::XpB10;
-        }
-        if buf[7] & 0x01 > 0 {
-            state |= XpPenButtons::XpRoCW;
-        }
-        if buf[7] & 0x02 > 0 {
-            state |= XpPenButtons::XpRoCCW;
+        let res = self.read_raw(&mut buf[..], timeout)?;
+        if res == 0 {
+            return Ok(XpPenResult::Timeout);
         }
 
-        return XpPenResult::Keys(state);
+        Ok(self.parse(&buf[..]))
     }
 }
@@ -0,0 +1,292 @@
+use std::fmt;
+
+use enumset::EnumSet;
+use serde::Deserialize;
+
+use super::{XpPenButtons, XpPenResult};
+
+/// The most buttons a report map can describe
+pub const MAX_BUTTONS: usize = 10;
+
+/// One bit of a raw report
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct ReportBit {
+    pub byte: usize,
+    pub bit: u8,
+}
+
+impl ReportBit {
+    pub const fn new(byte: usize, bit: u8) -> Self {
+        Self { byte, bit }
+    }
+
+    fn is_set(&self, buf: &[u8]) -> bool {
+        self.bit < 8 && buf[self.byte] & (1 << self.bit) > 0
+    }
+}
+
+impl fmt::Display for ReportBit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.byte, self.bit)
+    }
+}
+
+/// All the set bits of a raw report, the candidates for a new button
+pub fn set_bits(buf: &[u8]) -> Vec<ReportBit> {
+    buf.iter()
+        .enumerate()
+        .flat_map(|(byte, value)| {
+            (0..8)
+                .filter(move |bit| value & (1 << bit) > 0)
+                .map(move |bit| ReportBit::new(byte, bit))
+        })
+        .collect()
+}
+
+/// Describe a raw report for the debug output, the bytes in hex and the
+/// set bits as byte.bit, eg. `02 f0 01 | 0.1 1.4 1.5 1.6 1.7 2.0`
+pub fn describe_report(buf: &[u8]) -> String {
+    let bytes: Vec<String> = buf.iter().map(|b| format!("{:02x}", b)).collect();
+    let bits: Vec<String> = set_bits(buf).iter().map(ToString::to_string).collect();
+    format!("{} | {}", bytes.join(" "), bits.join(" "))
+}
+
+/// How the wheel movement is reported
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum WheelField {
+    /// One bit per direction, like the ACK05 does
+    Bits { cw: ReportBit, ccw: ReportBit },
+    /// A signed relative value in one byte, positive values are CW.
+    /// Only the direction is used, each report is a single tick.
+    Delta { delta_byte: usize },
+}
+
+/// Declarative description of the button reports of a device
+///
+/// The map is optional in layout files. When it is missing the ACK05
+/// one bit per key protocol is assumed, eg.
+///
+/// ```toml
+/// [report]
+/// id = 240
+/// buttons = [{ byte = 2, bit = 0 }, { byte = 2, bit = 1 }]
+/// wheel = { delta_byte = 4 }
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ReportMap {
+    /// Position of the byte identifying the button reports
+    #[serde(default = "default_id_byte")]
+    pub id_byte: usize,
+    /// Value of the identifying byte, other reports are ignored
+    pub id: u8,
+    /// Bits of the buttons 0, 1, 2, ..., at most `MAX_BUTTONS`
+    pub buttons: Vec<ReportBit>,
+    pub wheel: Option<WheelField>,
+}
+
+fn default_id_byte() -> usize {
+    1
+}
+
+impl ReportMap {
+    /// The ACK05 in the mode set by `XpPenAck05::configure`
+    pub fn ack05() -> Self {
+        let mut buttons: Vec<ReportBit> = (0..8).map(|bit| ReportBit::new(2, bit)).collect();
+        buttons.extend((0..2).map(|bit| ReportBit::new(3, bit)));
+
+        Self {
+            id_byte: default_id_byte(),
+            id: 240,
+            buttons,
+            wheel: Some(WheelField::Bits {
+                cw: ReportBit::new(7, 1),
+                ccw: ReportBit::new(7, 0),
+            }),
+        }
+    }
+
+    /// The shortest report containing all the fields
+    fn report_len(&self) -> usize {
+        let wheel = match self.wheel {
+            Some(WheelField::Bits { cw, ccw }) => cw.byte.max(ccw.byte),
+            Some(WheelField::Delta { delta_byte }) => delta_byte,
+            None => 0,
+        };
+
+        self.buttons
+            .iter()
+            .map(|b| b.byte)
+            .chain([self.id_byte, wheel])
+            .max()
+            .unwrap_or_default()
+            + 1
+    }
+
+    /// Decode a raw report. Foreign and truncated reports are skipped.
+    pub fn parse(&self, buf: &[u8]) -> XpPenResult {
+        if buf.len() < self.report_len() || buf[self.id_byte] != self.id {
+            return XpPenResult::TryAgain;
+        }
+
+        let mut state = EnumSet::<XpPenButtons>::all()
+            .iter()
+            .take(MAX_BUTTONS)
+            .zip(&self.buttons)
+            .filter(|(_, b)| b.is_set(buf))
+            .map(|(button, _)| button)
+            .collect();
+
+        match self.wheel {
+            Some(WheelField::Bits { cw, ccw }) => {
+                if cw.is_set(buf) {
+                    state |= XpPenButtons::XpRoCW;
+                }
+                if ccw.is_set(buf) {
+                    state |= XpPenButtons::XpRoCCW;
+                }
+            }
+            Some(WheelField::Delta { delta_byte }) => match (buf[delta_byte] as i8).signum() {
+                1 => state |= XpPenButtons::XpRoCW,
+                -1 => state |= XpPenButtons::XpRoCCW,
+                _ => {}
+            },
+            None => {}
+        }
+
+        XpPenResult::Keys(state)
+    }
+}
+
+impl Default for ReportMap {
+    fn default() -> Self {
+        Self::ack05()
+    }
+}
+
+/// A key of a standard HID keyboard report, the modifier bits and the
+/// usage id of the key. Usage 0 stands for the modifiers alone.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScanCode {
+    #[serde(default)]
+    pub modifiers: u8,
+    pub usage: u8,
+}
+
+impl ScanCode {
+    pub const fn new(modifiers: u8, usage: u8) -> Self {
+        Self { modifiers, usage }
+    }
+
+    /// Is the key in the report? The modifiers have to match exactly.
+    fn is_pressed(&self, modifiers: u8, usages: &[u8]) -> bool {
+        modifiers == self.modifiers
+            && if self.usage == 0 {
+                modifiers != 0 && usages.iter().all(|u| *u == 0)
+            } else {
+                usages.contains(&self.usage)
+            }
+    }
+}
+
+/// The keys the buttons type in the standard keyboard mode of the device
+///
+/// Used when the device cannot be switched to the one bit per key mode,
+/// eg. over Bluetooth or with an unknown firmware. Every button is recognized
+/// by the key it types, so only one button at a time is reliable.
+///
+/// ```toml
+/// [fallback]
+/// id = 2
+/// buttons = [{ usage = 5 }, { modifiers = 1, usage = 29 }]
+/// cw = { modifiers = 1, usage = 46 }
+/// ccw = { modifiers = 1, usage = 45 }
+/// ```
+///
+/// Without the section the factory keys of the ACK05 are assumed.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyboardReportMap {
+    /// The report id in the first byte, None for keyboards without report ids
+    pub id: Option<u8>,
+    /// The keys of the buttons 0, 1, 2, ..., at most `MAX_BUTTONS`
+    #[serde(default)]
+    pub buttons: Vec<ScanCode>,
+    /// The keys of the wheel directions
+    pub cw: Option<ScanCode>,
+    pub ccw: Option<ScanCode>,
+}
+
+impl KeyboardReportMap {
+    /// The factory keys of the ACK05: B, E, Space, Alt, Ctrl, Shift,
+    /// Ctrl+Z, Ctrl+Shift+Z, Ctrl+S and Tab, the wheel zooms with Ctrl+=
+    /// and Ctrl+-
+    pub fn ack05() -> Self {
+        Self {
+            id: Some(2),
+            buttons: vec![
+                ScanCode::new(0, 5),
+                ScanCode::new(0, 8),
+                ScanCode::new(0, 44),
+                ScanCode::new(4, 0),
+                ScanCode::new(1, 0),
+                ScanCode::new(2, 0),
+                ScanCode::new(1, 29),
+                ScanCode::new(3, 29),
+                ScanCode::new(1, 22),
+                ScanCode::new(0, 43),
+            ],
+            cw: Some(ScanCode::new(1, 46)),
+            ccw: Some(ScanCode::new(1, 45)),
+        }
+    }
+
+    /// Are there any keys to recognize?
+    pub fn is_empty(&self) -> bool {
+        self.buttons.is_empty() && self.cw.is_none() && self.ccw.is_none()
+    }
+
+    /// Decode a keyboard report, the modifiers byte, a reserved one and
+    /// up to six usages. Foreign and truncated reports are skipped.
+    pub fn parse(&self, buf: &[u8]) -> XpPenResult {
+        let report = match self.id {
+            Some(id) if buf.first() == Some(&id) => &buf[1..],
+            Some(_) => return XpPenResult::TryAgain,
+            None => buf,
+        };
+        if report.len() < 3 {
+            return XpPenResult::TryAgain;
+        }
+        let modifiers = report[0];
+        let usages = &report[2..report.len().min(8)];
+
+        let mut state: EnumSet<XpPenButtons> = EnumSet::<XpPenButtons>::all()
+            .iter()
+            .take(MAX_BUTTONS)
+            .zip(&self.buttons)
+            .filter(|(_, code)| code.is_pressed(modifiers, usages))
+            .map(|(button, _)| button)
+            .collect();
+        if self
+            .cw
+            .is_some_and(|code| code.is_pressed(modifiers, usages))
+        {
+            state |= XpPenButtons::XpRoCW;
+        }
+        if self
+            .ccw
+            .is_some_and(|code| code.is_pressed(modifiers, usages))
+        {
+            state |= XpPenButtons::XpRoCCW;
+        }
+
+        XpPenResult::Keys(state)
+    }
+}
+
+impl Default for KeyboardReportMap {
+    fn default() -> Self {
+        Self::ack05()
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+
+/// The usbfs ioctl resetting a USB device, _IO('U', 20)
+const USBDEVFS_RESET: libc::c_ulong = 0x5514;
+
+/// The usbfs node of the USB device the `hidraw` node belongs to
+fn usb_device_node(hidraw: &str) -> io::Result<PathBuf> {
+    let not_usb = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a USB device", hidraw),
+        )
+    };
+    let name = Path::new(hidraw).file_name().ok_or_else(not_usb)?;
+    // The HID device is a child of the USB interface, that of the USB device
+    let hid = fs::canonicalize(Path::new("/sys/class/hidraw").join(name).join("device"))?;
+    let usb = hid.ancestors().nth(2).ok_or_else(not_usb)?;
+    let number = |attr: &str| -> io::Result<u16> {
+        fs::read_to_string(usb.join(attr))?
+            .trim()
+            .parse()
+            .map_err(|_| not_usb())
+    };
+    Ok(PathBuf::from(format!(
+        "/dev/bus/usb/{:03}/{:03}",
+        number("busnum")?,
+        number("devnum")?
+    )))
+}
+
+/// Reset the USB device of the `hidraw` node. The keypad starts over like
+/// after a replug, in its standard keyboard mode, and is enumerated again.
+pub(super) fn reset_usb(hidraw: &str) -> io::Result<()> {
+    let node = usb_device_node(hidraw)?;
+    info!(
+        "Resetting {} to the standard keyboard mode.",
+        node.display()
+    );
+    let usb = OpenOptions::new().write(true).open(&node)?;
+    if unsafe { libc::ioctl(usb.as_raw_fd(), USBDEVFS_RESET) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use super::XpPenResult;
+
+/// Consecutive unrecognized reports that mean the device lost its mode
+pub const TRY_AGAIN_LIMIT: u32 = 50;
+
+/// Notices a device that stopped reporting properly
+///
+/// The ACK05 sometimes ends up sending only reports of the HID scan code
+/// protocol (eg. after a hub reset). The caller is expected to flush the
+/// state, release all keys and re-initialize the device when the watchdog
+/// fires. Silence is not a fault, the keypad only reports the changes and
+/// stays quiet for as long as a key is held.
+pub struct Watchdog {
+    try_again_limit: u32,
+    /// Unrecognized reports in a row
+    try_again: u32,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            try_again_limit: TRY_AGAIN_LIMIT,
+            try_again: 0,
+        }
+    }
+
+    /// Configure the number of unrecognized reports in a row that trigger
+    /// the watchdog, zero disables it
+    pub fn set_limit(&mut self, try_again: u32) {
+        self.try_again_limit = try_again;
+    }
+
+    /// Observe the result of a read. Returns true when the device looks
+    /// wedged, the watchdog starts over then.
+    pub fn check(&mut self, result: &XpPenResult) -> bool {
+        match result {
+            XpPenResult::Keys(_) => self.try_again = 0,
+            XpPenResult::TryAgain => self.try_again += 1,
+            XpPenResult::Timeout => {}
+        }
+
+        if self.try_again_limit > 0 && self.try_again >= self.try_again_limit {
+            self.reset();
+            return true;
+        }
+        false
+    }
+
+    /// Start over, eg. after the device was re-initialized
+    pub fn reset(&mut self) {
+        self.try_again = 0;
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}